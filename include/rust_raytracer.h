@@ -0,0 +1,79 @@
+/* The C interface to rust_raytracer, for a build with the "ffi" feature
+ * (cargo build --release --features ffi), linking against
+ * target/release/librust_raytracer.so or .a. Kept in step with src/ffi.rs; it can
+ * be regenerated with cbindgen --config cbindgen.toml --features ffi --output
+ * include/rust_raytracer.h */
+
+#ifndef RUST_RAYTRACER_H
+#define RUST_RAYTRACER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define RT_OK 0
+#define RT_NULL_POINTER -1
+#define RT_INVALID_ARGUMENT -2
+#define RT_BUFFER_TOO_SMALL -3
+
+#define RT_LAMBERTIAN 0
+#define RT_METAL 1
+#define RT_DIELECTRIC 2
+#define RT_LIGHT 3
+
+typedef struct RtScene RtScene;
+
+/* `color` is the albedo or the emitted color, and `parameter` a metal's fuzz, a
+ * dielectric's index of refraction or a light's intensity */
+typedef struct RtMaterial {
+  uint32_t kind;
+  double color[3];
+  double parameter;
+} RtMaterial;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An empty scene under the sky, seen from the default camera. Free it with
+ * rt_scene_free. */
+RtScene *rt_scene_new(void);
+
+void rt_scene_free(RtScene *scene);
+
+int32_t rt_scene_add_sphere(RtScene *scene, const double *center, double radius, const RtMaterial *material);
+
+/* A triangle mesh from `vertex_count` positions, packed as x, y, z doubles, and
+ * `triangle_count` triangles, each 3 indices into the positions. */
+int32_t rt_scene_add_mesh(RtScene *scene,
+                          const double *positions,
+                          size_t vertex_count,
+                          const uint32_t *indices,
+                          size_t triangle_count,
+                          const RtMaterial *material);
+
+/* Looks from `lookfrom` towards `lookat`, with `vup` up and a vertical field of
+ * view of `vfov` degrees. */
+int32_t rt_scene_set_camera(RtScene *scene, const double *lookfrom, const double *lookat, const double *vup, double vfov);
+
+/* A solid background color in place of the sky, or the sky again if `color` is
+ * null. */
+int32_t rt_scene_set_background(RtScene *scene, const double *color);
+
+/* Path traces the scene at `width` by `height` (each at least 2) into `out` as
+ * linear RGB floats, three to a pixel, row by row from the top. The same seed
+ * always gives the same image. `out_len` is the number of floats `out` has room
+ * for. */
+int32_t rt_render(const RtScene *scene,
+                  uint32_t width,
+                  uint32_t height,
+                  uint32_t samples_per_pixel,
+                  uint32_t max_depth,
+                  uint64_t seed,
+                  float *out,
+                  size_t out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUST_RAYTRACER_H */
@@ -0,0 +1,119 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Instant,
+};
+
+use crate::integrator::Integrator;
+use crate::render::{sample_pixel, RenderSettings};
+use crate::sampler::RandomSampler;
+use crate::scenes::Scene;
+use crate::types::*;
+
+const TILE_SIZE: u16 = 32;
+// Each tile is timed at this many samples per pixel and scaled up to the real count
+const PROBE_SAMPLES: i64 = 1;
+// Cheapest to most expensive, for the cost map
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+// Counts how many distinct things of each name there are among `items`, telling
+// shared ones apart by address
+fn tally<'a>(items: impl Iterator<Item = (*const (), &'a str)>) -> (usize, String) {
+    let mut seen = HashSet::new();
+    let mut counts = BTreeMap::new();
+    for (address, name) in items {
+        if seen.insert(address) {
+            *counts.entry(name).or_insert(0) += 1;
+        }
+    }
+    let breakdown: Vec<String> = counts.iter().map(|(name, count)| format!("{name} {count}")).collect();
+    (seen.len(), breakdown.join(", "))
+}
+
+// Prints what's in a scene, how good its BVH is and roughly how long each tile of
+// a render would take, without doing the render
+pub fn run(scene_name: &str, scene: &Scene, integrator_name: &str, integrator: &dyn Integrator, settings: &RenderSettings) {
+    println!("Scene: {scene_name}");
+
+    let objects = &scene.world.objects;
+    let mut kinds = BTreeMap::new();
+    for object in objects {
+        *kinds.entry(object.kind()).or_insert(0) += 1;
+    }
+    let kinds: Vec<String> = kinds.iter().map(|(kind, count)| format!("{kind} {count}")).collect();
+    println!("Objects: {} ({})", objects.len(), kinds.join(", "));
+
+    let materials: Vec<&dyn Material> = objects.iter().filter_map(|object| object.material()).collect();
+    let (material_count, breakdown) =
+        tally(materials.iter().map(|&m| (m as *const dyn Material as *const (), m.name())));
+    println!("Materials: {material_count} distinct ({breakdown})");
+    let (texture_count, breakdown) = tally(
+        materials
+            .iter()
+            .flat_map(|m| m.textures())
+            .map(|t| (t as *const dyn crate::texture::Texture as *const (), t.name())),
+    );
+    println!("Textures: {texture_count} distinct ({breakdown})");
+
+    let stats = scene.bvh.stats();
+    println!(
+        "BVH: {} nodes, {} leaves, depth {}, largest leaf {} objects, {} unbounded objects",
+        stats.nodes, stats.leaves, stats.max_depth, stats.max_leaf_size, stats.unbounded
+    );
+    println!(
+        "     SAH cost {:.2} (a flat list would be {}), sibling overlap {:.1}%",
+        stats.sah_cost,
+        stats.bounded + stats.unbounded,
+        stats.overlap * 100.0
+    );
+
+    match scene.bounds {
+        Some(b) => println!(
+            "Bounds: sphere at ({:.3}, {:.3}, {:.3}), radius {:.3}; camera rays that miss it aren't traced",
+            b.center.x, b.center.y, b.center.z, b.radius
+        ),
+        None => println!("Bounds: none, the scene has unbounded objects"),
+    }
+
+    let (width, height) = (settings.image_width, settings.image_height);
+    let camera = scene.camera.for_image_height(height);
+    let mut sampler = RandomSampler::new();
+    let (columns, rows) = (width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE));
+    let mut seconds = vec![0.0; columns as usize * rows as usize];
+    for (index, tile) in seconds.iter_mut().enumerate() {
+        let (tx, ty) = (index as u16 % columns, index as u16 / columns);
+        let start = Instant::now();
+        for row in ty * TILE_SIZE..((ty + 1) * TILE_SIZE).min(height) {
+            for i in tx * TILE_SIZE..((tx + 1) * TILE_SIZE).min(width) {
+                sample_pixel(scene, integrator, &camera, &mut sampler, settings, (i, height - 1 - row), PROBE_SAMPLES);
+            }
+        }
+        *tile = start.elapsed().as_secs_f64() * settings.samples_per_pixel as f64 / PROBE_SAMPLES as f64;
+    }
+
+    let total: f64 = seconds.iter().sum();
+    let (hottest, &most) = seconds
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap_or((0, &0.0));
+    println!(
+        "Estimated cost at {width}x{height}, {} spp with the {integrator_name} integrator, per {TILE_SIZE}x{TILE_SIZE} tile:",
+        settings.samples_per_pixel
+    );
+    for ty in 0..rows as usize {
+        let line: String = seconds[ty * columns as usize..(ty + 1) * columns as usize]
+            .iter()
+            .map(|&s| {
+                let level = if most > 0.0 { (s / most * (RAMP.len() - 1) as f64).round() as usize } else { 0 };
+                RAMP[level.min(RAMP.len() - 1)] as char
+            })
+            .collect();
+        println!("  |{line}|");
+    }
+    println!(
+        "Total: about {total:.1}s single-threaded; the most expensive tile is ({}, {}) at {:.2}s",
+        hottest % columns as usize,
+        hottest / columns as usize,
+        most
+    );
+}
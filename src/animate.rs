@@ -0,0 +1,45 @@
+use std::{fs::File, io::BufWriter};
+
+use crate::aov;
+use crate::integrator::Integrator;
+use crate::render::{render_view, RenderSettings};
+use crate::scenes::Scene;
+
+pub struct AnimationSettings {
+    pub frames: usize,
+    // How far the camera swings round its target over the whole animation, in degrees
+    pub orbit: f64,
+    pub motion_vectors: bool,
+}
+
+// Renders a camera move around the scene as numbered frames, `<prefix>0000.ppm`
+// and so on, with `<prefix>0000.motion.pfm` alongside each when motion vectors are on
+pub fn run(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    animation: &AnimationSettings,
+    prefix: &str,
+) -> Result<(), String> {
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let step = animation.orbit / animation.frames as f64;
+    let camera_at = |frame: usize| scene.camera.orbited(step * frame as f64, 0.0, 1.0);
+    let create = |path: &str| File::create(path).map(BufWriter::new).map_err(|e| format!("couldn't create {path}: {e}"));
+
+    for frame in 0..animation.frames {
+        eprintln!("Frame {} of {}", frame + 1, animation.frames);
+        let camera = camera_at(frame);
+        let image = render_view(scene, &camera, integrator, settings, true);
+        let path = format!("{prefix}{frame:04}.ppm");
+        image.write_ppm(&mut create(&path)?).map_err(|e| format!("couldn't write {path}: {e}"))?;
+
+        if animation.motion_vectors {
+            // The first frame has nothing to move from, so it's compared with itself
+            let previous = camera_at(frame.saturating_sub(1));
+            let motion = aov::motion_pass(scene, &camera, &previous, width, height);
+            let path = format!("{prefix}{frame:04}.motion.pfm");
+            motion.write_pfm(&mut create(&path)?).map_err(|e| format!("couldn't write {path}: {e}"))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{self, Write},
+};
+
+use crate::render::Image;
+use crate::scenes::Scene;
+use crate::types::*;
+
+// Keeps hit points from being found again on the surface they start on
+const T_MIN: f64 = 0.0001;
+
+// Floating-point pixels with any number of channels, top row first, for passes
+// that don't make sense as 8-bit colors
+pub struct FloatImage {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub data: Vec<f32>,
+}
+
+impl FloatImage {
+    pub fn new(width: usize, height: usize, channels: usize) -> Self {
+        FloatImage {
+            width,
+            height,
+            channels,
+            data: vec![0.0; width * height * channels],
+        }
+    }
+
+    // The linear colors of `image`, before the gamma its PPM would get
+    pub fn from_image(image: &Image) -> Self {
+        let data = image.pixels.iter().flat_map(|c| [c.x as f32, c.y as f32, c.z as f32]).collect();
+        FloatImage { width: image.width, height: image.height, channels: 3, data }
+    }
+
+    // Writes a Portable Float Map: "Pf" for one channel or "PF" for three, little
+    // endian (hence the negative scale), rows running bottom to top
+    pub fn write_pfm(&self, out: &mut dyn Write) -> io::Result<()> {
+        let magic = match self.channels {
+            1 => "Pf",
+            3 => "PF",
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "PFM holds one or three channels")),
+        };
+        writeln!(out, "{magic}\n{} {}\n-1.0", self.width, self.height)?;
+        let row_len = self.width * self.channels;
+        for row in self.data.chunks(row_len).rev() {
+            for value in row {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The first surface a camera ray meets, how far along the ray it is, and the
+// ray segment that found it
+struct PrimaryHit<'a> {
+    rec: HitRecord<'a>,
+    distance: f64,
+    ray: Ray,
+}
+
+// Finds the first hit, looking through emitters hidden from the camera
+fn primary_hit(scene: &Scene, mut r: Ray) -> Option<PrimaryHit<'_>> {
+    let mut distance = 0.0;
+    loop {
+        let mut rec = HitRecord::blank();
+        if !scene.hit(r, Interval::new(T_MIN, f64::INFINITY), &mut rec) {
+            return None;
+        }
+        distance += rec.t * r.direction.length();
+        match rec.mat_ptr {
+            Some(mat) if !mat.is_visible_to_camera() => r = Ray::new(rec.p, r.direction).with_time(r.time),
+            _ => return Some(PrimaryHit { rec, distance, ray: r }),
+        }
+    }
+}
+
+// Calls `f` with the row, column and primary hit of the ray through the center
+// of every pixel, as seen through `camera`
+fn for_each_primary_hit<'a>(
+    scene: &'a Scene,
+    camera: &Camera,
+    width: usize,
+    height: usize,
+    mut f: impl FnMut(usize, usize, Option<PrimaryHit<'a>>),
+) {
+    for row in 0..height {
+        let j = height - 1 - row;
+        for i in 0..width {
+            let u = (i as f64 + 0.5) / (width - 1) as f64;
+            let v = (j as f64 + 0.5) / (height - 1) as f64;
+            f(row, i, primary_hit(scene, camera.get_ray(u, v)));
+        }
+    }
+}
+
+// Distance from the camera to the first hit, in scene units. With a `range` the
+// distances are mapped so `near` is 0 and `far` is 1, clamped, and misses read 1;
+// without one misses are infinite.
+pub fn depth_pass(scene: &Scene, width: usize, height: usize, range: Option<(f64, f64)>) -> FloatImage {
+    let mut image = FloatImage::new(width, height, 1);
+    for_each_primary_hit(scene, &scene.camera, width, height, |row, i, hit| {
+        let distance = hit.map_or(f64::INFINITY, |hit| hit.distance);
+        let value = match range {
+            Some((near, far)) => ((distance - near) / (far - near)).clamp(0.0, 1.0),
+            None => distance,
+        };
+        image.data[row * width + i] = value as f32;
+    });
+    image
+}
+
+// World-space position of the first hit as three float channels; misses are
+// left at the origin, as compositing packages expect
+pub fn position_pass(scene: &Scene, width: usize, height: usize) -> FloatImage {
+    let mut image = FloatImage::new(width, height, 3);
+    for_each_primary_hit(scene, &scene.camera, width, height, |row, i, hit| {
+        if let Some(hit) = hit {
+            let p = hit.rec.p;
+            let index = (row * width + i) * 3;
+            image.data[index..index + 3].copy_from_slice(&[p.x as f32, p.y as f32, p.z as f32]);
+        }
+    });
+    image
+}
+
+// Screen-space motion of each pixel's first hit since the previous frame, in
+// pixels with x to the right and y down, stored in the first two of three float
+// channels. The scene is static, so only the camera moving from `previous` to
+// `camera` makes things move; points the previous camera couldn't see get no motion.
+pub fn motion_pass(scene: &Scene, camera: &Camera, previous: &Camera, width: usize, height: usize) -> FloatImage {
+    let mut image = FloatImage::new(width, height, 3);
+    let to_pixels = |(u, v): (f64, f64)| (u * (width - 1) as f64, (1.0 - v) * (height - 1) as f64);
+    for_each_primary_hit(scene, camera, width, height, |row, i, hit| {
+        let Some(hit) = hit else {
+            return;
+        };
+        if let (Some(now), Some(before)) = (camera.project(hit.rec.p), previous.project(hit.rec.p)) {
+            let (now, before) = (to_pixels(now), to_pixels(before));
+            let index = (row * width + i) * 3;
+            image.data[index] = (now.0 - before.0) as f32;
+            image.data[index + 1] = (now.1 - before.1) as f32;
+        }
+    });
+    image
+}
+
+// What the segmentation pass tells apart: every distinct material, or every
+// top-level object in the scene
+#[derive(Clone, Copy)]
+pub enum SegmentBy {
+    Material,
+    Object,
+}
+
+impl SegmentBy {
+    pub fn by_name(name: &str) -> Option<SegmentBy> {
+        match name {
+            "material" => Some(SegmentBy::Material),
+            "object" => Some(SegmentBy::Object),
+            _ => None,
+        }
+    }
+}
+
+// Flat 8-bit class colors per pixel plus the legend naming each color. Misses are black.
+pub struct Segmentation {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+    pub mode: &'static str,
+    pub classes: Vec<(String, [u8; 3])>,
+}
+
+// FNV-1a, so a label's color only depends on the label itself
+fn label_hash(label: &str, salt: u32) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for byte in label.bytes().chain(salt.to_le_bytes()) {
+        h ^= byte as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+// A color picked from the label's hash, rehashed until it's neither black nor
+// already taken
+fn label_color(label: &str, taken: &[[u8; 3]]) -> [u8; 3] {
+    let mut salt = 0;
+    loop {
+        let h = label_hash(label, salt);
+        let color = [(h >> 16) as u8 | 0x20, (h >> 32) as u8 | 0x20, (h >> 48) as u8 | 0x20];
+        if !taken.contains(&color) {
+            return color;
+        }
+        salt += 1;
+    }
+}
+
+// The index of the top-level object `r` hits first, which is the one a hit along
+// it belongs to
+pub fn nearest_object(scene: &Scene, r: Ray) -> Option<usize> {
+    let mut nearest = None;
+    let mut closest = f64::INFINITY;
+    let mut rec = HitRecord::blank();
+    for (index, object) in scene.world.objects.iter().enumerate() {
+        if object.hit(r, Interval::new(T_MIN, closest), &mut rec) {
+            closest = rec.t;
+            nearest = Some(index);
+        }
+    }
+    nearest
+}
+
+// Labels every class in the order the scene lists its objects. Materials are
+// named by type and numbered per type, objects by index and kind, so the same
+// scene gets the same labels and colors in every frame.
+pub fn segmentation_pass(scene: &Scene, width: usize, height: usize, by: SegmentBy) -> Segmentation {
+    let objects = &scene.world.objects;
+    let mut classes: Vec<(String, [u8; 3])> = Vec::new();
+    fn add_class(classes: &mut Vec<(String, [u8; 3])>, label: String) -> usize {
+        let taken: Vec<[u8; 3]> = classes.iter().map(|(_, color)| *color).collect();
+        let color = label_color(&label, &taken);
+        classes.push((label, color));
+        classes.len() - 1
+    }
+
+    // Materials are told apart by address, since several objects may share one
+    let mut material_classes = HashMap::new();
+    let mut per_name: HashMap<&str, usize> = HashMap::new();
+    if let SegmentBy::Material = by {
+        for mat in objects.iter().filter_map(|object| object.material()).map(|mat| scene.shading_material(mat)) {
+            let address = mat as *const dyn Material as *const ();
+            if let Entry::Vacant(entry) = material_classes.entry(address) {
+                let n = per_name.entry(mat.name()).or_insert(0);
+                let label = format!("{}-{n}", mat.name());
+                *n += 1;
+                entry.insert(add_class(&mut classes, label));
+            }
+        }
+    } else {
+        for (index, object) in objects.iter().enumerate() {
+            add_class(&mut classes, format!("object-{index}-{}", object.kind()));
+        }
+    }
+
+    let mut pixels = vec![[0, 0, 0]; width * height];
+    for_each_primary_hit(scene, &scene.camera, width, height, |row, i, hit| {
+        let Some(hit) = hit else {
+            return;
+        };
+        let class = match by {
+            SegmentBy::Material => hit
+                .rec
+                .mat_ptr
+                .and_then(|mat| material_classes.get(&(mat as *const dyn Material as *const ())).copied()),
+            SegmentBy::Object => nearest_object(scene, hit.ray),
+        };
+        if let Some(class) = class {
+            pixels[row * width + i] = classes[class].1;
+        }
+    });
+
+    let mode = match by {
+        SegmentBy::Material => "material",
+        SegmentBy::Object => "object",
+    };
+    Segmentation { width, height, pixels, mode, classes }
+}
+
+impl Segmentation {
+    // Binary PPM, so the class colors come out exactly as listed in the legend
+    pub fn write_ppm(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "P6\n{} {}\n255", self.width, self.height)?;
+        for pixel in &self.pixels {
+            out.write_all(pixel)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_legend(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"mode\": \"{}\",", self.mode)?;
+        writeln!(out, "  \"background\": [0, 0, 0],")?;
+        writeln!(out, "  \"classes\": [")?;
+        for (index, (label, [r, g, b])) in self.classes.iter().enumerate() {
+            let separator = if index + 1 < self.classes.len() { "," } else { "" };
+            writeln!(
+                out,
+                "    {{ \"id\": {}, \"label\": \"{}\", \"color\": [{r}, {g}, {b}] }}{separator}",
+                index + 1,
+                label.replace('\\', "\\\\").replace('"', "\\\"")
+            )?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,88 @@
+use crate::integrator::Integrator;
+use crate::render::{for_each_row, Image, RenderSettings};
+use crate::sampler::Sampler;
+use crate::sampling::cosine_hemisphere;
+use crate::scenes::Scene;
+use crate::types::*;
+
+// How far off the surface `Full` bakes look back at it from, comfortably more
+// than the integrators' own offset against self-intersection
+const STANDOFF: f64 = 0.001;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BakeMode {
+    // All the light arriving at the surface, straight from lights and the sky and
+    // bounced off everything else, divided by pi: a lambertian surface of albedo
+    // `a` reflects `a` times this. The surface's own color plays no part.
+    Irradiance,
+    // The surface as the camera sees it looking straight at it, its own color,
+    // glossy reflections and emission included
+    Full,
+}
+
+impl BakeMode {
+    pub fn by_name(name: &str) -> Option<BakeMode> {
+        match name {
+            "irradiance" => Some(BakeMode::Irradiance),
+            "full" => Some(BakeMode::Full),
+            _ => None,
+        }
+    }
+}
+
+// Bakes light into a `size` x `size` texture over the object's UV layout, top row
+// at v = 1 as image textures read it, with `settings.samples_per_pixel` samples
+// per texel jittered over the texel. Rows are shared between `settings.threads`
+// threads and seeded as in a render. None when the object has no UV layout.
+pub fn bake(
+    scene: &Scene,
+    object: &dyn Hittable,
+    mode: BakeMode,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    size: usize,
+) -> Option<Image> {
+    object.surface_at_uv(0.5, 0.5)?;
+    let mut image = Image::new(size, size);
+    for_each_row(
+        size,
+        settings,
+        &|row, sampler| -> Vec<Color> {
+            (0..size).map(|column| bake_texel(scene, object, mode, integrator, settings, sampler, (column, row), size)).collect()
+        },
+        &mut |row, texels| image.pixels[row * size..(row + 1) * size].copy_from_slice(&texels),
+    );
+    Some(image)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bake_texel(
+    scene: &Scene,
+    object: &dyn Hittable,
+    mode: BakeMode,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    (column, row): (usize, usize),
+    size: usize,
+) -> Color {
+    let samples = settings.samples_per_pixel.max(1);
+    let mut total = Color::zero();
+    for _ in 0..samples {
+        let u = (column as f64 + sampler.get_1d()) / size as f64;
+        let v = 1.0 - (row as f64 + sampler.get_1d()) / size as f64;
+        let Some((p, normal)) = object.surface_at_uv(u, v) else {
+            continue;
+        };
+        total += match mode {
+            // Cosine-weighted directions make the estimate of irradiance / pi a
+            // plain average of the light arriving along them
+            BakeMode::Irradiance => {
+                let direction = Onb::from_normal(normal).local_to_world(cosine_hemisphere(sampler.get_2d()));
+                integrator.li(Ray::new(p, direction), scene, sampler, integrator.max_depth())
+            }
+            BakeMode::Full => integrator.li(Ray::new(p + normal * STANDOFF, -normal), scene, sampler, integrator.max_depth()),
+        };
+    }
+    total / samples
+}
@@ -0,0 +1,156 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::integrator;
+use crate::render::{render, RenderSettings};
+use crate::scenes::{self, LayerSelection};
+use crate::threads;
+
+// One line of a batch manifest:
+//
+//     <scene> <output.ppm> [spp=N] [width=N] [depth=N] [integrator=NAME] [camera=NAME]
+//                          [layers=LAYER,!LAYER,...]
+//
+// Blank lines and anything after a '#' are ignored.
+pub struct Shot {
+    pub scene: String,
+    pub output: String,
+    pub integrator: String,
+    pub camera: Option<String>,
+    pub layers: Option<LayerSelection>,
+    pub settings: RenderSettings,
+}
+
+pub fn parse_manifest(text: &str) -> Result<Vec<Shot>, String> {
+    let mut shots = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |msg: String| format!("line {}: {msg}", number + 1);
+
+        let mut fields = line.split_whitespace();
+        let scene = fields.next().unwrap().to_string();
+        let output = fields.next().ok_or_else(|| error("missing output path".to_string()))?.to_string();
+        let mut shot = Shot {
+            scene,
+            output,
+            integrator: "path".to_string(),
+            camera: None,
+            layers: None,
+            settings: RenderSettings::default_settings(),
+        };
+
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected key=value, found '{field}'")))?;
+            let number = || value.parse::<i64>().map_err(|_| error(format!("'{value}' isn't a number")));
+            match key {
+                "spp" => shot.settings.samples_per_pixel = number()?.max(1),
+                "width" => shot.settings.set_width(number()?.clamp(2, u16::MAX as i64) as u16),
+                "depth" => shot.settings.max_depth = number()?,
+                "integrator" => shot.integrator = value.to_string(),
+                "camera" => shot.camera = Some(value.to_string()),
+                "layers" => shot.layers = Some(LayerSelection::parse(value).map_err(|e| error(format!("layers={value}: {e}")))?),
+                _ => return Err(error(format!("unknown override '{key}'"))),
+            }
+        }
+        shots.push(shot);
+    }
+    Ok(shots)
+}
+
+struct ShotResult {
+    seconds: f64,
+    error: Option<String>,
+}
+
+fn run_shot(shot: &Shot) -> Result<(), String> {
+    let mut scene = scenes::load(&shot.scene)?;
+    if let Some(selection) = &shot.layers {
+        scene.select_layers(selection);
+    }
+    if let Some(camera) = &shot.camera {
+        scene.use_camera(camera)?;
+    }
+    let integrator = integrator::by_name(&shot.integrator, shot.settings.max_depth)
+        .ok_or_else(|| format!("unknown integrator '{}'", shot.integrator))?;
+    let image = render(&scene, integrator.as_ref(), &shot.settings, false);
+
+    let file = File::create(&shot.output).map_err(|e| format!("couldn't create {}: {e}", shot.output))?;
+    image
+        .write_ppm(&mut BufWriter::new(file))
+        .map_err(|e| format!("couldn't write {}: {e}", shot.output))
+}
+
+// Renders every shot in the manifest, `jobs` at a time, then prints a summary.
+// Returns false if any shot failed.
+pub fn run(manifest_path: &str, jobs: usize) -> bool {
+    let mut shots = match fs::read_to_string(manifest_path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse_manifest(&text))
+    {
+        Ok(shots) => shots,
+        Err(e) => {
+            eprintln!("Couldn't read manifest {manifest_path}: {e}");
+            return false;
+        }
+    };
+
+    // Shots rendering side by side share the cores between them
+    let jobs = jobs.clamp(1, shots.len().max(1));
+    for shot in &mut shots {
+        shot.settings.threads = (threads::available() / jobs).max(1);
+    }
+
+    let started = Instant::now();
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<ShotResult>>> = Mutex::new(shots.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(shot) = shots.get(index) else { break };
+                eprintln!("[{}/{}] rendering {} -> {}", index + 1, shots.len(), shot.scene, shot.output);
+
+                let shot_started = Instant::now();
+                let error = run_shot(shot).err();
+                let result = ShotResult { seconds: shot_started.elapsed().as_secs_f64(), error };
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let mut failures = 0;
+    eprintln!("\n{:<4} {:<16} {:<32} {:>9}  status", "#", "scene", "output", "seconds");
+    for (index, (shot, result)) in shots.iter().zip(&results).enumerate() {
+        let result = result.as_ref().unwrap();
+        let status = match &result.error {
+            Some(e) => {
+                failures += 1;
+                format!("FAILED: {e}")
+            }
+            None => "ok".to_string(),
+        };
+        eprintln!("{:<4} {:<16} {:<32} {:>9.2}  {status}", index + 1, shot.scene, shot.output, result.seconds);
+    }
+    eprintln!(
+        "{} of {} shots rendered in {:.2}s",
+        shots.len() - failures,
+        shots.len(),
+        started.elapsed().as_secs_f64()
+    );
+    failures == 0
+}
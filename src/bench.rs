@@ -0,0 +1,160 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crate::alloc_counter;
+use crate::integrator::PathTracer;
+use crate::render::{render_view, RenderSettings};
+use crate::scenes;
+use crate::threads;
+
+// A fixed set of scenes rendered with fixed, seeded settings, so timings can be
+// compared between commits and between machines. Each one leans on a different
+// part of the renderer: many mixed materials, diffuse interreflection, long glass
+// paths, and a BVH over a great many objects.
+pub const SCENES: [&str; 4] = ["cover", "cornell", "caustics", "sphere-field"];
+
+pub struct BenchResult {
+    pub scene: String,
+    pub frames: usize,
+    pub ms_per_frame: f64,
+    pub best_ms: f64,
+    pub rays_per_frame: u64,
+    pub mrays_per_second: f64,
+    // Heap allocations made while rendering, which should stay near zero per
+    // sample: the hot loops keep their hit records and scratch space on the stack
+    pub allocations_per_frame: u64,
+    pub allocations_per_sample: f64,
+    pub peak_heap_bytes: u64,
+    // None where the OS doesn't say
+    pub peak_rss_bytes: Option<u64>,
+}
+
+// Small enough for the whole suite to run in a minute or so
+pub fn default_settings() -> RenderSettings {
+    let mut settings = RenderSettings::with_width(200);
+    settings.samples_per_pixel = 16;
+    settings.seed = Some(0);
+    settings
+}
+
+// The process's peak resident set, which Linux reports in /proc/self/status
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+// Starts the peak resident set over from the current one, where Linux allows it,
+// so each scene's figure is its own
+fn reset_peak_rss() {
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
+
+// Loads `name` and renders it `frames` times with the path tracer. The scene is
+// built before the clock starts, but its memory counts towards the peaks.
+pub fn run_scene(name: &str, settings: &RenderSettings, frames: usize) -> Result<BenchResult, String> {
+    reset_peak_rss();
+    alloc_counter::reset_peak_bytes();
+    let scene = scenes::load(name)?;
+    let integrator = PathTracer::new(settings.max_depth);
+
+    let rays_before = scenes::rays_traced();
+    let allocations_before = alloc_counter::allocations();
+    let mut times = Vec::new();
+    for _ in 0..frames.max(1) {
+        let start = Instant::now();
+        render_view(&scene, &scene.camera, &integrator, settings, false);
+        times.push(start.elapsed().as_secs_f64());
+    }
+    let rays = scenes::rays_traced() - rays_before;
+    let allocations = alloc_counter::allocations() - allocations_before;
+    let samples = settings.image_width as u64 * settings.image_height as u64 * settings.samples_per_pixel as u64;
+    let total: f64 = times.iter().sum();
+    Ok(BenchResult {
+        scene: name.to_string(),
+        frames: times.len(),
+        ms_per_frame: total / times.len() as f64 * 1000.0,
+        best_ms: times.iter().copied().fold(f64::INFINITY, f64::min) * 1000.0,
+        rays_per_frame: rays / times.len() as u64,
+        mrays_per_second: rays as f64 / total / 1e6,
+        allocations_per_frame: allocations / times.len() as u64,
+        allocations_per_sample: allocations as f64 / (samples * times.len() as u64) as f64,
+        peak_heap_bytes: alloc_counter::peak_bytes(),
+        peak_rss_bytes: peak_rss_bytes(),
+    })
+}
+
+// Runs each scene in turn, printing a line per scene to stderr as it finishes
+pub fn run(names: &[String], settings: &RenderSettings, frames: usize) -> Result<Vec<BenchResult>, String> {
+    eprintln!(
+        "Benchmarking at {}x{}, {} spp, max depth {}, {} thread(s), {frames} frame(s) each:",
+        settings.image_width, settings.image_height, settings.samples_per_pixel, settings.max_depth, settings.threads
+    );
+    let mut results = Vec::new();
+    for name in names {
+        let result = run_scene(name, settings, frames)?;
+        let rss = result.peak_rss_bytes.map_or("unknown".to_string(), |bytes| format!("{:.1} MiB", mebibytes(bytes)));
+        eprintln!(
+            "  {:<14}{:>10.1} ms/frame (best {:.1}), {:>7.3} Mrays/s, {:.4} allocations/sample, peak heap {:.1} MiB, peak RSS {rss}",
+            result.scene,
+            result.ms_per_frame,
+            result.best_ms,
+            result.mrays_per_second,
+            result.allocations_per_sample,
+            mebibytes(result.peak_heap_bytes)
+        );
+        results.push(result);
+    }
+    Ok(results)
+}
+
+fn mebibytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+// The report, with enough about the machine and settings to tell whether two of
+// them can be compared. Rays per frame only changes when the renderer does.
+pub fn write_json(results: &[BenchResult], settings: &RenderSettings, out: &mut dyn Write) -> io::Result<()> {
+    let cores = threads::available();
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"version\": \"{}\",", env!("CARGO_PKG_VERSION"))?;
+    writeln!(
+        out,
+        "  \"machine\": {{ \"os\": \"{}\", \"arch\": \"{}\", \"logical_cores\": {cores} }},",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )?;
+    writeln!(
+        out,
+        "  \"settings\": {{ \"width\": {}, \"height\": {}, \"samples_per_pixel\": {}, \"max_depth\": {}, \"seed\": {}, \"threads\": {} }},",
+        settings.image_width,
+        settings.image_height,
+        settings.samples_per_pixel,
+        settings.max_depth,
+        settings.seed.map_or("null".to_string(), |seed| seed.to_string()),
+        settings.threads
+    )?;
+    writeln!(out, "  \"scenes\": [")?;
+    for (index, result) in results.iter().enumerate() {
+        let separator = if index + 1 < results.len() { "," } else { "" };
+        writeln!(
+            out,
+            "    {{ \"scene\": \"{}\", \"frames\": {}, \"ms_per_frame\": {:.3}, \"best_ms\": {:.3}, \"rays_per_frame\": {}, \
+             \"mrays_per_second\": {:.4}, \"allocations_per_frame\": {}, \"allocations_per_sample\": {:.6}, \
+             \"peak_heap_bytes\": {}, \"peak_rss_bytes\": {} }}{separator}",
+            result.scene,
+            result.frames,
+            result.ms_per_frame,
+            result.best_ms,
+            result.rays_per_frame,
+            result.mrays_per_second,
+            result.allocations_per_frame,
+            result.allocations_per_sample,
+            result.peak_heap_bytes,
+            result.peak_rss_bytes.map_or("null".to_string(), |bytes| bytes.to_string())
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
@@ -0,0 +1,198 @@
+use crate::aov::FloatImage;
+use crate::bvh::Aabb;
+use crate::render::{for_each_row, Image, RenderSettings};
+use crate::scenes::Scene;
+use crate::types::*;
+
+// Neighbouring pixels whose surfaces are further apart than this many pixels'
+// width along the view, or whose normals are further apart than this angle, are
+// on either side of a line in the drawing
+const DEPTH_STEP_PIXELS: f64 = 4.0;
+const CREASE_COS: f64 = 0.866;
+
+// The grid behind the drawing is at least this many pixels to a square
+const MIN_GRID_PIXELS: f64 = 16.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BlueprintView {
+    // Looking down -y, x to the right and -z up the image, like a plan
+    Top,
+    // Looking down -z, x to the right and y up
+    Front,
+    // Looking down -x from the +x side, -z to the right and y up
+    Side,
+}
+
+impl BlueprintView {
+    pub fn by_name(name: &str) -> Option<BlueprintView> {
+        match name {
+            "top" => Some(BlueprintView::Top),
+            "front" => Some(BlueprintView::Front),
+            "side" => Some(BlueprintView::Side),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlueprintView::Top => "top",
+            BlueprintView::Front => "front",
+            BlueprintView::Side => "side",
+        }
+    }
+
+    // The directions across the image, up it and into it
+    pub fn axes(&self) -> (Vec3, Vec3, Vec3) {
+        let (x, y, z) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        match self {
+            BlueprintView::Top => (x, -z, -y),
+            BlueprintView::Front => (x, y, -z),
+            BlueprintView::Side => (-z, y, -x),
+        }
+    }
+}
+
+// An orthographic drawing of a region of the scene at an exact scale, for
+// diagrams and for checking that things are the size and in the place they
+// should be
+pub struct Blueprint {
+    pub view: BlueprintView,
+    pub pixels_per_unit: f64,
+    // Where the image's left edge is across the view and its top edge up it, in
+    // scene units along the view's axes
+    pub left: f64,
+    pub top: f64,
+    // The scene units between grid lines
+    pub grid: f64,
+    // Silhouettes and creases in black over the surfaces in gray, darker the
+    // nearer they are, on white with the grid in blue
+    pub drawing: Image,
+    // One channel: the coordinate of the nearest surface along the axis the view
+    // looks down (its height, from the top), or NaN where there's nothing
+    pub elevation: FloatImage,
+}
+
+impl Blueprint {
+    // The scale and placement, for the header of the drawing
+    pub fn comments(&self) -> Vec<String> {
+        vec![
+            format!("view: {}", self.view.name()),
+            format!("pixels_per_unit: {}", self.pixels_per_unit),
+            format!("left: {}", self.left),
+            format!("top: {}", self.top),
+            format!("grid: {}", self.grid),
+        ]
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    elevation: f64,
+    normal: Vec3,
+    // Tells apart surfaces of different materials meeting without a crease
+    material: usize,
+}
+
+// Draws `region` as seen from `view` at `pixels_per_unit`, one ray through the
+// center of each pixel, with rows shared between `settings.threads` threads.
+// Whatever is outside the region is left out, walls and ceilings included, so
+// the inside of a room can be drawn.
+pub fn render(scene: &Scene, view: BlueprintView, region: Aabb, pixels_per_unit: f64, settings: &RenderSettings) -> Blueprint {
+    let (right, up, forward) = view.axes();
+    let corners: Vec<Point3> = (0..8)
+        .map(|i| {
+            let pick = |axis: usize| if i >> axis & 1 == 1 { region.max } else { region.min };
+            Point3::new(pick(0).x, pick(1).y, pick(2).z)
+        })
+        .collect();
+    let range = |axis: Vec3| {
+        corners.iter().fold(Interval::EMPTY, |r, c| Interval::new(r.min.min(c.dot(axis)), r.max.max(c.dot(axis))))
+    };
+    let (across, along, depth) = (range(right), range(up), range(forward));
+    let width = ((across.size() * pixels_per_unit).ceil() as usize).max(1);
+    let height = ((along.size() * pixels_per_unit).ceil() as usize).max(1);
+
+    let mut samples = vec![None; width * height];
+    for_each_row(
+        height,
+        settings,
+        &|row, _| -> Vec<Option<Sample>> {
+            (0..width)
+                .map(|column| {
+                    let h = across.min + (column as f64 + 0.5) / pixels_per_unit;
+                    let v = along.max - (row as f64 + 0.5) / pixels_per_unit;
+                    // From the region's near face to its far one, so a region through
+                    // the middle of the scene cuts a section
+                    let origin = right * h + up * v + forward * depth.min;
+                    let mut rec = HitRecord::blank();
+                    if !scene.hit(Ray::new(origin, forward), Interval::new(0.0, depth.size()), &mut rec) {
+                        return None;
+                    }
+                    let material = rec.mat_ptr.map_or(0, |m| m as *const dyn Material as *const () as usize);
+                    Some(Sample { elevation: -rec.p.dot(forward), normal: rec.normal, material })
+                })
+                .collect()
+        },
+        &mut |row, line| samples[row * width..(row + 1) * width].copy_from_slice(&line),
+    );
+
+    let grid = grid_spacing(pixels_per_unit);
+    let mut drawing = Image::new(width, height);
+    let mut elevation = FloatImage::new(width, height, 1);
+    let depth_step = DEPTH_STEP_PIXELS / pixels_per_unit;
+    for row in 0..height {
+        for column in 0..width {
+            let i = row * width + column;
+            let Some(here) = samples[i] else {
+                elevation.data[i] = f32::NAN;
+                let h = across.min + (column as f64 + 0.5) / pixels_per_unit;
+                let v = along.max - (row as f64 + 0.5) / pixels_per_unit;
+                drawing.pixels[i] = if on_grid(h, grid, pixels_per_unit) || on_grid(v, grid, pixels_per_unit) {
+                    Color::new(0.6, 0.75, 0.95)
+                } else {
+                    Color::one()
+                };
+                continue;
+            };
+            elevation.data[i] = here.elevation as f32;
+            let neighbours = [
+                (column > 0).then(|| samples[i - 1]),
+                (column + 1 < width).then(|| samples[i + 1]),
+                (row > 0).then(|| samples[i - width]),
+                (row + 1 < height).then(|| samples[i + width]),
+            ];
+            // The line goes on the nearer side of a step, so it stays a pixel wide
+            let edge = neighbours.iter().flatten().any(|neighbour| match neighbour {
+                None => true,
+                Some(there) => {
+                    here.elevation >= there.elevation
+                        && (here.elevation - there.elevation > depth_step
+                            || here.material != there.material
+                            || here.normal.dot(there.normal) < CREASE_COS)
+                }
+            });
+            drawing.pixels[i] = if edge {
+                Color::zero()
+            } else {
+                let nearness = if depth.size() > 0.0 { (here.elevation + depth.max) / depth.size() } else { 0.5 };
+                Color::one() * (0.85 - 0.35 * nearness.clamp(0.0, 1.0))
+            };
+        }
+    }
+
+    Blueprint { view, pixels_per_unit, left: across.min, top: along.max, grid, drawing, elevation }
+}
+
+// The smallest of 1, 2 and 5 times a power of ten that leaves the grid's squares
+// at least MIN_GRID_PIXELS wide
+fn grid_spacing(pixels_per_unit: f64) -> f64 {
+    let least = MIN_GRID_PIXELS / pixels_per_unit;
+    let power = 10f64.powf(least.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * power).find(|&s| s >= least).unwrap_or(10.0 * power)
+}
+
+// Whether a pixel centered at `x` is the one a grid line runs through
+fn on_grid(x: f64, grid: f64, pixels_per_unit: f64) -> bool {
+    let offset = x - (x / grid).round() * grid;
+    offset.abs() * pixels_per_unit <= 0.5
+}
@@ -0,0 +1,431 @@
+use std::sync::Arc;
+
+use crate::sampler::Sampler;
+use crate::types::*;
+
+// Relative costs of stepping through a BVH node and of testing a primitive,
+// used by the surface area heuristic
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.0;
+// Leaves are never allowed to grow past this, however cheap the SAH thinks they are
+const MAX_LEAF_SIZE: usize = 4;
+// Past this depth splits fall back to the median, which keeps the tree shallow
+// enough for the fixed traversal stack
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+// The most rays `traverse_batch` takes at once, one to a bit of a mask
+pub const BATCH_SIZE: usize = 64;
+
+// An axis-aligned bounding box. An empty box has min > max on every axis.
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Aabb::new(Point3::one() * f64::INFINITY, Point3::one() * -f64::INFINITY)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.max(other.min), self.max.min(other.max))
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // The box's extent along one axis
+    pub fn slab(&self, axis: usize) -> Interval {
+        Interval::new(self.min[axis], self.max[axis])
+    }
+
+    // Slab test: does a ray pass through the box somewhere in `ray_t`? Takes the
+    // reciprocal of the ray direction so a traversal divides once per ray, not per box.
+    pub fn hit(&self, origin: Point3, inv_direction: Vec3, ray_t: Interval) -> bool {
+        self.clip(origin, inv_direction, ray_t).is_some()
+    }
+
+    // The part of `ray_t` the ray spends inside the box, if any
+    pub fn clip(&self, origin: Point3, inv_direction: Vec3, mut ray_t: Interval) -> Option<Interval> {
+        for axis in 0..3 {
+            let slab = self.slab(axis);
+            let inv_d = inv_direction[axis];
+            let mut t0 = (slab.min - origin[axis]) * inv_d;
+            let mut t1 = (slab.max - origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            ray_t.min = if t0 > ray_t.min { t0 } else { ray_t.min };
+            ray_t.max = if t1 < ray_t.max { t1 } else { ray_t.max };
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+}
+
+// A sphere enclosing a whole scene, for rejecting rays that can't come near it
+// before they reach the BVH
+#[derive(Clone, Copy)]
+pub struct BoundingSphere {
+    pub center: Point3,
+    pub radius: f64,
+}
+
+impl BoundingSphere {
+    // The sphere through the box's corners
+    pub fn around(bbox: &Aabb) -> Self {
+        BoundingSphere { center: bbox.centroid(), radius: (bbox.max - bbox.min).length() * 0.5 }
+    }
+
+    // Could any ray from `origin` within `half_angle` (in radians) of the unit
+    // vector `axis` hit the sphere? True whenever `origin` is inside it.
+    pub fn within_cone(&self, origin: Point3, axis: Vec3, half_angle: f64) -> bool {
+        let to_center = self.center - origin;
+        let distance = to_center.length();
+        if distance <= self.radius {
+            return true;
+        }
+        let angle = (axis.dot(to_center) / distance).clamp(-1.0, 1.0).acos();
+        angle <= half_angle + (self.radius / distance).asin()
+    }
+}
+
+// Leaves hold `count` objects starting at `start`; interior nodes have a count
+// of zero, their left child directly after them and their right child at `start`
+struct BvhNode {
+    bbox: Aabb,
+    start: usize,
+    count: usize,
+}
+
+// A bounding volume hierarchy over a scene's objects, split with the surface
+// area heuristic and stored flat in depth-first order. Objects without bounds
+// are kept aside and tested against every ray. Other things with bounds, such as
+// the triangles of a mesh by index, can go in one too.
+pub struct Bvh<T = Arc<dyn Hittable>> {
+    nodes: Vec<BvhNode>,
+    objects: Vec<T>,
+    unbounded: Vec<T>,
+}
+
+// Quality metrics for a built hierarchy. Costs are relative to testing one
+// primitive; overlap is the mean share of an interior node's surface area that
+// both of its children cover.
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+    pub bounded: usize,
+    pub unbounded: usize,
+    pub sah_cost: f64,
+    pub overlap: f64,
+}
+
+struct BuildItem<T> {
+    bbox: Aabb,
+    centroid: Point3,
+    object: T,
+}
+
+impl Bvh {
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
+        Bvh::with_bounds(objects.iter().map(|object| (object.clone(), object.bounding_box())))
+    }
+
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, mut sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        let mut temp_rec = HitRecord::blank();
+        self.traverse(r, ray_t, |object, range| {
+            let hit = hit_object(object.as_ref(), r, range, &mut temp_rec, sampler.as_deref_mut());
+            if hit {
+                *rec = temp_rec;
+            }
+            hit.then_some(temp_rec.t)
+        })
+    }
+}
+
+impl<T> Bvh<T> {
+    // Over anything at all, given its bounds (None for the unbounded)
+    pub fn with_bounds(objects: impl IntoIterator<Item = (T, Option<Aabb>)>) -> Self {
+        let mut items = Vec::new();
+        let mut unbounded = Vec::new();
+        for (object, bbox) in objects {
+            match bbox {
+                Some(bbox) => items.push(BuildItem { bbox, centroid: bbox.centroid(), object }),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            Bvh::build(&mut nodes, &mut items, 0, 0);
+        }
+        Bvh {
+            nodes,
+            objects: items.into_iter().map(|item| item.object).collect(),
+            unbounded,
+        }
+    }
+
+    // Builds the subtree over `items`, whose first object will sit at `first`, and
+    // leaves the items in leaf order
+    fn build(nodes: &mut Vec<BvhNode>, items: &mut [BuildItem<T>], first: usize, depth: usize) {
+        let bbox = items.iter().fold(Aabb::empty(), |b, item| b.union(&item.bbox));
+        let index = nodes.len();
+        nodes.push(BvhNode { bbox, start: first, count: items.len() });
+        if items.len() == 1 {
+            return;
+        }
+
+        let centroids = items.iter().fold(Aabb::empty(), |b, item| b.union(&Aabb::new(item.centroid, item.centroid)));
+        let axis = centroids.longest_axis();
+        items.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+
+        // Sweep from each end to get the area of every possible left and right part
+        let n = items.len();
+        let mut right_areas = vec![0.0; n];
+        let mut right = Aabb::empty();
+        for i in (1..n).rev() {
+            right = right.union(&items[i].bbox);
+            right_areas[i] = right.surface_area();
+        }
+        let mut left = Aabb::empty();
+        let mut best = (f64::INFINITY, n / 2);
+        for i in 1..n {
+            left = left.union(&items[i - 1].bbox);
+            let cost = left.surface_area() * i as f64 + right_areas[i] * (n - i) as f64;
+            if cost < best.0 {
+                best = (cost, i);
+            }
+        }
+
+        let area = bbox.surface_area();
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + INTERSECTION_COST * best.0 / area
+        } else {
+            TRAVERSAL_COST + INTERSECTION_COST * n as f64
+        };
+        if n <= MAX_LEAF_SIZE && INTERSECTION_COST * n as f64 <= split_cost {
+            return;
+        }
+
+        let split = if depth < MAX_SAH_DEPTH { best.1 } else { n / 2 };
+        let (left_items, right_items) = items.split_at_mut(split);
+        Bvh::build(nodes, left_items, first, depth + 1);
+        let right_index = nodes.len();
+        Bvh::build(nodes, right_items, first + split, depth + 1);
+        nodes[index].start = right_index;
+        nodes[index].count = 0;
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            nodes: self.nodes.len(),
+            leaves: 0,
+            max_depth: 0,
+            max_leaf_size: 0,
+            bounded: self.objects.len(),
+            unbounded: self.unbounded.len(),
+            sah_cost: INTERSECTION_COST * self.unbounded.len() as f64,
+            overlap: 0.0,
+        };
+        let Some(root) = self.nodes.first() else {
+            return stats;
+        };
+
+        let root_area = root.bbox.surface_area();
+        let mut interior = 0;
+        let mut overlap_total = 0.0;
+        let mut sah_cost = 0.0;
+        let mut stack = vec![(0, 0)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let relative_area = if root_area > 0.0 { node.bbox.surface_area() / root_area } else { 1.0 };
+            stats.max_depth = stats.max_depth.max(depth);
+            if node.count > 0 {
+                stats.leaves += 1;
+                stats.max_leaf_size = stats.max_leaf_size.max(node.count);
+                sah_cost += INTERSECTION_COST * relative_area * node.count as f64;
+            } else {
+                let (left, right) = (index + 1, node.start);
+                interior += 1;
+                sah_cost += TRAVERSAL_COST * relative_area;
+                let area = node.bbox.surface_area();
+                if area > 0.0 {
+                    overlap_total += self.nodes[left].bbox.intersection(&self.nodes[right].bbox).surface_area() / area;
+                }
+                stack.push((left, depth + 1));
+                stack.push((right, depth + 1));
+            }
+        }
+        stats.sah_cost += sah_cost;
+        stats.overlap = if interior > 0 { overlap_total / interior as f64 } else { 0.0 };
+        stats
+    }
+
+    // Offers `hit` every object the ray might reach, with the range still worth
+    // looking in; it returns the distance to the hit when it finds one closer, and
+    // the range narrows to match. True if anything was hit.
+    pub fn traverse<'a>(&'a self, r: Ray, ray_t: Interval, mut hit: impl FnMut(&'a T, Interval) -> Option<f64>) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        for object in &self.unbounded {
+            if let Some(t) = hit(object, ray_t.up_to(closest_so_far)) {
+                hit_anything = true;
+                closest_so_far = t;
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return hit_anything;
+        }
+        let inv_direction = Vec3::new(1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z);
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let index = stack[stack_len];
+            let node = &self.nodes[index];
+            if !node.bbox.hit(r.origin, inv_direction, ray_t.up_to(closest_so_far)) {
+                continue;
+            }
+            if node.count > 0 {
+                for object in &self.objects[node.start..node.start + node.count] {
+                    if let Some(t) = hit(object, ray_t.up_to(closest_so_far)) {
+                        hit_anything = true;
+                        closest_so_far = t;
+                    }
+                }
+            } else {
+                stack[stack_len] = node.start;
+                stack[stack_len + 1] = index + 1;
+                stack_len += 2;
+            }
+        }
+        hit_anything
+    }
+
+    // As `traverse`, for up to BATCH_SIZE rays stepping through the tree together:
+    // each node's box is tested against every ray still in the running from its
+    // parent, and the node is visited once for all those that reach it. That pays
+    // off when the rays are coherent, as they then mostly share a path. `hit` is
+    // also given the index of the ray in `rays`, and the ranges of rays that have
+    // hit something narrow independently.
+    pub fn traverse_batch<'a>(&'a self, rays: &[Ray], ray_t: Interval, mut hit: impl FnMut(usize, &'a T, Interval) -> Option<f64>) {
+        assert!(rays.len() <= BATCH_SIZE, "a batch holds at most {} rays", BATCH_SIZE);
+        let mut closest = [ray_t.max; BATCH_SIZE];
+        for object in &self.unbounded {
+            for (i, closest) in closest.iter_mut().enumerate().take(rays.len()) {
+                if let Some(t) = hit(i, object, ray_t.up_to(*closest)) {
+                    *closest = t;
+                }
+            }
+        }
+
+        if self.nodes.is_empty() || rays.is_empty() {
+            return;
+        }
+        let mut inv_directions = [Vec3::zero(); BATCH_SIZE];
+        for (inv, r) in inv_directions.iter_mut().zip(rays) {
+            *inv = Vec3::new(1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z);
+        }
+        // Each node on the stack goes with a mask of the rays that reached its parent
+        let mut stack = [(0, 0u64); STACK_SIZE];
+        stack[0] = (0, u64::MAX >> (BATCH_SIZE - rays.len()));
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let (index, mut candidates) = stack[stack_len];
+            let node = &self.nodes[index];
+            let mut active = 0u64;
+            while candidates != 0 {
+                let i = candidates.trailing_zeros() as usize;
+                candidates &= candidates - 1;
+                if node.bbox.hit(rays[i].origin, inv_directions[i], ray_t.up_to(closest[i])) {
+                    active |= 1 << i;
+                }
+            }
+            if active == 0 {
+                continue;
+            }
+            if node.count > 0 {
+                for object in &self.objects[node.start..node.start + node.count] {
+                    let mut rest = active;
+                    while rest != 0 {
+                        let i = rest.trailing_zeros() as usize;
+                        rest &= rest - 1;
+                        if let Some(t) = hit(i, object, ray_t.up_to(closest[i])) {
+                            closest[i] = t;
+                        }
+                    }
+                }
+            } else {
+                stack[stack_len] = (node.start, active);
+                stack[stack_len + 1] = (index + 1, active);
+                stack_len += 2;
+            }
+        }
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        Some(self.nodes.first().map_or(Aabb::empty(), |root| root.bbox))
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
+
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds()
+    }
+
+    fn kind(&self) -> &'static str {
+        "bvh"
+    }
+}
@@ -0,0 +1,348 @@
+use std::sync::Arc;
+
+use crate::sampler::{RandomSampler, Sampler};
+use crate::sampling;
+use crate::types::*;
+
+// Chi-squared tests of sampled directions against the density they claim to
+// follow: the sphere of directions is cut into bins of equal solid angle, the
+// samples landing in each are counted, and the counts are compared with what the
+// density integrates to over the bin. Light transport that weights samples by
+// their density is only unbiased if the two agree.
+
+// Bins are even in z = cos(theta) and in phi, so each covers the same solid angle
+const Z_BINS: usize = 10;
+const PHI_BINS: usize = 20;
+// Each bin's expected count comes from the density at this many points per side
+const SUBDIVISIONS: usize = 8;
+// Bins expecting fewer samples than this are pooled, as the test assumes
+const MIN_EXPECTED: f64 = 5.0;
+// Chance of a correct distribution failing, shared out over all the tests run
+const SIGNIFICANCE: f64 = 0.01;
+
+// Draws one direction, or nothing when the light was absorbed
+pub type DirectionSampler = Box<dyn Fn(&mut dyn Sampler) -> Option<Vec3>>;
+
+// Directions that should follow `pdf`. Absorbed samples land in no bin, so they
+// still count against it.
+pub struct DirectionTest {
+    pub name: String,
+    pub sample: DirectionSampler,
+    pub pdf: Box<dyn Fn(Vec3) -> f64>,
+}
+
+pub struct TestResult {
+    pub chi_squared: f64,
+    pub degrees_of_freedom: usize,
+    pub p_value: f64,
+    // The density summed over the whole sphere, which should be 1
+    pub integral: f64,
+}
+
+impl TestResult {
+    // Whether the counts fit at the `threshold` p-value and the density integrates to 1
+    pub fn passed(&self, threshold: f64) -> bool {
+        self.p_value >= threshold && (self.integral - 1.0).abs() < 0.01
+    }
+}
+
+// The p-value below which one of `tests` tests fails. Šidák's correction keeps the
+// chance of any correct distribution failing at SIGNIFICANCE.
+pub fn threshold(tests: usize) -> f64 {
+    1.0 - (1.0 - SIGNIFICANCE).powf(1.0 / tests as f64)
+}
+
+fn bin_direction(z: f64, phi: f64) -> Vec3 {
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn bin_index(direction: Vec3) -> usize {
+    let d = direction.unit_vector();
+    let z = ((d.z + 1.0) / 2.0 * Z_BINS as f64) as usize;
+    let phi = d.y.atan2(d.x).rem_euclid(2.0 * PI);
+    let phi = (phi / (2.0 * PI) * PHI_BINS as f64) as usize;
+    z.min(Z_BINS - 1) * PHI_BINS + phi.min(PHI_BINS - 1)
+}
+
+// What each bin should hold out of `samples`, integrating the density with the
+// midpoint rule; a bin's solid angle is its height in z times its width in phi
+fn expected_counts(pdf: &dyn Fn(Vec3) -> f64, samples: usize) -> Vec<f64> {
+    let (dz, dphi) = (2.0 / Z_BINS as f64, 2.0 * PI / PHI_BINS as f64);
+    let step = |d: f64| d / SUBDIVISIONS as f64;
+    let mut expected = vec![0.0; Z_BINS * PHI_BINS];
+    for (index, count) in expected.iter_mut().enumerate() {
+        let (z0, phi0) = (-1.0 + (index / PHI_BINS) as f64 * dz, (index % PHI_BINS) as f64 * dphi);
+        let mut integral = 0.0;
+        for a in 0..SUBDIVISIONS {
+            for b in 0..SUBDIVISIONS {
+                let z = z0 + (a as f64 + 0.5) * step(dz);
+                let phi = phi0 + (b as f64 + 0.5) * step(dphi);
+                integral += pdf(bin_direction(z, phi)) * step(dz) * step(dphi);
+            }
+        }
+        *count = integral * samples as f64;
+    }
+    expected
+}
+
+pub fn run_test(test: &DirectionTest, samples: usize, seed: u64) -> TestResult {
+    let mut sampler = RandomSampler::seeded(seed);
+    let mut observed = vec![0.0; Z_BINS * PHI_BINS];
+    for _ in 0..samples {
+        if let Some(direction) = (test.sample)(&mut sampler) {
+            observed[bin_index(direction)] += 1.0;
+        }
+    }
+    let expected = expected_counts(test.pdf.as_ref(), samples);
+    let integral = expected.iter().sum::<f64>() / samples as f64;
+    let (chi_squared, degrees_of_freedom) = statistic(&observed, &expected);
+    TestResult {
+        chi_squared,
+        degrees_of_freedom,
+        p_value: if chi_squared.is_finite() { gamma_q(degrees_of_freedom as f64 / 2.0, chi_squared / 2.0) } else { 0.0 },
+        integral,
+    }
+}
+
+// Pearson's statistic and its degrees of freedom, after pooling the sparsest bins
+// together until they expect enough samples. A sample in a bin the density says is
+// impossible makes it infinite.
+fn statistic(observed: &[f64], expected: &[f64]) -> (f64, usize) {
+    let mut order: Vec<usize> = (0..expected.len()).collect();
+    order.sort_by(|&a, &b| expected[a].total_cmp(&expected[b]));
+
+    let (mut chi_squared, mut bins) = (0.0, 0);
+    let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+    for &index in &order {
+        let (o, e) = (observed[index], expected[index]);
+        if e <= 0.0 {
+            if o > 0.0 {
+                return (f64::INFINITY, 1);
+            }
+            continue;
+        }
+        // Bins come smallest first, so once one expects enough on its own the rest do too
+        pooled_observed += o;
+        pooled_expected += e;
+        if pooled_expected >= MIN_EXPECTED {
+            chi_squared += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+            bins += 1;
+            (pooled_observed, pooled_expected) = (0.0, 0.0);
+        }
+    }
+    // Whatever is left over too small to stand alone still has to be accounted for
+    if pooled_expected > 0.0 {
+        chi_squared += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        bins += 1;
+    }
+    (chi_squared, bins.max(2) - 1)
+}
+
+// The regularized upper incomplete gamma function Q(a, x), which gives the chance
+// of a chi-squared statistic at least 2x with 2a degrees of freedom (Numerical
+// Recipes: a series below a + 1, a continued fraction above)
+fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        return 1.0 - sum * log_prefix.exp();
+    }
+    // Modified Lentz's method
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        d = if d.abs() < tiny { tiny } else { d };
+        c = b + an / c;
+        c = if c.abs() < tiny { tiny } else { c };
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    h * log_prefix.exp()
+}
+
+// Lanczos' approximation, good to about 15 digits for positive arguments
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] =
+        [76.18009172947146, -86.50532032941677, 24.01409824083091, -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5];
+    let tmp = x + 5.5;
+    let mut series = 1.000000000190015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    (2.5066282746310005 * series / x).ln() - (tmp - (x + 0.5) * tmp.ln())
+}
+
+// A surface at the origin facing +z, lit from `incidence` degrees off the normal
+fn material_test(name: &str, material: Arc<dyn Material>, incidence: f64) -> DirectionTest {
+    let theta = incidence.to_radians();
+    let r_in = Ray::new(Point3::new(-theta.sin(), 0.0, theta.cos()), Vec3::new(theta.sin(), 0.0, -theta.cos()));
+    let rec = move || {
+        let mut rec = HitRecord::blank();
+        rec.t = 1.0;
+        rec.front_face = true;
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec
+    };
+    let sampled = material.clone();
+    DirectionTest {
+        name: format!("{name} at {incidence} degrees"),
+        sample: Box::new(move |sampler| {
+            let (mut attenuation, mut scattered) = (Color::zero(), Ray::new(Point3::zero(), Vec3::zero()));
+            sampled.scatter(r_in, &rec(), &mut attenuation, &mut scattered, sampler).then_some(scattered.direction)
+        }),
+        pdf: Box::new(move |direction| {
+            material.scattering_pdf(r_in, &rec(), Ray::new(Point3::zero(), direction)).unwrap_or(0.0)
+        }),
+    }
+}
+
+// The warps in `sampling`, then every material with a density at a few angles
+pub fn tests() -> Vec<DirectionTest> {
+    let warp = |name: &str, sample: fn((f64, f64)) -> Vec3, pdf: Box<dyn Fn(Vec3) -> f64>| DirectionTest {
+        name: name.to_string(),
+        sample: Box::new(move |sampler| Some(sample(sampler.get_2d()))),
+        pdf,
+    };
+    const COS_CONE: f64 = 0.8;
+    let mut tests = vec![
+        warp("cosine hemisphere", sampling::cosine_hemisphere, Box::new(|d| sampling::cosine_hemisphere_pdf(d.z))),
+        warp(
+            "uniform hemisphere",
+            sampling::uniform_hemisphere,
+            Box::new(|d| if d.z >= 0.0 { sampling::uniform_hemisphere_pdf() } else { 0.0 }),
+        ),
+        warp("uniform sphere", sampling::uniform_sphere, Box::new(|_| sampling::uniform_sphere_pdf())),
+        DirectionTest {
+            name: format!("uniform cone (cos {COS_CONE})"),
+            sample: Box::new(|sampler| Some(sampling::uniform_cone(sampler.get_2d(), COS_CONE))),
+            pdf: Box::new(|d| if d.z >= COS_CONE { sampling::uniform_cone_pdf(COS_CONE) } else { 0.0 }),
+        },
+    ];
+
+    let lambertian = || Arc::new(LambertianMaterial::new(Color::one() * 0.5));
+    let materials: Vec<(&str, Arc<dyn Material>)> =
+        vec![("lambertian", lambertian()), ("mix", Arc::new(MixMaterial::new(lambertian(), lambertian(), 0.3)))];
+    for (name, material) in materials {
+        for incidence in [0.0, 45.0, 80.0] {
+            tests.push(material_test(name, material.clone(), incidence));
+        }
+    }
+    tests
+}
+
+// Runs every test and prints how each did; true if they all passed
+pub fn run(samples: usize) -> bool {
+    let tests = tests();
+    let threshold = threshold(tests.len());
+    println!("Chi-squared tests, {samples} samples each, failing below p = {threshold:.5}:");
+    let mut all_passed = true;
+    for (seed, test) in tests.iter().enumerate() {
+        let result = run_test(test, samples, seed as u64);
+        let passed = result.passed(threshold);
+        println!(
+            "  {:<28}chi2 {:>9.2} ({} dof), p {:.4}, density integrates to {:.4}  {}",
+            test.name,
+            result.chi_squared,
+            result.degrees_of_freedom,
+            result.p_value,
+            result.integral,
+            if passed { "ok" } else { "FAILED" }
+        );
+        all_passed &= passed;
+    }
+    all_passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 200_000;
+
+    // Runs the test named `name` with the seed `run` gives it: its place in the list
+    fn check(name: &str) {
+        let tests = tests();
+        let (seed, test) = tests.iter().enumerate().find(|(_, test)| test.name == name).unwrap();
+        let result = run_test(test, SAMPLES, seed as u64);
+        let threshold = threshold(tests.len());
+        assert!(
+            result.passed(threshold),
+            "{}: chi2 {:.2} ({} dof), p {:.5} below {:.5}, or the density integrates to {:.4}",
+            name,
+            result.chi_squared,
+            result.degrees_of_freedom,
+            result.p_value,
+            threshold,
+            result.integral
+        );
+    }
+
+    #[test]
+    fn cosine_hemisphere() {
+        check("cosine hemisphere");
+    }
+
+    #[test]
+    fn uniform_hemisphere() {
+        check("uniform hemisphere");
+    }
+
+    #[test]
+    fn uniform_sphere() {
+        check("uniform sphere");
+    }
+
+    #[test]
+    fn uniform_cone() {
+        check("uniform cone (cos 0.8)");
+    }
+
+    #[test]
+    fn lambertian_at_0_degrees() {
+        check("lambertian at 0 degrees");
+    }
+
+    #[test]
+    fn lambertian_at_45_degrees() {
+        check("lambertian at 45 degrees");
+    }
+
+    #[test]
+    fn lambertian_at_80_degrees() {
+        check("lambertian at 80 degrees");
+    }
+
+    #[test]
+    fn mix_at_0_degrees() {
+        check("mix at 0 degrees");
+    }
+
+    #[test]
+    fn mix_at_45_degrees() {
+        check("mix at 45 degrees");
+    }
+
+    #[test]
+    fn mix_at_80_degrees() {
+        check("mix at 80 degrees");
+    }
+}
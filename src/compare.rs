@@ -0,0 +1,308 @@
+use std::f64::consts::PI;
+
+use crate::render::Image;
+use crate::types::Color;
+
+// A single-channel image, row-major
+struct Plane {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+impl Plane {
+    fn from_fn(image: &Image, f: impl Fn(Color) -> f64) -> Self {
+        Plane {
+            width: image.width,
+            height: image.height,
+            values: image.pixels.iter().map(|&c| f(c)).collect(),
+        }
+    }
+
+    fn map2(&self, other: &Plane, f: impl Fn(f64, f64) -> f64) -> Plane {
+        Plane {
+            width: self.width,
+            height: self.height,
+            values: self.values.iter().zip(&other.values).map(|(&a, &b)| f(a, b)).collect(),
+        }
+    }
+
+    // Separable convolution with clamp-to-edge borders; kernels are centred and odd-sized
+    fn convolve(&self, horizontal: &[f64], vertical: &[f64]) -> Plane {
+        let pass = |src: &[f64], kernel: &[f64], along_x: bool| -> Vec<f64> {
+            let r = (kernel.len() / 2) as i64;
+            let mut out = vec![0.0; src.len()];
+            for y in 0..self.height as i64 {
+                for x in 0..self.width as i64 {
+                    let mut sum = 0.0;
+                    for (k, weight) in kernel.iter().enumerate() {
+                        let offset = k as i64 - r;
+                        let (sx, sy) = if along_x { (x + offset, y) } else { (x, y + offset) };
+                        let sx = sx.clamp(0, self.width as i64 - 1) as usize;
+                        let sy = sy.clamp(0, self.height as i64 - 1) as usize;
+                        sum += weight * src[sy * self.width + sx];
+                    }
+                    out[y as usize * self.width + x as usize] = sum;
+                }
+            }
+            out
+        };
+        let rows = pass(&self.values, horizontal, true);
+        Plane { width: self.width, height: self.height, values: pass(&rows, vertical, false) }
+    }
+}
+
+fn gaussian_kernel(sigma: f64, radius: usize) -> Vec<f64> {
+    let kernel: Vec<f64> = (0..=2 * radius)
+        .map(|i| {
+            let x = i as f64 - radius as f64;
+            (-x * x / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    kernel.iter().map(|w| w / total).collect()
+}
+
+// Image::pixels are linear under the tracer's gamma of 2; the metrics compare display values
+fn display(c: f64) -> f64 {
+    c.max(0.0).sqrt().min(1.0)
+}
+
+pub fn rmse(a: &Image, b: &Image) -> f64 {
+    let mut sum = 0.0;
+    for (p, q) in a.pixels.iter().zip(&b.pixels) {
+        for (x, y) in [(p.x, q.x), (p.y, q.y), (p.z, q.z)] {
+            let d = display(x) - display(y);
+            sum += d * d;
+        }
+    }
+    (sum / (a.pixels.len() * 3).max(1) as f64).sqrt()
+}
+
+// Mean structural similarity of the display luma, with the usual 11x11 Gaussian
+// window (sigma 1.5) and constants for a dynamic range of 1
+pub fn ssim(a: &Image, b: &Image) -> f64 {
+    let luma = |c: Color| 0.299 * display(c.x) + 0.587 * display(c.y) + 0.114 * display(c.z);
+    let x = Plane::from_fn(a, luma);
+    let y = Plane::from_fn(b, luma);
+    let window = gaussian_kernel(1.5, 5);
+    let blur = |p: &Plane| p.convolve(&window, &window);
+
+    let mu_x = blur(&x);
+    let mu_y = blur(&y);
+    let xx = blur(&x.map2(&x, |a, b| a * b));
+    let yy = blur(&y.map2(&y, |a, b| a * b));
+    let xy = blur(&x.map2(&y, |a, b| a * b));
+
+    let (c1, c2) = (0.01f64.powi(2), 0.03f64.powi(2));
+    let mut total = 0.0;
+    for i in 0..x.values.len() {
+        let (mx, my) = (mu_x.values[i], mu_y.values[i]);
+        let var_x = xx.values[i] - mx * mx;
+        let var_y = yy.values[i] - my * my;
+        let cov = xy.values[i] - mx * my;
+        total += ((2.0 * mx * my + c1) * (2.0 * cov + c2)) / ((mx * mx + my * my + c1) * (var_x + var_y + c2));
+    }
+    total / x.values.len().max(1) as f64
+}
+
+// ---- FLIP ----
+//
+// LDR FLIP (Andersson et al. 2020, "FLIP: A Difference Evaluator for Alternating
+// Images"), written from the paper: a colour error computed after filtering
+// both images with contrast sensitivity functions in YCxCz, combined with an
+// edge and point feature error. Values track the reference implementation
+// closely but aren't guaranteed to match it bit for bit.
+
+const PIXELS_PER_DEGREE: f64 = 67.0;
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_rgb_to_xyz(c: [f64; 3]) -> [f64; 3] {
+    [
+        0.4124564 * c[0] + 0.3575761 * c[1] + 0.1804375 * c[2],
+        0.2126729 * c[0] + 0.7151522 * c[1] + 0.0721750 * c[2],
+        0.0193339 * c[0] + 0.1191920 * c[1] + 0.9503041 * c[2],
+    ]
+}
+
+fn xyz_to_linear_rgb(c: [f64; 3]) -> [f64; 3] {
+    [
+        3.2404542 * c[0] - 1.5371385 * c[1] - 0.4985314 * c[2],
+        -0.9692660 * c[0] + 1.8760108 * c[1] + 0.0415560 * c[2],
+        0.0556434 * c[0] - 0.2040259 * c[1] + 1.0572252 * c[2],
+    ]
+}
+
+fn reference_white() -> [f64; 3] {
+    linear_rgb_to_xyz([1.0, 1.0, 1.0])
+}
+
+fn xyz_to_ycxcz(c: [f64; 3]) -> [f64; 3] {
+    let w = reference_white();
+    let (x, y, z) = (c[0] / w[0], c[1] / w[1], c[2] / w[2]);
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+fn ycxcz_to_xyz(c: [f64; 3]) -> [f64; 3] {
+    let w = reference_white();
+    let y = (c[0] + 16.0) / 116.0;
+    [(c[1] / 500.0 + y) * w[0], y * w[1], (y - c[2] / 200.0) * w[2]]
+}
+
+// CIELab with a and b scaled by 0.01 L (the Hunt effect)
+fn hunt_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let w = reference_white();
+    let xyz = linear_rgb_to_xyz(rgb);
+    let delta: f64 = 6.0 / 29.0;
+    let f = |t: f64| if t > delta.powi(3) { t.cbrt() } else { t / (3.0 * delta * delta) + 4.0 / 29.0 };
+    let (fx, fy, fz) = (f(xyz[0] / w[0]), f(xyz[1] / w[1]), f(xyz[2] / w[2]));
+    let l = 116.0 * fy - 16.0;
+    [l, 0.01 * l * 500.0 * (fx - fy), 0.01 * l * 200.0 * (fy - fz)]
+}
+
+fn hyab(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (a[0] - b[0]).abs() + ((a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// The CSF of one opponent channel is a sum of two Gaussians, each of which is
+// separable, so the 2D filter is applied as two separable passes
+fn csf_filter(plane: &Plane, (a1, b1, a2, b2): (f64, f64, f64, f64)) -> Plane {
+    let radius = (3.0 * (0.04f64 / (2.0 * PI * PI)).sqrt() * PIXELS_PER_DEGREE).ceil() as usize;
+    let profile = |a: f64, b: f64| -> (Vec<f64>, f64) {
+        let g: Vec<f64> = (0..=2 * radius)
+            .map(|i| {
+                let x = (i as f64 - radius as f64) / PIXELS_PER_DEGREE;
+                (-PI * PI * x * x / b).exp()
+            })
+            .collect();
+        let sum: f64 = g.iter().sum();
+        // Weight of this term in the 2D filter before normalization
+        (g.iter().map(|v| v / sum).collect(), a * (PI / b).sqrt() * sum * sum)
+    };
+
+    let (g1, w1) = profile(a1, b1);
+    if a2 == 0.0 {
+        return plane.convolve(&g1, &g1);
+    }
+    let (g2, w2) = profile(a2, b2);
+    let first = plane.convolve(&g1, &g1);
+    let second = plane.convolve(&g2, &g2);
+    first.map2(&second, |p, q| (w1 * p + w2 * q) / (w1 + w2))
+}
+
+// Edge (first derivative) or point (second derivative) detector on luminance,
+// returning the magnitude of the x and y responses
+fn features(luminance: &Plane, points: bool) -> Plane {
+    let sd = 0.5 * 0.082 * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sd).ceil() as usize;
+    let offsets: Vec<f64> = (0..=2 * radius).map(|i| i as f64 - radius as f64).collect();
+    let g: Vec<f64> = offsets.iter().map(|x| (-x * x / (2.0 * sd * sd)).exp()).collect();
+    let g_sum: f64 = g.iter().sum();
+    let smooth: Vec<f64> = g.iter().map(|v| v / g_sum).collect();
+
+    let mut derivative: Vec<f64> = offsets
+        .iter()
+        .zip(&g)
+        .map(|(x, g)| if points { (x * x / (sd * sd) - 1.0) * g } else { -x * g })
+        .collect();
+    // Positive and negative lobes are normalized separately so flat regions give zero
+    let positive: f64 = derivative.iter().filter(|v| **v > 0.0).sum();
+    let negative: f64 = -derivative.iter().filter(|v| **v < 0.0).sum::<f64>();
+    for v in derivative.iter_mut() {
+        *v /= if *v > 0.0 { positive } else { negative };
+    }
+
+    let along_x = luminance.convolve(&derivative, &smooth);
+    let along_y = luminance.convolve(&smooth, &derivative);
+    along_x.map2(&along_y, |x, y| (x * x + y * y).sqrt())
+}
+
+// Per-pixel FLIP error in [0, 1]
+pub fn flip_map(reference: &Image, test: &Image) -> Vec<f64> {
+    let to_ycxcz = |image: &Image| -> [Plane; 3] {
+        let converted: Vec<[f64; 3]> = image
+            .pixels
+            .iter()
+            .map(|c| {
+                let linear = [srgb_to_linear(display(c.x)), srgb_to_linear(display(c.y)), srgb_to_linear(display(c.z))];
+                xyz_to_ycxcz(linear_rgb_to_xyz(linear))
+            })
+            .collect();
+        let channel = |k: usize| Plane {
+            width: image.width,
+            height: image.height,
+            values: converted.iter().map(|c| c[k]).collect(),
+        };
+        [channel(0), channel(1), channel(2)]
+    };
+    let reference = to_ycxcz(reference);
+    let test = to_ycxcz(test);
+
+    // Colour pipeline
+    let achromatic = (1.0, 0.0047, 0.0, 1e-5);
+    let red_green = (1.0, 0.0053, 0.0, 1e-5);
+    let blue_yellow = (34.1, 0.04, 13.5, 0.025);
+    let filtered = |planes: &[Plane; 3]| -> Vec<[f64; 3]> {
+        let y = csf_filter(&planes[0], achromatic);
+        let cx = csf_filter(&planes[1], red_green);
+        let cz = csf_filter(&planes[2], blue_yellow);
+        (0..y.values.len())
+            .map(|i| {
+                let rgb = xyz_to_linear_rgb(ycxcz_to_xyz([y.values[i], cx.values[i], cz.values[i]]));
+                hunt_lab([rgb[0].clamp(0.0, 1.0), rgb[1].clamp(0.0, 1.0), rgb[2].clamp(0.0, 1.0)])
+            })
+            .collect()
+    };
+    let reference_lab = filtered(&reference);
+    let test_lab = filtered(&test);
+
+    let (qc, pc, pt) = (0.7, 0.4, 0.95);
+    let cmax = hyab(hunt_lab([0.0, 1.0, 0.0]), hunt_lab([0.0, 0.0, 1.0])).powf(qc);
+
+    // Feature pipeline on normalized luminance
+    let normalized = |planes: &[Plane; 3]| Plane {
+        width: planes[0].width,
+        height: planes[0].height,
+        values: planes[0].values.iter().map(|y| (y + 16.0) / 116.0).collect(),
+    };
+    let reference_y = normalized(&reference);
+    let test_y = normalized(&test);
+    let edge_difference = features(&reference_y, false).map2(&features(&test_y, false), |a, b| (a - b).abs());
+    let point_difference = features(&reference_y, true).map2(&features(&test_y, true), |a, b| (a - b).abs());
+
+    (0..reference_lab.len())
+        .map(|i| {
+            let power = hyab(reference_lab[i], test_lab[i]).powf(qc);
+            let color_error = if power < pc * cmax {
+                pt / (pc * cmax) * power
+            } else {
+                pt + (power - pc * cmax) / (cmax - pc * cmax) * (1.0 - pt)
+            };
+            let feature_error = (edge_difference.values[i].max(point_difference.values[i]) / 2f64.sqrt()).powf(0.5);
+            color_error.powf(1.0 - feature_error)
+        })
+        .collect()
+}
+
+// Maps errors in [0, 1] through a magma-like ramp from black to pale yellow
+pub fn heatmap(errors: &[f64], width: usize, height: usize) -> Image {
+    let stops = [
+        Color::new(0.0, 0.0, 0.016),
+        Color::new(0.31, 0.07, 0.48),
+        Color::new(0.72, 0.22, 0.47),
+        Color::new(0.99, 0.54, 0.38),
+        Color::new(0.99, 0.99, 0.75),
+    ];
+    let mut image = Image::new(width, height);
+    for (pixel, &e) in image.pixels.iter_mut().zip(errors) {
+        let t = e.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let i = (t as usize).min(stops.len() - 2);
+        let c = stops[i] * (1.0 - (t - i as f64)) + stops[i + 1] * (t - i as f64);
+        // Stored squared so write_ppm's gamma lands on the ramp's display values
+        *pixel = c * c;
+    }
+    image
+}
@@ -0,0 +1,64 @@
+use crate::integrator::Integrator;
+use crate::render::{render_view, Image, RenderSettings};
+use crate::scenes::Scene;
+use crate::types::*;
+
+const COLUMNS: usize = 3;
+// Pixels of dark border between thumbnails
+const GAP: usize = 2;
+
+// A framing to try, relative to the scene's own camera: swung round its target by
+// `yaw` degrees (positive to the right), raised by `pitch` degrees and moved out
+// to `distance` times as far away
+struct View {
+    name: &'static str,
+    yaw: f64,
+    pitch: f64,
+    distance: f64,
+}
+
+const VIEWS: [View; 9] = [
+    View { name: "scene camera", yaw: 0.0, pitch: 0.0, distance: 1.0 },
+    View { name: "pulled back", yaw: 0.0, pitch: 10.0, distance: 2.5 },
+    View { name: "high front", yaw: 0.0, pitch: 35.0, distance: 2.5 },
+    View { name: "left", yaw: -50.0, pitch: 15.0, distance: 2.5 },
+    View { name: "right", yaw: 50.0, pitch: 15.0, distance: 2.5 },
+    View { name: "top", yaw: 0.0, pitch: 80.0, distance: 3.0 },
+    View { name: "back left", yaw: -135.0, pitch: 20.0, distance: 2.5 },
+    View { name: "back right", yaw: 135.0, pitch: 20.0, distance: 2.5 },
+    View { name: "low", yaw: 25.0, pitch: 3.0, distance: 2.0 },
+];
+
+// Renders the scene from every predefined view at thumbnail size (`settings`
+// gives the size of one thumbnail) and lays them out in a grid, row by row.
+// The views are listed on stderr so the one picked can be recreated.
+pub fn render_contact_sheet(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings) -> Image {
+    let (thumb_width, thumb_height) = (settings.image_width as usize, settings.image_height as usize);
+    let rows = VIEWS.len().div_ceil(COLUMNS);
+    let mut sheet = Image::new(
+        COLUMNS * thumb_width + (COLUMNS + 1) * GAP,
+        rows * thumb_height + (rows + 1) * GAP,
+    );
+    for pixel in sheet.pixels.iter_mut() {
+        *pixel = Color::one() * 0.01;
+    }
+
+    for (index, view) in VIEWS.iter().enumerate() {
+        let camera = scene.camera.orbited(view.yaw, view.pitch, view.distance);
+        let (row, column) = (index / COLUMNS, index % COLUMNS);
+        let o = camera.origin;
+        eprintln!(
+            "[{row}, {column}] {}: from ({:.2}, {:.2}, {:.2}) towards ({:.2}, {:.2}, {:.2})",
+            view.name, o.x, o.y, o.z, camera.target.x, camera.target.y, camera.target.z
+        );
+
+        let thumbnail = render_view(scene, &camera, integrator, settings, false);
+        let (x0, y0) = (GAP + column * (thumb_width + GAP), GAP + row * (thumb_height + GAP));
+        for y in 0..thumb_height {
+            let source = &thumbnail.pixels[y * thumb_width..(y + 1) * thumb_width];
+            let start = (y0 + y) * sheet.width + x0;
+            sheet.pixels[start..start + thumb_width].copy_from_slice(source);
+        }
+    }
+    sheet
+}
@@ -0,0 +1,165 @@
+use std::io::{self, Write};
+
+use crate::integrator::Integrator;
+use crate::render::{render, Image, RenderSettings};
+use crate::scenes::Scene;
+use crate::types::Color;
+
+// The histogram has one bin per stop from 2^MIN_STOP up to 2^MAX_STOP; anything
+// darker lands in the first bin and anything brighter in the last
+const MIN_STOP: i32 = -12;
+const MAX_STOP: i32 = 4;
+
+// Keeps black pixels from dragging the log-average down to zero
+const LOG_DELTA: f64 = 1e-4;
+
+// Auto-exposure maps the log-average luminance to middle gray
+const KEY: f64 = 0.18;
+
+// Auto-exposure never moves further than this many stops, so an all-black probe
+// doesn't get blown up into noise
+const MAX_AUTO_STOPS: f64 = 10.0;
+
+// Relative luminance of a linear Rec. 709 color
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+// How bright a finished render is, for picking an exposure. A pixel counts as
+// clipped when any channel reaches 1, the brightest value the PPM can hold.
+pub struct ExposureStats {
+    pub pixels: usize,
+    pub average: f64,
+    pub log_average: f64,
+    pub max: f64,
+    pub clipped: usize,
+    pub histogram: Vec<usize>,
+}
+
+// Running sums for `ExposureStats`, for pixels that go by one at a time rather
+// than sitting in an image
+pub struct LuminanceTally {
+    pixels: usize,
+    sum: f64,
+    log_sum: f64,
+    max: f64,
+    clipped: usize,
+    histogram: Vec<usize>,
+}
+
+impl LuminanceTally {
+    pub fn new() -> Self {
+        LuminanceTally {
+            pixels: 0,
+            sum: 0.0,
+            log_sum: 0.0,
+            max: 0.0,
+            clipped: 0,
+            histogram: vec![0; (MAX_STOP - MIN_STOP) as usize],
+        }
+    }
+
+    pub fn add(&mut self, pixel: Color) {
+        let y = luminance(pixel);
+        self.pixels += 1;
+        self.sum += y;
+        self.log_sum += (LOG_DELTA + y.max(0.0)).ln();
+        self.max = self.max.max(y);
+        if pixel.x.max(pixel.y).max(pixel.z) >= 1.0 {
+            self.clipped += 1;
+        }
+        let bin = (y.log2().floor() as i32).clamp(MIN_STOP, MAX_STOP - 1) - MIN_STOP;
+        self.histogram[bin as usize] += 1;
+    }
+
+    pub fn stats(&self) -> ExposureStats {
+        let count = self.pixels.max(1) as f64;
+        ExposureStats {
+            pixels: self.pixels,
+            average: self.sum / count,
+            log_average: (self.log_sum / count).exp(),
+            max: self.max,
+            clipped: self.clipped,
+            histogram: self.histogram.clone(),
+        }
+    }
+}
+
+impl Default for LuminanceTally {
+    fn default() -> Self {
+        LuminanceTally::new()
+    }
+}
+
+impl ExposureStats {
+    pub fn of(image: &Image) -> Self {
+        let mut tally = LuminanceTally::new();
+        for &pixel in &image.pixels {
+            tally.add(pixel);
+        }
+        tally.stats()
+    }
+
+    pub fn clipped_percent(&self) -> f64 {
+        100.0 * self.clipped as f64 / self.pixels.max(1) as f64
+    }
+
+    // The summary and the occupied stretch of the histogram, drawn as bars
+    pub fn print(&self) {
+        eprintln!(
+            "Luminance: average {:.4}, log-average {:.4}, max {:.4}; {:.2}% of pixels clipped",
+            self.average,
+            self.log_average,
+            self.max,
+            self.clipped_percent()
+        );
+        let Some(first) = self.histogram.iter().position(|&n| n > 0) else {
+            return;
+        };
+        let last = self.histogram.iter().rposition(|&n| n > 0).unwrap_or(first);
+        let tallest = self.histogram.iter().copied().max().unwrap_or(1).max(1);
+        for (bin, &count) in self.histogram.iter().enumerate().take(last + 1).skip(first) {
+            let bar = "#".repeat((count * 40).div_ceil(tallest));
+            eprintln!("  2^{:<4}{:<41}{count}", MIN_STOP + bin as i32, bar);
+        }
+    }
+
+    pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"pixels\": {},", self.pixels)?;
+        writeln!(out, "  \"average_luminance\": {},", self.average)?;
+        writeln!(out, "  \"log_average_luminance\": {},", self.log_average)?;
+        writeln!(out, "  \"max_luminance\": {},", self.max)?;
+        writeln!(out, "  \"clipped_pixels\": {},", self.clipped)?;
+        writeln!(out, "  \"clipped_percent\": {},", self.clipped_percent())?;
+        writeln!(out, "  \"histogram\": [")?;
+        for (bin, count) in self.histogram.iter().enumerate() {
+            let separator = if bin + 1 < self.histogram.len() { "," } else { "" };
+            writeln!(out, "    {{ \"stop\": {}, \"count\": {count} }}{separator}", MIN_STOP + bin as i32)?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
+
+// Brightens (or, for negative stops, darkens) every pixel
+pub fn expose(image: &mut Image, stops: f64) {
+    let scale = stops.exp2();
+    for pixel in &mut image.pixels {
+        *pixel *= scale;
+    }
+}
+
+// The exposure, in stops, that brings the scene's log-average luminance to middle
+// gray, judged from a quick render at an eighth of the width. Too few samples
+// would leave enough noise to drag the log-average down.
+pub fn auto_exposure(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings) -> f64 {
+    let mut probe = RenderSettings::with_width((settings.image_width / 8).max(32));
+    probe.samples_per_pixel = 16;
+    probe.max_depth = settings.max_depth;
+    // A seeded render meters the same exposure every time
+    probe.seed = settings.seed;
+    probe.threads = settings.threads;
+    let stats = ExposureStats::of(&render(scene, integrator, &probe, false));
+    (KEY / stats.log_average).log2().clamp(-MAX_AUTO_STOPS, MAX_AUTO_STOPS)
+}
@@ -0,0 +1,235 @@
+// A C ABI over the core of the tracer, built with the "ffi" feature, so it can be
+// driven from C, C++ or Python (through ctypes or cffi) with include/rust_raytracer.h.
+// A scene is made with rt_scene_new, filled with spheres and meshes, given a camera
+// and rendered into a buffer the caller owns; every call but rt_scene_new and
+// rt_scene_free returns one of the RT_* status codes.
+use std::{slice, sync::Arc};
+
+use crate::integrator::PathTracer;
+use crate::render::{render_view, RenderSettings};
+use crate::scenes::Scene;
+use crate::texture::SolidColor;
+use crate::types::*;
+
+pub const RT_OK: i32 = 0;
+pub const RT_NULL_POINTER: i32 = -1;
+pub const RT_INVALID_ARGUMENT: i32 = -2;
+pub const RT_BUFFER_TOO_SMALL: i32 = -3;
+
+// What `RtMaterial::kind` can be
+pub const RT_LAMBERTIAN: u32 = 0;
+pub const RT_METAL: u32 = 1;
+pub const RT_DIELECTRIC: u32 = 2;
+pub const RT_LIGHT: u32 = 3;
+
+// A material as plain data: `color` is the albedo or the emitted color, and
+// `parameter` a metal's fuzz, a dielectric's index of refraction or a light's
+// intensity
+#[repr(C)]
+pub struct RtMaterial {
+    pub kind: u32,
+    pub color: [f64; 3],
+    pub parameter: f64,
+}
+
+// Opaque to C: only ever handled through a pointer from rt_scene_new
+pub struct RtScene {
+    objects: Vec<Arc<dyn Hittable>>,
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    vfov: f64,
+    // None for the sky gradient
+    background: Option<Color>,
+}
+
+/// An empty scene under the sky, seen from the default camera. Free it with
+/// rt_scene_free.
+#[no_mangle]
+pub extern "C" fn rt_scene_new() -> *mut RtScene {
+    let camera = Camera::default_camera();
+    Box::into_raw(Box::new(RtScene {
+        objects: Vec::new(),
+        lookfrom: camera.origin,
+        lookat: camera.target,
+        vup: camera.vup,
+        vfov: camera.vfov,
+        background: None,
+    }))
+}
+
+/// # Safety
+/// `scene` must be null or come from rt_scene_new, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_free(scene: *mut RtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// # Safety
+/// `scene` must come from rt_scene_new, `center` point to 3 doubles and `material`
+/// to an RtMaterial.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_sphere(scene: *mut RtScene, center: *const f64, radius: f64, material: *const RtMaterial) -> i32 {
+    let (Some(scene), Some(center), Some(material)) = (scene.as_mut(), point(center), material.as_ref()) else {
+        return RT_NULL_POINTER;
+    };
+    let Some(material) = to_material(material) else {
+        return RT_INVALID_ARGUMENT;
+    };
+    if !(radius.is_finite() && radius > 0.0) {
+        return RT_INVALID_ARGUMENT;
+    }
+    scene.objects.push(Arc::new(Sphere::new(center, radius, material)));
+    RT_OK
+}
+
+/// A triangle mesh from `vertex_count` positions, packed as x, y, z doubles, and
+/// `triangle_count` triangles, each 3 indices into the positions.
+///
+/// # Safety
+/// `scene` must come from rt_scene_new, `positions` point to 3 * vertex_count
+/// doubles, `indices` to 3 * triangle_count indices and `material` to an RtMaterial.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_mesh(
+    scene: *mut RtScene,
+    positions: *const f64,
+    vertex_count: usize,
+    indices: *const u32,
+    triangle_count: usize,
+    material: *const RtMaterial,
+) -> i32 {
+    let (Some(scene), Some(material)) = (scene.as_mut(), material.as_ref()) else {
+        return RT_NULL_POINTER;
+    };
+    if positions.is_null() || indices.is_null() {
+        return RT_NULL_POINTER;
+    }
+    let Some(material) = to_material(material) else {
+        return RT_INVALID_ARGUMENT;
+    };
+    let positions: Vec<Point3> = slice::from_raw_parts(positions, 3 * vertex_count)
+        .chunks_exact(3)
+        .map(|p| Point3::new(p[0], p[1], p[2]))
+        .collect();
+    let indices: Vec<[u32; 3]> = slice::from_raw_parts(indices, 3 * triangle_count)
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    if indices.iter().flatten().any(|&i| i as usize >= vertex_count) {
+        return RT_INVALID_ARGUMENT;
+    }
+    scene.objects.push(Arc::new(TriangleMesh::new(positions, indices, material)));
+    RT_OK
+}
+
+/// Looks from `lookfrom` towards `lookat`, with `vup` up and a vertical field of
+/// view of `vfov` degrees.
+///
+/// # Safety
+/// `scene` must come from rt_scene_new and the vectors each point to 3 doubles.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_camera(scene: *mut RtScene, lookfrom: *const f64, lookat: *const f64, vup: *const f64, vfov: f64) -> i32 {
+    let (Some(scene), Some(lookfrom), Some(lookat), Some(vup)) = (scene.as_mut(), point(lookfrom), point(lookat), point(vup)) else {
+        return RT_NULL_POINTER;
+    };
+    if !(vfov > 0.0 && vfov < 180.0) {
+        return RT_INVALID_ARGUMENT;
+    }
+    scene.lookfrom = lookfrom;
+    scene.lookat = lookat;
+    scene.vup = vup;
+    scene.vfov = vfov;
+    RT_OK
+}
+
+/// A solid background color in place of the sky, or the sky again if `color` is
+/// null.
+///
+/// # Safety
+/// `scene` must come from rt_scene_new and `color` be null or point to 3 doubles.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_background(scene: *mut RtScene, color: *const f64) -> i32 {
+    let Some(scene) = scene.as_mut() else {
+        return RT_NULL_POINTER;
+    };
+    scene.background = point(color);
+    RT_OK
+}
+
+/// Path traces the scene at `width` by `height` (each at least 2) into `out` as
+/// linear RGB floats, three to a pixel, row by row from the top. The same seed
+/// always gives the same image. `out_len` is the number of floats `out` has room
+/// for.
+///
+/// # Safety
+/// `scene` must come from rt_scene_new and `out` point to `out_len` floats.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render(
+    scene: *const RtScene,
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    seed: u64,
+    out: *mut f32,
+    out_len: usize,
+) -> i32 {
+    let Some(scene) = scene.as_ref() else {
+        return RT_NULL_POINTER;
+    };
+    if out.is_null() {
+        return RT_NULL_POINTER;
+    }
+    if width < 2 || height < 2 || width > u16::MAX as u32 || height > u16::MAX as u32 || samples_per_pixel < 1 {
+        return RT_INVALID_ARGUMENT;
+    }
+    let len = 3 * width as usize * height as usize;
+    if out_len < len {
+        return RT_BUFFER_TOO_SMALL;
+    }
+
+    let mut settings = RenderSettings::default_settings();
+    settings.image_width = width as u16;
+    settings.image_height = height as u16;
+    settings.samples_per_pixel = samples_per_pixel as i64;
+    settings.max_depth = max_depth as i64;
+    settings.seed = Some(seed);
+    let camera = Camera::new(scene.lookfrom, scene.lookat, scene.vup, scene.vfov, width as f64 / height as f64);
+    let mut world = HittableList::new();
+    for object in &scene.objects {
+        world.add(object.clone());
+    }
+    let mut render_scene = Scene::new(world).with_camera(camera);
+    if let Some(color) = scene.background {
+        render_scene.background = Background::Solid(color);
+    }
+
+    let image = render_view(&render_scene, &render_scene.camera, &PathTracer::new(settings.max_depth), &settings, false);
+    let out = slice::from_raw_parts_mut(out, len);
+    for (rgb, color) in out.chunks_exact_mut(3).zip(&image.pixels) {
+        rgb.copy_from_slice(&[color.x as f32, color.y as f32, color.z as f32]);
+    }
+    RT_OK
+}
+
+unsafe fn point(p: *const f64) -> Option<Point3> {
+    (!p.is_null()).then(|| {
+        let p = slice::from_raw_parts(p, 3);
+        Point3::new(p[0], p[1], p[2])
+    })
+}
+
+fn to_material(material: &RtMaterial) -> Option<Arc<dyn Material>> {
+    let [r, g, b] = material.color;
+    let color = Color::new(r, g, b);
+    let parameter = material.parameter;
+    Some(match material.kind {
+        RT_LAMBERTIAN => Arc::new(LambertianMaterial::new(color)),
+        RT_METAL => Arc::new(MetalMaterial::new(color, parameter)),
+        RT_DIELECTRIC => Arc::new(DielectricMaterial::new(parameter)),
+        RT_LIGHT => Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(color)), parameter)),
+        _ => return None,
+    })
+}
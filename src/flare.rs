@@ -0,0 +1,150 @@
+use crate::exposure::luminance;
+use crate::render::Image;
+use crate::types::*;
+
+// Ghost positions along the line from the image center through a source, as
+// multiples of the source's offset from the center: negative ghosts land on the
+// far side of the center, as reflections between lens elements put them. Ghosts
+// past these repeat the pattern further out.
+const GHOST_OFFSETS: [f64; 6] = [-0.35, -0.8, 0.45, -1.4, -0.15, 1.3];
+// The shares of the light above the threshold that the starburst and the first
+// ghost spread over the image
+const STARBURST_SHARE: f64 = 0.25;
+const GHOST_SHARE: f64 = 0.1;
+
+const GHOST_TINTS: [Color; 3] = [
+    Color { x: 1.0, y: 0.6, z: 0.3 },
+    Color { x: 0.4, y: 0.8, z: 1.0 },
+    Color { x: 0.7, y: 1.0, z: 0.5 },
+];
+
+// A lens flare added over a finished render: streaks from the brightest parts of
+// the image (a starburst, as from the blades of the aperture) and soft ghosts of
+// them mirrored through the center. Everything brighter than `threshold` gives
+// off flare in proportion to how far past it it is, so bare bulbs and suns do,
+// whether seen directly or in a mirror.
+pub struct FlareSettings {
+    pub threshold: f64,
+    pub strength: f64,
+    // Streaks in the starburst, evenly spaced round the circle; none turns it off
+    pub spokes: usize,
+    // How far the streaks reach before fading to about a third, as a fraction of the
+    // image's diagonal
+    pub streak_length: f64,
+    pub ghosts: usize,
+}
+
+impl FlareSettings {
+    pub fn new() -> Self {
+        FlareSettings { threshold: 1.0, strength: 1.0, spokes: 6, streak_length: 0.1, ghosts: 4 }
+    }
+}
+
+impl Default for FlareSettings {
+    fn default() -> Self {
+        FlareSettings::new()
+    }
+}
+
+// Adds the flare to `image`, which should already be exposed as it's to be shown
+pub fn apply_flare(image: &mut Image, settings: &FlareSettings) {
+    let bright = Image {
+        width: image.width,
+        height: image.height,
+        pixels: image
+            .pixels
+            .iter()
+            .map(|&c| {
+                let l = luminance(c);
+                if l > settings.threshold { c * ((l - settings.threshold) / l) } else { Color::zero() }
+            })
+            .collect(),
+    };
+    if bright.pixels.iter().all(|c| c.near_zero()) {
+        return;
+    }
+
+    let diagonal = ((image.width * image.width + image.height * image.height) as f64).sqrt();
+    let mut flare = vec![Color::zero(); image.pixels.len()];
+    // Each streak is tilted a little off the axes, as most apertures sit
+    for spoke in 0..settings.spokes {
+        let angle = 2.0 * PI * (spoke as f64 + 0.25) / settings.spokes as f64;
+        let streak = streak(&bright, (angle.cos(), angle.sin()), settings.streak_length * diagonal);
+        for (f, s) in flare.iter_mut().zip(&streak.pixels) {
+            *f += *s * (STARBURST_SHARE / settings.spokes as f64);
+        }
+    }
+
+    // Ghosts are defocused, so they come from a blurred copy of the bright parts
+    let radius = (diagonal / 150.0) as usize;
+    let soft = bright.box_blurred(radius).box_blurred(radius);
+    let (cx, cy) = (image.width as f64 / 2.0, image.height as f64 / 2.0);
+    for ghost in 0..settings.ghosts {
+        let round = (ghost / GHOST_OFFSETS.len()) as f64;
+        let offset = GHOST_OFFSETS[ghost % GHOST_OFFSETS.len()] * (1.0 + 0.5 * round);
+        // A ghost spread over a larger area is fainter, and each one further in the
+        // sequence has bounced off more glass
+        let weight = GHOST_TINTS[ghost % GHOST_TINTS.len()] * (GHOST_SHARE / (offset * offset).max(0.25) / (1.0 + ghost as f64));
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                let source = sample(&soft, cx + (px - cx) / offset, cy + (py - cy) / offset);
+                flare[y * image.width + x] += source * weight;
+            }
+        }
+    }
+
+    for (pixel, f) in image.pixels.iter_mut().zip(flare) {
+        *pixel += f * settings.strength;
+    }
+}
+
+// Smears every pixel of `image` along `direction` with an exponential falloff,
+// `length` pixels to about a third, by running a decaying sum along lines of that
+// slope. Lines are stepped a whole pixel along whichever axis the direction is
+// closer to and interpolated along the other.
+fn streak(image: &Image, direction: (f64, f64), length: f64) -> Image {
+    let (dx, dy) = direction;
+    let (width, height) = (image.width, image.height);
+    let across = dx.abs() >= dy.abs();
+    let (step, slope) = if across { (dx.signum(), dy / dx.abs()) } else { (dy.signum(), dx / dy.abs()) };
+    let step_length = (1.0 + slope * slope).sqrt();
+    let decay = (-step_length / length.max(1.0)).exp();
+
+    let mut out = Image::new(width, height);
+    let (lines, along) = if across { (width, height) } else { (height, width) };
+    let order: Vec<usize> = if step > 0.0 { (0..lines).collect() } else { (0..lines).rev().collect() };
+    let at = |line: usize, i: usize| if across { i * width + line } else { line * width + i };
+    for (n, &line) in order.iter().enumerate() {
+        let previous = if n == 0 { None } else { Some(order[n - 1]) };
+        for i in 0..along {
+            // The energy streaked out of a pixel adds up to what it had above the
+            // threshold, spread along the streak
+            let mut value = image.pixels[at(line, i)] * (1.0 - decay);
+            if let Some(previous) = previous {
+                let from = i as f64 - slope;
+                let (i0, t) = (from.floor(), from - from.floor());
+                let fetch = |i: f64| if i >= 0.0 && i < along as f64 { out.pixels[at(previous, i as usize)] } else { Color::zero() };
+                value += (fetch(i0) * (1.0 - t) + fetch(i0 + 1.0) * t) * decay;
+            }
+            out.pixels[at(line, i)] = value;
+        }
+    }
+    out
+}
+
+// Bilinear, and black outside the image
+fn sample(image: &Image, x: f64, y: f64) -> Color {
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let texel = |x: f64, y: f64| {
+        if x < 0.0 || y < 0.0 || x >= image.width as f64 || y >= image.height as f64 {
+            Color::zero()
+        } else {
+            image.pixels[y as usize * image.width + x as usize]
+        }
+    };
+    (texel(x0, y0) * (1.0 - tx) + texel(x0 + 1.0, y0) * tx) * (1.0 - ty)
+        + (texel(x0, y0 + 1.0) * (1.0 - tx) + texel(x0 + 1.0, y0 + 1.0) * tx) * ty
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use crate::exposure::luminance;
+use crate::integrator::PathTracer;
+use crate::render::{render_view, RenderSettings, ASPECT_RATIO};
+use crate::scenes::Scene;
+use crate::types::*;
+
+// Furnace tests: each material covers a sphere lit only by a uniform white sky. A
+// convex object never sees itself, so every path off a white, lossless material
+// ends in the sky and the sphere disappears into the background, while a gray one
+// comes out at its albedo. Brighter than that means the material creates energy;
+// darker means it loses some.
+//
+// There's no microfacet (GGX) BRDF in the renderer yet, so there's no GGX case:
+// rough specular comes from fuzzy metal, checked directly and as the principled
+// material's metallic lobe. A microfacet material should get a case here when
+// it lands.
+
+const WIDTH: u16 = 64;
+// How far the sphere's average luminance may stray from the expected value
+const TOLERANCE: f64 = 0.01;
+
+pub struct FurnaceCase {
+    pub name: &'static str,
+    pub material: Arc<dyn Material>,
+    pub expected: f64,
+    // Materials that absorb some light by design (fuzzy metal losing the rays it
+    // scatters below the surface) are only checked for not creating energy
+    pub lossy: bool,
+}
+
+pub struct FurnaceResult {
+    pub name: &'static str,
+    pub expected: f64,
+    pub measured: f64,
+    pub lossy: bool,
+}
+
+impl FurnaceResult {
+    pub fn passed(&self) -> bool {
+        if self.lossy {
+            self.measured <= self.expected + TOLERANCE
+        } else {
+            (self.measured - self.expected).abs() <= TOLERANCE
+        }
+    }
+}
+
+pub fn cases() -> Vec<FurnaceCase> {
+    let gray = || Arc::new(LambertianMaterial::new(Color::one() * 0.5));
+    let white = || Arc::new(LambertianMaterial::new(Color::one()));
+    let case = |name, material: Arc<dyn Material>, expected, lossy| FurnaceCase { name, material, expected, lossy };
+    vec![
+        case("lambertian", gray(), 0.5, false),
+        case("lambertian (white)", white(), 1.0, false),
+        case("metal", Arc::new(MetalMaterial::new(Color::one(), 0.0)), 1.0, false),
+        case("metal (fuzzy)", Arc::new(MetalMaterial::new(Color::one(), 0.5)), 1.0, true),
+        case("dielectric", Arc::new(DielectricMaterial::new(1.5)), 1.0, false),
+        case("clearcoat", Arc::new(ClearcoatMaterial::new(white(), 1.5)), 1.0, false),
+        case("sheen", Arc::new(SheenMaterial::new(white(), Color::one(), 0.5)), 1.0, false),
+        case("mix", Arc::new(MixMaterial::new(gray(), Arc::new(MetalMaterial::new(Color::one(), 0.0)), 0.5)), 0.75, false),
+        case(
+            "principled",
+            Arc::new(PrincipledMaterial::new(Color::one(), 0.5, 0.0).with_sheen(Color::one(), 0.5).with_clearcoat(1.5)),
+            1.0,
+            false,
+        ),
+        case("principled (metallic)", Arc::new(PrincipledMaterial::new(Color::one(), 1.0, 0.3)), 1.0, true),
+    ]
+}
+
+// The average luminance of the pixels lying wholly on a unit sphere of `material`
+pub fn measure(material: Arc<dyn Material>, samples_per_pixel: i64) -> f64 {
+    let mut world = HittableList::new();
+    world.add(Arc::new(Sphere::new(Point3::zero(), 1.0, material)));
+    let mut scene = Scene::new(world);
+    scene.background = Background::Solid(Color::one());
+    scene.camera = Camera::new(Point3::new(0.0, 0.0, 3.0), Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 45.0, ASPECT_RATIO);
+
+    let mut settings = RenderSettings::with_width(WIDTH);
+    settings.samples_per_pixel = samples_per_pixel;
+    settings.seed = Some(0);
+    let integrator = PathTracer::new(settings.max_depth);
+    let image = render_view(&scene, &scene.camera, &integrator, &settings, false);
+
+    // Pixels on the silhouette would average in some of the sky
+    let (width, height) = (settings.image_width, settings.image_height);
+    let camera = scene.camera.for_image_height(height);
+    let hits = |x: f64, y: f64| {
+        let r = camera.get_ray(x / (width - 1) as f64, y / (height - 1) as f64);
+        scene.hit(r, Interval::new(0.0, f64::INFINITY), &mut HitRecord::blank())
+    };
+    let (mut sum, mut count) = (0.0, 0);
+    for j in 0..height {
+        for i in 0..width {
+            let (x, y) = (i as f64, j as f64);
+            if hits(x, y) && hits(x + 1.0, y) && hits(x, y + 1.0) && hits(x + 1.0, y + 1.0) {
+                let row = (height - 1 - j) as usize;
+                sum += luminance(image.pixels[row * width as usize + i as usize]);
+                count += 1;
+            }
+        }
+    }
+    sum / count.max(1) as f64
+}
+
+// Runs every case and prints how each did; true if they all passed
+pub fn run(samples_per_pixel: i64) -> bool {
+    println!("Furnace test at {samples_per_pixel} spp, tolerance {TOLERANCE}:");
+    let mut all_passed = true;
+    for case in cases() {
+        let result = FurnaceResult {
+            name: case.name,
+            expected: case.expected,
+            measured: measure(case.material, samples_per_pixel),
+            lossy: case.lossy,
+        };
+        let bound = if result.lossy { "at most" } else { "expected" };
+        let verdict = if result.passed() { "ok" } else { "FAILED" };
+        println!(
+            "  {:<24}{bound} {:.3}, measured {:.4}  {verdict}",
+            result.name, result.expected, result.measured
+        );
+        all_passed &= result.passed();
+    }
+    all_passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES_PER_PIXEL: i64 = 64;
+
+    fn check(name: &str) {
+        let case = cases().into_iter().find(|case| case.name == name).unwrap();
+        let result = FurnaceResult {
+            name: case.name,
+            expected: case.expected,
+            measured: measure(case.material, SAMPLES_PER_PIXEL),
+            lossy: case.lossy,
+        };
+        assert!(result.passed(), "{} came out at {:.4}, expected {:.3}", name, result.measured, result.expected);
+    }
+
+    #[test]
+    fn lambertian() {
+        check("lambertian");
+    }
+
+    #[test]
+    fn lambertian_white() {
+        check("lambertian (white)");
+    }
+
+    #[test]
+    fn metal() {
+        check("metal");
+    }
+
+    #[test]
+    fn metal_fuzzy() {
+        check("metal (fuzzy)");
+    }
+
+    #[test]
+    fn dielectric() {
+        check("dielectric");
+    }
+
+    #[test]
+    fn clearcoat() {
+        check("clearcoat");
+    }
+
+    #[test]
+    fn sheen() {
+        check("sheen");
+    }
+
+    #[test]
+    fn mix() {
+        check("mix");
+    }
+
+    #[test]
+    fn principled() {
+        check("principled");
+    }
+
+    #[test]
+    fn principled_metallic() {
+        check("principled (metallic)");
+    }
+
+    #[test]
+    fn every_case_has_a_test() {
+        let tested = [
+            "lambertian",
+            "lambertian (white)",
+            "metal",
+            "metal (fuzzy)",
+            "dielectric",
+            "clearcoat",
+            "sheen",
+            "mix",
+            "principled",
+            "principled (metallic)",
+        ];
+        for case in cases() {
+            assert!(tested.contains(&case.name), "no test runs the {} case", case.name);
+        }
+    }
+}
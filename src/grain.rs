@@ -0,0 +1,92 @@
+use crate::render::Image;
+use crate::sampler::{RandomSampler, Sampler};
+use crate::types::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GrainModel {
+    // Clumps of silver, a few pixels across and the same in every channel, most
+    // visible in the midtones and fading out in the deepest shadows and highlights
+    // as fully exposed or bare film has no grain left to show
+    Film,
+    // Photon shot noise, growing with the square root of the light each photosite
+    // caught, over a faint floor of read noise; independent per pixel and channel
+    Sensor,
+}
+
+impl GrainModel {
+    pub fn by_name(name: &str) -> Option<GrainModel> {
+        match name {
+            "film" => Some(GrainModel::Film),
+            "sensor" => Some(GrainModel::Sensor),
+            _ => None,
+        }
+    }
+}
+
+// Read noise as a share of the shot noise at full exposure
+const READ_NOISE: f64 = 0.2;
+
+pub struct GrainSettings {
+    pub model: GrainModel,
+    // The grain's standard deviation at its strongest, in display values from 0 to 1
+    pub intensity: f64,
+    // Roughly how many pixels across a film grain is
+    pub size: usize,
+    pub seed: u64,
+}
+
+impl GrainSettings {
+    pub fn new(model: GrainModel) -> Self {
+        GrainSettings { model, intensity: 0.04, size: 2, seed: 0 }
+    }
+}
+
+// Adds grain to `image`, which should already be exposed as it's to be shown. The
+// same seed gives the same grain, so it holds still across re-renders of a frame.
+pub fn apply_grain(image: &mut Image, settings: &GrainSettings) {
+    let mut sampler = RandomSampler::seeded(settings.seed);
+    let mut noise = Image::new(image.width, image.height);
+    for n in noise.pixels.iter_mut() {
+        *n = match settings.model {
+            GrainModel::Film => Color::one() * gaussian(&mut sampler),
+            GrainModel::Sensor => Color::new(gaussian(&mut sampler), gaussian(&mut sampler), gaussian(&mut sampler)),
+        };
+    }
+
+    match settings.model {
+        GrainModel::Film => {
+            // Averaging over a square of grains shrinks the spread by its width;
+            // scaling back up keeps the intensity independent of the size
+            let radius = settings.size / 2;
+            let noise = noise.box_blurred(radius);
+            let rescale = (2 * radius + 1) as f64;
+            for (pixel, n) in image.pixels.iter_mut().zip(noise.pixels) {
+                let grain = |linear: f64, n: f64| {
+                    // Grain settles in display values, as it shows on the print
+                    let v = linear.clamp(0.0, 1.0).sqrt();
+                    let v = v + n * rescale * settings.intensity * 2.0 * (v * (1.0 - v)).sqrt();
+                    v.clamp(0.0, 1.0).powi(2)
+                };
+                *pixel = Color::new(grain(pixel.x, n.x), grain(pixel.y, n.y), grain(pixel.z, n.z));
+            }
+        }
+        GrainModel::Sensor => {
+            // Noise in the light the sensor caught, which a full-white pixel shows
+            // at `intensity` after the display's gamma
+            let sigma = 2.0 * settings.intensity;
+            for (pixel, n) in image.pixels.iter_mut().zip(noise.pixels) {
+                let noisy = |linear: f64, n: f64| {
+                    let linear = linear.max(0.0);
+                    (linear + n * sigma * (linear + READ_NOISE * READ_NOISE).sqrt()).max(0.0)
+                };
+                *pixel = Color::new(noisy(pixel.x, n.x), noisy(pixel.y, n.y), noisy(pixel.z, n.z));
+            }
+        }
+    }
+}
+
+// A standard normal sample, by the Box-Muller transform
+fn gaussian(sampler: &mut dyn Sampler) -> f64 {
+    let (u0, u1) = sampler.get_2d();
+    (-2.0 * (1.0 - u0).ln()).sqrt() * (2.0 * PI * u1).cos()
+}
@@ -0,0 +1,277 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use crate::json;
+use crate::render::decode_ppm_sample;
+use crate::texture::{Texture, TextureCoords};
+use crate::types::Color;
+
+const TILE_SIZE: usize = 64;
+pub const DEFAULT_CACHE_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+// How many tiles each thread keeps to hand
+const LOCAL_TILES: usize = 16;
+
+// A texture's id and a tile's column and row within it
+type TileKey = (usize, usize, usize);
+
+// Texture ids are unique across every cache, so a thread's own tiles can be
+// looked up by id alone
+static NEXT_TEXTURE_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The tiles this thread sampled most recently, so lookups within them skip
+    // the shared cache's lock. A tile the shared cache evicts stays usable here
+    // until this thread replaces it.
+    static LOCAL_TILES_CACHE: RefCell<Vec<(TileKey, Arc<Vec<u8>>)>> = const { RefCell::new(Vec::new()) };
+}
+
+struct CachedTile {
+    data: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+struct TileCacheInner {
+    tiles: HashMap<TileKey, CachedTile>,
+    used_bytes: usize,
+    clock: u64,
+}
+
+// Holds the most recently sampled tiles of every image texture sharing it,
+// evicting the least recently used ones once `budget_bytes` is exceeded.
+pub struct TileCache {
+    budget_bytes: usize,
+    inner: Mutex<TileCacheInner>,
+}
+
+impl TileCache {
+    pub fn new(budget_bytes: usize) -> Self {
+        TileCache {
+            budget_bytes,
+            inner: Mutex::new(TileCacheInner {
+                tiles: HashMap::new(),
+                used_bytes: 0,
+                clock: 0,
+            }),
+        }
+    }
+
+    fn register(&self) -> usize {
+        NEXT_TEXTURE_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // The tile from this thread's own tiles if it has it, or else from the shared
+    // cache, replacing the thread's oldest
+    fn get(&self, key: TileKey, load: impl FnOnce() -> Vec<u8>) -> Arc<Vec<u8>> {
+        let local = LOCAL_TILES_CACHE.with(|tiles| tiles.borrow().iter().find(|(k, _)| *k == key).map(|(_, tile)| tile.clone()));
+        if let Some(tile) = local {
+            return tile;
+        }
+        let tile = self.get_or_load(key, load);
+        LOCAL_TILES_CACHE.with(|tiles| {
+            let mut tiles = tiles.borrow_mut();
+            if tiles.len() == LOCAL_TILES {
+                tiles.remove(0);
+            }
+            tiles.push((key, tile.clone()));
+        });
+        tile
+    }
+
+    fn get_or_load(&self, key: TileKey, load: impl FnOnce() -> Vec<u8>) -> Arc<Vec<u8>> {
+        {
+            // A render row that panicked while holding the lock left nothing half
+            // done, so the cache is still good to use
+            let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            inner.clock += 1;
+            let clock = inner.clock;
+            if let Some(tile) = inner.tiles.get_mut(&key) {
+                tile.last_used = clock;
+                return tile.data.clone();
+            }
+        }
+
+        // Read outside the lock so other threads can keep sampling resident tiles
+        let data = Arc::new(load());
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        while inner.used_bytes + data.len() > self.budget_bytes && !inner.tiles.is_empty() {
+            let oldest = *inner.tiles.iter().min_by_key(|(_, tile)| tile.last_used).unwrap().0;
+            let evicted = inner.tiles.remove(&oldest).unwrap();
+            inner.used_bytes -= evicted.data.len();
+        }
+        let clock = inner.clock;
+        if let Some(previous) = inner.tiles.insert(key, CachedTile { data: data.clone(), last_used: clock }) {
+            inner.used_bytes -= previous.data.len();
+        }
+        inner.used_bytes += data.len();
+        data
+    }
+}
+
+// An image texture backed by a binary PPM (P6) file. Only the tiles that are
+// actually sampled are read from disk, so the full image never has to fit in memory.
+// The file can be read again after it changes, for painting textures while a
+// preview watches; the layout lives in atomics so sampling takes no lock for it.
+pub struct ImageTexture {
+    path: PathBuf,
+    file: Mutex<File>,
+    // When the file had last changed as it was read, if the filesystem says
+    modified: Mutex<Option<SystemTime>>,
+    data_offset: AtomicU64,
+    width: AtomicUsize,
+    height: AtomicUsize,
+    // The value white is stored as
+    max_value: AtomicUsize,
+    // Tiles are cached under this, and a reload takes a fresh one so the old
+    // tiles are never looked up again and age out of the cache
+    id: AtomicUsize,
+    cache: Arc<TileCache>,
+}
+
+// An opened PPM positioned at its pixels, with its width, height and maximum value
+type Header = (File, u64, usize, usize, usize);
+
+fn read_header(path: &Path) -> io::Result<Header> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut fields = Vec::new();
+    while fields.len() < 4 {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("truncated PPM header"));
+        }
+        let line = line.split('#').next().unwrap_or("");
+        fields.extend(line.split_whitespace().map(str::to_string));
+    }
+    if fields[0] != "P6" {
+        return Err(invalid("only binary (P6) PPM textures are supported"));
+    }
+    let parse = |s: &str| s.parse::<usize>().map_err(|_| invalid("bad PPM header"));
+    let (width, height, max_value) = (parse(&fields[1])?, parse(&fields[2])?, parse(&fields[3])?);
+    if fields.len() > 4 || max_value > 255 || max_value == 0 || width == 0 || height == 0 {
+        return Err(invalid("unsupported PPM layout"));
+    }
+
+    let data_offset = reader.stream_position()?;
+    Ok((reader.into_inner(), data_offset, width, height, max_value))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ImageTexture {
+    pub fn open<P: AsRef<Path>>(path: P, cache: Arc<TileCache>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let modified = modified(path);
+        let (file, data_offset, width, height, max_value) = read_header(path)?;
+        Ok(ImageTexture {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            modified: Mutex::new(modified),
+            data_offset: AtomicU64::new(data_offset),
+            width: AtomicUsize::new(width),
+            height: AtomicUsize::new(height),
+            max_value: AtomicUsize::new(max_value),
+            id: AtomicUsize::new(cache.register()),
+            cache,
+        })
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        (self.width.load(Ordering::Relaxed), self.height.load(Ordering::Relaxed))
+    }
+
+    fn tile_width(&self, tx: usize) -> usize {
+        TILE_SIZE.min(self.dimensions().0 - tx * TILE_SIZE)
+    }
+
+    fn load_tile(&self, tx: usize, ty: usize) -> Vec<u8> {
+        let (width, height) = self.dimensions();
+        let tile_width = self.tile_width(tx);
+        let tile_height = TILE_SIZE.min(height - ty * TILE_SIZE);
+        let mut data = vec![0; tile_width * tile_height * 3];
+
+        let data_offset = self.data_offset.load(Ordering::Relaxed);
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        for row in 0..tile_height {
+            let offset = ((ty * TILE_SIZE + row) * width + tx * TILE_SIZE) * 3;
+            let dest = &mut data[row * tile_width * 3..(row + 1) * tile_width * 3];
+            let read = file
+                .seek(SeekFrom::Start(data_offset + offset as u64))
+                .and_then(|_| file.read_exact(dest));
+            if let Err(e) = read {
+                // Leave the rest of the tile black rather than taking the render down
+                eprintln!("\nFailed to read texture tile ({tx}, {ty}): {e}");
+                break;
+            }
+        }
+        data
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        // Flip v to image coordinates, where rows run top to bottom
+        let u = tc.u.clamp(0.0, 1.0);
+        let v = 1.0 - tc.v.clamp(0.0, 1.0);
+        let (width, height) = self.dimensions();
+        let i = ((u * width as f64) as usize).min(width - 1);
+        let j = ((v * height as f64) as usize).min(height - 1);
+
+        let (tx, ty) = (i / TILE_SIZE, j / TILE_SIZE);
+        let id = self.id.load(Ordering::Relaxed);
+        let tile = self.cache.get((id, tx, ty), || self.load_tile(tx, ty));
+        let index = ((j % TILE_SIZE) * self.tile_width(tx) + i % TILE_SIZE) * 3;
+
+        let max_value = self.max_value.load(Ordering::Relaxed) as f64;
+        let decode = |b: u8| decode_ppm_sample(b as f64, max_value);
+        Color::new(decode(tile[index]), decode(tile[index + 1]), decode(tile[index + 2]))
+    }
+
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!("{{ \"type\": \"image\", \"path\": {} }}", json::string(&self.path.display().to_string())))
+    }
+
+    // Not while a render is sampling the texture, which could see the old layout
+    // with the new pixels. A file caught half written fails to read and keeps the
+    // old image for another try.
+    fn reload_if_changed(&self) -> bool {
+        let now = modified(&self.path);
+        let mut modified = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+        if now.is_none() || now == *modified {
+            return false;
+        }
+        let (file, data_offset, width, height, max_value) = match read_header(&self.path) {
+            Ok(header) => header,
+            Err(e) => {
+                eprintln!("Couldn't reload {}: {e}", self.path.display());
+                return false;
+            }
+        };
+        *self.file.lock().unwrap_or_else(|e| e.into_inner()) = file;
+        self.data_offset.store(data_offset, Ordering::Relaxed);
+        self.width.store(width, Ordering::Relaxed);
+        self.height.store(height, Ordering::Relaxed);
+        self.max_value.store(max_value, Ordering::Relaxed);
+        self.id.store(self.cache.register(), Ordering::Relaxed);
+        *modified = now;
+        true
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use crate::obj;
+use crate::ply;
+use crate::scene_file::{self, MaterialOverride, Variable};
+use crate::scenes::Scene;
+use crate::texture::{SolidColor, VertexColorTexture};
+use crate::types::*;
+use crate::usd;
+
+// A way of making a scene from somewhere other than the built-in loaders: a
+// custom binary format, a database row, anything a string can name. Importers
+// are registered once, before any loading, with `register`, and `scenes::load`
+// and everything built on it (the command line included) then try them in turn.
+pub trait SceneImporter: Send + Sync {
+    // What it reads, for messages, such as "USD"
+    fn name(&self) -> &str;
+
+    // Whether this importer is the one for `source`, usually judged by its extension
+    fn can_import(&self, source: &str) -> bool;
+
+    fn import(&self, source: &str) -> Result<Scene, String>;
+
+    // Formats that name their materials or have variables override this; the rest
+    // can't do either
+    fn import_with_overrides(&self, source: &str, overrides: &[MaterialOverride], variables: &[Variable]) -> Result<Scene, String> {
+        if !overrides.is_empty() || !variables.is_empty() {
+            return Err(format!("{source}: {} scenes can't have their materials overridden or variables set", self.name()));
+        }
+        self.import(source)
+    }
+}
+
+// Registered importers, latest first, so a downstream crate can take over an
+// extension from one of the built-in ones
+static REGISTERED: RwLock<Vec<Arc<dyn SceneImporter>>> = RwLock::new(Vec::new());
+
+pub fn register(importer: Arc<dyn SceneImporter>) {
+    REGISTERED.write().unwrap_or_else(|e| e.into_inner()).insert(0, importer);
+}
+
+// The importer for `source`: the latest registered one that takes it, or else a
+// built-in one. None for names of built-in scenes.
+pub fn find(source: &str) -> Option<Arc<dyn SceneImporter>> {
+    let registered = REGISTERED.read().unwrap_or_else(|e| e.into_inner());
+    let builtin: [Arc<dyn SceneImporter>; 3] = [Arc::new(SceneFileImporter), Arc::new(UsdImporter), Arc::new(MeshImporter)];
+    registered.iter().cloned().chain(builtin).find(|importer| importer.can_import(source))
+}
+
+// The JSON scene files of `scene_file`
+pub struct SceneFileImporter;
+
+impl SceneImporter for SceneFileImporter {
+    fn name(&self) -> &str {
+        "scene file"
+    }
+
+    fn can_import(&self, source: &str) -> bool {
+        source.ends_with(".json")
+    }
+
+    fn import(&self, source: &str) -> Result<Scene, String> {
+        scene_file::load(Path::new(source), &[], &[])
+    }
+
+    fn import_with_overrides(&self, source: &str, overrides: &[MaterialOverride], variables: &[Variable]) -> Result<Scene, String> {
+        scene_file::load(Path::new(source), overrides, variables)
+    }
+}
+
+// USD's text layers, through `usd`
+pub struct UsdImporter;
+
+impl SceneImporter for UsdImporter {
+    fn name(&self) -> &str {
+        "USD"
+    }
+
+    fn can_import(&self, source: &str) -> bool {
+        source.ends_with(".usda")
+    }
+
+    fn import(&self, source: &str) -> Result<Scene, String> {
+        usd::load(Path::new(source))
+    }
+}
+
+// A lone model in an OBJ or PLY file, through `obj` and `ply`, made of grey clay
+// or its own vertex colors and framed by the default camera, so `turntable` and
+// `--studio` can show it off as it is
+pub struct MeshImporter;
+
+impl SceneImporter for MeshImporter {
+    fn name(&self) -> &str {
+        "mesh"
+    }
+
+    fn can_import(&self, source: &str) -> bool {
+        source.ends_with(".obj") || source.ends_with(".ply")
+    }
+
+    fn import(&self, source: &str) -> Result<Scene, String> {
+        let path = Path::new(source);
+        let mesh = if source.ends_with(".obj") { obj::load(path)? } else { ply::load(path)? };
+        let clay = Arc::new(SolidColor::new(Color::one() * 0.5));
+        let material = Arc::new(LambertianMaterial::with_texture(Arc::new(VertexColorTexture::new(clay))));
+        let mut world = HittableList::new();
+        world.add(Arc::new(mesh.into_mesh(material, (0.0, 1.0))));
+        let mut scene = Scene::new(world);
+        scene.auto_frame(scene_file::DEFAULT_FRAMING_MARGIN).map_err(|e| format!("{source}: {e}"))?;
+        Ok(scene)
+    }
+}
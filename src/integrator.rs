@@ -0,0 +1,355 @@
+use crate::lights;
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::scenes::Scene;
+use crate::types::*;
+
+const INFINITY: f64 = f64::INFINITY;
+// Keeps scattered rays from re-hitting the surface they start on
+pub const T_MIN: f64 = 0.0001;
+
+// Computes the radiance arriving along a camera ray. `depth` counts the bounces
+// still allowed; camera rays start with the integrator's own maximum.
+pub trait Integrator: Send + Sync {
+    fn li(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i64) -> Color;
+
+    // Like `li`, also recording each bounce of the path in `path`, for debugging a
+    // pixel. Integrators that don't follow paths record nothing.
+    fn li_traced(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i64, _path: &mut Vec<Bounce>) -> Color {
+        self.li(r, scene, sampler, depth)
+    }
+
+    fn max_depth(&self) -> i64;
+
+    // What `li` returns for a camera ray that hits nothing, so the renderer can skip
+    // tracing rays it knows will miss
+    fn miss(&self, r: Ray, scene: &Scene) -> Color {
+        scene.background.camera_color(r)
+    }
+}
+
+// One step of a recorded path: the ray that arrived and what it found
+pub struct Bounce {
+    pub ray: Ray,
+    // What the light found along `ray` is multiplied by on its way into the pixel
+    pub throughput: Color,
+    // None when the ray escaped to the background
+    pub hit: Option<BounceHit>,
+    // Light picked up here: the surface's emission, or the background's
+    pub emitted: Color,
+}
+
+pub struct BounceHit {
+    pub t: f64,
+    pub point: Point3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    pub material: &'static str,
+    // Light sampled straight from the lights, already weighted, and not counted
+    // in `emitted`
+    pub direct: Color,
+    // The ray the path carries on along, with its attenuation and the density it was
+    // drawn with (when the material has one); None when the light was absorbed
+    pub scattered: Option<(Ray, Color, Option<f64>)>,
+}
+
+impl Bounce {
+    fn hit(
+        ray: Ray,
+        throughput: Color,
+        rec: &HitRecord,
+        mat: &dyn Material,
+        emitted: Color,
+        direct: Color,
+        scattered: Option<(Ray, Color, Option<f64>)>,
+    ) -> Self {
+        let hit = BounceHit {
+            t: rec.t,
+            point: rec.p,
+            normal: rec.normal,
+            front_face: rec.front_face,
+            material: mat.name(),
+            direct,
+            scattered,
+        };
+        Bounce { ray, throughput, hit: Some(hit), emitted }
+    }
+}
+
+// Path tracing: follow one scattered ray per bounce until it escapes, is
+// absorbed, or runs out of depth. Where the material's density is known, each
+// bounce also samples a point on one of the scene's lights and traces a shadow ray
+// to it, the two ways of finding a light weighed against each other by multiple
+// importance sampling.
+pub struct PathTracer {
+    pub max_depth: i64,
+    // Once a path has bounced off a rough surface, smooth surfaces further along
+    // it are treated as at least this rough. Caustics through glass and mirrors
+    // are then found far more often at the cost of blurring them a little; 0
+    // leaves every path as it is.
+    pub regularization: f64,
+    // Rays going further than this, in scene units, see the background as if they
+    // had escaped; unlimited by default
+    pub max_distance: f64,
+    // Whether to sample the lights directly as well as follow scattered rays
+    pub light_sampling: bool,
+}
+
+// Where the path left a surface whose lights were sampled: the density the ray
+// leaving it was scattered with, for weighing whatever light the ray finds
+// against the chance of sampling it directly
+#[derive(Clone, Copy)]
+struct Sampled {
+    pdf: f64,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: i64) -> Self {
+        PathTracer { max_depth, regularization: 0.0, max_distance: INFINITY, light_sampling: true }
+    }
+
+    // Only following scattered rays, as a reference for light sampling to agree with
+    pub fn without_light_sampling(mut self) -> Self {
+        self.light_sampling = false;
+        self
+    }
+
+    pub fn with_regularization(mut self, roughness: f64) -> Self {
+        self.regularization = roughness;
+        self
+    }
+
+    pub fn with_max_distance(mut self, max_distance: f64) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    // `throughput` is what the radiance found along `r` is multiplied by on its way
+    // into the pixel, which only matters when the path is recorded. `rough` is
+    // whether the path has scattered off a rough surface yet, and `sampled` is set
+    // when `r` left a surface whose lights were sampled directly.
+    #[allow(clippy::too_many_arguments)]
+    fn trace(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        depth: i64,
+        throughput: Color,
+        rough: bool,
+        sampled: Option<Sampled>,
+        mut path: Option<&mut Vec<Bounce>>,
+    ) -> Color {
+        let mut rec = HitRecord::blank();
+
+        if depth <= 0 {
+            return Color::zero();
+        }
+
+        if scene.hit_sampled(r, Interval::new(T_MIN, self.max_distance / r.direction.length()), &mut rec, sampler) {
+            rec.set_footprint(r);
+
+            let mut scattered = Ray::new(Vec3::zero(), Vec3::zero());
+            let mut attenuation = Color::zero();
+            if let Some(mat) = rec.mat_ptr {
+                // Camera rays pass straight through emitters that are hidden from the camera
+                if depth == self.max_depth && !mat.is_visible_to_camera() {
+                    let through = Ray { origin: rec.p, cone: r.cone.advanced(rec.t), ..r };
+                    if let Some(path) = path.as_deref_mut() {
+                        let skipped = Color::zero();
+                        path.push(Bounce::hit(r, throughput, &rec, mat, skipped, skipped, Some((through, Color::one(), None))));
+                    }
+                    return self.trace(through, scene, sampler, depth, throughput, rough, sampled, path);
+                }
+
+                if rough {
+                    rec.min_roughness = self.regularization;
+                }
+                let mut emitted = mat.emitted(&rec);
+                if let Some(Sampled { pdf }) = sampled.filter(|_| !emitted.near_zero()) {
+                    if let Some(light_pdf) = scene.lights.pdf(&scene.world.objects, r, &rec) {
+                        emitted *= power_heuristic(pdf, light_pdf);
+                    }
+                }
+                let scatters = mat.scatter(r, &rec, &mut attenuation, &mut scattered, sampler);
+                scattered.origin = rec.scatter_origin(scattered.direction);
+                // Lights can only be sampled where the material's density is known,
+                // and only so far as a scattered ray could still reach them
+                let pdf = if scatters { mat.scattering_pdf(r, &rec, scattered) } else { None };
+                let sampling = pdf.filter(|_| self.light_sampling && depth >= 2 && !scene.lights.is_empty());
+                let direct = match sampling {
+                    Some(_) => self.sample_light(r, scene, &rec, mat, attenuation, sampler),
+                    None => Color::zero(),
+                };
+                if let Some(path) = path.as_deref_mut() {
+                    let onward = scatters.then_some((scattered, attenuation, pdf));
+                    path.push(Bounce::hit(r, throughput, &rec, mat, emitted, direct, onward));
+                }
+                if scatters {
+                    let rough = rough || (self.regularization > 0.0 && pdf.is_some());
+                    let sampled = sampling.map(|pdf| Sampled { pdf });
+                    let onward = self.trace(scattered, scene, sampler, depth - 1, throughput * attenuation, rough, sampled, path);
+                    return emitted + direct + attenuation * onward;
+                }
+                return emitted;
+            }
+            Color::zero()
+        } else {
+            let background = if depth == self.max_depth { self.miss(r, scene) } else { scene.background.color(r) };
+            if let Some(path) = path {
+                path.push(Bounce { ray: r, throughput, hit: None, emitted: background });
+            }
+            background
+        }
+    }
+
+    // The light reaching `rec` from a point picked on one of the scene's lights,
+    // weighed against the chance of `mat` scattering towards it by itself. Materials
+    // only give a density, so this takes their reflectance times cosine towards
+    // the light as `attenuation` times that density, which it is for those that
+    // scatter in proportion to it, as Lambertian ones and phase functions do.
+    fn sample_light(&self, r: Ray, scene: &Scene, rec: &HitRecord, mat: &dyn Material, attenuation: Color, sampler: &mut dyn Sampler) -> Color {
+        let (u, (su, sv)) = (sampler.get_1d(), sampler.get_2d());
+        let Some((index, chosen)) = scene.lights.pick(u) else {
+            return Color::zero();
+        };
+        let object = scene.world.objects[index].as_ref();
+        let Some(point) = object.material().and_then(|light| lights::emission_at(object, light, su, sv)) else {
+            return Color::zero();
+        };
+        let origin = rec.scatter_origin(point.p - rec.p);
+        let towards = point.p - origin;
+        let distance_squared = towards.length_squared();
+        let cos_light = point.normal.dot(towards) / distance_squared.sqrt();
+        let radiance = if cos_light < 0.0 { point.front } else { point.back };
+        if radiance.near_zero() || cos_light.abs() < 1e-8 || point.area <= 0.0 {
+            return Color::zero();
+        }
+        let shadow = Ray::new(origin, towards).with_time(r.time);
+        let Some(pdf) = mat.scattering_pdf(r, rec, shadow).filter(|&pdf| pdf > 0.0) else {
+            return Color::zero();
+        };
+        let light_pdf = chosen / point.area * distance_squared / cos_light.abs();
+        // Short of the light itself
+        let visible = scene.transmittance(shadow, Interval::new(T_MIN, 1.0 - T_MIN), sampler);
+        if visible.near_zero() {
+            return Color::zero();
+        }
+        attenuation * radiance * visible * (pdf / light_pdf * power_heuristic(light_pdf, pdf))
+    }
+}
+
+impl Integrator for PathTracer {
+    fn li(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i64) -> Color {
+        self.trace(r, scene, sampler, depth, Color::one(), false, None, None)
+    }
+
+    fn li_traced(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i64, path: &mut Vec<Bounce>) -> Color {
+        self.trace(r, scene, sampler, depth, Color::one(), false, None, Some(path))
+    }
+
+    fn max_depth(&self) -> i64 {
+        self.max_depth
+    }
+}
+
+// Veach's power heuristic, weighing a sample drawn with density `a` against the
+// other strategy's density `b` for the same direction
+fn power_heuristic(a: f64, b: f64) -> f64 {
+    if a.is_infinite() {
+        return 1.0;
+    }
+    a * a / (a * a + b * b)
+}
+
+// Only light that reaches the camera after at most one bounce: emission seen
+// directly plus the lights sampled from the first surface, and the emission (or
+// background) reached by a single scattered ray
+pub struct DirectLighting;
+
+impl Integrator for DirectLighting {
+    fn li(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i64) -> Color {
+        PathTracer::new(2).li(r, scene, sampler, depth.min(2))
+    }
+
+    fn li_traced(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i64, path: &mut Vec<Bounce>) -> Color {
+        PathTracer::new(2).li_traced(r, scene, sampler, depth.min(2), path)
+    }
+
+    fn max_depth(&self) -> i64 {
+        2
+    }
+}
+
+// Ambient occlusion: the fraction of cosine-weighted rays leaving the first hit
+// that travel `radius` without hitting anything, with transparent surfaces letting
+// their tint through
+pub struct AmbientOcclusion {
+    pub radius: f64,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn li(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, _depth: i64) -> Color {
+        let mut rec = HitRecord::blank();
+        if !scene.hit_sampled(r, Interval::new(T_MIN, INFINITY), &mut rec, sampler) {
+            return self.miss(r, scene);
+        }
+
+        let direction = Onb::from_normal(rec.normal).local_to_world(sampling::cosine_hemisphere(sampler.get_2d()));
+        let probe = Ray::new(rec.scatter_origin(direction), direction).with_time(r.time);
+        scene.transmittance(probe, Interval::new(T_MIN, self.radius / direction.length()), sampler)
+    }
+
+    fn max_depth(&self) -> i64 {
+        1
+    }
+
+    fn miss(&self, _r: Ray, _scene: &Scene) -> Color {
+        Color::one()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DebugView {
+    Normals,
+    Uv,
+}
+
+// Shows a surface attribute of the first hit instead of shading it
+pub struct DebugIntegrator {
+    pub view: DebugView,
+}
+
+impl Integrator for DebugIntegrator {
+    fn li(&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, _depth: i64) -> Color {
+        let mut rec = HitRecord::blank();
+        if !scene.hit_sampled(r, Interval::new(T_MIN, INFINITY), &mut rec, sampler) {
+            return self.miss(r, scene);
+        }
+
+        match self.view {
+            DebugView::Normals => (rec.normal.unit_vector() + Color::one()) * 0.5,
+            DebugView::Uv => Color::new(rec.u, rec.v, 0.0),
+        }
+    }
+
+    fn max_depth(&self) -> i64 {
+        1
+    }
+
+    fn miss(&self, _r: Ray, _scene: &Scene) -> Color {
+        Color::zero()
+    }
+}
+
+pub fn by_name(name: &str, max_depth: i64) -> Option<Box<dyn Integrator>> {
+    match name {
+        "path" => Some(Box::new(PathTracer::new(max_depth))),
+        "path-bsdf" => Some(Box::new(PathTracer::new(max_depth).without_light_sampling())),
+        "direct" => Some(Box::new(DirectLighting)),
+        "ao" => Some(Box::new(AmbientOcclusion { radius: 0.5 })),
+        "normals" => Some(Box::new(DebugIntegrator { view: DebugView::Normals })),
+        "uv" => Some(Box::new(DebugIntegrator { view: DebugView::Uv })),
+        _ => None,
+    }
+}
@@ -0,0 +1,380 @@
+use crate::types::Vec3;
+
+// A parsed JSON value. Objects keep their keys in file order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { bytes: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.bytes.len() {
+            return Err(parser.error("unexpected text after the end of the document"));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        let before = &self.bytes[..self.pos.min(self.bytes.len())];
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&b| b != b'\n').count() + 1;
+        format!("line {line}, column {column}: {message}")
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+        text.parse().map(Json::Number).map_err(|_| {
+            self.pos = start;
+            self.error(&format!("'{text}' isn't a number"))
+        })
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.bytes.get(self.pos..self.pos + 4).ok_or_else(|| self.error("truncated \\u escape"))?;
+        let value = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("bad \\u escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // A high surrogate must be followed by the low half of the pair;
+                            // anything else stands for itself after a replacement character
+                            if (0xd800..0xdc00).contains(&code) && self.bytes[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                } else {
+                                    out.extend_from_slice("\u{fffd}".as_bytes());
+                                    code = low;
+                                }
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    let mut buffer = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => out.push(byte),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+}
+
+// A number for a JSON file being written. JSON has no infinities or NaNs, so they
+// come out as null. Finite numbers are written as precisely as they're held.
+pub fn number(x: f64) -> String {
+    if x.is_finite() {
+        x.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn vector(v: Vec3) -> String {
+    format!("[{}, {}, {}]", number(v.x), number(v.y), number(v.z))
+}
+
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Json {
+        Json::parse(text).unwrap_or_else(|e| panic!("{}: {}", text, e))
+    }
+
+    fn error(text: &str) -> String {
+        match Json::parse(text) {
+            Ok(value) => panic!("{} parsed as {:?}", text, value),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn reads_string_escapes() {
+        let text = r#""quote \" backslash \\ slash \/ \b\f\n\r\t end""#;
+        assert_eq!(parse(text), Json::String("quote \" backslash \\ slash / \u{8}\u{c}\n\r\t end".to_string()));
+    }
+
+    #[test]
+    fn reads_unicode_escapes_and_surrogate_pairs() {
+        assert_eq!(parse(r#""\u0041\u00e9\u4E2D""#), Json::String("Aé中".to_string()));
+        assert_eq!(parse(r#""\ud83d\ude00""#), Json::String("😀".to_string()));
+        // A high surrogate on its own has no character to stand for
+        assert_eq!(parse(r#""\ud800""#), Json::String("\u{fffd}".to_string()));
+        assert_eq!(parse(r#""\ud800\u0041""#), Json::String("\u{fffd}A".to_string()));
+        // Text that isn't escaped passes through as it is
+        assert_eq!(parse("\"naïve ✓\""), Json::String("naïve ✓".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert!(error(r#""\x""#).contains("unknown escape"));
+        assert!(error(r#""\u12""#).contains("\\u escape"));
+        assert!(error(r#""\u12zz""#).contains("bad \\u escape"));
+        assert!(error(r#""open"#).contains("unterminated string"));
+    }
+
+    #[test]
+    fn reads_numbers_with_signs_fractions_and_exponents() {
+        for (text, expected) in [
+            ("0", 0.0),
+            ("-7", -7.0),
+            ("3.25", 3.25),
+            ("1e3", 1000.0),
+            ("2.5E-2", 0.025),
+            ("-1.5e+2", -150.0),
+            ("6.02214076e23", 6.02214076e23),
+        ] {
+            assert_eq!(parse(text), Json::Number(expected), "{text}");
+        }
+        assert!(error("-").contains("isn't a number"));
+        assert!(error("1e").contains("isn't a number"));
+        assert!(error("1.2.3").contains("'1.2.3' isn't a number"));
+        assert!(error("+1").contains("unexpected character"));
+    }
+
+    #[test]
+    fn reads_nested_values_in_order() {
+        let value = parse(r#" { "b": [1, [true, null], {}], "a": { "c": "d", "e": [] } } "#);
+        let fields = value.as_object().unwrap();
+        assert_eq!(fields.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["b", "a"]);
+        assert_eq!(
+            value.get("b"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Array(vec![Json::Bool(true), Json::Null]),
+                Json::Object(Vec::new()),
+            ]))
+        );
+        let inner = value.get("a").unwrap();
+        assert_eq!(inner.get("c").and_then(Json::as_str), Some("d"));
+        assert_eq!(inner.get("e").and_then(Json::as_array), Some(&[][..]));
+        assert_eq!(inner.get("missing"), None);
+    }
+
+    #[test]
+    fn rejects_trailing_text() {
+        assert_eq!(error("[1, 2] x"), "line 1, column 8: unexpected text after the end of the document");
+        assert!(error("{} {}").contains("unexpected text after the end of the document"));
+        assert!(error("truex").contains("unexpected text after the end of the document"));
+    }
+
+    #[test]
+    fn reports_where_errors_are() {
+        assert_eq!(error("{\n  \"a\": 1,\n  \"b\": x\n}"), "line 3, column 8: unexpected character");
+        assert_eq!(error("[1, 2"), "line 1, column 6: expected ',' or ']'");
+        assert_eq!(error("{\"a\" 1}"), "line 1, column 6: expected ':'");
+        assert_eq!(error("{1: 2}"), "line 1, column 2: expected a key");
+        assert_eq!(error("\n\n  "), "line 3, column 3: unexpected end of input");
+        // A bad number is reported where it starts
+        assert_eq!(error("[1, 2x]"), "line 1, column 6: expected ',' or ']'");
+        assert_eq!(error("[1, -x]"), "line 1, column 5: '-' isn't a number");
+    }
+
+    #[test]
+    fn written_strings_and_numbers_read_back() {
+        for s in ["plain", "quote \" and \\ backslash", "control \u{1} \n \t", "ünïcödé 中"] {
+            assert_eq!(parse(&string(s)), Json::String(s.to_string()));
+        }
+        for x in [0.0, -2.5, 1.0 / 3.0, 1e-300, 6.02e23] {
+            assert_eq!(parse(&number(x)), Json::Number(x));
+        }
+        assert_eq!(parse(&number(f64::INFINITY)), Json::Null);
+        assert_eq!(parse(&vector(Vec3::new(1.0, -2.0, 0.5))), parse("[1, -2, 0.5]"));
+    }
+}
@@ -0,0 +1,50 @@
+// The raytracer as a library; the command line tool in main.rs is built on it
+pub mod alloc_counter;
+pub mod analyze;
+pub mod animate;
+pub mod aov;
+pub mod bake;
+pub mod batch;
+pub mod bench;
+pub mod blueprint;
+pub mod bvh;
+pub mod chi_squared;
+pub mod compare;
+pub mod contact_sheet;
+pub mod exposure;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flare;
+pub mod furnace;
+pub mod grain;
+pub mod image_texture;
+pub mod importer;
+pub mod integrator;
+pub mod json;
+pub mod lights;
+pub mod metadata;
+pub mod obj;
+pub mod overscan;
+pub mod path_export;
+pub mod pixel_debug;
+pub mod ply;
+pub mod presets;
+pub mod preview;
+pub mod probe;
+pub mod ray_batch;
+pub mod render;
+pub mod sampler;
+pub mod sampling;
+pub mod scatter;
+pub mod scene_file;
+pub mod scenes;
+pub mod sdf;
+pub mod sppm;
+pub mod stereo;
+pub mod studio;
+pub mod texture;
+pub mod threads;
+pub mod transform;
+pub mod types;
+pub mod usd;
+pub mod wedge;
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use crate::exposure::luminance;
+use crate::types::*;
+
+// The step in u and v for measuring how much surface an emitter's UV layout
+// stretches over, and the grid of samples estimating each emitter's power
+const UV_STEP: f64 = 1e-4;
+const POWER_SAMPLES: usize = 8;
+
+// The emitters among a scene's top-level objects, for sampling light straight
+// from them. They need a UV layout to be sampled, as spheres, triangles and rects
+// have; lights nested inside groups are only found by the rays that hit them.
+#[derive(Default)]
+pub struct Lights {
+    // Each emitter's index among the objects, with its power, roughly
+    lights: Vec<(usize, f64)>,
+    total_power: f64,
+}
+
+impl Lights {
+    pub fn find(objects: &[Arc<dyn Hittable>]) -> Self {
+        let mut lights = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            let Some(material) = object.material().filter(|m| m.is_emitter()) else {
+                continue;
+            };
+            let power = power(object.as_ref(), material);
+            if power > 0.0 {
+                lights.push((index, power));
+            }
+        }
+        let total_power = lights.iter().map(|(_, power)| power).sum();
+        Lights { lights, total_power }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    // Picks an emitter in proportion to its power from a uniform sample `u`: its
+    // index among the objects and the chance of picking it
+    pub fn pick(&self, u: f64) -> Option<(usize, f64)> {
+        let mut left = u * self.total_power;
+        let &(index, power) = self
+            .lights
+            .iter()
+            .find(|(_, power)| {
+                left -= power;
+                left < 0.0
+            })
+            .or(self.lights.last())?;
+        Some((index, power / self.total_power))
+    }
+
+    // The density, per unit solid angle, of sampling the point `rec` found along `r`
+    // by picking an emitter and then a point on it, or None if the point isn't on
+    // one of the emitters
+    pub fn pdf(&self, objects: &[Arc<dyn Hittable>], r: Ray, rec: &HitRecord) -> Option<f64> {
+        let window = Interval::new(rec.t * (1.0 - 1e-6), rec.t * (1.0 + 1e-6));
+        self.lights.iter().find_map(|&(index, power)| {
+            let object = objects[index].as_ref();
+            let mut on = HitRecord::blank();
+            if !object.hit(r, window, &mut on) {
+                return None;
+            }
+            let area = area_at(object, on.u, on.v)?;
+            let cos = on.normal.dot(r.direction.unit_vector()).abs();
+            let distance_squared = (on.p - r.origin).length_squared();
+            Some(power / self.total_power / area * distance_squared / cos)
+        })
+    }
+}
+
+// A point on an emitting object's surface
+pub struct EmitterPoint {
+    pub p: Point3,
+    // Outward
+    pub normal: Vec3,
+    // The radiance it gives off from the front of its surface and from the back
+    pub front: Color,
+    pub back: Color,
+    // The area of surface per unit of uv there
+    pub area: f64,
+}
+
+// The point of an emitting object at (u, v)
+pub fn emission_at(object: &dyn Hittable, material: &dyn Material, u: f64, v: f64) -> Option<EmitterPoint> {
+    let (p, normal) = object.surface_at_uv(u, v)?;
+    let area = area_at(object, u, v)?;
+
+    let mut rec = HitRecord::blank();
+    rec.p = p;
+    rec.local_p = p;
+    rec.u = u;
+    rec.v = v;
+    rec.normal = normal;
+    rec.front_face = true;
+    let front = material.emitted(&rec);
+    rec.normal = -normal;
+    rec.front_face = false;
+    let back = material.emitted(&rec);
+    Some(EmitterPoint { p, normal, front, back, area })
+}
+
+// The area of the object's surface per unit of uv at (u, v)
+fn area_at(object: &dyn Hittable, u: f64, v: f64) -> Option<f64> {
+    let (p, _) = object.surface_at_uv(u, v)?;
+    // A step forward, or back where that would leave the layout
+    let derivative = |du: f64, dv: f64| {
+        object
+            .surface_at_uv(u + du, v + dv)
+            .map(|(q, _)| (q - p) / UV_STEP)
+            .or_else(|| object.surface_at_uv(u - du, v - dv).map(|(q, _)| (p - q) / UV_STEP))
+    };
+    Some(derivative(UV_STEP, 0.0)?.cross(derivative(0.0, UV_STEP)?).length())
+}
+
+// The luminous power an emitting object gives off, estimated on a grid over its
+// UV layout; 0 when it has none
+pub fn power(object: &dyn Hittable, material: &dyn Material) -> f64 {
+    let mut power = 0.0;
+    for n in 0..POWER_SAMPLES * POWER_SAMPLES {
+        let u = ((n % POWER_SAMPLES) as f64 + 0.5) / POWER_SAMPLES as f64;
+        let v = ((n / POWER_SAMPLES) as f64 + 0.5) / POWER_SAMPLES as f64;
+        if let Some(point) = emission_at(object, material, u, v) {
+            power += luminance(point.front + point.back) * point.area * PI;
+        }
+    }
+    power / (POWER_SAMPLES * POWER_SAMPLES) as f64
+}
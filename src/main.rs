@@ -1,112 +1,1259 @@
-mod types;
-use std::sync::Arc;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
 
-use rand::{thread_rng, Rng, prelude::ThreadRng};
-use types::*;
+use rust_raytracer::bvh::Aabb;
+use rust_raytracer::integrator::{self, Integrator};
+use rust_raytracer::render::{render, render_halves, render_view_to_ppm, Image, RenderSettings};
+use rust_raytracer::scenes::{self, Scene};
+use rust_raytracer::types::{Background, Color, Point3};
+use rust_raytracer::{alloc_counter, analyze, animate, aov, bake, batch, bench, blueprint, chi_squared, compare, contact_sheet, exposure, flare, furnace, grain, metadata, overscan, path_export, pixel_debug, presets, preview, probe, scene_file, sppm, stereo, studio, wedge};
 
-const INFINITY: f64 = f64::INFINITY;
-const PI: f64 = 3.1415926535897932385;
+#[global_allocator]
+static GLOBAL: alloc_counter::CountingAllocator = alloc_counter::CountingAllocator;
 
-fn degrees_to_radians(degrees: f64) -> f64 {
-    degrees * PI / 180.0
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
 }
 
-fn hit_sphere(center: Point3, radius: f64, ray: Ray) -> f64 {
-    let oc = ray.origin - center;
-    let a = ray.direction.length_squared();
-    let half_b = oc.dot(ray.direction);
-    let c = oc.length_squared() - radius * radius;
-    let discriminant = half_b * half_b - a * c;
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
 
-    if discriminant < 0.0 {
-        -1.0
-    } else {
-        (-half_b - discriminant.sqrt()) / a
+    // rust_raytracer render-batch <manifest> [--jobs N]
+    if args.peek().map(String::as_str) == Some("render-batch") {
+        args.next();
+        let manifest = args.next().unwrap_or_else(|| fail("render-batch needs a manifest path"));
+        let mut jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--jobs" => jobs = number(&mut args, "--jobs", "a positive number", |&n| n > 0),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+        if !batch::run(&manifest, jobs) {
+            std::process::exit(1);
+        }
+        return;
     }
-}
 
-fn ray_color(r: Ray, world: &dyn Hittable, depth: i64, rng: &mut ThreadRng) -> Color {
-    let mut rec = HitRecord::blank();
+    // rust_raytracer compare <reference.ppm> <test.ppm> [--heatmap diff.ppm]
+    if args.peek().map(String::as_str) == Some("compare") {
+        args.next();
+        let reference_path = args.next().unwrap_or_else(|| fail("compare needs two images"));
+        let test_path = args.next().unwrap_or_else(|| fail("compare needs two images"));
+        let mut heatmap_path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--heatmap" => heatmap_path = Some(args.next().unwrap_or_else(|| fail("--heatmap needs a path"))),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let open = |path: &str| Image::read_ppm(path).unwrap_or_else(|e| fail(&format!("Couldn't read {path}: {e}")));
+        let reference = open(&reference_path);
+        let test = open(&test_path);
+        if (reference.width, reference.height) != (test.width, test.height) {
+            fail("The images have different sizes");
+        }
+
+        let flip = compare::flip_map(&reference, &test);
+        println!("RMSE: {:.6}", compare::rmse(&reference, &test));
+        println!("SSIM: {:.6}", compare::ssim(&reference, &test));
+        println!("FLIP: {:.6}", flip.iter().sum::<f64>() / flip.len().max(1) as f64);
+
+        if let Some(path) = heatmap_path {
+            let heatmap = compare::heatmap(&flip, reference.width, reference.height);
+            let file = std::fs::File::create(&path).unwrap_or_else(|e| fail(&format!("Couldn't create {path}: {e}")));
+            heatmap.write_ppm(&mut BufWriter::new(file)).unwrap_or_else(|e| fail(&e.to_string()));
+        }
+        return;
+    }
+
+    // rust_raytracer furnace [--spp N]
+    if args.peek().map(String::as_str) == Some("furnace") {
+        args.next();
+        let mut samples = 64;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--spp" => samples = spp_flag(&mut args),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+        if !furnace::run(samples) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // rust_raytracer chi-squared [--samples N]
+    if args.peek().map(String::as_str) == Some("chi-squared") {
+        args.next();
+        let mut samples = 200_000;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--samples" => samples = number(&mut args, "--samples", "a positive number", |&n| n > 0),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+        if !chi_squared::run(samples) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // rust_raytracer bench [scene...] [--width N] [--spp N] [--seed N] [--frames N] [--threads N] [--output report.json]
+    if args.peek().map(String::as_str) == Some("bench") {
+        args.next();
+        let mut settings = bench::default_settings();
+        let mut frames = 3;
+        let mut output = None;
+        let mut names = Vec::new();
+        while let Some(arg) = args.next() {
+            if parse_render_settings(&arg, &mut args, &mut settings) {
+                continue;
+            }
+            match arg.as_str() {
+                "--frames" => frames = number(&mut args, "--frames", "a positive number", |&n| n > 0),
+                "--output" => output = Some(args.next().unwrap_or_else(|| fail("--output needs a path"))),
+                _ if arg.starts_with("--") => fail(&format!("Unknown option '{arg}'")),
+                _ => names.push(arg),
+            }
+        }
+        if names.is_empty() {
+            names = bench::SCENES.iter().map(|name| name.to_string()).collect();
+        }
+        let results = bench::run(&names, &settings, frames).unwrap_or_else(|e| fail(&e));
+        let written = match &output {
+            Some(path) => std::fs::File::create(path).and_then(|mut file| bench::write_json(&results, &settings, &mut file)),
+            None => bench::write_json(&results, &settings, &mut std::io::stdout()),
+        };
+        written.unwrap_or_else(|e| fail(&format!("Couldn't write the report: {e}")));
+        return;
+    }
+
+    // rust_raytracer snapshot <scene> <scene.json> [--seed N] [--set NAME=VALUE]...
+    if args.peek().map(String::as_str) == Some("snapshot") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("snapshot needs a scene name"));
+        let path = args.next().unwrap_or_else(|| fail("snapshot needs an output path"));
+        let mut seed = None;
+        let mut variables = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => seed = Some(seed_flag(&mut args)),
+                "--set" => {
+                    let spec = args.next().unwrap_or_else(|| fail("--set needs NAME=VALUE"));
+                    variables.push(scene_file::Variable::parse(&spec).unwrap_or_else(|e| fail(&format!("--set {spec}: {e}"))));
+                }
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+        let usual_seed = scenes::GENERATED.iter().find(|(name, _)| *name == scene_name).map(|&(_, seed)| seed);
+        let (scene, generated_from) = match (seed, usual_seed) {
+            (Some(seed), Some(_)) => (scenes::generate(&scene_name, seed).unwrap(), Some((scene_name.as_str(), seed))),
+            (Some(_), None) => fail(&format!("'{scene_name}' isn't made from random numbers, so it has no seed to set")),
+            (None, usual) => (
+                scenes::load_with_overrides(&scene_name, &[], &variables).unwrap_or_else(|e| fail(&e)),
+                usual.map(|seed| (scene_name.as_str(), seed)),
+            ),
+        };
+        scene_file::save(&scene, generated_from, &mut create(Path::new(&path))).unwrap_or_else(|e| fail(&e));
+        eprintln!("Saved {} objects to {path}", scene.world.objects.len());
+        return;
+    }
+
+    // rust_raytracer analyze <scene> [--integrator NAME]
+    if args.peek().map(String::as_str) == Some("analyze") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("analyze needs a scene name"));
+        let mut integrator_name = "path".to_string();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        let settings = RenderSettings::default_settings();
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        analyze::run(&scene_name, &scene, &integrator_name, integrator.as_ref(), &settings);
+        return;
+    }
+
+    // rust_raytracer preview <scene> <image.ppm> [--levels N] [--camera NAME] [--integrator NAME]
+    //                        [--width N] [--spp N] [--seed N] [--threads N] [--watch]
+    if args.peek().map(String::as_str) == Some("preview") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("preview needs a scene name"));
+        let path = PathBuf::from(args.next().unwrap_or_else(|| fail("preview needs an output path")));
+        let mut integrator_name = "path".to_string();
+        let mut camera_name = None;
+        let mut levels = 4;
+        let mut watch = false;
+        let mut settings = RenderSettings::default_settings();
+        while let Some(arg) = args.next() {
+            if parse_render_settings(&arg, &mut args, &mut settings) {
+                continue;
+            }
+            match arg.as_str() {
+                "--levels" => levels = number(&mut args, "--levels", "a number from 1 to 8", |n| (1..=8).contains(n)),
+                "--watch" => watch = true,
+                "--camera" => camera_name = Some(args.next().unwrap_or_else(|| fail("--camera needs a name"))),
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let mut scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        if let Some(name) = &camera_name {
+            scene.use_camera(name).unwrap_or_else(|e| fail(&e));
+        }
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        // Each rung replaces the last in one rename, so a viewer reloading the file
+        // never catches it half written
+        let partial = path.with_extension("partial.ppm");
+        let mut write_rung = |rung: usize, image: &Image| {
+            let mut out = create(&partial);
+            image
+                .write_ppm(&mut out)
+                .and_then(|_| out.flush())
+                .and_then(|_| fs::rename(&partial, &path))
+                .unwrap_or_else(|e| fail(&format!("Couldn't write {}: {e}", path.display())));
+            eprintln!("Level {} of {levels} written", rung + 1);
+            true
+        };
+        preview::render_ladder(&scene, &scene.camera, integrator.as_ref(), &settings, levels, &mut write_rung);
+        // Keeps going until interrupted, starting the ladder over from the coarsest
+        // rung whenever a texture file is saved
+        if !watch {
+            return;
+        }
+        loop {
+            thread::sleep(Duration::from_millis(250));
+            let reloaded = scene.reload_changed_textures();
+            if reloaded > 0 {
+                eprintln!("{reloaded} texture(s) changed, rendering again");
+                preview::render_ladder(&scene, &scene.camera, integrator.as_ref(), &settings, levels, &mut write_rung);
+            }
+        }
+    }
+
+    // rust_raytracer animate <scene> <prefix> [--frames N] [--orbit DEGREES] [--motion-vectors]
+    //                        [--integrator NAME] [--width N] [--spp N] [--seed N] [--threads N]
+    if args.peek().map(String::as_str) == Some("animate") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("animate needs a scene name"));
+        let prefix = args.next().unwrap_or_else(|| fail("animate needs an output prefix"));
+        let mut integrator_name = "path".to_string();
+        let mut settings = RenderSettings::default_settings();
+        let mut animation = animate::AnimationSettings { frames: 24, orbit: 30.0, motion_vectors: false };
+        while let Some(arg) = args.next() {
+            if parse_render_settings(&arg, &mut args, &mut settings) {
+                continue;
+            }
+            match arg.as_str() {
+                "--frames" => animation.frames = number(&mut args, "--frames", "a positive number", |&n| n > 0),
+                "--orbit" => animation.orbit = number(&mut args, "--orbit", "a number of degrees", |_| true),
+                "--motion-vectors" => animation.motion_vectors = true,
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        animate::run(&scene, integrator.as_ref(), &settings, &animation, &prefix).unwrap_or_else(|e| fail(&e));
+        return;
+    }
+
+    // rust_raytracer turntable <scene> <prefix> [--frames N] [--pitch DEGREES] [--margin X]
+    //                          [--integrator NAME] [--width N] [--spp N] [--seed N] [--threads N]
+    if args.peek().map(String::as_str) == Some("turntable") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("turntable needs a scene name"));
+        let prefix = args.next().unwrap_or_else(|| fail("turntable needs an output prefix"));
+        let mut integrator_name = "path".to_string();
+        let mut settings = RenderSettings::default_settings();
+        let mut studio_settings = studio::StudioSettings::new();
+        let mut frames = 120;
+        while let Some(arg) = args.next() {
+            if parse_render_settings(&arg, &mut args, &mut settings) {
+                continue;
+            }
+            match arg.as_str() {
+                "--frames" => frames = number(&mut args, "--frames", "a positive number", |&n| n > 0),
+                "--pitch" => studio_settings.pitch = number(&mut args, "--pitch", "a number of degrees", |_| true),
+                "--margin" => studio_settings.margin = number(&mut args, "--margin", "a number, at least 0.1", |&m| m >= 0.1),
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        studio::turntable(&scene, &studio_settings, integrator.as_ref(), &settings, frames, &prefix).unwrap_or_else(|e| fail(&e));
+        return;
+    }
+
+    // rust_raytracer contact-sheet <scene> [--integrator NAME] [--width N] [--spp N] [--seed N] [--threads N]
+    if args.peek().map(String::as_str) == Some("contact-sheet") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("contact-sheet needs a scene name"));
+        let mut integrator_name = "path".to_string();
+        // Small, noisy thumbnails are plenty for judging a framing
+        let mut settings = RenderSettings::with_width(160);
+        settings.samples_per_pixel = 16;
+        while let Some(arg) = args.next() {
+            if parse_render_settings(&arg, &mut args, &mut settings) {
+                continue;
+            }
+            match arg.as_str() {
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        let sheet = contact_sheet::render_contact_sheet(&scene, integrator.as_ref(), &settings);
+        sheet.write_ppm(&mut BufWriter::new(io::stdout().lock())).unwrap_or_else(|e| fail(&e.to_string()));
+        return;
+    }
+
+    // rust_raytracer wedge <scene.json> --vary NAME=FROM:TO:COUNT [--vary NAME=A,B,...] [--set NAME=VALUE]...
+    //                      [--integrator NAME] [--width N] [--spp N] [--seed N] [--threads N]
+    if args.peek().map(String::as_str) == Some("wedge") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("wedge needs a scene file"));
+        let mut integrator_name = "path".to_string();
+        let mut sweeps = Vec::new();
+        let mut variables = Vec::new();
+        let mut settings = RenderSettings::with_width(160);
+        settings.samples_per_pixel = 32;
+        // The same noise in every cell, so only the swept values tell them apart
+        settings.seed = Some(0);
+        while let Some(arg) = args.next() {
+            if parse_render_settings(&arg, &mut args, &mut settings) {
+                continue;
+            }
+            match arg.as_str() {
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                "--vary" => {
+                    let spec = args.next().unwrap_or_else(|| fail("--vary needs NAME=FROM:TO:COUNT"));
+                    sweeps.push(wedge::Sweep::parse(&spec).unwrap_or_else(|e| fail(&format!("--vary {spec}: {e}"))));
+                }
+                "--set" => {
+                    let spec = args.next().unwrap_or_else(|| fail("--set needs NAME=VALUE"));
+                    variables.push(scene_file::Variable::parse(&spec).unwrap_or_else(|e| fail(&format!("--set {spec}: {e}"))));
+                }
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        let sheet = wedge::render_wedge(&scene_name, &sweeps, &variables, integrator.as_ref(), &settings).unwrap_or_else(|e| fail(&e));
+        sheet.write_ppm(&mut BufWriter::new(io::stdout().lock())).unwrap_or_else(|e| fail(&e.to_string()));
+        return;
+    }
+
+    // rust_raytracer blueprint <scene> <drawing.ppm> [--view top|front|side] [--scale PIXELS_PER_UNIT | --width N]
+    //                          [--min X,Y,Z --max X,Y,Z] [--elevation elevation.pfm] [--threads N]
+    if args.peek().map(String::as_str) == Some("blueprint") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("blueprint needs a scene name"));
+        let path = PathBuf::from(args.next().unwrap_or_else(|| fail("blueprint needs an output path")));
+        let mut view = blueprint::BlueprintView::Top;
+        let mut scale = None;
+        let mut width = 800.0;
+        let mut min = None;
+        let mut max = None;
+        let mut elevation_path = None;
+        let mut settings = RenderSettings::default_settings();
+        while let Some(arg) = args.next() {
+            let mut point = |flag: &str| {
+                let list = args.next().unwrap_or_default();
+                let xyz: Vec<f64> = list.split(',').filter_map(|n| n.parse().ok()).collect();
+                if xyz.len() != 3 {
+                    fail(&format!("{flag} needs a point as X,Y,Z"));
+                }
+                Point3::new(xyz[0], xyz[1], xyz[2])
+            };
+            match arg.as_str() {
+                "--min" => min = Some(point("--min")),
+                "--max" => max = Some(point("--max")),
+                "--view" => {
+                    let name = args.next().unwrap_or_default();
+                    view = blueprint::BlueprintView::by_name(&name)
+                        .unwrap_or_else(|| fail(&format!("Unknown view '{name}', expected top, front or side")));
+                }
+                "--scale" => scale = Some(number(&mut args, "--scale", "a positive number of pixels per unit", |&p| p > 0.0)),
+                "--width" => width = number::<u16>(&mut args, "--width", "a positive number of pixels", |&w| w > 0) as f64,
+                "--elevation" => elevation_path = Some(PathBuf::from(args.next().unwrap_or_else(|| fail("--elevation needs a path")))),
+                "--threads" => settings.threads = threads_flag(&mut args),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        // Without --min and --max, everything in the scene
+        let bounds = scene.bvh.bounds();
+        let region = match (min, max, bounds) {
+            (Some(min), Some(max), _) => Aabb::new(min, max),
+            (None, None, Some(bounds)) => bounds,
+            (None, None, None) => fail(&format!("'{scene_name}' goes on forever, so give the region to draw with --min and --max")),
+            _ => fail("--min and --max go together"),
+        };
+        if !(region.min.x < region.max.x && region.min.y < region.max.y && region.min.z < region.max.z) {
+            fail("The region needs --max above --min along every axis");
+        }
+        let (right, up, _) = view.axes();
+        let (across, along) = ((region.max - region.min).dot(right).abs(), (region.max - region.min).dot(up).abs());
+        let pixels_per_unit = scale.unwrap_or(width / across);
+        if across.max(along) * pixels_per_unit > u16::MAX as f64 {
+            fail("That scale makes the drawing too big; lower --scale");
+        }
+        let drawing = blueprint::render(&scene, view, region, pixels_per_unit, &settings);
+        drawing
+            .drawing
+            .write_ppm_with_comments(&mut create(&path), &drawing.comments())
+            .unwrap_or_else(|e| fail(&format!("Couldn't write {}: {e}", path.display())));
+        if let Some(elevation_path) = &elevation_path {
+            drawing
+                .elevation
+                .write_pfm(&mut create(elevation_path))
+                .unwrap_or_else(|e| fail(&format!("Couldn't write {}: {e}", elevation_path.display())));
+        }
+        eprintln!(
+            "Drew the {} view at {} pixels per unit ({}x{}), the grid every {} units",
+            view.name(),
+            pixels_per_unit,
+            drawing.drawing.width,
+            drawing.drawing.height,
+            drawing.grid
+        );
+        return;
+    }
 
-    if depth <= 0 {
-        return Color::zero();
+    // rust_raytracer bake <scene> <lightmap.ppm|lightmap.pfm> [--object N]... [--mode irradiance|full] [--size N]
+    //                     [--spp N] [--integrator NAME] [--seed N] [--threads N]
+    if args.peek().map(String::as_str) == Some("bake") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("bake needs a scene name"));
+        let path = args.next().unwrap_or_else(|| fail("bake needs an output path ending in .ppm or .pfm"));
+        let mut integrator_name = "path".to_string();
+        let mut mode = bake::BakeMode::Irradiance;
+        let mut objects = Vec::new();
+        let mut size = 256;
+        let mut settings = RenderSettings::default_settings();
+        settings.samples_per_pixel = 64;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--object" => objects.push(number(&mut args, "--object", "an object number", |_| true)),
+                "--size" => size = number(&mut args, "--size", "a positive number", |&n| n > 0),
+                "--spp" => settings.samples_per_pixel = spp_flag(&mut args),
+                "--seed" => settings.seed = Some(seed_flag(&mut args)),
+                "--threads" => settings.threads = threads_flag(&mut args),
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                "--mode" => {
+                    let name = args.next().unwrap_or_default();
+                    mode = bake::BakeMode::by_name(&name).unwrap_or_else(|| fail(&format!("Unknown bake mode '{name}'")));
+                }
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+        let pfm = match Path::new(&path).extension().and_then(|e| e.to_str()) {
+            Some("ppm") => false,
+            Some("pfm") => true,
+            _ => fail("bake writes .ppm or .pfm files"),
+        };
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        // Without --object, everything that has a UV layout
+        let every = objects.is_empty();
+        if every {
+            objects = (0..scene.world.objects.len()).filter(|&i| scene.world.objects[i].surface_at_uv(0.5, 0.5).is_some()).collect();
+        }
+        if objects.is_empty() {
+            fail(&format!("Nothing in '{scene_name}' has a UV layout to bake into"));
+        }
+        for &index in &objects {
+            let object = scene.world.objects.get(index).unwrap_or_else(|| {
+                fail(&format!("There's no object {index}; '{scene_name}' has {}", scene.world.objects.len()))
+            });
+            let lightmap = bake::bake(&scene, object.as_ref(), mode, integrator.as_ref(), &settings, size)
+                .unwrap_or_else(|| fail(&format!("Object {index} (a {}) has no UV layout to bake into", object.kind())));
+            let tag = index.to_string();
+            let out_path = tagged_path(&path, (every || objects.len() > 1).then_some(tag.as_str()));
+            let written = if pfm {
+                aov::FloatImage::from_image(&lightmap).write_pfm(&mut create(&out_path))
+            } else {
+                lightmap.write_ppm(&mut create(&out_path))
+            };
+            written.unwrap_or_else(|e| fail(&format!("Couldn't write {}: {e}", out_path.display())));
+            eprintln!("Baked object {index} (a {}) into {}", object.kind(), out_path.display());
+        }
+        return;
     }
 
-    if world.hit(r, 0.0001, INFINITY, &mut rec) {
+    // rust_raytracer probes <scene> <cubemap.ppm|cubemap.pfm> --at X,Y,Z [--at X,Y,Z]... [--size N] [--spp N]
+    //                       [--integrator NAME] [--seed N] [--threads N]
+    if args.peek().map(String::as_str) == Some("probes") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("probes needs a scene name"));
+        let path = args.next().unwrap_or_else(|| fail("probes needs an output path ending in .ppm or .pfm"));
+        let mut integrator_name = "path".to_string();
+        let mut positions = Vec::new();
+        let mut size = 64;
+        let mut settings = RenderSettings::default_settings();
+        settings.samples_per_pixel = 16;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--at" => {
+                    let list = args.next().unwrap_or_default();
+                    let xyz: Vec<f64> = list.split(',').filter_map(|n| n.parse().ok()).collect();
+                    if xyz.len() != 3 {
+                        fail("--at needs a point as X,Y,Z");
+                    }
+                    positions.push(Point3::new(xyz[0], xyz[1], xyz[2]));
+                }
+                "--size" => size = number(&mut args, "--size", "a positive number", |&n| n > 0),
+                "--spp" => settings.samples_per_pixel = spp_flag(&mut args),
+                "--seed" => settings.seed = Some(seed_flag(&mut args)),
+                "--threads" => settings.threads = threads_flag(&mut args),
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                _ => fail(&format!("Unknown option '{arg}'")),
+            }
+        }
+        if positions.is_empty() {
+            fail("probes needs at least one --at X,Y,Z");
+        }
+        let pfm = match Path::new(&path).extension().and_then(|e| e.to_str()) {
+            Some("ppm") => false,
+            Some("pfm") => true,
+            _ => fail("probes writes .ppm or .pfm cubemaps"),
+        };
+
+        let scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        let mut probes = Vec::new();
+        for (index, &position) in positions.iter().enumerate() {
+            let probe = probe::bake_probe(&scene, integrator.as_ref(), &settings, position, size);
+            let tag = index.to_string();
+            let out_path = tagged_path(&path, (positions.len() > 1).then_some(tag.as_str()));
+            let written = if pfm {
+                aov::FloatImage::from_image(&probe.cubemap).write_pfm(&mut create(&out_path))
+            } else {
+                probe.cubemap.write_ppm(&mut create(&out_path))
+            };
+            written.unwrap_or_else(|e| fail(&format!("Couldn't write {}: {e}", out_path.display())));
+            eprintln!("Baked the probe at ({}, {}, {}) into {}", position.x, position.y, position.z, out_path.display());
+            let file_name = out_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            probes.push((probe, file_name));
+        }
+        let json_path = Path::new(&path).with_extension("json");
+        probe::write_json(&probes, &mut create(&json_path))
+            .unwrap_or_else(|e| fail(&format!("Couldn't write {}: {e}", json_path.display())));
+        return;
+    }
 
-        let mut scattered = Ray { origin: Vec3::zero(), direction: Vec3::zero() };
-        let mut attenuation = Color::zero();
-        if let Some(ref mat) = rec.mat_ptr {
-            if mat.scatter(r, &rec, &mut attenuation, &mut scattered, rng) {
-                return attenuation * ray_color(scattered, world, depth - 1, rng)
+    // rust_raytracer paths <scene> <paths.obj|paths.svg> [--pixel X,Y | --region X,Y,W,H] [--spp N]
+    //                      [--integrator NAME] [--camera NAME] [--seed N]
+    if args.peek().map(String::as_str) == Some("paths") {
+        args.next();
+        let scene_name = args.next().unwrap_or_else(|| fail("paths needs a scene name"));
+        let path = args.next().unwrap_or_else(|| fail("paths needs an output path ending in .obj or .svg"));
+        let mut integrator_name = "path".to_string();
+        let mut camera_name = None;
+        let mut settings = RenderSettings::default_settings();
+        // A few paths per pixel are plenty to look at
+        settings.samples_per_pixel = 8;
+        let mut region = (settings.image_width / 2, settings.image_height / 2, 1, 1);
+        while let Some(arg) = args.next() {
+            let mut numbers = |flag: &str, count: usize| -> Vec<u16> {
+                let list = args.next().unwrap_or_default();
+                let numbers: Vec<u16> = list.split(',').filter_map(|n| n.parse().ok()).collect();
+                if numbers.len() != count {
+                    fail(&format!("{flag} needs {count} comma-separated whole numbers"));
+                }
+                numbers
+            };
+            match arg.as_str() {
+                "--pixel" => {
+                    let pixel = numbers("--pixel", 2);
+                    region = (pixel[0], pixel[1], 1, 1);
+                }
+                "--region" => {
+                    let block = numbers("--region", 4);
+                    region = (block[0], block[1], block[2].max(1), block[3].max(1));
+                }
+                "--spp" => settings.samples_per_pixel = spp_flag(&mut args),
+                "--integrator" => integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name")),
+                "--camera" => camera_name = Some(args.next().unwrap_or_else(|| fail("--camera needs a name"))),
+                "--seed" => settings.seed = Some(seed_flag(&mut args)),
+                _ => fail(&format!("Unknown option '{arg}'")),
             }
         }
-        Color::zero()
-    } else {
-        let unit_direction = r.direction.unit_vector();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        Color::one() * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+        let (x, y, width, height) = region;
+        if x as u32 + width as u32 > settings.image_width as u32 || y as u32 + height as u32 > settings.image_height as u32 {
+            fail(&format!("The pixels asked for run outside the {}x{} image", settings.image_width, settings.image_height));
+        }
+
+        let mut scene = scenes::load(&scene_name).unwrap_or_else(|e| fail(&e));
+        if let Some(name) = camera_name {
+            scene.use_camera(&name).unwrap_or_else(|e| fail(&e));
+        }
+        let integrator = integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'")));
+        let traces = pixel_debug::trace_region(&scene, integrator.as_ref(), &settings, region);
+        let file = File::create(&path).unwrap_or_else(|e| fail(&format!("Couldn't create {path}: {e}")));
+        let mut out = BufWriter::new(file);
+        let written = match Path::new(&path).extension().and_then(|e| e.to_str()) {
+            Some("obj") => path_export::write_obj(&traces, &mut out),
+            Some("svg") => path_export::write_svg(&traces, &scene, &mut out),
+            _ => fail("paths writes .obj or .svg files"),
+        };
+        written.unwrap_or_else(|e| fail(&format!("Couldn't write {path}: {e}")));
+        return;
+    }
+
+    // rust_raytracer [scene] [--camera NAME|all] [--output image.ppm] [--integrator path|path-bsdf|direct|ao|normals|uv|sppm|hybrid]
+    //                [--stereo side-by-side|top-bottom [--interaxial X] [--convergence X]]
+    //                [--depth depth.pfm [--depth-range NEAR,FAR]]
+    //                [--segmentation seg.ppm [--segment-by material|object]] [--position p.pfm]
+    //                [--override-material NAME=preset:PRESET]... [--set NAME=VALUE]... [--clay] [--opaque-shadows]
+    //                [--studio [--studio-yaw DEGREES] [--studio-pitch DEGREES]]
+    //                [--import-camera camera.json]... [--auto-frame] [--layers LAYER,!LAYER,...] [--ambient INTENSITY]
+    //                [--seed N] [--overscan PERCENT] [--exposure STOPS] [--auto-exposure] [--exposure-report stats.json]
+    //                [--flare] [--flare-threshold X] [--flare-strength X] [--flare-spokes N] [--flare-ghosts N]
+    //                [--grain film|sensor] [--grain-intensity X] [--grain-size N] [--grain-seed N]
+    //                [--halves halves.pfm] [--regularize ROUGHNESS] [--clamp MAX] [--max-distance D] [--rolling-shutter T]
+    //                [--photons N] [--photon-radius R] [--metadata info.json]
+    //                [--debug-pixel X,Y] [--threads N] [--pin-threads] [--low-priority] [--width N] [--spp N] [--stream]
+    let mut scene_name = "test".to_string();
+    let mut integrator_name = "path".to_string();
+    let mut camera_name = None;
+    let mut overscan_percent = None;
+    let mut overrides = Vec::new();
+    let mut variables = Vec::new();
+    let mut imported_cameras = Vec::new();
+    let mut clay = false;
+    let mut auto_frame = false;
+    let mut ambient = None;
+    let mut layers = None;
+    let mut studio_settings = None;
+    let mut opaque_shadows = false;
+    let mut seed = None;
+    let mut threads = None;
+    let mut pin_threads = false;
+    let mut low_priority = false;
+    let mut width = None;
+    let mut samples = None;
+    let mut debug_pixel = None;
+    let mut regularization = None;
+    let mut max_distance = None;
+    let mut rolling_shutter = None;
+    let mut max_sample_value = None;
+    let mut photons = None;
+    let mut photon_radius = None;
+    let mut options = RenderOptions {
+        output: None,
+        stereo_layout: None,
+        interaxial: None,
+        convergence: None,
+        depth_path: None,
+        depth_range: None,
+        segmentation_path: None,
+        segment_by: aov::SegmentBy::Material,
+        position_path: None,
+        overscan: None,
+        exposure: 0.0,
+        auto_exposure: false,
+        exposure_report: None,
+        flare: None,
+        grain: None,
+        halves_path: None,
+        sppm: None,
+        hybrid: false,
+        stream: false,
+        metadata: metadata::RenderMetadata::new("", ""),
+        metadata_path: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--integrator" => {
+                integrator_name = args.next().unwrap_or_else(|| fail("--integrator needs a name"));
+            }
+            "--camera" => camera_name = Some(args.next().unwrap_or_else(|| fail("--camera needs a name"))),
+            "--override-material" => {
+                let spec = args.next().unwrap_or_else(|| fail("--override-material needs NAME=preset:PRESET"));
+                overrides.push(
+                    scene_file::MaterialOverride::parse(&spec)
+                        .unwrap_or_else(|e| fail(&format!("--override-material {spec}: {e}"))),
+                );
+            }
+            "--set" => {
+                let spec = args.next().unwrap_or_else(|| fail("--set needs NAME=VALUE"));
+                variables.push(scene_file::Variable::parse(&spec).unwrap_or_else(|e| fail(&format!("--set {spec}: {e}"))));
+            }
+            "--import-camera" => {
+                let path = args.next().unwrap_or_else(|| fail("--import-camera needs a path"));
+                imported_cameras.extend(scene_file::load_cameras(Path::new(&path)).unwrap_or_else(|e| fail(&e)));
+            }
+            "--clay" => clay = true,
+            "--auto-frame" => auto_frame = true,
+            "--ambient" => {
+                ambient = Some(number(&mut args, "--ambient", "an intensity, at least 0", |&n| n >= 0.0));
+            }
+            "--layers" => {
+                let spec = args.next().unwrap_or_else(|| fail("--layers needs a list of layers"));
+                layers = Some(scenes::LayerSelection::parse(&spec).unwrap_or_else(|e| fail(&format!("--layers {spec}: {e}"))));
+            }
+            "--studio" => {
+                studio_settings.get_or_insert_with(studio::StudioSettings::new);
+            }
+            "--studio-yaw" | "--studio-pitch" => {
+                let degrees: f64 = number(&mut args, &arg, "a number of degrees", |_| true);
+                let settings = studio_settings.get_or_insert_with(studio::StudioSettings::new);
+                if arg == "--studio-yaw" { settings.yaw = degrees } else { settings.pitch = degrees }
+            }
+            "--opaque-shadows" => opaque_shadows = true,
+            "--seed" => seed = Some(seed_flag(&mut args)),
+            "--threads" => threads = Some(threads_flag(&mut args)),
+            "--pin-threads" => pin_threads = true,
+            "--low-priority" => low_priority = true,
+            "--overscan" => {
+                overscan_percent = Some(number(&mut args, "--overscan", "a percentage", |&p| p >= 0.0));
+            }
+            "--exposure" => {
+                options.exposure = number(&mut args, "--exposure", "a number of stops", |_| true);
+            }
+            "--auto-exposure" => options.auto_exposure = true,
+            "--exposure-report" => {
+                options.exposure_report = Some(args.next().unwrap_or_else(|| fail("--exposure-report needs a path")));
+            }
+            // Any of the --flare settings turns the flare on
+            "--flare" => {
+                options.flare.get_or_insert_with(flare::FlareSettings::new);
+            }
+            "--flare-threshold" | "--flare-strength" => {
+                let value = number(&mut args, &arg, "a number, at least 0", |&n| n >= 0.0);
+                let flare = options.flare.get_or_insert_with(flare::FlareSettings::new);
+                if arg == "--flare-threshold" {
+                    flare.threshold = value;
+                } else {
+                    flare.strength = value;
+                }
+            }
+            "--flare-spokes" | "--flare-ghosts" => {
+                let count = number(&mut args, &arg, "a count", |_| true);
+                let flare = options.flare.get_or_insert_with(flare::FlareSettings::new);
+                if arg == "--flare-spokes" {
+                    flare.spokes = count;
+                } else {
+                    flare.ghosts = count;
+                }
+            }
+            // As with the flare, any of the --grain settings turns grain on, film grain
+            // unless --grain says otherwise
+            "--grain" => {
+                let name = args.next().unwrap_or_default();
+                let model = grain::GrainModel::by_name(&name).unwrap_or_else(|| fail("--grain needs film or sensor"));
+                options.grain.get_or_insert_with(|| grain::GrainSettings::new(model)).model = model;
+            }
+            "--grain-intensity" => {
+                let intensity = number(&mut args, "--grain-intensity", "a number, at least 0", |&n| n >= 0.0);
+                options.grain.get_or_insert_with(|| grain::GrainSettings::new(grain::GrainModel::Film)).intensity = intensity;
+            }
+            "--grain-size" => {
+                let size = number(&mut args, "--grain-size", "a positive number of pixels", |&n| n > 0);
+                options.grain.get_or_insert_with(|| grain::GrainSettings::new(grain::GrainModel::Film)).size = size;
+            }
+            "--grain-seed" => {
+                let seed = number(&mut args, "--grain-seed", "a number", |_| true);
+                options.grain.get_or_insert_with(|| grain::GrainSettings::new(grain::GrainModel::Film)).seed = seed;
+            }
+            "--output" => options.output = Some(args.next().unwrap_or_else(|| fail("--output needs a path"))),
+            "--stream" => options.stream = true,
+            "--width" => width = Some(width_flag(&mut args)),
+            "--spp" => samples = Some(spp_flag(&mut args)),
+            "--stereo" => {
+                let layout = args.next().unwrap_or_else(|| fail("--stereo needs a layout"));
+                options.stereo_layout = Some(
+                    stereo::StereoLayout::by_name(&layout)
+                        .unwrap_or_else(|| fail(&format!("Unknown stereo layout '{layout}'"))),
+                );
+            }
+            "--interaxial" => options.interaxial = Some(distance_flag(&mut args, "--interaxial")),
+            "--convergence" => options.convergence = Some(distance_flag(&mut args, "--convergence")),
+            "--depth" => options.depth_path = Some(args.next().unwrap_or_else(|| fail("--depth needs a path"))),
+            "--depth-range" => {
+                let range = args.next().unwrap_or_default();
+                options.depth_range = Some(
+                    range
+                        .split_once(',')
+                        .and_then(|(near, far)| Some((near.parse::<f64>().ok()?, far.parse::<f64>().ok()?)))
+                        .filter(|(near, far)| far > near)
+                        .unwrap_or_else(|| fail("--depth-range needs NEAR,FAR with FAR > NEAR")),
+                );
+            }
+            "--debug-pixel" => {
+                let pixel = args.next().unwrap_or_default();
+                debug_pixel = Some(
+                    pixel
+                        .split_once(',')
+                        .and_then(|(x, y)| Some((x.parse::<u16>().ok()?, y.parse::<u16>().ok()?)))
+                        .unwrap_or_else(|| fail("--debug-pixel needs X,Y")),
+                );
+            }
+            "--segmentation" => {
+                options.segmentation_path = Some(args.next().unwrap_or_else(|| fail("--segmentation needs a path")));
+            }
+            "--regularize" => {
+                regularization = Some(number(&mut args, "--regularize", "a roughness from 0 to 1", |r| (0.0..=1.0).contains(r)));
+            }
+            "--max-distance" => max_distance = Some(distance_flag(&mut args, "--max-distance")),
+            "--rolling-shutter" => rolling_shutter = Some(number(&mut args, "--rolling-shutter", "a readout time, at least 0", |&t| t >= 0.0)),
+            "--photons" => {
+                photons = Some(number(&mut args, "--photons", "a positive number", |&n| n > 0));
+            }
+            "--photon-radius" => photon_radius = Some(distance_flag(&mut args, "--photon-radius")),
+            "--clamp" => {
+                max_sample_value = Some(number(&mut args, "--clamp", "a positive number", |&n| n > 0.0));
+            }
+            "--halves" => options.halves_path = Some(args.next().unwrap_or_else(|| fail("--halves needs a path"))),
+            "--metadata" => options.metadata_path = Some(args.next().unwrap_or_else(|| fail("--metadata needs a path"))),
+            "--position" => options.position_path = Some(args.next().unwrap_or_else(|| fail("--position needs a path"))),
+            "--segment-by" => {
+                let by = args.next().unwrap_or_default();
+                options.segment_by =
+                    aov::SegmentBy::by_name(&by).unwrap_or_else(|| fail("--segment-by needs material or object"));
+            }
+            _ if arg.starts_with("--") => fail(&format!("Unknown option '{arg}'")),
+            _ => scene_name = arg,
+        }
+    }
+
+    let has_passes = options.depth_path.is_some() || options.position_path.is_some() || options.segmentation_path.is_some();
+    if options.stream && (options.stereo_layout.is_some() || has_passes) {
+        fail("--stream writes only the image, so it can't be combined with --stereo or extra passes");
+    }
+    if options.halves_path.is_some() && (options.stream || options.stereo_layout.is_some()) {
+        fail("--halves can't be combined with --stream or --stereo");
+    }
+    if options.stream && (options.flare.is_some() || options.grain.is_some()) {
+        fail("--flare and --grain need the whole image, so they can't be combined with --stream");
+    }
+
+    let mut scene = scenes::load_with_overrides(&scene_name, &overrides, &variables).unwrap_or_else(|e| fail(&e));
+    if let Some(selection) = &layers {
+        scene.select_layers(selection);
+    }
+    if let Some(settings) = &studio_settings {
+        scene = studio::studio(&scene, settings).unwrap_or_else(|e| fail(&e));
+    }
+    // Imported cameras join the scene's, and the first of them is looked through
+    // unless --camera says otherwise
+    for (name, camera) in &imported_cameras {
+        scene.add_camera(name, *camera);
+    }
+    if let Some((_, camera)) = imported_cameras.first() {
+        scene.camera = *camera;
+    }
+    if auto_frame {
+        scene.auto_frame(scene_file::DEFAULT_FRAMING_MARGIN).unwrap_or_else(|e| fail(&format!("--auto-frame: {e}")));
+    }
+    // Every camera reads out the same way, whichever is looked through
+    if let Some(readout) = rolling_shutter {
+        scene.camera = scene.camera.with_rolling_shutter(readout);
+        for (_, camera) in &mut scene.cameras {
+            *camera = camera.with_rolling_shutter(readout);
+        }
+    }
+    if let Some(intensity) = ambient {
+        scene.background = Background::Constant { color: Color::one(), intensity, visible_to_camera: true };
+    }
+    if clay {
+        scene.material_override = presets::by_name("clay");
+    }
+    scene.opaque_shadows = opaque_shadows;
+    let mut frame = RenderSettings::default_settings();
+    if let Some(width) = width {
+        frame.set_width(width);
+    }
+    frame.samples_per_pixel = samples.unwrap_or(frame.samples_per_pixel);
+    frame.seed = seed;
+    frame.threads = threads.unwrap_or(frame.threads);
+    frame.pin_threads = pin_threads;
+    frame.low_priority = low_priority;
+    frame.max_sample_value = max_sample_value;
+    options.overscan = overscan_percent.map(|percent| overscan::Overscan::new(&frame, percent));
+    let settings = options.overscan.map_or(frame, |overscan| overscan.settings(&frame));
+    if options.halves_path.is_some() && settings.samples_per_pixel < 2 {
+        fail("--halves needs at least 2 samples per pixel, one for each half");
+    }
+    if regularization.is_some() && integrator_name != "path" {
+        fail("--regularize only applies to the path integrator");
+    }
+    if integrator_name == "sppm" || integrator_name == "hybrid" {
+        if options.stream || options.stereo_layout.is_some() || options.halves_path.is_some() || debug_pixel.is_some() {
+            fail(&format!(
+                "--integrator {integrator_name} renders the whole image at once, so it can't be combined with --stream, --stereo, --halves or --debug-pixel"
+            ));
+        }
+        options.sppm = Some(sppm::SppmSettings { photons_per_pass: photons, initial_radius: photon_radius });
+        options.hybrid = integrator_name == "hybrid";
+    } else if photons.is_some() || photon_radius.is_some() {
+        fail("--photons and --photon-radius only apply to the sppm and hybrid integrators");
+    }
+    let integrator: Box<dyn Integrator> = match (integrator_name.as_str(), max_distance) {
+        ("path" | "path-bsdf", _) if regularization.is_some() || max_distance.is_some() => {
+            let path = integrator::PathTracer::new(settings.max_depth)
+                .with_regularization(regularization.unwrap_or(0.0))
+                .with_max_distance(max_distance.unwrap_or(f64::INFINITY));
+            Box::new(if integrator_name == "path" { path } else { path.without_light_sampling() })
+        }
+        // How far the occlusion rays look is ambient occlusion's own distance limit
+        ("ao", Some(radius)) => Box::new(integrator::AmbientOcclusion { radius }),
+        // SPPM renders the image itself; the path tracer, which sees the same light
+        // bar the caustics it can't find, meters the exposure for it
+        // and the hybrid path traces what isn't caustics with it
+        ("sppm" | "hybrid", None) => Box::new(integrator::PathTracer::new(settings.max_depth)),
+        (_, Some(_)) => fail("--max-distance only applies to the path, path-bsdf and ao integrators"),
+        _ => integrator::by_name(&integrator_name, settings.max_depth)
+            .unwrap_or_else(|| fail(&format!("Unknown integrator '{integrator_name}'"))),
+    };
+
+    options.metadata = metadata::RenderMetadata::new(&scene_name, &integrator_name);
+    options.metadata.camera = camera_name.clone().filter(|name| name != "all");
+
+    let views = match camera_name.as_deref() {
+        // Every camera gets its own set of outputs, tagged with the camera's name
+        Some("all") => {
+            if options.output.is_none() {
+                fail("--camera all needs --output, since each camera gets its own image");
+            }
+            scene.cameras.iter().map(|(name, camera)| (Some(name.clone()), *camera)).collect()
+        }
+        Some(name) => {
+            scene.use_camera(name).unwrap_or_else(|e| fail(&e));
+            vec![(None, scene.camera)]
+        }
+        None => vec![(None, scene.camera)],
+    };
+    // Only the one pixel is traced, and its paths are printed instead of an image
+    if let Some((x, y)) = debug_pixel {
+        if camera_name.as_deref() == Some("all") {
+            fail("--debug-pixel traces through one camera, not all of them");
+        }
+        if x >= frame.image_width || y >= frame.image_height {
+            fail(&format!("--debug-pixel {x},{y} is outside the {}x{} image", frame.image_width, frame.image_height));
+        }
+        let trace = pixel_debug::trace_pixel(&scene, integrator.as_ref(), &frame, (x, y));
+        trace.write_json(&scene, &mut BufWriter::new(io::stdout())).unwrap_or_else(|e| fail(&e.to_string()));
+        return;
+    }
+
+    for (name, camera) in views {
+        if let Some(name) = &name {
+            eprintln!("Camera '{name}'");
+        }
+        scene.camera = options.overscan.map_or(camera, |overscan| overscan.camera(camera));
+        render_outputs(&scene, integrator.as_ref(), &settings, &options, name.as_deref());
     }
 }
 
-fn write_color(color: Color, samples_per_pixel: i64) {
-    let scale = 1.0 / samples_per_pixel as f64;
-    
-    let r = (color.x * scale).sqrt();
-    let g = (color.y * scale).sqrt();
-    let b = (color.z * scale).sqrt();
+// What the plain render command writes besides the image itself
+struct RenderOptions {
+    // The image goes to stdout unless this is set
+    output: Option<String>,
+    stereo_layout: Option<stereo::StereoLayout>,
+    // In scene units; a typical 6.5cm between the eyes when None
+    interaxial: Option<f64>,
+    convergence: Option<f64>,
+    depth_path: Option<String>,
+    depth_range: Option<(f64, f64)>,
+    segmentation_path: Option<String>,
+    segment_by: aov::SegmentBy,
+    position_path: Option<String>,
+    // Every output is rendered this much larger than the frame, with the crop
+    // written next to the image
+    overscan: Option<overscan::Overscan>,
+    // Stops to brighten the image by; with auto-exposure on, this is added to the
+    // exposure it picks
+    exposure: f64,
+    auto_exposure: bool,
+    // The luminance statistics are always printed; this saves them as JSON too
+    exposure_report: Option<String>,
+    // The image's two independent halves and their average, as PFMs: halves.pfm
+    // gets halves-a.pfm, halves-b.pfm and the average in halves.pfm itself
+    halves_path: Option<String>,
+    // Renders the image by progressive photon mapping instead of with the integrator
+    sppm: Option<sppm::SppmSettings>,
+    // Uses the photons only where there are caustics, and the integrator elsewhere
+    hybrid: bool,
+    // Added over the exposed image, in that order
+    flare: Option<flare::FlareSettings>,
+    grain: Option<grain::GrainSettings>,
+    // Writes the image out a row at a time as the render goes, rather than keeping
+    // it all in memory, for renders too big for that
+    stream: bool,
+    // What goes in the image's header, bar what's only known once it's rendered
+    metadata: metadata::RenderMetadata,
+    // The same metadata as JSON, for images whose format has nowhere to keep it
+    metadata_path: Option<String>,
+}
 
-    print!(
-        "{} {} {}\n",
-        (256.0 * r.clamp(0.0, 0.999)) as i64,
-        (256.0 * g.clamp(0.0, 0.999)) as i64,
-        (256.0 * b.clamp(0.0, 0.999)) as i64
-    );
+// Inserts `tag` before the extension: image.ppm becomes image-tag.ppm
+fn tagged_path(path: &str, tag: Option<&str>) -> PathBuf {
+    let path = Path::new(path);
+    let Some(tag) = tag else {
+        return path.to_path_buf();
+    };
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{tag}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{tag}"),
+    };
+    path.with_file_name(name)
 }
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
-const IMAGE_WIDTH: u16 = 400;
-const IMAGE_HEIGHT: u16 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u16;
-const SAMPLES_PER_PIXEL: i64 = 100;
-const MAX_DEPTH: i64 = 50;
+fn create(path: &Path) -> BufWriter<File> {
+    BufWriter::new(File::create(path).unwrap_or_else(|e| fail(&format!("Couldn't create {}: {e}", path.display()))))
+}
 
-fn render_test_image() {
-    // World
-    let mut world = HittableList::new();
+// The number after `flag`, failing with "`flag` needs `needs`" unless it parses
+// and is `valid`
+fn number<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str, needs: &str, valid: impl Fn(&T) -> bool) -> T {
+    args.next().and_then(|n| n.parse().ok()).filter(|n| valid(n)).unwrap_or_else(|| fail(&format!("{flag} needs {needs}")))
+}
 
-    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.8, 0.8, 0.0)));
-    let material_center = Arc::new(LambertianMaterial::new(Color::new(0.7, 0.3, 0.3)));
-    let material_left = Arc::new(MetalMaterial::new(Color::new(0.8, 0.8, 0.8), 0.3));
-    let material_right = Arc::new(MetalMaterial::new(Color::new(0.8, 0.6, 0.2), 1.0));
+fn width_flag(args: &mut impl Iterator<Item = String>) -> u16 {
+    number(args, "--width", "a number of pixels, at least 2", |&w| w >= 2)
+}
 
-    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
-    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
-    world.add(Arc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
-    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, material_right)));
-    
-    // Camera
-    let camera = Camera::default_camera();
+fn spp_flag(args: &mut impl Iterator<Item = String>) -> i64 {
+    number(args, "--spp", "a positive number", |&n| n > 0)
+}
 
-    print!("P3\n{IMAGE_WIDTH} {IMAGE_HEIGHT}\n255\n");
+fn seed_flag(args: &mut impl Iterator<Item = String>) -> u64 {
+    number(args, "--seed", "a number", |_| true)
+}
 
-    let mut rng = thread_rng();
+fn distance_flag(args: &mut impl Iterator<Item = String>, flag: &str) -> f64 {
+    number(args, flag, "a positive distance", |&d| d > 0.0)
+}
+
+fn threads_flag(args: &mut impl Iterator<Item = String>) -> usize {
+    number(args, "--threads", "a positive number", |&n| n > 0)
+}
+
+// Takes the options every rendering command shares (--width, --spp, --seed and
+// --threads) into `settings`, returning false for anything else
+fn parse_render_settings(arg: &str, args: &mut impl Iterator<Item = String>, settings: &mut RenderSettings) -> bool {
+    match arg {
+        "--width" => settings.set_width(width_flag(args)),
+        "--spp" => settings.samples_per_pixel = spp_flag(args),
+        "--seed" => settings.seed = Some(seed_flag(args)),
+        "--threads" => settings.threads = threads_flag(args),
+        _ => return false,
+    }
+    true
+}
 
-    for j in (0..IMAGE_HEIGHT).rev() {
-        eprint!("\rScanlines remaining: {j}      ");
-        for i in 0..IMAGE_WIDTH {
-            let mut pixel_color = Color::zero();
-            for _s in 0..SAMPLES_PER_PIXEL {
-                let u = (i as f64 + rng.gen_range(0.0..=1.0)) / (IMAGE_WIDTH - 1) as f64;
-                let v = (j as f64 + rng.gen_range(0.0..=1.0)) / (IMAGE_HEIGHT - 1) as f64;
-                let r = camera.get_ray(u, v);
-                pixel_color += ray_color(r, &world, MAX_DEPTH, &mut rng);
+// Renders the scene through its current camera and writes the image and any
+// requested passes, with `tag` added to every file name
+fn render_outputs(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, options: &RenderOptions, tag: Option<&str>) {
+    let started = Instant::now();
+    if options.stream {
+        stream_image(scene, integrator, settings, options, tag);
+        write_crop(options, tag);
+        let metadata = render_metadata(settings.image_width as usize, settings.image_height as usize, settings, options, tag, started);
+        write_metadata(&metadata, options, tag);
+        return;
+    }
+    let allocations_before = alloc_counter::allocations();
+    let mut halves = None;
+    let mut image = match options.stereo_layout {
+        Some(layout) => {
+            let interaxial = options.interaxial.unwrap_or(0.065 / scene.meters_per_unit);
+            let stereo = stereo::StereoSettings { layout, interaxial, convergence: options.convergence };
+            stereo::render_stereo(scene, integrator, settings, &stereo)
+        }
+        None if options.halves_path.is_some() => {
+            let (a, b) = render_halves(scene, &scene.camera, integrator, settings, true);
+            let n = settings.samples_per_pixel as f64;
+            let (weight_a, weight_b) = (((n + 1.0) / 2.0).floor() / n, (n / 2.0).floor() / n);
+            let mut average = Image::new(a.width, a.height);
+            for (pixel, (&color_a, &color_b)) in average.pixels.iter_mut().zip(a.pixels.iter().zip(&b.pixels)) {
+                *pixel = color_a * weight_a + color_b * weight_b;
             }
-            write_color(pixel_color, SAMPLES_PER_PIXEL);
+            halves = Some((a, b));
+            average
+        }
+        None => match &options.sppm {
+            Some(sppm) if options.hybrid => sppm::render_hybrid(scene, &scene.camera, integrator, settings, sppm, true),
+            Some(sppm) => sppm::render_sppm(scene, &scene.camera, settings, sppm, true),
+            None => render(scene, integrator, settings, true),
+        },
+    };
+    let allocations = alloc_counter::allocations() - allocations_before;
+    let samples = (image.width * image.height) as u64 * settings.samples_per_pixel as u64;
+    eprintln!(
+        "{allocations} heap allocations during the render ({:.4} per sample)",
+        allocations as f64 / samples as f64
+    );
+
+    let stops = exposure_stops(scene, integrator, settings, options);
+    if stops != 0.0 {
+        exposure::expose(&mut image, stops);
+    }
+    // The halves are written as rendered, bar the exposure, before any flare or
+    // grain is added to the image
+    if let (Some(path), Some((a, b))) = (&options.halves_path, &mut halves) {
+        let path = tagged_path(path, tag).to_string_lossy().into_owned();
+        for (half, name) in [(&mut *a, "a"), (&mut *b, "b")] {
+            exposure::expose(half, stops);
+            aov::FloatImage::from_image(half)
+                .write_pfm(&mut create(&tagged_path(&path, Some(name))))
+                .unwrap_or_else(|e| fail(&e.to_string()));
         }
+        aov::FloatImage::from_image(&image).write_pfm(&mut create(Path::new(&path))).unwrap_or_else(|e| fail(&e.to_string()));
+    }
+    if let Some(flare) = &options.flare {
+        flare::apply_flare(&mut image, flare);
+    }
+    if let Some(grain) = &options.grain {
+        grain::apply_grain(&mut image, grain);
+    }
+    report_exposure(&exposure::ExposureStats::of(&image), options, tag);
+
+    let metadata = render_metadata(image.width, image.height, settings, options, tag, started);
+    let comments = metadata.comments();
+    let written = match &options.output {
+        Some(path) => image.write_ppm_with_comments(&mut create(&tagged_path(path, tag)), &comments),
+        None => image.write_ppm_with_comments(&mut BufWriter::new(io::stdout().lock()), &comments),
+    };
+    written.unwrap_or_else(|e| fail(&e.to_string()));
+    write_crop(options, tag);
+    write_metadata(&metadata, options, tag);
+
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    if let Some(path) = &options.depth_path {
+        let depth = aov::depth_pass(scene, width, height, options.depth_range);
+        depth.write_pfm(&mut create(&tagged_path(path, tag))).unwrap_or_else(|e| fail(&e.to_string()));
+    }
+    if let Some(path) = &options.position_path {
+        let position = aov::position_pass(scene, width, height);
+        position.write_pfm(&mut create(&tagged_path(path, tag))).unwrap_or_else(|e| fail(&e.to_string()));
+    }
+    if let Some(path) = &options.segmentation_path {
+        // The legend goes next to the image, e.g. seg.ppm and seg.json
+        let path = tagged_path(path, tag);
+        let segmentation = aov::segmentation_pass(scene, width, height, options.segment_by);
+        segmentation
+            .write_ppm(&mut create(&path))
+            .and_then(|_| segmentation.write_legend(&mut create(&path.with_extension("json"))))
+            .unwrap_or_else(|e| fail(&e.to_string()));
     }
-    eprintln!("\rOperation complete.      ")
 }
 
-fn main() {
-    render_test_image();
+// Writes the image as it renders, never holding all of it: the exposure is settled
+// beforehand and the luminance figures are tallied as the pixels go by
+fn stream_image(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, options: &RenderOptions, tag: Option<&str>) {
+    let scale = exposure_stops(scene, integrator, settings, options).exp2();
+    let mut tally = exposure::LuminanceTally::new();
+    let mut on_pixel = |_: usize, _: usize, color: &mut Color| {
+        *color *= scale;
+        tally.add(*color);
+    };
+    let written = match &options.output {
+        Some(path) => {
+            render_view_to_ppm(scene, &scene.camera, integrator, settings, true, &mut on_pixel, &mut create(&tagged_path(path, tag)))
+        }
+        None => render_view_to_ppm(
+            scene,
+            &scene.camera,
+            integrator,
+            settings,
+            true,
+            &mut on_pixel,
+            &mut BufWriter::new(io::stdout().lock()),
+        ),
+    };
+    written.unwrap_or_else(|e| fail(&e.to_string()));
+    report_exposure(&tally.stats(), options, tag);
+}
+
+// The stops to brighten the image by, counting auto-exposure's
+fn exposure_stops(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, options: &RenderOptions) -> f64 {
+    let mut stops = options.exposure;
+    if options.auto_exposure {
+        let auto = exposure::auto_exposure(scene, integrator, settings);
+        eprintln!("Auto-exposure: {auto:+.2} stops");
+        stops += auto;
+    }
+    stops
+}
+
+fn report_exposure(stats: &exposure::ExposureStats, options: &RenderOptions, tag: Option<&str>) {
+    stats.print();
+    if let Some(path) = &options.exposure_report {
+        stats.write_json(&mut create(&tagged_path(path, tag))).unwrap_or_else(|e| fail(&e.to_string()));
+    }
+}
+
+// The metadata for an image of the given size, rendered since `started`
+fn render_metadata(
+    width: usize,
+    height: usize,
+    settings: &RenderSettings,
+    options: &RenderOptions,
+    tag: Option<&str>,
+    started: Instant,
+) -> metadata::RenderMetadata {
+    let mut metadata = options.metadata.clone();
+    if let Some(tag) = tag {
+        metadata.camera = Some(tag.to_string());
+    }
+    metadata.width = width;
+    metadata.height = height;
+    metadata.samples_per_pixel = settings.samples_per_pixel;
+    metadata.max_depth = settings.max_depth;
+    metadata.seed = settings.seed;
+    metadata.render_seconds = started.elapsed().as_secs_f64();
+    metadata
+}
+
+fn write_metadata(metadata: &metadata::RenderMetadata, options: &RenderOptions, tag: Option<&str>) {
+    if let Some(path) = &options.metadata_path {
+        metadata.write_json(&mut create(&tagged_path(path, tag))).unwrap_or_else(|e| fail(&e.to_string()));
+    }
+}
+
+fn write_crop(options: &RenderOptions, tag: Option<&str>) {
+    if let Some(overscan) = &options.overscan {
+        // image.ppm gets image.crop.json; without an output file, stderr gets the crop
+        let written = match &options.output {
+            Some(path) => overscan.write_metadata(&mut create(&tagged_path(path, tag).with_extension("crop.json"))),
+            None => overscan.write_metadata(&mut io::stderr().lock()),
+        };
+        written.unwrap_or_else(|e| fail(&e.to_string()));
+    }
 }
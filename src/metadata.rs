@@ -0,0 +1,77 @@
+use std::io::{self, Write};
+
+use crate::json;
+
+// How an image was made, kept with it so a render found later can be traced back
+// to the scene and settings that produced it, and made again
+#[derive(Clone)]
+pub struct RenderMetadata {
+    // The scene's path, or the name of a built-in scene
+    pub scene: String,
+    // None for the scene's own camera
+    pub camera: Option<String>,
+    pub integrator: String,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: i64,
+    pub max_depth: i64,
+    // None when the render was seeded afresh, so it can't be repeated exactly
+    pub seed: Option<u64>,
+    pub render_seconds: f64,
+}
+
+impl RenderMetadata {
+    pub fn new(scene: &str, integrator: &str) -> Self {
+        RenderMetadata {
+            scene: scene.to_string(),
+            camera: None,
+            integrator: integrator.to_string(),
+            width: 0,
+            height: 0,
+            samples_per_pixel: 0,
+            max_depth: 0,
+            seed: None,
+            render_seconds: 0.0,
+        }
+    }
+
+    // As "key: value" lines for the comments in a PPM's header, which readers skip
+    pub fn comments(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("rust_raytracer: {}", env!("CARGO_PKG_VERSION")),
+            format!("scene: {}", one_line(&self.scene)),
+        ];
+        if let Some(camera) = &self.camera {
+            lines.push(format!("camera: {}", one_line(camera)));
+        }
+        lines.push(format!("integrator: {}", self.integrator));
+        lines.push(format!("resolution: {}x{}", self.width, self.height));
+        lines.push(format!("spp: {}", self.samples_per_pixel));
+        lines.push(format!("max_depth: {}", self.max_depth));
+        lines.push(format!("seed: {}", self.seed.map_or("none".to_string(), |seed| seed.to_string())));
+        lines.push(format!("render_seconds: {:.3}", self.render_seconds));
+        lines
+    }
+
+    pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"version\": {},", json::string(env!("CARGO_PKG_VERSION")))?;
+        writeln!(out, "  \"scene\": {},", json::string(&self.scene))?;
+        let camera = self.camera.as_deref().map_or("null".to_string(), json::string);
+        writeln!(out, "  \"camera\": {camera},")?;
+        writeln!(out, "  \"integrator\": {},", json::string(&self.integrator))?;
+        writeln!(out, "  \"width\": {},", self.width)?;
+        writeln!(out, "  \"height\": {},", self.height)?;
+        writeln!(out, "  \"samples_per_pixel\": {},", self.samples_per_pixel)?;
+        writeln!(out, "  \"max_depth\": {},", self.max_depth)?;
+        let seed = self.seed.map_or("null".to_string(), |seed| seed.to_string());
+        writeln!(out, "  \"seed\": {seed},")?;
+        writeln!(out, "  \"render_seconds\": {}", json::number(self.render_seconds))?;
+        writeln!(out, "}}")
+    }
+}
+
+// A comment runs to the end of its line, so a value can't be allowed to break it
+fn one_line(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
@@ -0,0 +1,189 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::types::*;
+
+// Reads the geometry of a Wavefront OBJ file: its vertices ("v") and faces ("f"),
+// polygons split into fans of triangles, its vertex normals ("vn") when every
+// face corner has one, and its vertex colors when every vertex has one, written
+// after the position as "v x y z r g b" in the common extension. Texture
+// coordinates, groups, smoothing and materials ("mtllib", "usemtl") are skipped,
+// as the scene gives the mesh its material. A vertex whose corners use more than
+// one normal is split, one copy per normal.
+pub fn load(path: &Path) -> Result<MeshData, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+pub fn parse(text: &str) -> Result<MeshData, String> {
+    let (mut vs, mut vns) = (Vec::new(), Vec::new());
+    // Each face corner's vertex and normal, by their indices in the file
+    let mut faces: Vec<Vec<(usize, Option<usize>)>> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line_number = n + 1;
+        let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+        let numbers = |words: &mut dyn Iterator<Item = &str>| -> Result<Vec3, String> {
+            let mut xyz = [0.0; 3];
+            for x in &mut xyz {
+                let word = words.next().ok_or_else(|| format!("line {line_number}: expected three numbers"))?;
+                *x = word.parse().map_err(|_| format!("line {line_number}: '{word}' isn't a number"))?;
+            }
+            Ok(Vec3::new(xyz[0], xyz[1], xyz[2]))
+        };
+        match words.next() {
+            Some("v") => {
+                let p = numbers(&mut words)?;
+                // Three more numbers are its color; one is a weight, which is ignored
+                let rest: Vec<&str> = words.collect();
+                let color = if rest.len() == 3 { Some(numbers(&mut rest.into_iter())?) } else { None };
+                vs.push((p, color));
+            }
+            Some("vn") => vns.push(numbers(&mut words)?),
+            Some("f") => {
+                let corners = words
+                    .map(|corner| parse_corner(corner, vs.len(), vns.len()).map_err(|e| format!("line {line_number}: {e}")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(format!("line {line_number}: a face needs at least three corners"));
+                }
+                faces.push(corners);
+            }
+            _ => {}
+        }
+    }
+
+    let smooth = !faces.is_empty() && faces.iter().flatten().all(|(_, vn)| vn.is_some());
+    let colored = !vs.is_empty() && vs.iter().all(|(_, color)| color.is_some());
+    let mut vertices = HashMap::new();
+    let (mut positions, mut normals, mut colors, mut indices) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut vertex = |(v, vn): (usize, Option<usize>)| {
+        let vn = vn.filter(|_| smooth);
+        *vertices.entry((v, vn)).or_insert_with(|| {
+            let (p, color) = vs[v];
+            positions.push(p);
+            normals.extend(vn.map(|vn| vns[vn]));
+            colors.extend(color.filter(|_| colored));
+            positions.len() as u32 - 1
+        })
+    };
+    for face in &faces {
+        let first = vertex(face[0]);
+        for pair in face[1..].windows(2) {
+            indices.push([first, vertex(pair[0]), vertex(pair[1])]);
+        }
+    }
+    let normals = Some(normals).filter(|_| smooth);
+    let colors = Some(colors).filter(|_| colored);
+    Ok(MeshData { positions, normals, colors, velocities: None, indices })
+}
+
+// A face corner, "v", "v/vt", "v//vn" or "v/vt/vn", as zero-based indices of its
+// vertex and normal. Indices count from 1, or back from the latest when negative.
+fn parse_corner(corner: &str, vertices: usize, normals: usize) -> Result<(usize, Option<usize>), String> {
+    let index = |word: &str, count: usize| -> Result<usize, String> {
+        let i: i64 = word.parse().map_err(|_| format!("'{corner}' isn't a face corner"))?;
+        let zero_based = if i < 0 { count as i64 + i } else { i - 1 };
+        if zero_based < 0 || zero_based >= count as i64 {
+            return Err(format!("'{corner}' refers past the {count} defined so far"));
+        }
+        Ok(zero_based as usize)
+    };
+    let mut parts = corner.split('/');
+    let v = index(parts.next().unwrap_or(""), vertices)?;
+    let vn = match parts.nth(1) {
+        Some(word) if !word.is_empty() => Some(index(word, normals)?),
+        _ => None,
+    };
+    Ok((v, vn))
+}
+
+// The file for `frame` of a sequence written one file per frame, such as
+// "walk.####.obj", whose last run of '#' becomes the frame number padded to as
+// many digits: frame 12 is "walk.0012.obj"
+pub fn frame_path(pattern: &str, frame: i64) -> Result<String, String> {
+    let end = pattern.rfind('#').ok_or_else(|| format!("'{pattern}' has no '#' to put the frame number in"))? + 1;
+    let start = pattern[..end].trim_end_matches('#').len();
+    let digits = end - start;
+    Ok(format!("{}{frame:0digits$}{}", &pattern[..start], &pattern[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    fn xyz(vs: &[Vec3]) -> Vec<[f64; 3]> {
+        vs.iter().map(|v| [v.x, v.y, v.z]).collect()
+    }
+
+    #[test]
+    fn polygons_become_fans_of_triangles() {
+        let mesh = parse(&format!("{SQUARE}v 0.5 1.5 0\nf 1 2 3 5 4\n")).unwrap();
+        assert_eq!(mesh.positions.len(), 5);
+        assert_eq!(mesh.indices, [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert!(mesh.normals.is_none() && mesh.colors.is_none());
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest() {
+        let absolute = parse(&format!("{SQUARE}f 1 2 3 4\n")).unwrap();
+        let relative = parse(&format!("{SQUARE}f -4 -3 -2 -1\n")).unwrap();
+        assert_eq!(relative.indices, absolute.indices);
+        assert_eq!(xyz(&relative.positions), xyz(&absolute.positions));
+
+        // Relative to the vertices so far, not to the whole file
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\nf -4 -3 -1\n").unwrap();
+        assert_eq!(mesh.indices, [[0, 1, 2], [0, 1, 3]]);
+        assert_eq!(xyz(&mesh.positions)[3], [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn every_corner_form_reads_the_same_vertex() {
+        let text = format!("{SQUARE}vt 0 0\nvn 0 0 1\nf 1 2/1 3//1 4/1/1\n");
+        let mesh = parse(&text).unwrap();
+        assert_eq!(mesh.indices, [[0, 1, 2], [0, 2, 3]]);
+        // Not every corner has a normal, so none are kept
+        assert!(mesh.normals.is_none());
+    }
+
+    #[test]
+    fn vertices_are_split_per_normal() {
+        // Two triangles sharing an edge with different normals along it
+        let text = format!("{SQUARE}vn 0 0 1\nvn 0 1 0\nf 1//1 2//1 3//1\nf 1//2 3//2 4//2\n");
+        let mesh = parse(&text).unwrap();
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.indices, [[0, 1, 2], [3, 4, 5]]);
+        let normals = xyz(&mesh.normals.unwrap());
+        assert_eq!(normals[..3], [[0.0, 0.0, 1.0]; 3]);
+        assert_eq!(normals[3..], [[0.0, 1.0, 0.0]; 3]);
+    }
+
+    #[test]
+    fn vertex_colors_follow_the_position() {
+        let mesh = parse("v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0 0 0 1 # a comment\nf 1 2 3\n").unwrap();
+        assert_eq!(xyz(&mesh.colors.unwrap()), [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        // A weight isn't a color
+        assert!(parse("v 0 0 0 1\nv 1 0 0 1\nv 0 1 0 1\nf 1 2 3\n").unwrap().colors.is_none());
+    }
+
+    #[test]
+    fn bad_input_names_its_line() {
+        let error = |text: &str| parse(text).err().unwrap();
+        assert_eq!(error(&format!("{SQUARE}f 1 2 5\n")), "line 5: '5' refers past the 4 defined so far");
+        assert_eq!(error(&format!("{SQUARE}f 1 2 -5\n")), "line 5: '-5' refers past the 4 defined so far");
+        assert_eq!(error(&format!("{SQUARE}f 0 1 2\n")), "line 5: '0' refers past the 4 defined so far");
+        assert_eq!(error(&format!("{SQUARE}vn 0 0 1\nf 1//1 2//2 3//1\n")), "line 6: '2//2' refers past the 1 defined so far");
+        assert_eq!(error(&format!("{SQUARE}f 1 2\n")), "line 5: a face needs at least three corners");
+        assert_eq!(error("v 0 0\n"), "line 1: expected three numbers");
+        assert_eq!(error("v 0 zero 0\n"), "line 1: 'zero' isn't a number");
+        assert_eq!(error(&format!("{SQUARE}f 1 2 x\n")), "line 5: 'x' isn't a face corner");
+    }
+
+    #[test]
+    fn frame_paths_pad_the_last_run_of_hashes() {
+        assert_eq!(frame_path("walk.####.obj", 12).unwrap(), "walk.0012.obj");
+        assert_eq!(frame_path("take#2/walk.##.obj", 7).unwrap(), "take#2/walk.07.obj");
+        assert_eq!(frame_path("walk.#.obj", 123).unwrap(), "walk.123.obj");
+        assert!(frame_path("walk.obj", 1).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::{self, Write};
+
+use crate::render::RenderSettings;
+use crate::types::Camera;
+
+// Extra pixels rendered around the frame so compositors have room to shake or
+// stabilize the shot without pulling black in at the edges. The frame grows by
+// `percent` of its width and height, split evenly between opposite sides.
+#[derive(Clone, Copy)]
+pub struct Overscan {
+    pub percent: f64,
+    frame: (u16, u16),
+    pad: (u16, u16),
+}
+
+impl Overscan {
+    pub fn new(frame: &RenderSettings, percent: f64) -> Self {
+        let (width, height) = (frame.image_width, frame.image_height);
+        let pad = |size: u16| {
+            let room = (u16::MAX - size) / 2;
+            (size as f64 * percent / 200.0).round().min(room as f64) as u16
+        };
+        Overscan { percent, frame: (width, height), pad: (pad(width), pad(height)) }
+    }
+
+    // The settings for the whole overscanned image
+    pub fn settings(&self, frame: &RenderSettings) -> RenderSettings {
+        let mut settings = *frame;
+        settings.image_width = self.frame.0 + 2 * self.pad.0;
+        settings.image_height = self.frame.1 + 2 * self.pad.1;
+        settings
+    }
+
+    pub fn camera(&self, camera: Camera) -> Camera {
+        camera.overscanned(self.frame, self.pad)
+    }
+
+    // Where the original frame sits in the overscanned image, counted from the top left
+    pub fn write_metadata(&self, out: &mut dyn Write) -> io::Result<()> {
+        let (width, height) = self.frame;
+        let (pad_x, pad_y) = self.pad;
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"overscan_percent\": {},", self.percent)?;
+        writeln!(out, "  \"full_size\": [{}, {}],", width + 2 * pad_x, height + 2 * pad_y)?;
+        writeln!(out, "  \"crop\": {{ \"x\": {pad_x}, \"y\": {pad_y}, \"width\": {width}, \"height\": {height} }}")?;
+        writeln!(out, "}}")
+    }
+}
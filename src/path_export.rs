@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+use crate::pixel_debug::PixelTrace;
+use crate::scenes::Scene;
+use crate::types::*;
+
+// Recorded paths as line geometry, to see where an integrator actually sends its
+// rays: an OBJ to load next to the scene in a 3D viewer, or an SVG seen from above.
+
+const SVG_WIDTH: f64 = 800.0;
+// Colors of successive bounces in the SVG, repeating for longer paths
+const BOUNCE_COLORS: [&str; 6] = ["#e41a1c", "#377eb8", "#4daf4a", "#984ea3", "#ff7f00", "#a65628"];
+
+struct Segment {
+    start: Point3,
+    end: Point3,
+    depth: usize,
+    escaped: bool,
+}
+
+// One list of segments per sample. Rays that escaped have no end, so they're drawn
+// as long as the longest segment that did hit something.
+fn segments(traces: &[PixelTrace]) -> Vec<(String, Vec<Segment>)> {
+    let bounces = || traces.iter().flat_map(|trace| trace.samples.iter().flat_map(|(_, path)| path));
+    let escape_length = bounces()
+        .filter_map(|bounce| bounce.hit.as_ref().map(|hit| (hit.point - bounce.ray.origin).length()))
+        .fold(0.0, f64::max);
+    let escape_length = if escape_length > 0.0 { escape_length } else { 1.0 };
+
+    let mut paths = Vec::new();
+    for trace in traces {
+        for (sample, (_, path)) in trace.samples.iter().enumerate() {
+            let name = format!("pixel-{}-{}-sample-{sample}", trace.pixel.0, trace.pixel.1);
+            let segments = path
+                .iter()
+                .enumerate()
+                .map(|(depth, bounce)| {
+                    let start = bounce.ray.origin;
+                    match &bounce.hit {
+                        Some(hit) => Segment { start, end: hit.point, depth, escaped: false },
+                        None => {
+                            let end = start + bounce.ray.direction.unit_vector() * escape_length;
+                            Segment { start, end, depth, escaped: true }
+                        }
+                    }
+                })
+                .collect();
+            paths.push((name, segments));
+        }
+    }
+    paths
+}
+
+// One object per sample, made of a line per bounce
+pub fn write_obj(traces: &[PixelTrace], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "# Ray paths: one object per sample, one line per bounce")?;
+    let mut vertices = 0;
+    for (name, segments) in segments(traces) {
+        writeln!(out, "o {name}")?;
+        for segment in &segments {
+            for p in [segment.start, segment.end] {
+                writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+            }
+            writeln!(out, "l {} {}", vertices + 1, vertices + 2)?;
+            vertices += 2;
+        }
+    }
+    Ok(())
+}
+
+// Looking down the y axis with x to the right and -z up the page, so a camera
+// looking down -z looks up the picture. Bounces are colored by depth and escaped
+// rays dashed; objects are outlined from their bounding boxes, spheres as circles.
+pub fn write_svg(traces: &[PixelTrace], scene: &Scene, out: &mut dyn Write) -> io::Result<()> {
+    let paths = segments(traces);
+    let mut min = (f64::INFINITY, f64::INFINITY);
+    let mut max = (-f64::INFINITY, -f64::INFINITY);
+    for segment in paths.iter().flat_map(|(_, segments)| segments) {
+        for p in [segment.start, segment.end] {
+            min = (min.0.min(p.x), min.1.min(p.z));
+            max = (max.0.max(p.x), max.1.max(p.z));
+        }
+    }
+    if min.0 > max.0 {
+        (min, max) = ((-1.0, -1.0), (1.0, 1.0));
+    }
+    // A margin all round, and never less than a unit across
+    let margin = 0.05 * (max.0 - min.0).max(max.1 - min.1).max(1.0);
+    let (min, max) = ((min.0 - margin, min.1 - margin), (max.0 + margin, max.1 + margin));
+    let scale = SVG_WIDTH / (max.0 - min.0);
+    let height = ((max.1 - min.1) * scale).ceil();
+    let to_svg = |x: f64, z: f64| ((x - min.0) * scale, (z - min.1) * scale);
+
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SVG_WIDTH}\" height=\"{height}\">")?;
+    writeln!(out, "  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>")?;
+    for object in &scene.world.objects {
+        let Some(bbox) = object.bounding_box() else {
+            continue;
+        };
+        let (x0, y0) = to_svg(bbox.min.x, bbox.min.z);
+        let (x1, y1) = to_svg(bbox.max.x, bbox.max.z);
+        let style = "fill=\"none\" stroke=\"#bbbbbb\"";
+        if object.kind() == "sphere" {
+            let (cx, cy, rx, ry) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0, (x1 - x0) / 2.0, (y1 - y0) / 2.0);
+            writeln!(out, "  <ellipse cx=\"{cx:.2}\" cy=\"{cy:.2}\" rx=\"{rx:.2}\" ry=\"{ry:.2}\" {style}/>")?;
+        } else {
+            let (w, h) = (x1 - x0, y1 - y0);
+            writeln!(out, "  <rect x=\"{x0:.2}\" y=\"{y0:.2}\" width=\"{w:.2}\" height=\"{h:.2}\" {style}/>")?;
+        }
+    }
+    for (name, segments) in &paths {
+        writeln!(out, "  <g id=\"{name}\" stroke-width=\"1\">")?;
+        for segment in segments {
+            let (x1, y1) = to_svg(segment.start.x, segment.start.z);
+            let (x2, y2) = to_svg(segment.end.x, segment.end.z);
+            let color = BOUNCE_COLORS[segment.depth % BOUNCE_COLORS.len()];
+            let dash = if segment.escaped { " stroke-dasharray=\"4 3\"" } else { "" };
+            writeln!(
+                out,
+                "    <line x1=\"{x1:.2}\" y1=\"{y1:.2}\" x2=\"{x2:.2}\" y2=\"{y2:.2}\" stroke=\"{color}\"{dash}/>"
+            )?;
+        }
+        writeln!(out, "  </g>")?;
+    }
+    let (cx, cy) = to_svg(scene.camera.origin.x, scene.camera.origin.z);
+    writeln!(out, "  <circle cx=\"{cx:.2}\" cy=\"{cy:.2}\" r=\"4\" fill=\"black\"/>")?;
+    writeln!(out, "</svg>")
+}
@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+
+use crate::aov::nearest_object;
+use crate::integrator::{Bounce, Integrator};
+// Infinities and NaNs, which a broken path tends to produce, come out as null
+use crate::json::{number, vector};
+use crate::render::{pixel_seed, RenderSettings};
+use crate::sampler::{RandomSampler, Sampler};
+use crate::scenes::Scene;
+use crate::types::*;
+
+// Every sample a pixel would get, traced on its own with each bounce recorded
+pub struct PixelTrace {
+    pub pixel: (u16, u16),
+    pub samples: Vec<(Color, Vec<Bounce>)>,
+}
+
+// Traces pixel (x, y), counted from the top left like the image, through the
+// scene's camera with all of `settings.samples_per_pixel` samples
+pub fn trace_pixel(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, (x, y): (u16, u16)) -> PixelTrace {
+    trace_region(scene, integrator, settings, (x, y, 1, 1)).remove(0)
+}
+
+// Traces every pixel of the `width` by `height` block whose top left is (x, y),
+// row by row. With a seed, each pixel draws from the same stream of samples as
+// in a render with these settings, so its paths are the ones that made it.
+pub fn trace_region(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    (x, y, width, height): (u16, u16, u16, u16),
+) -> Vec<PixelTrace> {
+    let mut random = RandomSampler::new();
+    let camera = scene.camera.for_image_height(settings.image_height);
+    let mut traces = Vec::new();
+    for py in y..y + height {
+        for px in x..x + width {
+            let trace = match settings.seed {
+                Some(seed) => {
+                    let j = settings.image_height - 1 - py;
+                    let mut seeded = RandomSampler::seeded(pixel_seed(seed, (px, j)));
+                    trace_pixel_with(scene, integrator, settings, &camera, (px, py), &mut seeded)
+                }
+                None => trace_pixel_with(scene, integrator, settings, &camera, (px, py), &mut random),
+            };
+            traces.push(trace);
+        }
+    }
+    traces
+}
+
+fn trace_pixel_with(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    camera: &Camera,
+    (x, y): (u16, u16),
+    sampler: &mut dyn Sampler,
+) -> PixelTrace {
+    let (width, height) = (settings.image_width, settings.image_height);
+    let j = height - 1 - y;
+    let samples = (0..settings.samples_per_pixel)
+        .map(|_| {
+            let u = (x as f64 + sampler.get_1d()) / (width - 1) as f64;
+            let v = (j as f64 + sampler.get_1d()) / (height - 1) as f64;
+            let mut path = Vec::new();
+            let color = integrator.li_traced(camera.sample_ray(u, v, y as f64 / height as f64, sampler), scene, sampler, integrator.max_depth(), &mut path);
+            (color, path)
+        })
+        .collect();
+    PixelTrace { pixel: (x, y), samples }
+}
+
+impl PixelTrace {
+    pub fn mean(&self) -> Color {
+        let sum = self.samples.iter().fold(Color::zero(), |sum, (color, _)| sum + *color);
+        sum / self.samples.len().max(1) as f64
+    }
+
+    // Objects are labelled by index and kind, as in the segmentation pass
+    pub fn write_json(&self, scene: &Scene, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"pixel\": [{}, {}],", self.pixel.0, self.pixel.1)?;
+        writeln!(out, "  \"mean\": {},", vector(self.mean()))?;
+        writeln!(out, "  \"samples\": [")?;
+        for (index, (color, path)) in self.samples.iter().enumerate() {
+            writeln!(out, "    {{")?;
+            writeln!(out, "      \"radiance\": {},", vector(*color))?;
+            writeln!(out, "      \"bounces\": [")?;
+            for (depth, bounce) in path.iter().enumerate() {
+                let mut fields = vec![
+                    format!("\"origin\": {}", vector(bounce.ray.origin)),
+                    format!("\"direction\": {}", vector(bounce.ray.direction)),
+                    format!("\"throughput\": {}", vector(bounce.throughput)),
+                ];
+                match &bounce.hit {
+                    None => fields.push(format!("\"background\": {}", vector(bounce.emitted))),
+                    Some(hit) => {
+                        let object = nearest_object(scene, bounce.ray)
+                            .map_or("null".to_string(), |i| format!("\"object-{i}-{}\"", scene.world.objects[i].kind()));
+                        fields.push(format!("\"t\": {}", number(hit.t)));
+                        fields.push(format!("\"point\": {}", vector(hit.point)));
+                        fields.push(format!("\"normal\": {}", vector(hit.normal)));
+                        fields.push(format!("\"front_face\": {}", hit.front_face));
+                        fields.push(format!("\"object\": {object}"));
+                        fields.push(format!("\"material\": \"{}\"", hit.material));
+                        fields.push(format!("\"emitted\": {}", vector(bounce.emitted)));
+                        fields.push(format!("\"direct\": {}", vector(hit.direct)));
+                        match hit.scattered {
+                            Some((scattered, attenuation, pdf)) => {
+                                fields.push(format!("\"scattered\": {}", vector(scattered.direction)));
+                                fields.push(format!("\"attenuation\": {}", vector(attenuation)));
+                                fields.push(format!("\"pdf\": {}", pdf.map_or("null".to_string(), number)));
+                            }
+                            None => fields.push("\"absorbed\": true".to_string()),
+                        }
+                    }
+                }
+                let separator = if depth + 1 < path.len() { "," } else { "" };
+                writeln!(out, "        {{ \"depth\": {depth}, {} }}{separator}", fields.join(", "))?;
+            }
+            writeln!(out, "      ]")?;
+            let separator = if index + 1 < self.samples.len() { "," } else { "" };
+            writeln!(out, "    }}{separator}")?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
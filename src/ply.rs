@@ -0,0 +1,351 @@
+use std::{fs, path::Path};
+
+use crate::types::*;
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, String> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            other => return Err(format!("unknown property type '{other}'")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+// A property of an element: one value, or a list of them after their count
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// The data after the header, read one value at a time
+struct Body<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    // None for ASCII
+    big_endian: Option<bool>,
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        let Some(big_endian) = self.big_endian else {
+            let rest = &self.bytes[self.at..];
+            let start = rest.iter().position(|b| !b.is_ascii_whitespace()).ok_or("the file ends early")?;
+            let len = rest[start..].iter().position(u8::is_ascii_whitespace).unwrap_or(rest.len() - start);
+            self.at += start + len;
+            let word = String::from_utf8_lossy(&rest[start..start + len]);
+            return word.parse().map_err(|_| format!("'{word}' isn't a number"));
+        };
+        let size = scalar.size();
+        let bytes = self.bytes.get(self.at..self.at + size).ok_or("the file ends early")?;
+        self.at += size;
+        // Little-endian from here on
+        let mut b = [0; 8];
+        b[..size].copy_from_slice(bytes);
+        if big_endian {
+            b[..size].reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+
+    // One value for each of the element's properties, lists whole
+    fn row(&mut self, element: &Element) -> Result<Vec<Vec<f64>>, String> {
+        let mut row = Vec::with_capacity(element.properties.len());
+        for property in &element.properties {
+            row.push(match *property {
+                Property::Scalar(_, scalar) => vec![self.read(scalar)?],
+                Property::List(_, count, item) => {
+                    let count = self.read(count)? as usize;
+                    (0..count).map(|_| self.read(item)).collect::<Result<_, _>>()?
+                }
+            });
+        }
+        Ok(row)
+    }
+}
+
+// Reads the geometry of a PLY file, as scanners and photogrammetry tools write
+// them: its vertices, with their normals, colors and velocities ("vx", "vy", "vz")
+// when every vertex has them, and its faces, split into fans of triangles. ASCII
+// and both binary encodings are read; other elements and properties are skipped.
+// Colors stored as integers run from 0 to 255 and are scaled to 1, as image
+// textures' are.
+pub fn load(path: &Path) -> Result<MeshData, String> {
+    let bytes = fs::read(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    parse(&bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, String> {
+    let mut at = 0;
+    let mut header = Vec::new();
+    loop {
+        let end = at + bytes[at..].iter().position(|&b| b == b'\n').ok_or("the header has no end_header")?;
+        let line = std::str::from_utf8(&bytes[at..end]).map_err(|_| "the header isn't text")?.trim();
+        at = end + 1;
+        if line == "end_header" {
+            break;
+        }
+        header.push(line);
+    }
+    if header.first() != Some(&"ply") {
+        return Err("not a PLY file".to_string());
+    }
+
+    let mut big_endian = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (n, line) in header.iter().enumerate().skip(1) {
+        let line_number = n + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _] => {
+                big_endian = match *format {
+                    "ascii" => None,
+                    "binary_little_endian" => Some(false),
+                    "binary_big_endian" => Some(true),
+                    other => return Err(format!("line {line_number}: unknown format '{other}'")),
+                }
+            }
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| format!("line {line_number}: '{count}' isn't a count"))?;
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            }
+            ["property", rest @ ..] => {
+                let at_line = |e: String| format!("line {line_number}: {e}");
+                let property = match rest {
+                    ["list", count, item, name] => {
+                        Property::List(name.to_string(), Scalar::parse(count).map_err(at_line)?, Scalar::parse(item).map_err(at_line)?)
+                    }
+                    [scalar, name] => Property::Scalar(name.to_string(), Scalar::parse(scalar).map_err(at_line)?),
+                    _ => return Err(format!("line {line_number}: expected a property's type and name")),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| format!("line {line_number}: a property outside any element"))?
+                    .properties
+                    .push(property);
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(format!("line {line_number}: unexpected '{line}'")),
+        }
+    }
+
+    let mut body = Body { bytes, at, big_endian };
+    let (mut positions, mut normals, mut colors, mut velocities) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut indices = Vec::new();
+    for element in &elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name()));
+        let xyz = |names: [&[&str]; 3]| Some([find(names[0])?, find(names[1])?, find(names[2])?]);
+        let position = xyz([&["x"], &["y"], &["z"]]);
+        let normal = xyz([&["nx"], &["ny"], &["nz"]]);
+        let velocity = xyz([&["vx"], &["vy"], &["vz"]]);
+        let color = xyz([&["red", "diffuse_red"], &["green", "diffuse_green"], &["blue", "diffuse_blue"]]);
+        // Integer colors run to 255
+        let color_scale = color.map(|rgb| {
+            rgb.map(|i| match element.properties[i] {
+                Property::Scalar(_, Scalar::F32 | Scalar::F64) => 1.0,
+                _ => 1.0 / 255.0,
+            })
+        });
+        let corners = find(&["vertex_indices", "vertex_index"]);
+        for i in 0..element.count {
+            let row = body.row(element).map_err(|e| format!("{} {i}: {e}", element.name))?;
+            let vector = |[a, b, c]: [usize; 3]| Vec3::new(row[a][0], row[b][0], row[c][0]);
+            match element.name.as_str() {
+                "vertex" => {
+                    positions.push(vector(position.ok_or("the vertices have no x, y and z")?));
+                    normals.extend(normal.map(vector));
+                    velocities.extend(velocity.map(vector));
+                    if let (Some(rgb), Some(scale)) = (color, color_scale) {
+                        let c = vector(rgb);
+                        colors.push(Color::new(c.x * scale[0], c.y * scale[1], c.z * scale[2]));
+                    }
+                }
+                "face" => {
+                    let corners = &row[corners.ok_or("the faces have no vertex_indices")?];
+                    if corners.len() < 3 {
+                        return Err(format!("face {i}: a face needs at least three corners"));
+                    }
+                    if corners.iter().any(|&c| c < 0.0) {
+                        return Err(format!("face {i}: negative vertex indices"));
+                    }
+                    for pair in corners[1..].windows(2) {
+                        indices.push([corners[0], pair[0], pair[1]].map(|c| c as u32));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some(i) = indices.iter().flatten().position(|&c| c as usize >= positions.len()) {
+        return Err(format!("face corner {i} is past the {} vertices", positions.len()));
+    }
+    let normals = Some(normals).filter(|n| !n.is_empty() && n.len() == positions.len());
+    let colors = Some(colors).filter(|c| !c.is_empty() && c.len() == positions.len());
+    let velocities = Some(velocities).filter(|v| !v.is_empty() && v.len() == positions.len());
+    Ok(MeshData { positions, normals, colors, velocities, indices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xyz(vs: &[Vec3]) -> Vec<[f64; 3]> {
+        vs.iter().map(|v| [v.x, v.y, v.z]).collect()
+    }
+
+    // A colored quad, with float positions, byte colors and a four-corner face
+    fn header(format: &str) -> String {
+        format!(
+            "ply\nformat {format} 1.0\ncomment a quad\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\nelement face 1\n\
+             property list uchar int vertex_indices\nend_header\n"
+        )
+    }
+
+    const POSITIONS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [51, 102, 255]];
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = header(format).into_bytes();
+        let f32_bytes = |x: f32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        for (p, c) in POSITIONS.iter().zip(COLORS) {
+            for &x in p {
+                bytes.extend(f32_bytes(x));
+            }
+            bytes.extend(c);
+        }
+        bytes.push(4);
+        for i in 0..4i32 {
+            bytes.extend(if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+        }
+        bytes
+    }
+
+    fn check_quad(mesh: MeshData) {
+        assert_eq!(xyz(&mesh.positions), POSITIONS.map(|p| p.map(f64::from)));
+        assert_eq!(mesh.indices, [[0, 1, 2], [0, 2, 3]]);
+        let colors = xyz(&mesh.colors.unwrap());
+        assert_eq!(colors[..3], [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(colors[3], [0.2, 0.4, 1.0]);
+        assert!(mesh.normals.is_none() && mesh.velocities.is_none());
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let body = "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 51 102 255\n4 0 1 2 3\n";
+        check_quad(parse(format!("{}{body}", header("ascii")).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_little_endian() {
+        check_quad(parse(&binary(false)).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_big_endian() {
+        check_quad(parse(&binary(true)).unwrap());
+    }
+
+    #[test]
+    fn reads_normals_velocities_and_float_colors_and_skips_the_rest() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 3\nproperty double x\nproperty double y\nproperty double z\n\
+                    property float nx\nproperty float ny\nproperty float nz\nproperty float vx\nproperty float vy\nproperty float vz\n\
+                    property float diffuse_red\nproperty float diffuse_green\nproperty float diffuse_blue\nproperty float confidence\n\
+                    element face 1\nproperty list uchar uint vertex_index\nelement edge 1\nproperty int vertex1\nproperty int vertex2\n\
+                    end_header\n\
+                    0 0 0 0 0 1 1 0 0 0.5 0.5 0.5 9\n1 0 0 0 0 1 1 0 0 0.5 0.5 0.5 9\n0 1 0 0 0 1 1 0 0 0.5 0.5 0.5 9\n3 2 1 0\n0 1\n";
+        let mesh = parse(text.as_bytes()).unwrap();
+        assert_eq!(mesh.indices, [[2, 1, 0]]);
+        assert_eq!(xyz(&mesh.normals.unwrap()), [[0.0, 0.0, 1.0]; 3]);
+        assert_eq!(xyz(&mesh.velocities.unwrap()), [[1.0, 0.0, 0.0]; 3]);
+        // Float colors are already scaled to 1
+        assert_eq!(xyz(&mesh.colors.unwrap()), [[0.5, 0.5, 0.5]; 3]);
+    }
+
+    #[test]
+    fn truncated_files_end_early() {
+        let bytes = binary(false);
+        for cut in [1, 4, 13, 20] {
+            let error = parse(&bytes[..bytes.len() - cut]).err().unwrap();
+            assert!(error.ends_with("the file ends early"), "cutting {} bytes gave '{}'", cut, error);
+        }
+        let error = parse(format!("{}0 0 0 255 0 0\n1 0 0", header("ascii")).as_bytes()).err().unwrap();
+        assert_eq!(error, "vertex 1: the file ends early");
+        assert_eq!(parse(b"ply\nformat ascii 1.0\n").err().unwrap(), "the header has no end_header");
+    }
+
+    #[test]
+    fn bad_faces_and_headers_are_errors() {
+        let triangle = |face: &str| {
+            let text = format!(
+                "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                 element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n{face}\n"
+            );
+            parse(text.as_bytes())
+        };
+        assert!(triangle("3 0 1 2").is_ok());
+        assert_eq!(triangle("3 0 1 3").err().unwrap(), "face corner 2 is past the 3 vertices");
+        assert_eq!(triangle("3 0 -1 2").err().unwrap(), "face 0: negative vertex indices");
+        assert_eq!(triangle("2 0 1").err().unwrap(), "face 0: a face needs at least three corners");
+        assert_eq!(triangle("3 0 one 2").err().unwrap(), "face 0: 'one' isn't a number");
+
+        assert_eq!(parse(b"obj\nend_header\n").err().unwrap(), "not a PLY file");
+        assert_eq!(parse(b"ply\nformat utf8 1.0\nend_header\n").err().unwrap(), "line 2: unknown format 'utf8'");
+        assert_eq!(parse(b"ply\nproperty float x\nend_header\n").err().unwrap(), "line 2: a property outside any element");
+        assert_eq!(
+            parse(b"ply\nelement vertex 1\nproperty half x\nend_header\n").err().unwrap(),
+            "line 3: unknown property type 'half'"
+        );
+    }
+}
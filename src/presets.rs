@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use crate::types::*;
+
+pub const NAMES: [&str; 10] = [
+    "gold", "silver", "copper", "aluminium", "chrome", "clay", "plastic", "car-paint", "velvet", "glass",
+];
+
+// Ready-made materials for quick lookdev, so a scene's material can be swapped
+// without writing a new one. The metals use their measured reflectance at normal incidence.
+pub fn by_name(name: &str) -> Option<Arc<dyn Material>> {
+    let material: Arc<dyn Material> = match name {
+        "gold" => Arc::new(MetalMaterial::new(Color::new(1.0, 0.71, 0.29), 0.05)),
+        "silver" => Arc::new(MetalMaterial::new(Color::new(0.95, 0.93, 0.88), 0.05)),
+        "copper" => Arc::new(MetalMaterial::new(Color::new(0.95, 0.64, 0.54), 0.05)),
+        // Brushed rather than polished
+        "aluminium" => Arc::new(MetalMaterial::new(Color::new(0.91, 0.92, 0.92), 0.15)),
+        "chrome" => Arc::new(MetalMaterial::new(Color::new(0.55, 0.56, 0.55), 0.0)),
+        "clay" => Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5))),
+        "plastic" => Arc::new(ClearcoatMaterial::new(Arc::new(LambertianMaterial::new(Color::new(0.8, 0.8, 0.8))), 1.5)),
+        "car-paint" => Arc::new(ClearcoatMaterial::new(Arc::new(LambertianMaterial::new(Color::new(0.7, 0.05, 0.05))), 1.5)),
+        "velvet" => Arc::new(SheenMaterial::velvet(Color::new(0.5, 0.05, 0.1))),
+        "glass" => Arc::new(DielectricMaterial::new(1.5)),
+        _ => return None,
+    };
+    Some(material)
+}
@@ -0,0 +1,51 @@
+use crate::integrator::Integrator;
+use crate::render::{render_view, Image, RenderSettings};
+use crate::scenes::Scene;
+use crate::types::Camera;
+
+// Renders the view as a ladder of `levels` images, each twice the width of the one
+// before and ending at the full `settings` size, for quick feedback on heavy
+// scenes: the first comes back at 1/2^(levels - 1) of the resolution in a small
+// fraction of the time. Rungs below the top take half the samples per pixel of
+// the one above them, so all of them together cost about a seventh more than the
+// full image alone, which is exactly the one a plain render with these settings
+// makes. Every rung is handed to `on_rung`, scaled up to the full size, with its
+// index (0 for the coarsest); returning false there stops the ladder early, as an
+// interactive viewer would once the camera moves again.
+pub fn render_ladder(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    levels: usize,
+    on_rung: &mut dyn FnMut(usize, &Image) -> bool,
+) {
+    let levels = levels.max(1);
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    for rung in 0..levels {
+        let steps_down = (levels - 1 - rung) as u32;
+        let mut rung_settings = *settings;
+        if steps_down > 0 {
+            rung_settings.set_width((settings.image_width >> steps_down.min(15)).max(2));
+            rung_settings.samples_per_pixel = (settings.samples_per_pixel >> steps_down.min(62)).max(1);
+        }
+        let image = render_view(scene, camera, integrator, &rung_settings, false);
+        let image = if image.width == width && image.height == height { image } else { scale_up(&image, width, height) };
+        if !on_rung(rung, &image) {
+            return;
+        }
+    }
+}
+
+// Nearest-neighbour, so the coarse rungs show as blocks rather than a blur
+fn scale_up(image: &Image, width: usize, height: usize) -> Image {
+    let mut scaled = Image::new(width, height);
+    for y in 0..height {
+        let source_y = (y * image.height / height).min(image.height - 1);
+        for x in 0..width {
+            let source_x = (x * image.width / width).min(image.width - 1);
+            scaled.pixels[y * width + x] = image.pixels[source_y * image.width + source_x];
+        }
+    }
+    scaled
+}
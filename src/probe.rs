@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+
+use crate::integrator::Integrator;
+use crate::json;
+use crate::render::{for_each_row, Image, RenderSettings};
+use crate::scenes::Scene;
+use crate::types::*;
+
+// The cube's faces in the order and orientation OpenGL (and most engines) load
+// them: +x, -x, +y, -y, +z, -z. `direction` turns a face's texel position, s
+// across and t down, both from -1 to 1, into the direction it looks along.
+pub const FACES: [&str; 6] = ["+x", "-x", "+y", "-y", "+z", "-z"];
+
+fn direction(face: usize, s: f64, t: f64) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    }
+}
+
+// The light arriving at one point from every direction
+pub struct Probe {
+    pub position: Point3,
+    // The six faces side by side in a strip, each `size` texels square
+    pub cubemap: Image,
+    // Radiance projected onto the nine real spherical harmonics of bands 0 to 2,
+    // ordered (l, m) = (0, 0), (1, -1), (1, 0), (1, 1), (2, -2) ... (2, 2), in
+    // world axes
+    pub sh: [Color; 9],
+}
+
+// Renders a cubemap of the light reaching `position` with `settings.samples_per_pixel`
+// rays per texel, and projects it onto spherical harmonics
+pub fn bake_probe(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, position: Point3, size: usize) -> Probe {
+    let size = size.max(1);
+    let samples = settings.samples_per_pixel.max(1);
+    let mut cubemap = Image::new(6 * size, size);
+    // Rows run through every face in turn, the top row of +x first
+    for_each_row(
+        6 * size,
+        settings,
+        &|index, sampler| -> Vec<Color> {
+            let (face, row) = (index / size, index % size);
+            (0..size)
+                .map(|column| {
+                    let mut total = Color::zero();
+                    for _ in 0..samples {
+                        let s = 2.0 * (column as f64 + sampler.get_1d()) / size as f64 - 1.0;
+                        let t = 2.0 * (row as f64 + sampler.get_1d()) / size as f64 - 1.0;
+                        let r = Ray::new(position, direction(face, s, t));
+                        total += integrator.li(r, scene, sampler, integrator.max_depth());
+                    }
+                    total / samples
+                })
+                .collect()
+        },
+        &mut |index, texels| {
+            let (face, row) = (index / size, index % size);
+            let start = row * cubemap.width + face * size;
+            cubemap.pixels[start..start + size].copy_from_slice(&texels);
+        },
+    );
+    let sh = project_sh(&cubemap, size);
+    Probe { position, cubemap, sh }
+}
+
+// Each texel weighted by the solid angle it covers, which shrinks towards a
+// face's corners
+fn project_sh(cubemap: &Image, size: usize) -> [Color; 9] {
+    let mut sh = [Color::zero(); 9];
+    let texel = 2.0 / size as f64;
+    for face in 0..6 {
+        for row in 0..size {
+            for column in 0..size {
+                let (s0, t0) = (column as f64 * texel - 1.0, row as f64 * texel - 1.0);
+                let (s, t) = (s0 + texel / 2.0, t0 + texel / 2.0);
+                let solid_angle = corner_solid_angle(s0, t0) - corner_solid_angle(s0 + texel, t0)
+                    - corner_solid_angle(s0, t0 + texel)
+                    + corner_solid_angle(s0 + texel, t0 + texel);
+                let radiance = cubemap.pixels[row * cubemap.width + face * size + column];
+                for (coefficient, basis) in sh.iter_mut().zip(sh_basis(direction(face, s, t).unit_vector())) {
+                    *coefficient += radiance * (basis * solid_angle);
+                }
+            }
+        }
+    }
+    sh
+}
+
+// The solid angle of the part of a face between its center and (s, t), signed
+// by quadrant, so any texel's is a sum over its corners
+fn corner_solid_angle(s: f64, t: f64) -> f64 {
+    (s * t).atan2((s * s + t * t + 1.0).sqrt())
+}
+
+fn sh_basis(d: Vec3) -> [f64; 9] {
+    let (x, y, z) = (d.x, d.y, d.z);
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+// Every probe's position, cubemap file and coefficients
+pub fn write_json(probes: &[(Probe, String)], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"faces\": [{}],", FACES.map(json::string).join(", "))?;
+    writeln!(out, "  \"probes\": [")?;
+    for (index, (probe, cubemap)) in probes.iter().enumerate() {
+        let separator = if index + 1 < probes.len() { "," } else { "" };
+        let sh: Vec<String> = probe.sh.iter().map(|&c| json::vector(c)).collect();
+        writeln!(
+            out,
+            "    {{ \"position\": {}, \"cubemap\": {}, \"sh\": [{}] }}{separator}",
+            json::vector(probe.position),
+            json::string(cubemap),
+            sh.join(", ")
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use crate::bvh::{Aabb, Bvh, BATCH_SIZE};
+use crate::integrator::T_MIN;
+use crate::scenes::Scene;
+use crate::types::*;
+
+// Where a ray first met a surface, without the borrows a HitRecord holds, for
+// engines using the crate only to find intersections
+#[derive(Clone, Copy)]
+pub struct Hit {
+    pub t: f64,
+    pub p: Point3,
+    // Faces against the ray
+    pub normal: Vec3,
+    pub u: f64,
+    pub v: f64,
+    pub front_face: bool,
+    // The index of the object that was hit among those the intersector was made
+    // from
+    pub object: usize,
+}
+
+// Finds the nearest hits of whole batches of rays against a fixed set of objects,
+// as an intersection backend for another engine that does its own shading
+pub struct Intersector {
+    objects: Vec<Arc<dyn Hittable>>,
+    bvh: Bvh<usize>,
+}
+
+impl Intersector {
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
+        let bvh = Bvh::with_bounds(objects.iter().enumerate().map(|(i, object)| (i, object.bounding_box())));
+        Intersector { objects: objects.to_vec(), bvh }
+    }
+
+    // Over everything in the scene's world, so `Hit::object` indexes that
+    pub fn for_scene(scene: &Scene) -> Self {
+        Intersector::new(&scene.world.objects)
+    }
+
+    // The nearest hit of each ray between `ray_t`, or None where it hits nothing,
+    // in the order the rays were given. The rays are sorted so that those heading
+    // the same way from near the same place go down the tree together.
+    pub fn trace_rays_in(&self, rays: &[Ray], ray_t: Interval) -> Vec<Option<Hit>> {
+        let mut hits = vec![None; rays.len()];
+        let order = coherent_order(rays);
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for indices in order.chunks(BATCH_SIZE) {
+            batch.clear();
+            batch.extend(indices.iter().map(|&i| rays[i]));
+            let mut rec = HitRecord::blank();
+            self.bvh.traverse_batch(&batch, ray_t, |k, &object, range| {
+                if !self.objects[object].hit(batch[k], range, &mut rec) {
+                    return None;
+                }
+                hits[indices[k]] = Some(Hit {
+                    t: rec.t,
+                    p: rec.p,
+                    normal: rec.normal,
+                    u: rec.u,
+                    v: rec.v,
+                    front_face: rec.front_face,
+                    object,
+                });
+                Some(rec.t)
+            });
+        }
+        hits
+    }
+
+    // As `trace_rays_in`, ignoring hits closer than the integrators' T_MIN, which
+    // keeps rays off the surface they start on
+    pub fn trace_rays(&self, rays: &[Ray]) -> Vec<Option<Hit>> {
+        self.trace_rays_in(rays, Interval::new(T_MIN, f64::INFINITY))
+    }
+}
+
+// The rays' indices sorted by the octant their directions point into, so each
+// batch's rays take the same side of every split, then along a Morton curve
+// through their directions and origins, so neighbours in the order are alike
+fn coherent_order(rays: &[Ray]) -> Vec<usize> {
+    let bounds = rays.iter().fold(Aabb::empty(), |b, r| b.union(&Aabb::new(r.origin, r.origin)));
+    let extent = bounds.max - bounds.min;
+    let mut keyed: Vec<(u64, usize)> = rays
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let d = r.direction.unit_vector();
+            let octant = (d.x < 0.0) as u64 | ((d.y < 0.0) as u64) << 1 | ((d.z < 0.0) as u64) << 2;
+            let quantize = |x: f64, size: f64| if size > 0.0 { ((x * 1023.0 / size) as u64).min(1023) } else { 0 };
+            let heading = interleave([
+                quantize(d.x + 1.0, 2.0),
+                quantize(d.y + 1.0, 2.0),
+                quantize(d.z + 1.0, 2.0),
+            ]);
+            let place = interleave([
+                quantize(r.origin.x - bounds.min.x, extent.x),
+                quantize(r.origin.y - bounds.min.y, extent.y),
+                quantize(r.origin.z - bounds.min.z, extent.z),
+            ]);
+            // The top half of each curve is plenty to group rays 64 at a time
+            (octant << 30 | (heading >> 15) << 15 | place >> 15, i)
+        })
+        .collect();
+    keyed.sort_unstable();
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+// Spreads the low ten bits of each coordinate out and interleaves them, x lowest
+fn interleave(coords: [u64; 3]) -> u64 {
+    let mut key = 0;
+    for bit in 0..10 {
+        for (axis, coord) in coords.iter().enumerate() {
+            key |= ((coord >> bit) & 1) << (3 * bit + axis);
+        }
+    }
+    key
+}
@@ -0,0 +1,554 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    sync::mpsc,
+    thread,
+};
+
+use crate::integrator::Integrator;
+use crate::sampler::{RandomSampler, Sampler};
+use crate::scenes::Scene;
+use crate::threads;
+use crate::types::*;
+
+pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
+
+// Camera samples that came out NaN or infinite, from every render so far
+static NON_FINITE_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+pub fn non_finite_samples() -> u64 {
+    NON_FINITE_SAMPLES.load(Ordering::Relaxed)
+}
+
+// What a row that panicked is filled with, so it stands out
+const FAILED_ROW_COLOR: Color = Color { x: 1.0, y: 0.0, z: 1.0 };
+
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: u16,
+    pub image_height: u16,
+    pub samples_per_pixel: i64,
+    pub max_depth: i64,
+    // Renders with the same seed come out identical; without one every render differs
+    pub seed: Option<u64>,
+    // Rows are shared out between this many worker threads
+    pub threads: usize,
+    // Keeps each worker on a core of its own, where the OS allows it
+    pub pin_threads: bool,
+    // Workers run at the lowest priority and step aside after every row, so a long
+    // render can go on in the background
+    pub low_priority: bool,
+    // Each camera sample is scaled down so no channel goes over this, trading a
+    // little lost energy for far fewer fireflies
+    pub max_sample_value: Option<f64>,
+}
+
+impl RenderSettings {
+    pub fn default_settings() -> Self {
+        RenderSettings::with_width(400)
+    }
+
+    // The camera is fixed at 16:9, so the height always follows the width
+    pub fn with_width(image_width: u16) -> Self {
+        RenderSettings {
+            image_width,
+            image_height: (image_width as f64 / ASPECT_RATIO) as u16,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            seed: None,
+            threads: threads::available(),
+            pin_threads: false,
+            low_priority: false,
+            max_sample_value: None,
+        }
+    }
+
+    pub fn set_width(&mut self, image_width: u16) {
+        self.image_width = image_width;
+        self.image_height = (image_width as f64 / ASPECT_RATIO) as u16;
+    }
+}
+
+// Averaged linear radiance per pixel, top row first
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::zero(); width * height],
+        }
+    }
+
+    // Reads an ASCII (P3) or binary (P6) PPM, undoing the gamma `write_ppm` applies
+    pub fn read_ppm<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let bytes = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        // Header tokens are whitespace separated, with '#' comments running to the end of the line
+        let mut pos = 0;
+        let next_token = |pos: &mut usize| -> Option<String> {
+            loop {
+                while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+                    *pos += 1;
+                }
+                if *pos < bytes.len() && bytes[*pos] == b'#' {
+                    while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                        *pos += 1;
+                    }
+                    continue;
+                }
+                break;
+            }
+            let start = *pos;
+            while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            (start < *pos).then(|| String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+        };
+
+        let magic = next_token(&mut pos).ok_or_else(|| invalid("empty file"))?;
+        let number = |pos: &mut usize| -> io::Result<usize> {
+            next_token(pos)
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| invalid("bad PPM header"))
+        };
+        let width = number(&mut pos)?;
+        let height = number(&mut pos)?;
+        let max_value = number(&mut pos)?.max(1) as f64;
+
+        let count = width * height * 3;
+        let samples: Vec<f64> = match magic.as_str() {
+            "P3" => {
+                let mut samples = Vec::with_capacity(count);
+                for _ in 0..count {
+                    samples.push(number(&mut pos)? as f64);
+                }
+                samples
+            }
+            "P6" if max_value < 256.0 => {
+                // Exactly one whitespace byte separates the header from the raster
+                let data = bytes.get(pos + 1..pos + 1 + count).ok_or_else(|| invalid("truncated PPM data"))?;
+                data.iter().map(|&b| b as f64).collect()
+            }
+            _ => return Err(invalid("only P3 and 8-bit P6 PPM files are supported")),
+        };
+
+        let mut image = Image::new(width, height);
+        for (pixel, rgb) in image.pixels.iter_mut().zip(samples.chunks(3)) {
+            let decode = |c: f64| decode_ppm_sample(c, max_value);
+            *pixel = Color::new(decode(rgb[0]), decode(rgb[1]), decode(rgb[2]));
+        }
+        Ok(image)
+    }
+
+    // The mean over a (2 radius + 1) pixel square, done as two passes of running sums
+    pub fn box_blurred(&self, radius: usize) -> Image {
+        if radius == 0 {
+            return Image { width: self.width, height: self.height, pixels: self.pixels.clone() };
+        }
+        let (width, height) = (self.width, self.height);
+        let pass = |source: &[Color], lines: usize, along: usize, at: &dyn Fn(usize, usize) -> usize| -> Vec<Color> {
+            let mut out = vec![Color::zero(); source.len()];
+            let scale = 1.0 / (2 * radius + 1) as f64;
+            for line in 0..lines {
+                let mut sum = Color::zero();
+                for i in 0..radius.min(along) {
+                    sum += source[at(line, i)];
+                }
+                for i in 0..along {
+                    if i + radius < along {
+                        sum += source[at(line, i + radius)];
+                    }
+                    if i > radius {
+                        sum = sum - source[at(line, i - radius - 1)];
+                    }
+                    out[at(line, i)] = sum * scale;
+                }
+            }
+            out
+        };
+        let rows = pass(&self.pixels, height, width, &|y, x| y * width + x);
+        let pixels = pass(&rows, width, height, &|x, y| y * width + x);
+        Image { width, height, pixels }
+    }
+
+    // Gamma-corrects and quantizes to 8 bits per channel
+    pub fn write_ppm(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.write_ppm_with_comments(out, &[])
+    }
+
+    // As write_ppm, with each of `comments` on a '#' line of its own in the header
+    pub fn write_ppm_with_comments(&self, out: &mut dyn Write, comments: &[String]) -> io::Result<()> {
+        write_ppm_header(out, self.width, self.height, comments)?;
+        for &color in &self.pixels {
+            write_ppm_pixel(out, color)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_ppm_header(out: &mut dyn Write, width: usize, height: usize, comments: &[String]) -> io::Result<()> {
+    writeln!(out, "P3")?;
+    for comment in comments {
+        writeln!(out, "# {comment}")?;
+    }
+    writeln!(out, "{width} {height}\n255")
+}
+
+// PPM files hold colors gamma-encoded by a square root, as `write_ppm_pixel`
+// writes them, so a sample out of `max_value` is squared back to linear light
+pub fn decode_ppm_sample(value: f64, max_value: f64) -> f64 {
+    (value / max_value).powi(2)
+}
+
+fn write_ppm_pixel(out: &mut dyn Write, color: Color) -> io::Result<()> {
+    let r = color.x.sqrt();
+    let g = color.y.sqrt();
+    let b = color.z.sqrt();
+
+    writeln!(
+        out,
+        "{} {} {}",
+        (256.0 * r.clamp(0.0, 0.999)) as i64,
+        (256.0 * g.clamp(0.0, 0.999)) as i64,
+        (256.0 * b.clamp(0.0, 0.999)) as i64
+    )
+}
+
+pub fn render(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, show_progress: bool) -> Image {
+    render_view(scene, &scene.camera, integrator, settings, show_progress)
+}
+
+// Renders the scene through `camera` instead of the scene's own
+pub fn render_view(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    show_progress: bool,
+) -> Image {
+    render_view_with(scene, camera, integrator, settings, show_progress, &mut |_, _, _| {})
+}
+
+// Like `render_view`, calling `on_pixel` with the column, row (top row first) and
+// averaged linear color of each pixel as soon as it's finished. Whatever the
+// callback leaves in the color is what goes into the image, so it can tonemap or
+// filter pixels as well as watch them go by.
+pub fn render_view_with(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    show_progress: bool,
+    on_pixel: &mut dyn FnMut(usize, usize, &mut Color),
+) -> Image {
+    let width = settings.image_width as usize;
+    let mut image = Image::new(width, settings.image_height as usize);
+    render_rows(scene, camera, integrator, settings, show_progress, &mut |row, colors| {
+        for (i, mut color) in colors.into_iter().enumerate() {
+            on_pixel(i, row, &mut color);
+            image.pixels[row * width + i] = color;
+        }
+    });
+    image
+}
+
+// Renders the image as two independent halves, each from its own half of every
+// pixel's samples (the first from one more when the count is odd), for estimating
+// variance and error or cross-denoising downstream. The halves' average, weighted
+// by their sample counts, is the image `render_view` makes; with a seed, the same
+// one down to rounding. Needs at least two samples per pixel.
+pub fn render_halves(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    show_progress: bool,
+) -> (Image, Image) {
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let (mut a, mut b) = (Image::new(width, height), Image::new(width, height));
+    let first = (settings.samples_per_pixel + 1) / 2;
+    let second = settings.samples_per_pixel - first;
+    // Drawing the halves' samples one after the other takes them from the sampler
+    // in the same order as a plain render
+    let camera = camera.for_image_height(settings.image_height);
+    let pixel = |sampler: &mut dyn Sampler, i, j| {
+        let a = sample_pixel(scene, integrator, &camera, sampler, settings, (i, j), first);
+        (a, sample_pixel(scene, integrator, &camera, sampler, settings, (i, j), second))
+    };
+    render_rows_with(settings, show_progress, &pixel, &(FAILED_ROW_COLOR, FAILED_ROW_COLOR), &mut |row, pairs| {
+        for (i, (color_a, color_b)) in pairs.into_iter().enumerate() {
+            a.pixels[row * width + i] = color_a;
+            b.pixels[row * width + i] = color_b;
+        }
+    });
+    (a, b)
+}
+
+// Like `render_view_with`, but writes the image to `out` as a PPM a row at a time,
+// each as soon as it and every row above it are done, instead of holding on to the
+// whole image. However large the render, only the few rows the threads are still
+// working on are kept in memory.
+pub fn render_view_to_ppm(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    show_progress: bool,
+    on_pixel: &mut dyn FnMut(usize, usize, &mut Color),
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    write_ppm_header(out, settings.image_width as usize, settings.image_height as usize, &[])?;
+    // Rows that finished before one above them
+    let mut waiting = BTreeMap::new();
+    let mut next_row = 0;
+    let mut written = Ok(());
+    render_rows(scene, camera, integrator, settings, show_progress, &mut |row, colors| {
+        waiting.insert(row, colors);
+        while let Some(colors) = waiting.remove(&next_row) {
+            for (i, mut color) in colors.into_iter().enumerate() {
+                on_pixel(i, next_row, &mut color);
+                if written.is_ok() {
+                    written = write_ppm_pixel(out, color);
+                }
+            }
+            next_row += 1;
+        }
+    });
+    written
+}
+
+// Renders every row of the image on `settings.threads` threads, handing each to
+// `on_row` on the calling thread as it finishes: its index counted from the top,
+// and the averaged linear color of each pixel in it. Rows are started from the
+// top, but may finish in any order.
+fn render_rows(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    show_progress: bool,
+    on_row: &mut dyn FnMut(usize, Vec<Color>),
+) {
+    let camera = camera.for_image_height(settings.image_height);
+    let pixel = |sampler: &mut dyn Sampler, i, j| {
+        sample_pixel(scene, integrator, &camera, sampler, settings, (i, j), settings.samples_per_pixel)
+    };
+    render_rows_with(settings, show_progress, &pixel, &FAILED_ROW_COLOR, on_row);
+}
+
+// Like `render_rows`, with `pixel` working out what each pixel (i, j) holds from
+// the sampler it's given, j counted up from the bottom row. A row that panics (a bad
+// asset, say) is reported and filled with `failed`, and the rest of the render
+// goes on without it.
+fn render_rows_with<T: Send + Sync + Clone>(
+    settings: &RenderSettings,
+    show_progress: bool,
+    pixel: &(dyn Fn(&mut dyn Sampler, u16, u16) -> T + Sync),
+    failed: &T,
+    on_row: &mut dyn FnMut(usize, Vec<T>),
+) {
+    let (width, height) = (settings.image_width, settings.image_height);
+    let non_finite_before = non_finite_samples();
+    let failed_rows = AtomicUsize::new(0);
+    let workers = settings.threads.clamp(1, height.max(1) as usize);
+    let cores = if settings.pin_threads { threads::allowed_cores() } else { Vec::new() };
+    // Rows are handed out from the top, as a single thread would render them
+    let next = AtomicUsize::new(0);
+    let (finished, rows) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for worker in 0..workers {
+            let (next, finished, cores, failed_rows) = (&next, finished.clone(), &cores, &failed_rows);
+            scope.spawn(move || {
+                if let Some(&core) = cores.get(worker % cores.len().max(1)) {
+                    threads::pin_current(core);
+                }
+                if settings.low_priority {
+                    threads::lower_priority();
+                }
+                let mut random = RandomSampler::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= height as usize {
+                        break;
+                    }
+                    let j = height - 1 - index as u16;
+                    let row = panic::catch_unwind(AssertUnwindSafe(|| {
+                        (0..width)
+                            .map(|i| match settings.seed {
+                                Some(seed) => pixel(&mut RandomSampler::seeded(pixel_seed(seed, (i, j))), i, j),
+                                None => pixel(&mut random, i, j),
+                            })
+                            .collect()
+                    }));
+                    let colors: Vec<T> = row.unwrap_or_else(|_| {
+                        failed_rows.fetch_add(1, Ordering::Relaxed);
+                        eprintln!("\nRow {index} (counted from the top) panicked; its pixels are marked in magenta");
+                        vec![failed.clone(); width as usize]
+                    });
+                    if finished.send((j, colors)).is_err() {
+                        break;
+                    }
+                    if settings.low_priority {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+        drop(finished);
+
+        for (remaining, (j, colors)) in rows.iter().enumerate().map(|(done, row)| (height as usize - 1 - done, row)) {
+            if show_progress {
+                eprint!("\rScanlines remaining: {remaining}      ");
+            }
+            on_row((height - 1 - j) as usize, colors);
+        }
+    });
+    if show_progress {
+        eprintln!("\rOperation complete.      ");
+        let non_finite = non_finite_samples() - non_finite_before;
+        if non_finite > 0 {
+            eprintln!("{non_finite} samples came out NaN or infinite and were left out of their pixels");
+        }
+    }
+    let failed_rows = failed_rows.into_inner();
+    if failed_rows > 0 {
+        eprintln!("{failed_rows} rows panicked and were marked in magenta; the rest of the image is complete");
+    }
+}
+
+// The seed of row j's samples, spread out so neighbouring rows' streams don't
+// start out alike
+fn row_seed(seed: u64, j: u64) -> u64 {
+    seed ^ (j + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+// The seed of pixel (i, j)'s samples, with j counted up from the bottom row. Every
+// pixel of a seeded render has a stream of its own, so what it gets depends on
+// neither which thread renders it nor what was rendered before it on that thread,
+// and a pixel traced on its own sees the same samples as in the full image.
+pub fn pixel_seed(seed: u64, (i, j): (u16, u16)) -> u64 {
+    row_seed(seed, (j as u64) << 16 | i as u64)
+}
+
+// Works out `rows` rows of anything that isn't a camera's image (texels, say) on
+// `settings.threads` threads, handing each result to `on_row` on the calling
+// thread as it finishes, in any order. Each row gets its own sampler, seeded from
+// the row's index when `settings` are seeded, so the results don't depend on the
+// number of threads.
+pub fn for_each_row<T: Send>(
+    rows: usize,
+    settings: &RenderSettings,
+    work: &(dyn Fn(usize, &mut dyn Sampler) -> T + Sync),
+    on_row: &mut dyn FnMut(usize, T),
+) {
+    let next = AtomicUsize::new(0);
+    let (finished, results) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..settings.threads.clamp(1, rows.max(1)) {
+            let (next, finished) = (&next, finished.clone());
+            scope.spawn(move || {
+                if settings.low_priority {
+                    threads::lower_priority();
+                }
+                let mut random = RandomSampler::new();
+                loop {
+                    let row = next.fetch_add(1, Ordering::Relaxed);
+                    if row >= rows {
+                        break;
+                    }
+                    let mut seeded = settings.seed.map(|seed| RandomSampler::seeded(row_seed(seed, row as u64)));
+                    let sampler: &mut dyn Sampler = match &mut seeded {
+                        Some(seeded) => seeded,
+                        None => &mut random,
+                    };
+                    if finished.send((row, work(row, sampler))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(finished);
+        for (row, result) in results {
+            on_row(row, result);
+        }
+    });
+}
+
+// The mean of `samples` camera rays through pixel (i, j), with j counted up from the bottom row.
+// A sample that comes out NaN or infinite (a degenerate direction somewhere along
+// its path, say) is counted and left out rather than spoiling the whole pixel.
+// Samples brighter than `settings.max_sample_value` are scaled down to it.
+pub fn sample_pixel(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    camera: &Camera,
+    sampler: &mut dyn Sampler,
+    settings: &RenderSettings,
+    (i, j): (u16, u16),
+    samples: i64,
+) -> Color {
+    let (width, height) = (settings.image_width, settings.image_height);
+    let to_uv = |x: f64, y: f64| (x / (width - 1) as f64, y / (height - 1) as f64);
+    let misses = !pixel_may_hit(scene, camera, (i, j), to_uv);
+    // Rows count up from the bottom, and a rolling shutter reads from the top
+    let scanline = (height - 1 - j) as f64 / height as f64;
+    let mut pixel_color = Color::zero();
+    let mut good = 0;
+    for _s in 0..samples {
+        let (u, v) = to_uv(i as f64 + sampler.get_1d(), j as f64 + sampler.get_1d());
+        let r = camera.sample_ray(u, v, scanline, sampler);
+        let mut color = if misses {
+            integrator.miss(r, scene)
+        } else {
+            integrator.li(r, scene, sampler, integrator.max_depth())
+        };
+        if !(color.x.is_finite() && color.y.is_finite() && color.z.is_finite()) {
+            NON_FINITE_SAMPLES.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+        if let Some(max) = settings.max_sample_value {
+            let brightest = color.x.max(color.y).max(color.z);
+            if brightest > max {
+                color *= max / brightest;
+            }
+        }
+        pixel_color += color;
+        good += 1;
+    }
+    if good == 0 {
+        return Color::zero();
+    }
+    pixel_color / good
+}
+
+// Whether any camera ray through pixel (i, j) could reach the scene's bounding
+// sphere. Every ray through the pixel starts at the camera and lies within the
+// cone around its center that takes in all four corners.
+fn pixel_may_hit(scene: &Scene, camera: &Camera, (i, j): (u16, u16), to_uv: impl Fn(f64, f64) -> (f64, f64)) -> bool {
+    let Some(bounds) = scene.bounds else {
+        return true;
+    };
+    let direction = |x: f64, y: f64| {
+        let (u, v) = to_uv(x, y);
+        camera.get_ray(u, v).direction.unit_vector()
+    };
+    let (x, y) = (i as f64, j as f64);
+    let axis = direction(x + 0.5, y + 0.5);
+    let half_angle = [(x, y), (x + 1.0, y), (x, y + 1.0), (x + 1.0, y + 1.0)]
+        .iter()
+        .map(|&(cx, cy)| axis.dot(direction(cx, cy)).clamp(-1.0, 1.0).acos())
+        .fold(0.0, f64::max);
+    bounds.within_cone(camera.origin, axis, half_angle)
+}
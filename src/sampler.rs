@@ -0,0 +1,52 @@
+use rand::{
+    prelude::{StdRng, ThreadRng},
+    thread_rng, Rng, RngCore, SeedableRng,
+};
+
+// Source of every random number a render consumes, from pixel jitter to the
+// directions materials scatter in
+pub trait Sampler {
+    // A uniform sample in [0, 1)
+    fn get_1d(&mut self) -> f64;
+
+    // A uniform sample in [0, 1)^2, for the warps in `sampling`
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Independent uniform random samples from any random number generator: by
+// default the thread's own, or a seeded one for repeatable renders
+pub struct RandomSampler<R = ThreadRng> {
+    rng: R,
+}
+
+impl RandomSampler {
+    pub fn new() -> Self {
+        RandomSampler { rng: thread_rng() }
+    }
+}
+
+impl RandomSampler<StdRng> {
+    pub fn seeded(seed: u64) -> Self {
+        RandomSampler::from_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: RngCore> RandomSampler<R> {
+    pub fn from_rng(rng: R) -> Self {
+        RandomSampler { rng }
+    }
+}
+
+impl Default for RandomSampler {
+    fn default() -> Self {
+        RandomSampler::new()
+    }
+}
+
+impl<R: RngCore> Sampler for RandomSampler<R> {
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+}
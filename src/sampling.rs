@@ -0,0 +1,175 @@
+use crate::types::*;
+
+// Warps uniform samples in [0, 1)^2 into directions and points with known
+// densities. Directions are in a local frame with z up (the surface normal, or
+// the axis of a cone); each warp has a matching `_pdf` giving its density per
+// unit solid angle (or per unit area, for the disk).
+
+// Shirley and Chiu's concentric mapping of the square onto the unit disk, which
+// keeps nearby samples nearby and spreads them evenly without rejection
+pub fn concentric_disk((u0, u1): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2.0 * u0 - 1.0, 2.0 * u1 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn concentric_disk_pdf() -> f64 {
+    1.0 / PI
+}
+
+// Malley's method: points spread evenly over the disk, lifted onto the hemisphere
+pub fn cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let (x, y) = concentric_disk(u);
+    Vec3::new(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.0) / PI
+}
+
+pub fn uniform_sphere((u0, u1): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+pub fn uniform_hemisphere((u0, u1): (f64, f64)) -> Vec3 {
+    let z = u0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_hemisphere_pdf() -> f64 {
+    1.0 / (2.0 * PI)
+}
+
+// Directions within `theta_max` of the z axis, given as its cosine, all equally likely
+pub fn uniform_cone((u0, u1): (f64, f64), cos_theta_max: f64) -> Vec3 {
+    let cos_theta = (1.0 - u0) + u0 * cos_theta_max;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u1;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+// The cosine of the half-angle of the cone of directions from `p` that hit the
+// sphere, for sampling the sphere with `uniform_cone` around the direction to its
+// center; None when `p` is inside it and every direction does
+pub fn sphere_cone_cos_theta_max(p: Point3, center: Point3, radius: f64) -> Option<f64> {
+    let distance_squared = (center - p).length_squared();
+    let radius_squared = radius * radius;
+    if distance_squared <= radius_squared {
+        return None;
+    }
+    Some((1.0 - radius_squared / distance_squared).max(0.0).sqrt())
+}
+
+// A point spread evenly through the unit ball, from three uniform samples
+pub fn uniform_ball(u: (f64, f64), u_radius: f64) -> Vec3 {
+    uniform_sphere(u) * u_radius.cbrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N: usize = 200;
+
+    // The centers of an N by N grid over the unit square
+    fn grid() -> impl Iterator<Item = (f64, f64)> {
+        (0..N * N).map(|i| (((i % N) as f64 + 0.5) / N as f64, ((i / N) as f64 + 0.5) / N as f64))
+    }
+
+    // Integrates `f` over the sphere of directions. `uniform_sphere` keeps areas,
+    // so the grid's points each stand for an equal share of the sphere.
+    fn integrate_sphere(f: impl Fn(Vec3) -> f64) -> f64 {
+        grid().map(|u| f(uniform_sphere(u))).sum::<f64>() * 4.0 * PI / (N * N) as f64
+    }
+
+    #[test]
+    fn concentric_disk_stays_in_the_unit_disk() {
+        for u in grid().chain([(0.0, 0.0), (0.0, 0.999_999), (0.999_999, 0.5), (0.5, 0.5)]) {
+            let (x, y) = concentric_disk(u);
+            assert!(x * x + y * y <= 1.0 + 1e-12, "{:?} went to ({}, {})", u, x, y);
+        }
+    }
+
+    #[test]
+    fn uniform_ball_stays_in_the_unit_ball() {
+        for (i, u) in grid().enumerate() {
+            let p = uniform_ball(u, (i % 97) as f64 / 96.0);
+            assert!(p.length_squared() <= 1.0 + 1e-12, "{:?} went to length {}", u, p.length());
+        }
+    }
+
+    #[test]
+    fn cosine_hemisphere_stays_above_the_surface() {
+        for u in grid() {
+            let d = cosine_hemisphere(u);
+            assert!(d.z >= 0.0, "{:?} went below, to z = {}", u, d.z);
+            assert!((d.length() - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn uniform_cone_stays_in_the_cone() {
+        for cos_theta_max in [-0.5, 0.0, 0.5, 0.99] {
+            for u in grid() {
+                let d = uniform_cone(u, cos_theta_max);
+                assert!(d.z >= cos_theta_max - 1e-12, "{:?} left the cone of cosine {}, at z = {}", u, cos_theta_max, d.z);
+            }
+        }
+    }
+
+    #[test]
+    fn direction_pdfs_integrate_to_one() {
+        let cosine = integrate_sphere(|d| cosine_hemisphere_pdf(d.z));
+        let sphere = integrate_sphere(|_| uniform_sphere_pdf());
+        let hemisphere = integrate_sphere(|d| if d.z > 0.0 { uniform_hemisphere_pdf() } else { 0.0 });
+        let cone = integrate_sphere(|d| if d.z >= 0.7 { uniform_cone_pdf(0.7) } else { 0.0 });
+        for (name, total) in [("cosine", cosine), ("sphere", sphere), ("hemisphere", hemisphere), ("cone", cone)] {
+            assert!((total - 1.0).abs() < 1e-2, "the {} pdf integrates to {}", name, total);
+        }
+    }
+
+    #[test]
+    fn disk_pdf_integrates_to_one() {
+        // Over the square from -1 to 1, with the pdf zero outside the disk
+        let total = grid()
+            .map(|(u0, u1)| (2.0 * u0 - 1.0, 2.0 * u1 - 1.0))
+            .map(|(x, y)| if x * x + y * y <= 1.0 { concentric_disk_pdf() } else { 0.0 })
+            .sum::<f64>()
+            * 4.0
+            / (N * N) as f64;
+        assert!((total - 1.0).abs() < 1e-2, "the disk pdf integrates to {}", total);
+    }
+
+    #[test]
+    fn sphere_cone_cos_theta_max_inside_and_outside() {
+        let center = Point3::new(1.0, 2.0, 3.0);
+        assert_eq!(sphere_cone_cos_theta_max(center, center, 1.0), None);
+        assert_eq!(sphere_cone_cos_theta_max(center + Vec3::new(0.5, 0.0, 0.0), center, 1.0), None);
+        // On the surface every direction into the sphere's side hits it too
+        assert_eq!(sphere_cone_cos_theta_max(center + Vec3::new(0.0, 1.0, 0.0), center, 1.0), None);
+
+        // Twice the radius away, the tangent rays are 30 degrees off the center
+        let cos_theta_max = sphere_cone_cos_theta_max(center + Vec3::new(0.0, 0.0, 2.0), center, 1.0).unwrap();
+        assert!((cos_theta_max - (PI / 6.0).cos()).abs() < 1e-12);
+    }
+}
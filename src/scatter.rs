@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use crate::sampler::{RandomSampler, Sampler};
+use crate::transform::{Instance, Quat, Transform};
+use crate::types::*;
+
+// Gives up on a scatter after this many misses per copy asked for, so a region
+// that mostly misses the surface can't loop forever
+const TRIES_PER_COPY: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScatterRotation {
+    // Keeps the prototype as it was modeled
+    Fixed,
+    // Turns each copy a random amount about its up axis, as trees and rocks stand
+    Up,
+    // Turns each copy to a uniformly random orientation, for tumbled debris
+    Any,
+}
+
+impl ScatterRotation {
+    pub fn by_name(name: &str) -> Option<ScatterRotation> {
+        match name {
+            "none" => Some(ScatterRotation::Fixed),
+            "up" => Some(ScatterRotation::Up),
+            "any" => Some(ScatterRotation::Any),
+            _ => None,
+        }
+    }
+}
+
+// How copies of a prototype are strewn over a surface. Copies land at points
+// spread evenly over `region`, a disk of (center x, center z, radius) seen from
+// above, or the surface's whole bounding box when None, and each copy is set
+// down with its origin on the surface.
+pub struct Scatter {
+    pub count: usize,
+    pub seed: u64,
+    pub region: Option<(f64, f64, f64)>,
+    // Each copy is scaled by a uniformly random factor between the two
+    pub scale: (f64, f64),
+    pub rotation: ScatterRotation,
+    // Tilts each copy's up axis to the surface normal where it lands
+    pub align_to_normal: bool,
+    // Each copy gets one of these at random, as an instance tint; none when empty
+    pub tints: Vec<Color>,
+}
+
+impl Scatter {
+    pub fn new(count: usize) -> Self {
+        Scatter {
+            count,
+            seed: 0,
+            region: None,
+            scale: (1.0, 1.0),
+            rotation: ScatterRotation::Up,
+            align_to_normal: false,
+            tints: Vec::new(),
+        }
+    }
+}
+
+// Instances of `prototype` placed by dropping rays straight down onto `surface`
+// from above it. Points where the ray misses are tried again elsewhere, so fewer
+// than `count` copies come back only when the region hardly covers the surface.
+// Copies may overlap. None when the surface has no bounds and no region is given.
+pub fn scatter(surface: &dyn Hittable, prototype: Arc<dyn Hittable>, settings: &Scatter) -> Option<Vec<Arc<dyn Hittable>>> {
+    let bounds = surface.bounding_box();
+    let (min_x, max_x, min_z, max_z) = match (settings.region, bounds) {
+        (Some((x, z, r)), _) => (x - r, x + r, z - r, z + r),
+        (None, Some(b)) => (b.min.x, b.max.x, b.min.z, b.max.z),
+        (None, None) => return None,
+    };
+    let top = bounds.map_or(1e4, |b| b.max.y + 1.0);
+
+    let mut sampler = RandomSampler::seeded(settings.seed);
+    let mut copies: Vec<Arc<dyn Hittable>> = Vec::with_capacity(settings.count);
+    let mut tries = 0;
+    while copies.len() < settings.count && tries < settings.count * TRIES_PER_COPY {
+        tries += 1;
+        let (x, z) = match settings.region {
+            Some((x, z, r)) => {
+                let (radius, angle) = (r * sampler.get_1d().sqrt(), 2.0 * PI * sampler.get_1d());
+                (x + radius * angle.cos(), z + radius * angle.sin())
+            }
+            None => (min_x + (max_x - min_x) * sampler.get_1d(), min_z + (max_z - min_z) * sampler.get_1d()),
+        };
+        let ray = Ray::new(Point3::new(x, top, z), Vec3::new(0.0, -1.0, 0.0));
+        let mut rec = HitRecord::blank();
+        if !surface.hit(ray, Interval::new(0.0, f64::INFINITY), &mut rec) {
+            continue;
+        }
+
+        // Drawn in the same order whatever the options, so changing one leaves
+        // the others' choices alone
+        let (s0, s1, s2, s3, s4) = (sampler.get_1d(), sampler.get_1d(), sampler.get_1d(), sampler.get_1d(), sampler.get_1d());
+        let scale = settings.scale.0 + (settings.scale.1 - settings.scale.0) * s0;
+        let spin = match settings.rotation {
+            ScatterRotation::Fixed => Quat::IDENTITY,
+            ScatterRotation::Up => Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 360.0 * s1),
+            // Shoemake's uniform random rotation
+            ScatterRotation::Any => {
+                let (a, b) = ((1.0 - s1).sqrt(), s1.sqrt());
+                let (t2, t3) = (2.0 * PI * s2, 2.0 * PI * s3);
+                Quat { w: a * t2.sin(), x: a * t2.cos(), y: b * t3.sin(), z: b * t3.cos() }
+            }
+        };
+        let tilt = if settings.align_to_normal { tilt_onto(rec.normal) } else { Quat::IDENTITY };
+
+        let Some(scaling) = Transform::scale(Vec3::one() * scale) else {
+            continue;
+        };
+        let transform = scaling.then(&(tilt * spin).to_transform()).then(&Transform::translate(rec.p));
+        let mut copy = Instance::new(prototype.clone(), transform);
+        if !settings.tints.is_empty() {
+            let index = ((s4 * settings.tints.len() as f64) as usize).min(settings.tints.len() - 1);
+            copy = copy.with_tint(settings.tints[index]);
+        }
+        copies.push(Arc::new(copy));
+    }
+    Some(copies)
+}
+
+// The smallest rotation turning +y onto `normal`
+fn tilt_onto(normal: Vec3) -> Quat {
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let axis = up.cross(normal);
+    if axis.length() < 1e-9 {
+        return Quat::IDENTITY;
+    }
+    Quat::from_axis_angle(axis, up.dot(normal).clamp(-1.0, 1.0).acos().to_degrees())
+}
@@ -0,0 +1,1461 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use crate::bvh::Bvh;
+use crate::image_texture::{ImageTexture, TileCache, DEFAULT_CACHE_BUDGET_BYTES};
+use crate::json::{self, Json};
+use crate::obj;
+use crate::ply;
+use crate::presets;
+use crate::scatter::{self, Scatter, ScatterRotation};
+use crate::scenes::{LayerSelection, Scene};
+use crate::sdf::{Sdf, SdfObject};
+use crate::texture::*;
+use crate::transform::{Instance, Matrix4, Quat, Transform, Translate};
+use crate::types::*;
+
+// The room auto-framing leaves round the scene, as a multiple of what just fits
+pub const DEFAULT_FRAMING_MARGIN: f64 = 1.1;
+
+// How long each kind of scene unit is in meters
+pub fn meters_per_unit(units: &str) -> Option<f64> {
+    match units {
+        "meters" => Some(1.0),
+        "centimeters" => Some(0.01),
+        "millimeters" => Some(0.001),
+        "inches" => Some(0.0254),
+        "feet" => Some(0.3048),
+        _ => None,
+    }
+}
+
+// Loads a scene described in JSON:
+//
+//     {
+//         "version": 2,
+//         "background": "sky" or [r, g, b] or
+//             { "color": [r, g, b], "intensity": 1, "visible_to_camera": true },
+//         "cameras": { "hero": { "lookfrom": [x, y, z], "lookat": [x, y, z], "vup": [0, 1, 0], "vfov": 90 } },
+//         "camera": "hero",
+//         "materials": { "ground": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } },
+//         "objects": [ { "type": "sphere", "center": [0, -100.5, -1], "radius": 100, "material": "ground" } ]
+//     }
+//
+// Cameras can also be given as DCC tools such as Blender export them, with a lens
+// instead of a field of view:
+//
+//     { "position": [x, y, z], "rotation": [x, y, z], "focal_length": 50,
+//       "sensor_width": 36, "sensor_height": 24, "sensor_fit": "auto", "up_axis": "z" }
+//
+// The camera looks down its own -z with y up, turned by "rotation" (XYZ Euler
+// degrees, as Blender shows them) or "quaternion" ([w, x, y, z]). Positions and
+// rotations are in the tool's axes: "up_axis" "z" (the default, Blender's) turns
+// them into this renderer's y-up ones, and "y" (USD, Maya) leaves them be. The
+// sensor is fitted to the image as Blender does: "auto" lays the 36mm width along
+// the longer side, "horizontal" along the width and "vertical" puts the height
+// up the image. "focus_distance" (default 10) places the point orbits turn about.
+//
+// Either kind of camera may have a "shutter": [open, close], the times it's open
+// between (by default only for the instant at 0), for motion blur, and a
+// "rolling_shutter": readout time, over which the rows open one after another
+// from the top, each open for as long as the shutter.
+//
+// Besides spheres there are ellipsoids, { "type": "ellipsoid", "center": [x, y, z],
+// "radii": [x, y, z], "material": ... }, spheres with a radius of their own along
+// each axis, and triangles, { "type": "triangle", "vertices": [a, b, c],
+// "material": ... }, facing the side their vertices run counter-clockwise from
+// and smooth-shaded if given "normals" at the three vertices,
+// and axis-aligned rectangles, { "type": "rect", "plane": "xz", "min": [x, z],
+// "max": [x, z], "k": y, "material": ... }, facing the positive end of the axis
+// they lie across (here +y) at "k" along it, and boxes, { "type": "box", "min":
+// [x, y, z], "max": [x, y, z], "material": ... }, and cylinders, { "type":
+// "cylinder", "base": [x, y, z], "axis": [0, 1, 0], "radius": r, "height": h,
+// "material": ... }, standing on the disk at "base" and capped unless "caps" is
+// false, and disks, { "type": "disk", "center": [x, y, z], "normal": [0, 1, 0],
+// "radius": r, "material": ... }, with a hole in the middle out to
+// "inner_radius" if given one. A mesh, { "type": "mesh", "positions": [[x, y, z],
+// ...], "indices": [[0, 1, 2], ...], "material": ... }, is triangles indexing a
+// shared list of vertices, each facing as a lone triangle would, and smooth-shaded
+// if given "normals", one for each position. Its "colors", one for each position
+// too, are blended across the faces and shown by a { "type": "vertex_color",
+// "fallback": [r, g, b] } texture, which is the fallback (grey by default) off
+// meshes without them. A { "type": "uv_mapped", "texture": {...}, "scale": [u, v],
+// "rotation": degrees, "offset": [u, v], "wrap": "repeat" } texture scales, turns
+// and moves the UVs, in that order, before another texture looks them up, folding
+// them back into the unit square by repeating (the default), "clamp" or "mirror". { "type": "obj", "path": "model.obj", "material": ... }
+// loads a mesh from a Wavefront OBJ file and "ply" one from a PLY file, with their
+// normals and vertex colors; with a "frame" number the path names one file per
+// frame of an animation, "walk.####.obj", the last run of '#' standing for the
+// frame padded to as many digits, for a cache of a deforming mesh stepped with a
+// variable. Meshes given "velocities", one for each position, deform under a
+// camera's shutter, bounded over "time0" (0) to "time1" (1); PLY files may carry
+// them as vx, vy and vz, and a sequence with "motion_blur": true takes them from
+// the difference to the next frame, with the shutter's times counted in frames. A { "type": "moving_sphere",
+// "center0": [x, y, z], "center1": [x, y, z], "time0": 0, "time1": 1, "radius": r,
+// "material": ... } moves from one center to the other between the two times.
+// A { "type": "curve", "points": [[x, y, z], ...], "width": 0.01, "material": ... }
+// is a strand of hair, a blade of grass or a wire along cubic Bezier segments,
+// each from the end of the last through two more control points to its own end,
+// so 4, 7, 10 and so on in all. It narrows to "tip_width" at its end if given one.
+// A heightfield, { "type": "heightfield", "heights": [[...], ...], "min": [x, y,
+// z], "size": [x, y, z], "material": ... }, is terrain over the rectangle from
+// "min" across "size", its heights rows along x running down z, scaled by the
+// size's y; "path": "terrain.ppm" takes them from a grayscale image instead, black
+// as 0 and white as 1.
+//
+// { "type": "sdf", "shape": {...}, "material": ... } is a surface sphere traced
+// from a signed distance function, built from shapes { "type": "sphere",
+// "center", "radius" }, { "type": "box", "center", "half_size": [x, y, z] } and
+// { "type": "torus", "center", "major_radius", "minor_radius" } (flat, around y),
+// combined as { "type": "union", "a": {...}, "b": {...} }, "subtract" (b carved
+// out of a), "intersect" or "smooth_union", which fillets the seam over its
+// "blend" distance. "epsilon" (default 1e-4) is how close counts as a hit and
+// "max_steps" (default 256) how far a ray may march before giving up.
+//
+// The fractals are shapes too: { "type": "mandelbulb", "center", "radius": 1,
+// "power": 8, "iterations": 8 } and { "type": "menger_sponge", "center",
+// "half_size": 1, "iterations": 4 }. Either may also stand alone as an object,
+// with its "material", "epsilon" and "max_steps" beside the shape's own fields.
+//
+// { "type": "csg", "operation": "difference", "a": {...}, "b": {...} } combines
+// two closed objects, each any object at all, into their "union", "intersection"
+// or "difference" (a with b carved out of it); every surface keeps its own
+// object's material.
+//
+// { "type": "constant_medium", "boundary": {...}, "density": 0.5, "albedo": [1, 1,
+// 1] } fills a convex boundary object with fog or smoke, scattering rays
+// "density" times per unit they travel through it on average. "material" may
+// stand in for "albedo", as a { "type": "isotropic", "albedo": ... } material or
+// the name of one; the boundary's own material is never seen.
+//
+// A { "type": "heterogeneous_medium", "boundary": {...}, "density": {...},
+// "max_density": 4, "albedo": [1, 1, 1] } is thicker in some places than others:
+// "density" is a texture, such as { "type": "noise" }, whose brightness is the
+// fraction of "max_density" at each point. { "type": "voxels", "resolution":
+// [x, y, z], "min": [x, y, z], "max": [x, y, z], "values": [...] } is a grid of
+// them from a simulation, x fastest, then y, then z, filling the box from "min"
+// to "max".
+//
+// { "type": "instance", "object": {...}, "matrix": [[1, 0, 0, x], [0, 1, 0, y],
+// [0, 0, 1, z]] } places an object through any affine transform, with scale,
+// rotation and shear as well as a move; the matrix has three rows of four numbers
+// (or a fourth of [0, 0, 0, 1]) acting on column vectors. Named "prototypes": {
+// "rock": {...} } are built once, and an object given as just a name ("rock") is
+// that prototype, shared rather than copied, so a mesh can be instanced any number
+// of times for the memory of one. Prototypes may use those defined before them.
+//
+// "flip_normals": true turns any object's faces the other way round, for light
+// panels facing away from what they should light and meshes wound backwards.
+//
+// Any object may also have a "rotation" about its own center, either
+// { "axis": [x, y, z], "angle": degrees } or { "euler": [x, y, z] } in degrees,
+// applied about x, then y, then z. A "tint" color multiplies the colors of
+// whatever material it's made of and "roughness_scale" its roughness, so objects
+// can share one material and still differ. "translate": [x, y, z] then moves it,
+// so an object modelled round the origin can be copied about the scene.
+//
+// A { "type": "scatter", "count": 200, "surface": {...}, "prototype": {...} }
+// puts the surface in the scene with copies of the prototype (an object, or an
+// array of objects copied together) strewn over it from above, each set down
+// with its origin where it lands. "region": { "center": [x, z], "radius": r }
+// limits them to a disk (by default they cover the surface's bounds), "scale" is
+// a factor or a [min, max] range, "rotation" is "up" (a random turn about y, the
+// default), "any" or "none", "align_to_normal" tilts them with the ground,
+// "tints" gives colors to pick from per copy and "seed" (default 0) picks a
+// different arrangement.
+//
+// "units" ("meters", the default, "centimeters", "millimeters", "inches" or
+// "feet") says how long the scene's units are, for the few settings given in
+// real-world lengths. "auto_frame": true (or a margin, 1.1 when true) backs the
+// camera off along its line of sight until it frames the scene, as a camera
+// named "auto".
+//
+// Objects may be put in render layers, "layers": ["foreground", ...], and
+// "render_layers": ["foreground", "!debug"] keeps only the objects in at least one
+// of the layers named (or any, when none are) and in none of those with a '!'.
+// Objects in no layer are always kept. It's most useful set from a variable, so
+// one scene can make several renders.
+//
+// Materials may refer to materials defined before them by name, or nest them
+// inline. Textures are either a color or an object with a "type". Relative image
+// paths are resolved against the scene file's directory.
+//
+// "include": ["lib/metals.json", ...] pulls in other scene files, relative to
+// this one, as if their contents came first: their materials can be used here and
+// their objects and cameras are added. Anything this file defines itself wins over
+// what it includes, and its own cameras come first. Included files may leave out
+// whatever they don't need, objects included, and may include files in turn.
+//
+// Any string may use variables: "${glass_ior}" on its own becomes the variable's
+// value, whatever type it is, and inside a longer string its text. "variables":
+// { "glass_ior": 1.5 } gives defaults, which included files can supply too, and
+// each of `variables` sets one, over any default.
+//
+// Files without a "version" are version 1, and files from older versions are
+// brought up to date as they load, with a warning for anything that had to change.
+//
+// Each of `overrides` swaps one named material for another everywhere it's used,
+// including inside the materials layered over it.
+pub fn load(path: &Path, overrides: &[MaterialOverride], variables: &[Variable]) -> Result<Scene, String> {
+    let mut doc = read_document(path, &mut Vec::new())?;
+    substitute_variables(&mut doc, variables).map_err(|e| format!("{}: {e}", path.display()))?;
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    SceneLoader::new(base_dir, overrides).scene(&doc).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+// The cameras in a file of their own, such as one exported from another tool, to
+// be used with any scene: either a single camera, named after the file, or
+// { "cameras": { ... } } as in a scene file
+pub fn load_cameras(path: &Path) -> Result<Vec<(String, Camera)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    let doc = Json::parse(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    let in_file = |e: String| format!("{}: {e}", path.display());
+    let Some(cameras) = doc.get("cameras") else {
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let camera = SceneLoader::camera(&doc, "camera").map_err(in_file)?;
+        return Ok(vec![(name, camera)]);
+    };
+    let cameras = cameras.as_object().ok_or_else(|| in_file("cameras: expected an object of named cameras".to_string()))?;
+    if cameras.is_empty() {
+        return Err(in_file("cameras: define at least one camera".to_string()));
+    }
+    cameras
+        .iter()
+        .map(|(name, camera)| Ok((name.clone(), SceneLoader::camera(camera, &format!("cameras.{name}")).map_err(in_file)?)))
+        .collect()
+}
+
+// One file, upgraded to the current version and with its includes merged in.
+// `including` is the chain of files that led here, to catch include cycles.
+fn read_document(path: &Path, including: &mut Vec<PathBuf>) -> Result<Json, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    let doc = Json::parse(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    let (doc, warnings) = migrate(doc).map_err(|e| format!("{}: {e}", path.display()))?;
+    for warning in warnings {
+        eprintln!("{}: warning: {warning}", path.display());
+    }
+    let Some(includes) = doc.get("include") else {
+        return Ok(doc);
+    };
+    let includes: Vec<&str> = includes
+        .as_array()
+        .and_then(|items| items.iter().map(Json::as_str).collect())
+        .ok_or_else(|| format!("{}: include: expected an array of paths", path.display()))?;
+
+    let canonical = fs::canonicalize(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    including.push(canonical);
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut merged = doc.clone();
+    for include in includes.iter().rev() {
+        let include_path = dir.join(include);
+        let canonical = fs::canonicalize(&include_path)
+            .map_err(|e| format!("{}: include: couldn't read {}: {e}", path.display(), include_path.display()))?;
+        if let Some(start) = including.iter().position(|p| *p == canonical) {
+            let chain: Vec<String> = including[start..].iter().chain([&canonical]).map(|p| p.display().to_string()).collect();
+            return Err(format!("include cycle: {}", chain.join(" -> ")));
+        }
+        let mut included = read_document(&include_path, including)?;
+        // Images in the included file are relative to it, not to this one
+        if let Some(included_dir) = canonical.parent() {
+            anchor_image_paths(&mut included, included_dir);
+        }
+        merged = merge_include(merged, included);
+    }
+    including.pop();
+    if let Json::Object(fields) = &mut merged {
+        fields.retain(|(key, _)| key != "include");
+    }
+    Ok(merged)
+}
+
+// `doc` with `included` merged in underneath it. Named materials and cameras are
+// kept in their lists with `doc`'s winning a clash, except that its own materials
+// go after the included ones so they can build on them.
+fn merge_include(doc: Json, included: Json) -> Json {
+    let (Json::Object(mut fields), Json::Object(included)) = (doc, included) else {
+        return Json::Null;
+    };
+    for (key, value) in included {
+        let Some(index) = fields.iter().position(|(k, _)| *k == key) else {
+            if key != "include" {
+                fields.push((key, value));
+            }
+            continue;
+        };
+        let combined = match (key.as_str(), &mut fields[index].1, value) {
+            ("materials" | "variables" | "prototypes", Json::Object(own), Json::Object(mut theirs)) => {
+                theirs.retain(|(name, _)| !own.iter().any(|(n, _)| n == name));
+                theirs.append(own);
+                Json::Object(theirs)
+            }
+            ("cameras", Json::Object(own), Json::Object(theirs)) => {
+                let theirs: Vec<_> = theirs.into_iter().filter(|(name, _)| !own.iter().any(|(n, _)| n == name)).collect();
+                own.extend(theirs);
+                Json::Object(std::mem::take(own))
+            }
+            ("objects", Json::Array(own), Json::Array(mut theirs)) => {
+                theirs.append(own);
+                Json::Array(theirs)
+            }
+            _ => continue,
+        };
+        fields[index].1 = combined;
+    }
+    Json::Object(fields)
+}
+
+// Makes the relative paths of image textures, heightfields and OBJ and PLY
+// meshes absolute, resolved against `dir`
+fn anchor_image_paths(value: &mut Json, dir: &Path) {
+    match value {
+        Json::Object(fields) => {
+            let is_image = fields.iter().any(|(k, v)| k == "type" && matches!(v.as_str(), Some("image" | "heightfield" | "obj" | "ply")));
+            for (key, v) in fields.iter_mut() {
+                match v {
+                    Json::String(path) if is_image && key == "path" => *path = dir.join(&*path).display().to_string(),
+                    _ => anchor_image_paths(v, dir),
+                }
+            }
+        }
+        Json::Array(items) => items.iter_mut().for_each(|item| anchor_image_paths(item, dir)),
+        _ => {}
+    }
+}
+
+// Replaces every "${name}" in `doc` with the variable's value: the scene's own
+// default, unless `variables` sets it
+fn substitute_variables(doc: &mut Json, variables: &[Variable]) -> Result<(), String> {
+    let mut values: HashMap<String, Json> = HashMap::new();
+    let Json::Object(fields) = doc else {
+        return Ok(());
+    };
+    if let Some(index) = fields.iter().position(|(key, _)| key == "variables") {
+        let Json::Object(defaults) = fields.remove(index).1 else {
+            return Err("variables: expected an object of default values".to_string());
+        };
+        values.extend(defaults);
+    }
+    let declared: Vec<String> = values.keys().cloned().collect();
+    for variable in variables {
+        values.insert(variable.name.clone(), variable.value.clone());
+    }
+
+    let mut used = Vec::new();
+    for (key, value) in fields.iter_mut() {
+        substitute(value, &values, &mut used, key)?;
+    }
+    // A variable that's set but goes nowhere is most likely misspelled
+    if let Some(unused) = variables.iter().find(|v| !used.contains(&v.name) && !declared.contains(&v.name)) {
+        return Err(format!("--set {}: the scene has no ${{{}}} to set", unused.name, unused.name));
+    }
+    Ok(())
+}
+
+fn substitute(value: &mut Json, values: &HashMap<String, Json>, used: &mut Vec<String>, at: &str) -> Result<(), String> {
+    match value {
+        Json::Object(fields) => {
+            for (key, v) in fields.iter_mut() {
+                substitute(v, values, used, &format!("{at}.{key}"))?;
+            }
+        }
+        Json::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                substitute(item, values, used, &format!("{at}[{index}]"))?;
+            }
+        }
+        Json::String(text) if text.contains("${") => {
+            let mut lookup = |name: &str| {
+                used.push(name.to_string());
+                values.get(name).cloned().ok_or_else(|| format!("{at}: no variable named '{name}' is set"))
+            };
+            // A placeholder standing alone takes on its value's type
+            if let Some(name) = text.strip_prefix("${").and_then(|t| t.strip_suffix('}')).filter(|n| !n.contains('}')) {
+                *value = lookup(name)?;
+                return Ok(());
+            }
+            let mut interpolated = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("${") {
+                let end = rest[start..].find('}').ok_or_else(|| format!("{at}: unclosed ${{ in \"{text}\""))? + start;
+                interpolated.push_str(&rest[..start]);
+                match lookup(&rest[start + 2..end])? {
+                    Json::String(s) => interpolated.push_str(&s),
+                    Json::Number(n) => interpolated.push_str(&json::number(n)),
+                    Json::Bool(b) => interpolated.push_str(&b.to_string()),
+                    _ => return Err(format!("{at}: only strings, numbers and booleans can go inside a longer string")),
+                }
+                rest = &rest[end + 1..];
+            }
+            interpolated.push_str(rest);
+            *value = Json::String(interpolated);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// The version of the format this build writes and reads. Version 2 lays checker
+// textures out in object space by default, like every other procedural texture;
+// version 1 put them in UV space.
+pub const VERSION: u32 = 2;
+
+// Upgrades a document from whatever version it was written for to VERSION,
+// returning a warning for each change made along the way
+fn migrate(mut doc: Json) -> Result<(Json, Vec<String>), String> {
+    let version = match doc.get("version") {
+        None => 1,
+        Some(v) => match v.as_f64() {
+            Some(n) if n >= 1.0 && n.fract() == 0.0 => n as u32,
+            _ => return Err("version: expected a whole number from 1 up".to_string()),
+        },
+    };
+    if version > VERSION {
+        return Err(format!("the file is version {version}, newer than this build reads (up to {VERSION})"));
+    }
+
+    let mut warnings = Vec::new();
+    if version < 2 {
+        let checkers = set_missing_checker_space(&mut doc);
+        if checkers > 0 {
+            warnings.push(format!(
+                "version 1 file: kept {checkers} checker texture(s) without a \"space\" in UV space; \
+                 from version 2 they default to object space, so add \"space\": \"uv\" and \"version\": {VERSION}"
+            ));
+        }
+    }
+    Ok((doc, warnings))
+}
+
+// Gives every checker texture that doesn't say what space it's in the UV space
+// version 1 assumed, returning how many there were
+fn set_missing_checker_space(value: &mut Json) -> usize {
+    match value {
+        Json::Object(fields) => {
+            let mut count: usize = fields.iter_mut().map(|(_, v)| set_missing_checker_space(v)).sum();
+            let is_checker = fields.iter().any(|(k, v)| k == "type" && v.as_str() == Some("checker"));
+            if is_checker && !fields.iter().any(|(k, _)| k == "space") {
+                fields.push(("space".to_string(), Json::String("uv".to_string())));
+                count += 1;
+            }
+            count
+        }
+        Json::Array(items) => items.iter_mut().map(set_missing_checker_space).sum(),
+        _ => 0,
+    }
+}
+
+// Writes `scene` in the format `load` reads, with every object's material spelled
+// out in full, so it loads again exactly as it is now even once the code that built
+// it has changed. `generated_from` notes the built-in scene and seed it came from;
+// loading ignores it.
+pub fn save(scene: &Scene, generated_from: Option<(&str, u64)>, out: &mut dyn Write) -> Result<(), String> {
+    let objects = scene
+        .world
+        .objects
+        .iter()
+        .enumerate()
+        .map(|(index, object)| {
+            object.scene_json().ok_or_else(|| {
+                format!("objects[{index}]: this {} is made of something scene files can't describe", object.kind())
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let write = |out: &mut dyn Write| -> std::io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"version\": {VERSION},")?;
+        if let Some((name, seed)) = generated_from {
+            writeln!(out, "  \"generated_from\": {{ \"scene\": {}, \"seed\": {seed} }},", json::string(name))?;
+        }
+        let background = match scene.background {
+            Background::Sky => "\"sky\"".to_string(),
+            Background::Solid(color) => json::vector(color),
+            Background::Constant { color, intensity, visible_to_camera } => format!(
+                "{{ \"color\": {}, \"intensity\": {}, \"visible_to_camera\": {visible_to_camera} }}",
+                json::vector(color),
+                json::number(intensity)
+            ),
+        };
+        writeln!(out, "  \"background\": {background},")?;
+        writeln!(out, "  \"cameras\": {{")?;
+        for (index, (name, camera)) in scene.cameras.iter().enumerate() {
+            let separator = if index + 1 < scene.cameras.len() { "," } else { "" };
+            writeln!(
+                out,
+                "    {}: {{ \"lookfrom\": {}, \"lookat\": {}, \"vup\": {}, \"vfov\": {}{}{} }}{separator}",
+                json::string(name),
+                json::vector(camera.origin),
+                json::vector(camera.target),
+                json::vector(camera.vup),
+                json::number(camera.vfov),
+                match camera.shutter {
+                    (open, close) if open != close => format!(", \"shutter\": [{}, {}]", json::number(open), json::number(close)),
+                    _ => String::new(),
+                },
+                if camera.readout > 0.0 { format!(", \"rolling_shutter\": {}", json::number(camera.readout)) } else { String::new() }
+            )?;
+        }
+        writeln!(out, "  }},")?;
+        writeln!(out, "  \"objects\": [")?;
+        for (index, object) in objects.iter().enumerate() {
+            let separator = if index + 1 < objects.len() { "," } else { "" };
+            writeln!(out, "    {object}{separator}")?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    };
+    write(out).map_err(|e| e.to_string())
+}
+
+// Builds a material of a registered type from its JSON definition, reading any
+// materials or textures inside it through the context. `at` is where the
+// definition is in the file, to start error messages with.
+pub type MaterialConstructor = dyn Fn(&Json, &MaterialContext, &str) -> Result<Arc<dyn Material>, String> + Send + Sync;
+
+// Registered material types by name, latest first, so a later registration (or
+// one of a built-in type's name) takes over from what was there
+static MATERIAL_TYPES: RwLock<Vec<(String, Arc<MaterialConstructor>)>> = RwLock::new(Vec::new());
+
+// Makes { "type": `name`, ... } a material scene files can use, built by
+// `constructor`. Register before loading anything; a material of the new type
+// saves back through its own `scene_json`, under the same name.
+pub fn register_material<F>(name: &str, constructor: F)
+where
+    F: Fn(&Json, &MaterialContext, &str) -> Result<Arc<dyn Material>, String> + Send + Sync + 'static,
+{
+    MATERIAL_TYPES.write().unwrap_or_else(|e| e.into_inner()).insert(0, (name.to_string(), Arc::new(constructor)));
+}
+
+fn registered_material(name: &str) -> Option<Arc<MaterialConstructor>> {
+    let types = MATERIAL_TYPES.read().unwrap_or_else(|e| e.into_inner());
+    types.iter().find(|(n, _)| n == name).map(|(_, constructor)| constructor.clone())
+}
+
+// What a registered material's constructor can read the rest of its definition
+// with, such as a base material named elsewhere in the file
+pub struct MaterialContext<'a, 'b> {
+    loader: &'a SceneLoader<'b>,
+}
+
+impl MaterialContext<'_, '_> {
+    // A material by name, or defined in place
+    pub fn material(&self, value: &Json, at: &str) -> Result<Arc<dyn Material>, String> {
+        self.loader.material(value, at)
+    }
+
+    // A texture, or [r, g, b] for a solid color
+    pub fn texture(&self, value: &Json, at: &str) -> Result<Arc<dyn Texture>, String> {
+        self.loader.texture(value, at)
+    }
+
+    // A path in the file, relative to the file's own directory
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.loader.base_dir.join(path)
+    }
+}
+
+// A material to use in place of the scene's material `name`, given on the command
+// line as `name=preset:gold`
+pub struct MaterialOverride {
+    pub name: String,
+    pub material: Arc<dyn Material>,
+}
+
+impl MaterialOverride {
+    pub fn parse(spec: &str) -> Result<MaterialOverride, String> {
+        let (name, replacement) = spec.split_once('=').ok_or("expected NAME=preset:PRESET")?;
+        let preset = replacement
+            .strip_prefix("preset:")
+            .ok_or_else(|| format!("'{replacement}' should be preset:PRESET"))?;
+        let material = presets::by_name(preset)
+            .ok_or_else(|| format!("unknown preset '{preset}' (the presets are {})", presets::NAMES.join(", ")))?;
+        Ok(MaterialOverride { name: name.to_string(), material })
+    }
+}
+
+// A value for a scene file's variable `name`, set on the command line as
+// `name=value`. A value that reads as JSON (a number, [r, g, b], true) is used as
+// that; anything else is taken as a string.
+#[derive(Clone)]
+pub struct Variable {
+    pub name: String,
+    pub value: Json,
+}
+
+impl Variable {
+    pub fn parse(spec: &str) -> Result<Variable, String> {
+        let (name, value) = spec.split_once('=').ok_or("expected NAME=VALUE")?;
+        if name.is_empty() {
+            return Err("the variable needs a name".to_string());
+        }
+        let value = Json::parse(value).unwrap_or_else(|_| Json::String(value.to_string()));
+        Ok(Variable { name: name.to_string(), value })
+    }
+}
+
+struct SceneLoader<'a> {
+    base_dir: PathBuf,
+    overrides: &'a [MaterialOverride],
+    materials: HashMap<String, Arc<dyn Material>>,
+    // Objects built once and shared by every instance of them
+    prototypes: HashMap<String, Arc<dyn Hittable>>,
+    tile_cache: Arc<TileCache>,
+}
+
+fn field<'a>(value: &'a Json, key: &str, at: &str) -> Result<&'a Json, String> {
+    value.get(key).ok_or_else(|| format!("{at}: missing \"{key}\""))
+}
+
+fn number(value: &Json, at: &str) -> Result<f64, String> {
+    value.as_f64().ok_or_else(|| format!("{at}: expected a number"))
+}
+
+fn number_or(value: &Json, key: &str, default: f64, at: &str) -> Result<f64, String> {
+    value.get(key).map_or(Ok(default), |v| number(v, &format!("{at}.{key}")))
+}
+
+fn bool_or(value: &Json, key: &str, default: bool, at: &str) -> Result<bool, String> {
+    value
+        .get(key)
+        .map_or(Ok(default), |v| v.as_bool().ok_or_else(|| format!("{at}.{key}: expected true or false")))
+}
+
+fn string<'a>(value: &'a Json, at: &str) -> Result<&'a str, String> {
+    value.as_str().ok_or_else(|| format!("{at}: expected a string"))
+}
+
+fn strings<'a>(value: &'a Json, at: &str) -> Result<Vec<&'a str>, String> {
+    let items = value.as_array().ok_or_else(|| format!("{at}: expected an array of strings"))?;
+    items.iter().enumerate().map(|(i, item)| string(item, &format!("{at}[{i}]"))).collect()
+}
+
+// The times a deforming mesh's BVH covers its motion over, "time0" (default 0) to
+// "time1" (default 1) as a moving sphere's
+fn motion_times(value: &Json, at: &str) -> Result<(f64, f64), String> {
+    Ok((number_or(value, "time0", 0.0, at)?, number_or(value, "time1", 1.0, at)?))
+}
+
+fn pair_or(value: &Json, key: &str, default: (f64, f64), at: &str) -> Result<(f64, f64), String> {
+    let at = format!("{at}.{key}");
+    match value.get(key).map(Json::as_array) {
+        None => Ok(default),
+        Some(Some([a, b])) => Ok((number(a, &at)?, number(b, &at)?)),
+        Some(_) => Err(format!("{at}: expected a pair of numbers")),
+    }
+}
+
+fn vec3(value: &Json, at: &str) -> Result<Vec3, String> {
+    match value.as_array() {
+        Some([x, y, z]) => Ok(Vec3::new(number(x, at)?, number(y, at)?, number(z, at)?)),
+        _ => Err(format!("{at}: expected [x, y, z]")),
+    }
+}
+
+fn texture_space(value: &Json, at: &str) -> Result<TextureSpace, String> {
+    match value.get("space").map(|s| string(s, &format!("{at}.space"))).transpose()? {
+        None => Ok(TextureSpace::Object),
+        Some("uv") => Ok(TextureSpace::Uv),
+        Some("world") => Ok(TextureSpace::World),
+        Some("object") => Ok(TextureSpace::Object),
+        Some(other) => Err(format!("{at}.space: unknown space '{other}', expected uv, world or object")),
+    }
+}
+
+fn rotation(value: &Json, at: &str) -> Result<Quat, String> {
+    if let Some(euler) = value.get("euler") {
+        let angles = vec3(euler, &format!("{at}.euler"))?;
+        return Ok(Quat::from_euler(angles.x, angles.y, angles.z));
+    }
+    let axis = vec3(field(value, "axis", at)?, &format!("{at}.axis"))?;
+    if axis.near_zero() {
+        return Err(format!("{at}.axis: the axis can't be zero"));
+    }
+    Ok(Quat::from_axis_angle(axis, number(field(value, "angle", at)?, &format!("{at}.angle"))?))
+}
+
+// Three rows of four numbers, or four with a last row of [0, 0, 0, 1], for the
+// affine transforms instances are placed with
+fn affine_matrix(value: &Json, at: &str) -> Result<Matrix4, String> {
+    let rows = value.as_array().filter(|rows| rows.len() == 3 || rows.len() == 4);
+    let rows = rows.ok_or_else(|| format!("{at}: expected three or four rows of four numbers"))?;
+    let mut m = Matrix4::IDENTITY.m;
+    for (i, row) in rows.iter().enumerate() {
+        let at = format!("{at}[{i}]");
+        let row = row.as_array().filter(|row| row.len() == 4).ok_or_else(|| format!("{at}: expected four numbers"))?;
+        for (j, value) in row.iter().enumerate() {
+            m[i][j] = number(value, &at)?;
+        }
+    }
+    if m[3] != [0.0, 0.0, 0.0, 1.0] {
+        return Err(format!("{at}[3]: expected [0, 0, 0, 1], as the transform has to be affine"));
+    }
+    Ok(Matrix4::new(m))
+}
+
+fn kind<'a>(value: &'a Json, at: &str) -> Result<&'a str, String> {
+    string(field(value, "type", at)?, &format!("{at}.type"))
+}
+
+fn sdf_shape(value: &Json, at: &str) -> Result<Sdf, String> {
+    let center = || vec3(field(value, "center", at)?, &format!("{at}.center"));
+    let pair = || -> Result<(Box<Sdf>, Box<Sdf>), String> {
+        let a = sdf_shape(field(value, "a", at)?, &format!("{at}.a"))?;
+        let b = sdf_shape(field(value, "b", at)?, &format!("{at}.b"))?;
+        Ok((Box::new(a), Box::new(b)))
+    };
+    Ok(match kind(value, at)? {
+        "sphere" => Sdf::Sphere {
+            center: center()?,
+            radius: number(field(value, "radius", at)?, &format!("{at}.radius"))?,
+        },
+        "box" => Sdf::Box {
+            center: center()?,
+            half_size: vec3(field(value, "half_size", at)?, &format!("{at}.half_size"))?,
+        },
+        "torus" => Sdf::Torus {
+            center: center()?,
+            major_radius: number(field(value, "major_radius", at)?, &format!("{at}.major_radius"))?,
+            minor_radius: number(field(value, "minor_radius", at)?, &format!("{at}.minor_radius"))?,
+        },
+        "union" => {
+            let (a, b) = pair()?;
+            Sdf::Union(a, b)
+        }
+        "smooth_union" => {
+            let (a, b) = pair()?;
+            Sdf::SmoothUnion(a, b, number(field(value, "blend", at)?, &format!("{at}.blend"))?)
+        }
+        "subtract" => {
+            let (a, b) = pair()?;
+            Sdf::Subtract(a, b)
+        }
+        "intersect" => {
+            let (a, b) = pair()?;
+            Sdf::Intersect(a, b)
+        }
+        "mandelbulb" => Sdf::Mandelbulb {
+            center: center()?,
+            radius: number_or(value, "radius", 1.0, at)?,
+            power: number_or(value, "power", 8.0, at)?,
+            iterations: number_or(value, "iterations", 8.0, at)?.max(0.0) as usize,
+        },
+        "menger_sponge" => Sdf::MengerSponge {
+            center: center()?,
+            half_size: number_or(value, "half_size", 1.0, at)?,
+            iterations: number_or(value, "iterations", 4.0, at)?.max(0.0) as usize,
+        },
+        other => return Err(format!("{at}: unknown shape type '{other}'")),
+    })
+}
+
+impl<'a> SceneLoader<'a> {
+    fn new(base_dir: PathBuf, overrides: &'a [MaterialOverride]) -> Self {
+        SceneLoader {
+            base_dir,
+            overrides,
+            materials: HashMap::new(),
+            prototypes: HashMap::new(),
+            tile_cache: Arc::new(TileCache::new(DEFAULT_CACHE_BUDGET_BYTES)),
+        }
+    }
+
+    fn scene(&mut self, doc: &Json) -> Result<Scene, String> {
+        if doc.as_object().is_none() {
+            return Err("the scene must be a JSON object".to_string());
+        }
+
+        if let Some(materials) = doc.get("materials") {
+            let materials = materials.as_object().ok_or("materials: expected an object of named materials")?;
+            for (name, definition) in materials {
+                // Overridden materials are still read, so mistakes in them show up
+                let mut material = self.material(definition, &format!("materials.{name}"))?;
+                if let Some(replacement) = self.overrides.iter().find(|o| &o.name == name) {
+                    material = replacement.material.clone();
+                }
+                self.materials.insert(name.clone(), material);
+            }
+        }
+        if let Some(unknown) = self.overrides.iter().find(|o| !self.materials.contains_key(&o.name)) {
+            let mut names: Vec<&str> = self.materials.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            return Err(format!("no material named '{}' to override (the scene has {})", unknown.name, names.join(", ")));
+        }
+
+        if let Some(prototypes) = doc.get("prototypes") {
+            let prototypes = prototypes.as_object().ok_or("prototypes: expected an object of named objects")?;
+            for (name, definition) in prototypes {
+                let prototype = self.object(definition, &format!("prototypes.{name}"))?;
+                self.prototypes.insert(name.clone(), prototype);
+            }
+        }
+
+        let mut world = HittableList::new();
+        let mut object_layers = Vec::new();
+        let objects = field(doc, "objects", "scene")?.as_array().ok_or("objects: expected an array")?;
+        for (index, object) in objects.iter().enumerate() {
+            let at = format!("objects[{index}]");
+            let layers = match object.get("layers") {
+                None => Vec::new(),
+                Some(layers) => strings(layers, &format!("{at}.layers"))?.into_iter().map(str::to_string).collect(),
+            };
+            if object.as_str().is_none() && kind(object, &at)? == "scatter" {
+                for copy in self.scatter(object, &at)? {
+                    world.add(copy);
+                    object_layers.push(layers.clone());
+                }
+            } else {
+                world.add(self.object(object, &at)?);
+                object_layers.push(layers);
+            }
+        }
+
+        let mut scene = Scene::new(world);
+        scene.object_layers = object_layers;
+        if let Some(selection) = doc.get("render_layers") {
+            let terms = strings(selection, "render_layers")?;
+            scene.select_layers(&LayerSelection::from_terms(terms).map_err(|e| format!("render_layers: {e}"))?);
+        }
+        match doc.get("background") {
+            None => {}
+            Some(Json::String(s)) if s == "sky" => scene.background = Background::Sky,
+            Some(light) if light.as_object().is_some() => {
+                scene.background = Background::Constant {
+                    color: vec3(field(light, "color", "background")?, "background.color")?,
+                    intensity: number_or(light, "intensity", 1.0, "background")?,
+                    visible_to_camera: bool_or(light, "visible_to_camera", true, "background")?,
+                }
+            }
+            Some(color) => scene.background = Background::Solid(vec3(color, "background")?),
+        }
+
+        if let Some(cameras) = doc.get("cameras") {
+            let cameras = cameras.as_object().ok_or("cameras: expected an object of named cameras")?;
+            if cameras.is_empty() {
+                return Err("cameras: define at least one camera".to_string());
+            }
+            scene.cameras = cameras
+                .iter()
+                .map(|(name, camera)| Ok((name.clone(), SceneLoader::camera(camera, &format!("cameras.{name}"))?)))
+                .collect::<Result<_, String>>()?;
+            scene.camera = scene.cameras[0].1;
+        }
+        if let Some(name) = doc.get("camera") {
+            scene.use_camera(string(name, "camera")?).map_err(|e| format!("camera: {e}"))?;
+        }
+        match doc.get("auto_frame") {
+            None | Some(Json::Bool(false)) => {}
+            Some(Json::Bool(true)) => scene.auto_frame(DEFAULT_FRAMING_MARGIN).map_err(|e| format!("auto_frame: {e}"))?,
+            Some(margin) => scene.auto_frame(number(margin, "auto_frame")?).map_err(|e| format!("auto_frame: {e}"))?,
+        }
+        if let Some(units) = doc.get("units") {
+            let units = string(units, "units")?;
+            scene.meters_per_unit = meters_per_unit(units).ok_or_else(|| {
+                format!("units: unknown units '{units}', expected meters, centimeters, millimeters, inches or feet")
+            })?;
+        }
+        Ok(scene)
+    }
+
+    fn camera(value: &Json, at: &str) -> Result<Camera, String> {
+        let camera = if value.get("focal_length").is_some() {
+            SceneLoader::dcc_camera(value, at)?
+        } else {
+            let lookfrom = vec3(field(value, "lookfrom", at)?, &format!("{at}.lookfrom"))?;
+            let lookat = vec3(field(value, "lookat", at)?, &format!("{at}.lookat"))?;
+            let vup = value.get("vup").map_or(Ok(Vec3::new(0.0, 1.0, 0.0)), |v| vec3(v, &format!("{at}.vup")))?;
+            let vfov = number_or(value, "vfov", 90.0, at)?;
+            Camera::new(lookfrom, lookat, vup, vfov, 16.0 / 9.0)
+        };
+        let readout = number_or(value, "rolling_shutter", 0.0, at)?;
+        if readout < 0.0 {
+            return Err(format!("{at}.rolling_shutter: the readout time can't be negative"));
+        }
+        let camera = camera.with_rolling_shutter(readout);
+        let Some(shutter) = value.get("shutter") else {
+            return Ok(camera);
+        };
+        match shutter.as_array() {
+            Some([open, close]) => {
+                let (open, close) = (number(open, &format!("{at}.shutter"))?, number(close, &format!("{at}.shutter"))?);
+                if close < open {
+                    return Err(format!("{at}.shutter: the shutter can't close before it opens"));
+                }
+                Ok(camera.with_shutter(open, close))
+            }
+            _ => Err(format!("{at}.shutter: expected [open, close]")),
+        }
+    }
+
+    fn dcc_camera(value: &Json, at: &str) -> Result<Camera, String> {
+        let to_y_up = match value.get("up_axis").map(|s| string(s, &format!("{at}.up_axis"))).transpose()? {
+            None | Some("z") => |v: Vec3| Vec3::new(v.x, v.z, -v.y),
+            Some("y") => |v: Vec3| v,
+            Some(other) => return Err(format!("{at}.up_axis: expected \"z\" or \"y\", not '{other}'")),
+        };
+        let position = vec3(field(value, "position", at)?, &format!("{at}.position"))?;
+        let rotation = match value.get("quaternion") {
+            Some(q) => match q.as_array() {
+                Some([w, x, y, z]) => {
+                    let at = format!("{at}.quaternion");
+                    Quat { w: number(w, &at)?, x: number(x, &at)?, y: number(y, &at)?, z: number(z, &at)? }.normalized()
+                }
+                _ => return Err(format!("{at}.quaternion: expected [w, x, y, z]")),
+            },
+            None => {
+                let euler = value.get("rotation").map_or(Ok(Vec3::zero()), |v| vec3(v, &format!("{at}.rotation")))?;
+                Quat::from_euler(euler.x, euler.y, euler.z)
+            }
+        };
+        // Off-center lenses would need a skewed view this camera can't express
+        if number_or(value, "shift_x", 0.0, at)? != 0.0 || number_or(value, "shift_y", 0.0, at)? != 0.0 {
+            return Err(format!("{at}: lens shift isn't supported"));
+        }
+
+        let focal_length = number(field(value, "focal_length", at)?, &format!("{at}.focal_length"))?;
+        if focal_length <= 0.0 {
+            return Err(format!("{at}.focal_length: expected a positive length in mm"));
+        }
+        let sensor_width = number_or(value, "sensor_width", 36.0, at)?;
+        let sensor_height = number_or(value, "sensor_height", 24.0, at)?;
+        let aspect_ratio = 16.0 / 9.0;
+        // Half the sensor's extent up the image, in mm
+        let half_height = match value.get("sensor_fit").map(|s| string(s, &format!("{at}.sensor_fit"))).transpose()? {
+            None | Some("auto") if aspect_ratio >= 1.0 => sensor_width / 2.0 / aspect_ratio,
+            None | Some("auto") => sensor_width / 2.0,
+            Some("horizontal") => sensor_width / 2.0 / aspect_ratio,
+            Some("vertical") => sensor_height / 2.0,
+            Some(other) => return Err(format!("{at}.sensor_fit: expected auto, horizontal or vertical, not '{other}'")),
+        };
+        let vfov = 2.0 * half_height.atan2(focal_length).to_degrees();
+
+        let focus_distance = number_or(value, "focus_distance", 10.0, at)?;
+        let forward = to_y_up(rotation.rotate(Vec3::new(0.0, 0.0, -1.0)));
+        let up = to_y_up(rotation.rotate(Vec3::new(0.0, 1.0, 0.0)));
+        let origin = to_y_up(position);
+        Ok(Camera::new(origin, origin + forward * focus_distance.max(1e-3), up, vfov, aspect_ratio))
+    }
+
+    fn object(&self, value: &Json, at: &str) -> Result<Arc<dyn Hittable>, String> {
+        if let Some(name) = value.as_str() {
+            return self
+                .prototypes
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("{at}: no prototype named '{name}' has been defined yet"));
+        }
+        let object: Arc<dyn Hittable> = match kind(value, at)? {
+            "sphere" => {
+                let center = vec3(field(value, "center", at)?, &format!("{at}.center"))?;
+                let radius = number(field(value, "radius", at)?, &format!("{at}.radius"))?;
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(Sphere::new(center, radius, material))
+            }
+            "ellipsoid" => {
+                let center = vec3(field(value, "center", at)?, &format!("{at}.center"))?;
+                let radii = vec3(field(value, "radii", at)?, &format!("{at}.radii"))?;
+                if !(radii.x > 0.0 && radii.y > 0.0 && radii.z > 0.0) {
+                    return Err(format!("{at}.radii: expected a positive radius along every axis"));
+                }
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(Ellipsoid::new(center, radii, material))
+            }
+            "triangle" => {
+                let vertices = match field(value, "vertices", at)?.as_array() {
+                    Some(vertices @ [_, _, _]) => vertices
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| vec3(v, &format!("{at}.vertices[{i}]")))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    _ => return Err(format!("{at}.vertices: expected three points")),
+                };
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                let triangle = Triangle::new(vertices[0], vertices[1], vertices[2], material);
+                match value.get("normals").map(|normals| normals.as_array()) {
+                    None => Arc::new(triangle),
+                    Some(Some(normals @ [_, _, _])) => {
+                        let normals = normals
+                            .iter()
+                            .enumerate()
+                            .map(|(i, n)| vec3(n, &format!("{at}.normals[{i}]")))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Arc::new(triangle.with_normals(normals[0], normals[1], normals[2]))
+                    }
+                    Some(_) => return Err(format!("{at}.normals: expected three vectors")),
+                }
+            }
+            "cylinder" => {
+                let base = vec3(field(value, "base", at)?, &format!("{at}.base"))?;
+                let axis = value.get("axis").map_or(Ok(Vec3::new(0.0, 1.0, 0.0)), |a| vec3(a, &format!("{at}.axis")))?;
+                if axis.near_zero() {
+                    return Err(format!("{at}.axis: expected a direction, not zero"));
+                }
+                let radius = number(field(value, "radius", at)?, &format!("{at}.radius"))?;
+                let height = number(field(value, "height", at)?, &format!("{at}.height"))?;
+                if radius <= 0.0 || height <= 0.0 {
+                    return Err(format!("{at}: expected a positive radius and height"));
+                }
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                let cylinder = Cylinder::new(base, axis, radius, height, material);
+                Arc::new(if bool_or(value, "caps", true, at)? { cylinder } else { cylinder.open() })
+            }
+            "mesh" => {
+                let points = |key: &str| -> Result<Vec<Vec3>, String> {
+                    let list = field(value, key, at)?.as_array().ok_or_else(|| format!("{at}.{key}: expected a list of vectors"))?;
+                    list.iter().enumerate().map(|(i, p)| vec3(p, &format!("{at}.{key}[{i}]"))).collect()
+                };
+                let positions = points("positions")?;
+                let triangles = field(value, "indices", at)?.as_array().ok_or_else(|| format!("{at}.indices: expected a list of triangles"))?;
+                let mut indices = Vec::with_capacity(triangles.len());
+                for (i, triangle) in triangles.iter().enumerate() {
+                    let corner = |c: &Json| {
+                        c.as_f64().filter(|c| c.fract() == 0.0 && *c >= 0.0 && (*c as usize) < positions.len()).map(|c| c as u32)
+                    };
+                    match triangle.as_array() {
+                        Some([a, b, c]) => match (corner(a), corner(b), corner(c)) {
+                            (Some(a), Some(b), Some(c)) => indices.push([a, b, c]),
+                            _ => return Err(format!("{at}.indices[{i}]: expected indices of the {} positions", positions.len())),
+                        },
+                        _ => return Err(format!("{at}.indices[{i}]: expected three indices")),
+                    }
+                }
+                let per_position = |key: &str| -> Result<Option<Vec<Vec3>>, String> {
+                    let values = value.get(key).map(|_| points(key)).transpose()?;
+                    if values.as_ref().is_some_and(|values| values.len() != positions.len()) {
+                        return Err(format!("{at}.{key}: expected one for each of the {} positions", positions.len()));
+                    }
+                    Ok(values)
+                };
+                let (normals, colors, velocities) = (per_position("normals")?, per_position("colors")?, per_position("velocities")?);
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                let mesh = MeshData { positions, normals, colors, velocities, indices };
+                Arc::new(mesh.into_mesh(material, motion_times(value, at)?))
+            }
+            "obj" | "ply" => {
+                let pattern = string(field(value, "path", at)?, &format!("{at}.path"))?;
+                let frame = value.get("frame").map(|frame| number(frame, &format!("{at}.frame"))).transpose()?;
+                if frame.is_some_and(|frame| frame.fract() != 0.0) {
+                    return Err(format!("{at}.frame: expected a whole frame number"));
+                }
+                let frame = frame.map(|frame| frame as i64);
+                let read = |frame: Option<i64>| -> Result<MeshData, String> {
+                    let path = match frame {
+                        Some(frame) => obj::frame_path(pattern, frame).map_err(|e| format!("{at}.path: {e}"))?,
+                        None => pattern.to_string(),
+                    };
+                    let path = self.base_dir.join(path);
+                    let mesh = if kind(value, at)? == "obj" { obj::load(&path) } else { ply::load(&path) };
+                    mesh.map_err(|e| format!("{at}: {e}"))
+                };
+                let mut mesh = read(frame)?;
+                // Each vertex moves in a straight line to where it is in the next frame,
+                // so a shutter's times are counted in frames
+                if bool_or(value, "motion_blur", false, at)? {
+                    let frame = frame.ok_or_else(|| format!("{at}.motion_blur: only a sequence with a \"frame\" has a next frame to blur towards"))?;
+                    let next = read(Some(frame + 1))?;
+                    if next.indices != mesh.indices || next.positions.len() != mesh.positions.len() {
+                        return Err(format!("{at}: frame {} isn't frame {frame}'s mesh moved, so it can't be blurred between them", frame + 1));
+                    }
+                    mesh.velocities = Some(next.positions.iter().zip(&mesh.positions).map(|(&b, &a)| b - a).collect());
+                }
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(mesh.into_mesh(material, motion_times(value, at)?))
+            }
+            "csg" => {
+                let name = string(field(value, "operation", at)?, &format!("{at}.operation"))?;
+                let operation = CsgOperation::by_name(name).ok_or_else(|| {
+                    format!("{at}.operation: unknown operation '{name}', expected union, intersection or difference")
+                })?;
+                let a = self.object(field(value, "a", at)?, &format!("{at}.a"))?;
+                let b = self.object(field(value, "b", at)?, &format!("{at}.b"))?;
+                Arc::new(Csg::new(operation, a, b))
+            }
+            "constant_medium" => {
+                let boundary = self.object(field(value, "boundary", at)?, &format!("{at}.boundary"))?;
+                let density = number(field(value, "density", at)?, &format!("{at}.density"))?;
+                if density <= 0.0 {
+                    return Err(format!("{at}.density: expected a positive density"));
+                }
+                Arc::new(ConstantMedium::with_phase_function(boundary, density, self.phase_function(value, at)?))
+            }
+            "heterogeneous_medium" => {
+                let boundary = self.object(field(value, "boundary", at)?, &format!("{at}.boundary"))?;
+                let density = self.texture(field(value, "density", at)?, &format!("{at}.density"))?;
+                let max_density = number(field(value, "max_density", at)?, &format!("{at}.max_density"))?;
+                if max_density <= 0.0 {
+                    return Err(format!("{at}.max_density: expected a positive density"));
+                }
+                let phase_function = self.phase_function(value, at)?;
+                Arc::new(HeterogeneousMedium::with_phase_function(boundary, density, max_density, phase_function))
+            }
+            "instance" => {
+                let object = self.object(field(value, "object", at)?, &format!("{at}.object"))?;
+                let matrix = match value.get("matrix") {
+                    Some(matrix) => affine_matrix(matrix, &format!("{at}.matrix"))?,
+                    None => Matrix4::IDENTITY,
+                };
+                let transform = Transform::from_matrix(matrix)
+                    .ok_or_else(|| format!("{at}.matrix: the matrix flattens space, so it can't be undone"))?;
+                Arc::new(Instance::new(object, transform))
+            }
+            "disk" => {
+                let center = vec3(field(value, "center", at)?, &format!("{at}.center"))?;
+                let normal = value.get("normal").map_or(Ok(Vec3::new(0.0, 1.0, 0.0)), |n| vec3(n, &format!("{at}.normal")))?;
+                if normal.near_zero() {
+                    return Err(format!("{at}.normal: expected a direction, not zero"));
+                }
+                let radius = number(field(value, "radius", at)?, &format!("{at}.radius"))?;
+                let inner_radius = number_or(value, "inner_radius", 0.0, at)?;
+                if radius <= 0.0 || !(0.0..radius).contains(&inner_radius) {
+                    return Err(format!("{at}: expected a positive radius and an inner radius from 0 up to it"));
+                }
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(Disk::new(center, normal, radius, material).with_inner_radius(inner_radius))
+            }
+            object_kind @ ("sdf" | "mandelbulb" | "menger_sponge") => {
+                // The fractals can stand as objects by themselves, with the shape's
+                // fields alongside the material
+                let shape = if object_kind == "sdf" {
+                    sdf_shape(field(value, "shape", at)?, &format!("{at}.shape"))?
+                } else {
+                    sdf_shape(value, at)?
+                };
+                let epsilon = number_or(value, "epsilon", 1e-4, at)?;
+                let max_steps = number_or(value, "max_steps", 256.0, at)?;
+                if epsilon <= 0.0 || max_steps < 1.0 {
+                    return Err(format!("{at}: epsilon and max_steps have to be positive"));
+                }
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(SdfObject::new(shape, material).with_epsilon(epsilon).with_max_steps(max_steps as usize))
+            }
+            "heightfield" => {
+                let min = vec3(field(value, "min", at)?, &format!("{at}.min"))?;
+                let size = vec3(field(value, "size", at)?, &format!("{at}.size"))?;
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                if let Some(path) = value.get("path") {
+                    let path = self.base_dir.join(string(path, &format!("{at}.path"))?);
+                    let heightfield = Heightfield::from_image(&path, min, size, material);
+                    Arc::new(heightfield.map_err(|e| format!("{at}.path: couldn't read {}: {e}", path.display()))?)
+                } else {
+                    let rows = field(value, "heights", at)?.as_array().ok_or_else(|| format!("{at}.heights: expected a list of rows"))?;
+                    let mut heights = Vec::new();
+                    let mut columns = None;
+                    for (j, row) in rows.iter().enumerate() {
+                        let row = row.as_array().ok_or_else(|| format!("{at}.heights[{j}]: expected a row of heights"))?;
+                        if *columns.get_or_insert(row.len()) != row.len() {
+                            return Err(format!("{at}.heights[{j}]: every row needs the same number of heights"));
+                        }
+                        for (i, h) in row.iter().enumerate() {
+                            heights.push(number(h, &format!("{at}.heights[{j}][{i}]"))?);
+                        }
+                    }
+                    if rows.len() < 2 || columns.unwrap_or(0) < 2 {
+                        return Err(format!("{at}.heights: expected at least 2 rows of 2"));
+                    }
+                    Arc::new(Heightfield::new(heights, columns.unwrap_or(0), min, size, material))
+                }
+            }
+            "curve" => {
+                let list = field(value, "points", at)?.as_array().ok_or_else(|| format!("{at}.points: expected a list of points"))?;
+                let points = list.iter().enumerate().map(|(i, p)| vec3(p, &format!("{at}.points[{i}]"))).collect::<Result<Vec<_>, _>>()?;
+                if points.len() < 4 || !(points.len() - 1).is_multiple_of(3) {
+                    return Err(format!("{at}.points: expected 4, 7, 10... control points, 3 more for each segment after the first"));
+                }
+                let width = number(field(value, "width", at)?, &format!("{at}.width"))?;
+                let tip_width = number_or(value, "tip_width", width, at)?;
+                if width < 0.0 || tip_width < 0.0 || width.max(tip_width) == 0.0 {
+                    return Err(format!("{at}: expected widths of 0 or more, not both 0"));
+                }
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(Curve::new(points, width, material).with_tip_width(tip_width))
+            }
+            "moving_sphere" => {
+                let center0 = vec3(field(value, "center0", at)?, &format!("{at}.center0"))?;
+                let center1 = vec3(field(value, "center1", at)?, &format!("{at}.center1"))?;
+                let time0 = number_or(value, "time0", 0.0, at)?;
+                let time1 = number_or(value, "time1", 1.0, at)?;
+                let radius = number(field(value, "radius", at)?, &format!("{at}.radius"))?;
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(MovingSphere::new(center0, center1, time0, time1, radius, material))
+            }
+            "box" => {
+                let min = vec3(field(value, "min", at)?, &format!("{at}.min"))?;
+                let max = vec3(field(value, "max", at)?, &format!("{at}.max"))?;
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                Arc::new(BoxObj::new(min, max, material))
+            }
+            "rect" => {
+                let pair = |key: &str| -> Result<(f64, f64), String> {
+                    match field(value, key, at)?.as_array() {
+                        Some([a, b]) => Ok((number(a, &format!("{at}.{key}"))?, number(b, &format!("{at}.{key}"))?)),
+                        _ => Err(format!("{at}.{key}: expected a pair of numbers")),
+                    }
+                };
+                let (min, max) = (pair("min")?, pair("max")?);
+                let k = number(field(value, "k", at)?, &format!("{at}.k"))?;
+                let material = self.material(field(value, "material", at)?, &format!("{at}.material"))?;
+                let plane = string(field(value, "plane", at)?, &format!("{at}.plane"))?;
+                Arc::new(match plane {
+                    "xy" => AxisRect::xy((min.0, max.0), (min.1, max.1), k, material),
+                    "xz" => AxisRect::xz((min.0, max.0), (min.1, max.1), k, material),
+                    "yz" => AxisRect::yz((min.0, max.0), (min.1, max.1), k, material),
+                    other => return Err(format!("{at}.plane: expected xy, xz or yz, not '{other}'")),
+                })
+            }
+            other => return Err(format!("{at}: unknown object type '{other}'")),
+        };
+        let object: Arc<dyn Hittable> = if bool_or(value, "flip_normals", false, at)? { Arc::new(FlipFace::new(object)) } else { object };
+
+        let spin = value.get("rotation").map(|spin| rotation(spin, &format!("{at}.rotation"))).transpose()?;
+        let tint = value.get("tint").map(|tint| vec3(tint, &format!("{at}.tint"))).transpose()?;
+        let roughness_scale = value.get("roughness_scale").map(|s| number(s, &format!("{at}.roughness_scale"))).transpose()?;
+        let object: Arc<dyn Hittable> = if spin.is_none() && tint.is_none() && roughness_scale.is_none() {
+            object
+        } else {
+            let transform = match spin {
+                Some(spin) => {
+                    let center = object.bounding_box().map_or(Point3::zero(), |b| b.centroid());
+                    Transform::translate(-center).then(&spin.to_transform()).then(&Transform::translate(center))
+                }
+                None => Transform::IDENTITY,
+            };
+            let instance = Instance::new(object, transform)
+                .with_tint(tint.unwrap_or(Color::one()))
+                .with_roughness_scale(roughness_scale.unwrap_or(1.0));
+            Arc::new(instance)
+        };
+        match value.get("translate") {
+            Some(offset) => Ok(Arc::new(Translate::new(object, vec3(offset, &format!("{at}.translate"))?))),
+            None => Ok(object),
+        }
+    }
+
+    // The surface of a scatter followed by every copy strewn over it
+    fn scatter(&self, value: &Json, at: &str) -> Result<Vec<Arc<dyn Hittable>>, String> {
+        let sub = |key: &str| format!("{at}.{key}");
+        let count = number(field(value, "count", at)?, &sub("count"))?;
+        if count < 0.0 || count.fract() != 0.0 {
+            return Err(format!("{}: expected a whole number of copies", sub("count")));
+        }
+        let mut settings = Scatter::new(count as usize);
+        settings.seed = number_or(value, "seed", 0.0, at)? as u64;
+        if let Some(region) = value.get("region") {
+            let center = match field(region, "center", &sub("region"))?.as_array() {
+                Some([x, z]) => (number(x, &sub("region.center"))?, number(z, &sub("region.center"))?),
+                _ => return Err(format!("{}: expected [x, z]", sub("region.center"))),
+            };
+            let radius = number(field(region, "radius", &sub("region"))?, &sub("region.radius"))?;
+            settings.region = Some((center.0, center.1, radius));
+        }
+        settings.scale = match value.get("scale") {
+            None => (1.0, 1.0),
+            Some(scale) => match scale.as_array() {
+                Some([min, max]) => (number(min, &sub("scale"))?, number(max, &sub("scale"))?),
+                _ => {
+                    let scale = number(scale, &sub("scale"))?;
+                    (scale, scale)
+                }
+            },
+        };
+        if let Some(rotation) = value.get("rotation") {
+            let name = string(rotation, &sub("rotation"))?;
+            settings.rotation = ScatterRotation::by_name(name)
+                .ok_or_else(|| format!("{}: unknown rotation '{name}', expected up, any or none", sub("rotation")))?;
+        }
+        settings.align_to_normal = bool_or(value, "align_to_normal", false, at)?;
+        if let Some(tints) = value.get("tints") {
+            let tints = tints.as_array().ok_or_else(|| format!("{}: expected an array of colors", sub("tints")))?;
+            settings.tints = tints.iter().enumerate().map(|(i, tint)| vec3(tint, &format!("{at}.tints[{i}]"))).collect::<Result<_, _>>()?;
+        }
+
+        let surface = self.object(field(value, "surface", at)?, &sub("surface"))?;
+        // Several objects make one prototype, copied together
+        let prototype: Arc<dyn Hittable> = match field(value, "prototype", at)?.as_array() {
+            Some(parts) => {
+                let parts = parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, part)| self.object(part, &format!("{at}.prototype[{i}]")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Arc::new(Bvh::new(&parts))
+            }
+            None => self.object(field(value, "prototype", at)?, &sub("prototype"))?,
+        };
+        let copies = scatter::scatter(surface.as_ref(), prototype, &settings)
+            .ok_or_else(|| format!("{at}: the surface has no bounds, so the scatter needs a \"region\""))?;
+        if copies.len() < settings.count {
+            eprintln!("{at}: warning: only {} of {} copies landed on the surface", copies.len(), settings.count);
+        }
+        Ok(std::iter::once(surface).chain(copies).collect())
+    }
+
+    // What a medium scatters with: its "material", or else an isotropic one of its "albedo"
+    fn phase_function(&self, value: &Json, at: &str) -> Result<Arc<dyn Material>, String> {
+        match value.get("material") {
+            Some(material) => self.material(material, &format!("{at}.material")),
+            None => Ok(Arc::new(Isotropic::new(vec3(field(value, "albedo", at)?, &format!("{at}.albedo"))?))),
+        }
+    }
+
+    // A material by name, or defined right here
+    fn material(&self, value: &Json, at: &str) -> Result<Arc<dyn Material>, String> {
+        if let Some(name) = value.as_str() {
+            return self
+                .materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("{at}: no material named '{name}' has been defined yet"));
+        }
+
+        if let Some(constructor) = registered_material(kind(value, at)?) {
+            return constructor(value, &MaterialContext { loader: self }, at);
+        }
+
+        let sub = |key: &str| format!("{at}.{key}");
+        let material: Arc<dyn Material> = match kind(value, at)? {
+            "lambertian" => Arc::new(LambertianMaterial::with_texture(self.texture(field(value, "albedo", at)?, &sub("albedo"))?)),
+            "metal" => Arc::new(MetalMaterial::new(
+                vec3(field(value, "albedo", at)?, &sub("albedo"))?,
+                number_or(value, "fuzz", 0.0, at)?,
+            )),
+            "clearcoat" => {
+                let base = self.material(field(value, "base", at)?, &sub("base"))?;
+                let coat = ClearcoatMaterial::new(base, number_or(value, "ior", 1.5, at)?);
+                match value.get("flakes") {
+                    Some(flakes) => {
+                        Arc::new(coat.with_flakes(self.texture(flakes, &sub("flakes"))?, number_or(value, "flake_fuzz", 0.0, at)?))
+                    }
+                    None => Arc::new(coat),
+                }
+            }
+            "velvet" => Arc::new(SheenMaterial::velvet(vec3(field(value, "color", at)?, &sub("color"))?)),
+            "sheen" => Arc::new(SheenMaterial::new(
+                self.material(field(value, "base", at)?, &sub("base"))?,
+                vec3(field(value, "sheen", at)?, &sub("sheen"))?,
+                number_or(value, "roughness", 0.5, at)?,
+            )),
+            "principled" => {
+                let mut principled = PrincipledMaterial::new(
+                    vec3(field(value, "base_color", at)?, &sub("base_color"))?,
+                    number_or(value, "metallic", 0.0, at)?,
+                    number_or(value, "roughness", 0.5, at)?,
+                );
+                if let Some(sheen) = value.get("sheen") {
+                    principled = principled.with_sheen(vec3(sheen, &sub("sheen"))?, number_or(value, "sheen_roughness", 0.5, at)?);
+                }
+                if let Some(ior) = value.get("clearcoat_ior") {
+                    principled = principled.with_clearcoat(number(ior, &sub("clearcoat_ior"))?);
+                }
+                Arc::new(principled)
+            }
+            "mix" => {
+                let a = self.material(field(value, "a", at)?, &sub("a"))?;
+                let b = self.material(field(value, "b", at)?, &sub("b"))?;
+                match value.get("mask") {
+                    Some(mask) => Arc::new(MixMaterial::with_mask(a, b, self.texture(mask, &sub("mask"))?)),
+                    None => Arc::new(MixMaterial::new(a, b, number_or(value, "factor", 0.5, at)?)),
+                }
+            }
+            "dielectric" => {
+                let priority = number_or(value, "priority", 0.0, at)?;
+                if priority.fract() != 0.0 {
+                    return Err(format!("{at}.priority: expected a whole number"));
+                }
+                let glass = DielectricMaterial::new(number_or(value, "ior", 1.5, at)?).with_priority(priority as i32);
+                match value.get("tint") {
+                    Some(tint) => Arc::new(glass.with_tint(vec3(tint, &sub("tint"))?)),
+                    None => Arc::new(glass),
+                }
+            }
+            "isotropic" => Arc::new(Isotropic::with_texture(self.texture(field(value, "albedo", at)?, &sub("albedo"))?)),
+            "light" => Arc::new(
+                DiffuseLight::new(self.texture(field(value, "emit", at)?, &sub("emit"))?, number_or(value, "intensity", 1.0, at)?)
+                    .two_sided(bool_or(value, "two_sided", false, at)?)
+                    .visible_to_camera(bool_or(value, "visible_to_camera", true, at)?),
+            ),
+            other => return Err(format!("{at}: unknown material type '{other}'")),
+        };
+        Ok(material)
+    }
+
+    fn texture(&self, value: &Json, at: &str) -> Result<Arc<dyn Texture>, String> {
+        if value.as_array().is_some() {
+            return Ok(Arc::new(SolidColor::new(vec3(value, at)?)));
+        }
+
+        let sub = |key: &str| format!("{at}.{key}");
+        let texture: Arc<dyn Texture> = match kind(value, at)? {
+            "checker" => {
+                let even = self.texture(field(value, "even", at)?, &sub("even"))?;
+                let odd = self.texture(field(value, "odd", at)?, &sub("odd"))?;
+                Arc::new(CheckerTexture::new(even, odd, number_or(value, "squares", 2.0, at)?).in_space(texture_space(value, at)?))
+            }
+            "noise" => Arc::new(
+                NoiseTexture::new(number_or(value, "scale", 4.0, at)?, number_or(value, "octaves", 4.0, at)? as u32)
+                    .with_contrast(number_or(value, "contrast", 1.0, at)?)
+                    .in_space(texture_space(value, at)?),
+            ),
+            "voxels" => {
+                let resolution = match field(value, "resolution", at)?.as_array() {
+                    Some([x, y, z]) => [x, y, z].map(|n| n.as_f64().filter(|n| n.fract() == 0.0 && *n >= 1.0).map(|n| n as usize)),
+                    _ => [None; 3],
+                };
+                let [Some(nx), Some(ny), Some(nz)] = resolution else {
+                    return Err(format!("{at}.resolution: expected [x, y, z] voxels, each at least 1"));
+                };
+                let values = field(value, "values", at)?.as_array().ok_or_else(|| format!("{at}.values: expected a list of numbers"))?;
+                if values.len() != nx * ny * nz {
+                    return Err(format!("{at}.values: expected {} values, one for each voxel", nx * ny * nz));
+                }
+                let values = values.iter().enumerate().map(|(i, v)| number(v, &format!("{at}.values[{i}]"))).collect::<Result<_, _>>()?;
+                let min = vec3(field(value, "min", at)?, &sub("min"))?;
+                let max = vec3(field(value, "max", at)?, &sub("max"))?;
+                if !(min.x < max.x && min.y < max.y && min.z < max.z) {
+                    return Err(format!("{at}: expected max to be above min along every axis"));
+                }
+                Arc::new(VoxelTexture::new([nx, ny, nz], values, min, max).in_space(texture_space(value, at)?))
+            }
+            "flakes" => Arc::new(
+                FlakeTexture::new(
+                    vec3(field(value, "color", at)?, &sub("color"))?,
+                    number_or(value, "density", 0.25, at)?,
+                    number_or(value, "scale", 150.0, at)?,
+                )
+                .in_space(texture_space(value, at)?),
+            ),
+            "vertex_color" => {
+                let fallback = match value.get("fallback") {
+                    Some(fallback) => self.texture(fallback, &sub("fallback"))?,
+                    None => Arc::new(SolidColor::new(Color::one() * 0.5)),
+                };
+                Arc::new(VertexColorTexture::new(fallback))
+            }
+            "image" => {
+                let path = self.base_dir.join(string(field(value, "path", at)?, &sub("path"))?);
+                let image = ImageTexture::open(&path, self.tile_cache.clone())
+                    .map_err(|e| format!("{at}: couldn't open {}: {e}", path.display()))?;
+                Arc::new(image)
+            }
+            "uv_mapped" => {
+                let inner = self.texture(field(value, "texture", at)?, &sub("texture"))?;
+                let transform = UvTransform {
+                    offset: pair_or(value, "offset", (0.0, 0.0), at)?,
+                    scale: pair_or(value, "scale", (1.0, 1.0), at)?,
+                    rotation: number_or(value, "rotation", 0.0, at)?,
+                };
+                let wrap = match value.get("wrap").map(|w| string(w, &sub("wrap"))).transpose()? {
+                    None => WrapMode::Repeat,
+                    Some(name) => WrapMode::by_name(name)
+                        .ok_or_else(|| format!("{at}.wrap: unknown wrap mode '{name}', expected repeat, clamp or mirror"))?,
+                };
+                Arc::new(UvMapped::new(inner, transform, wrap))
+            }
+            other => return Err(format!("{at}: unknown texture type '{other}'")),
+        };
+        Ok(texture)
+    }
+}
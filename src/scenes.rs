@@ -0,0 +1,628 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+};
+
+use crate::bvh::{Aabb, BoundingSphere, Bvh};
+use crate::image_texture::*;
+use crate::sampler::{RandomSampler, Sampler};
+use crate::importer;
+use crate::lights::Lights;
+use crate::scene_file::{MaterialOverride, Variable};
+use crate::texture::*;
+use crate::types::*;
+
+// How far past each transparent surface a shadow ray carries on from, so it
+// doesn't find the same surface again
+const SHADOW_EPSILON: f64 = 0.0001;
+
+// The seed the cover scene's random spheres come from
+const COVER_SEED: u64 = 1;
+
+// Built-in scenes made from random numbers, with the seed each uses
+pub const GENERATED: [(&str, u64); 1] = [("cover", COVER_SEED)];
+
+// Every ray traced against any scene, for throughput figures
+static RAYS_TRACED: AtomicU64 = AtomicU64::new(0);
+
+pub fn rays_traced() -> u64 {
+    RAYS_TRACED.load(Ordering::Relaxed)
+}
+
+// Objects more than this many times the size of the median one are backdrop to
+// auto-framing rather than part of what it frames
+const BACKDROP_SCALE: f64 = 20.0;
+
+// Everything a render needs to know besides the image settings.
+// Rays are traced against `bvh`, which is built over `world` up front, and so
+// are the `lights` that integrators sample directly.
+pub struct Scene {
+    pub world: HittableList,
+    pub bvh: Bvh,
+    pub lights: Lights,
+    // Encloses everything in `world`; None when something in it is unbounded
+    pub bounds: Option<BoundingSphere>,
+    pub background: Background,
+    pub camera: Camera,
+    // Named viewpoints to pick from; `camera` starts out as the first of them
+    pub cameras: Vec<(String, Camera)>,
+    // Shades every surface but the lights with this instead of its own material
+    pub material_override: Option<Arc<dyn Material>>,
+    // Shadow rays stop at transparent surfaces as if they were opaque
+    pub opaque_shadows: bool,
+    // How long one scene unit is, so lengths known in meters (such as the distance
+    // between a viewer's eyes) can be given in the scene's own units
+    pub meters_per_unit: f64,
+    // The render layers of each object in `world`, by index; objects in none are in
+    // every render
+    pub object_layers: Vec<Vec<String>>,
+}
+
+// Which render layers a render includes: those named, or all when none are, but
+// never those named with a leading '!'
+#[derive(Clone, Default)]
+pub struct LayerSelection {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl LayerSelection {
+    // From terms like "foreground" and "!debug"
+    pub fn from_terms<'s>(terms: impl IntoIterator<Item = &'s str>) -> Result<Self, String> {
+        let mut selection = LayerSelection::default();
+        for term in terms {
+            let term = term.trim();
+            let name = term.strip_prefix('!').unwrap_or(term);
+            if name.is_empty() {
+                return Err(format!("'{term}' names no layer"));
+            }
+            if name.len() < term.len() {
+                selection.exclude.push(name.to_string());
+            } else {
+                selection.include.push(name.to_string());
+            }
+        }
+        Ok(selection)
+    }
+
+    // From a comma-separated list, such as "foreground,!debug"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        LayerSelection::from_terms(spec.split(','))
+    }
+
+    // Objects in no layer are always included
+    pub fn admits(&self, layers: &[String]) -> bool {
+        if layers.is_empty() {
+            return true;
+        }
+        let included = self.include.is_empty() || layers.iter().any(|l| self.include.contains(l));
+        included && !layers.iter().any(|l| self.exclude.contains(l))
+    }
+}
+
+impl Scene {
+    pub fn new(world: HittableList) -> Self {
+        let bvh = Bvh::new(&world.objects);
+        Scene {
+            bounds: bvh.bounding_box().filter(|b| !b.is_empty()).map(|b| BoundingSphere::around(&b)),
+            bvh,
+            lights: Lights::find(&world.objects),
+            world,
+            background: Background::Sky,
+            camera: Camera::default_camera(),
+            cameras: vec![("default".to_string(), Camera::default_camera())],
+            material_override: None,
+            opaque_shadows: false,
+            meters_per_unit: 1.0,
+            object_layers: Vec::new(),
+        }
+    }
+
+    // The scene's only camera, rather than the default one
+    pub fn with_camera(mut self, camera: Camera) -> Self {
+        self.camera = camera;
+        self.cameras = vec![("default".to_string(), camera)];
+        self
+    }
+
+    // Adds a named camera, replacing any of the same name, and looks through it
+    pub fn add_camera(&mut self, name: &str, camera: Camera) {
+        self.cameras.retain(|(n, _)| n != name);
+        self.cameras.push((name.to_string(), camera));
+        self.camera = camera;
+    }
+
+    // Adds a camera named "auto" looking the same way as the current one and
+    // backed off to frame everything in the scene with `margin` times the room it
+    // needs, and looks through it. Objects far larger than the rest, like ground
+    // spheres and walls, are taken as backdrop and left out of the framing, and
+    // so are unbounded ones. Errors when there's nothing bounded to frame.
+    pub fn auto_frame(&mut self, margin: f64) -> Result<(), String> {
+        let boxes: Vec<Aabb> =
+            self.world.objects.iter().filter_map(|o| o.bounding_box()).filter(|b| !b.is_empty()).collect();
+        let size = |b: &Aabb| (b.max - b.min).length();
+        let mut sizes: Vec<f64> = boxes.iter().map(size).collect();
+        sizes.sort_by(f64::total_cmp);
+        let Some(&median) = sizes.get(sizes.len() / 2) else {
+            return Err("the scene has nothing with bounds to frame".to_string());
+        };
+        let subject = boxes
+            .iter()
+            .filter(|b| size(b) <= BACKDROP_SCALE * median.max(1e-9))
+            .fold(Aabb::empty(), |bbox, b| bbox.union(b));
+        let bounds = BoundingSphere::around(&subject);
+        let camera = self.camera.framing(bounds.center, bounds.radius.max(1e-3), margin);
+        self.add_camera("auto", camera);
+        Ok(())
+    }
+
+    // Reads the image textures whose files have changed again, returning how
+    // many did. Only between renders: a render sampling one as it reloads could
+    // come out garbled.
+    pub fn reload_changed_textures(&self) -> usize {
+        let materials = self.world.objects.iter().filter_map(|o| o.material()).chain(self.material_override.as_deref());
+        materials.flat_map(|m| m.textures()).filter(|t| t.reload_if_changed()).count()
+    }
+
+    // Drops the objects outside `selection` and rebuilds the BVH over the rest
+    pub fn select_layers(&mut self, selection: &LayerSelection) {
+        let mut objects = Vec::new();
+        let mut object_layers = Vec::new();
+        for (index, object) in self.world.objects.drain(..).enumerate() {
+            let layers = self.object_layers.get(index).cloned().unwrap_or_default();
+            if selection.admits(&layers) {
+                objects.push(object);
+                object_layers.push(layers);
+            }
+        }
+        self.world.objects = objects;
+        self.object_layers = object_layers;
+        self.bvh = Bvh::new(&self.world.objects);
+        self.lights = Lights::find(&self.world.objects);
+        self.bounds = self.bvh.bounding_box().filter(|b| !b.is_empty()).map(|b| BoundingSphere::around(&b));
+    }
+
+    pub fn use_camera(&mut self, name: &str) -> Result<(), String> {
+        let names: Vec<&str> = self.cameras.iter().map(|(n, _)| n.as_str()).collect();
+        match self.cameras.iter().find(|(n, _)| n == name) {
+            Some((_, camera)) => {
+                self.camera = *camera;
+                Ok(())
+            }
+            None => Err(format!("no camera named '{name}' (the scene has {})", names.join(", "))),
+        }
+    }
+
+    // The first surface along `r`, passing through media, which only rays with a
+    // sampler see
+    pub fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
+
+    // The first surface along `r`, or the point it scatters in a medium, drawn from
+    // `sampler`, as the integrators trace
+    pub fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        RAYS_TRACED.fetch_add(1, Ordering::Relaxed);
+        if !hit_object(&self.bvh, r, ray_t, rec, sampler) {
+            return false;
+        }
+        rec.mat_ptr = rec.mat_ptr.map(|mat| self.shading_material(mat));
+        true
+    }
+
+    // The first surface along `r` within `max_distance` of its origin, in scene
+    // units whatever the length of its direction, for queries like a sensor's
+    // with a limited range. Materials come back as they'd be shaded.
+    pub fn raycast(&self, r: Ray, max_distance: f64) -> Option<HitRecord<'_>> {
+        let mut rec = HitRecord::blank();
+        self.hit(r, Interval::new(0.0, max_distance / r.direction.length()), &mut rec).then_some(rec)
+    }
+
+    // How much light gets from one end of the shadow ray `r` to the other. Opaque
+    // surfaces block it; transparent ones tint it, seen straight through without the
+    // bend refraction would give. Inside a medium of higher priority, the boundaries
+    // of a lower one aren't really there and are passed untinted. Fog and smoke
+    // block it where the ray would scatter in them, drawn from `sampler`, so on
+    // average it gets through as much as their transmittance.
+    pub fn transmittance(&self, r: Ray, ray_t: Interval, sampler: &mut dyn Sampler) -> Color {
+        let mut throughput = Color::one();
+        // Priorities of the media the ray is inside
+        let mut inside: Vec<i32> = Vec::new();
+        let mut rec = HitRecord::blank();
+        let mut t_min = ray_t.min;
+        while self.hit_sampled(r, Interval::new(t_min, ray_t.max), &mut rec, sampler) {
+            let transmission = match rec.mat_ptr.and_then(|mat| mat.transmission()) {
+                Some(transmission) if !self.opaque_shadows => transmission,
+                _ => return Color::zero(),
+            };
+            if !rec.front_face {
+                if let Some(index) = inside.iter().position(|&p| p == transmission.priority) {
+                    inside.remove(index);
+                }
+            }
+            if inside.iter().all(|&p| p <= transmission.priority) {
+                throughput = throughput * transmission.tint;
+                if throughput.near_zero() {
+                    return Color::zero();
+                }
+            }
+            if rec.front_face {
+                inside.push(transmission.priority);
+            }
+            t_min = rec.t + SHADOW_EPSILON;
+        }
+        throughput
+    }
+
+    // The material a surface made of `material` is actually shaded with
+    pub fn shading_material<'a>(&'a self, material: &'a dyn Material) -> &'a dyn Material {
+        match &self.material_override {
+            Some(replacement) if !material.is_emitter() => replacement.as_ref(),
+            _ => material,
+        }
+    }
+}
+
+// A built-in scene by name, or whatever an importer makes of it: a scene file
+// when given a path ending in .json, a USD layer for .usda, or anything a
+// registered importer takes
+pub fn load(name: &str) -> Result<Scene, String> {
+    load_with_overrides(name, &[], &[])
+}
+
+// Only scene files name their materials, so only they can have them overridden,
+// and only they have variables to set
+pub fn load_with_overrides(name: &str, overrides: &[MaterialOverride], variables: &[Variable]) -> Result<Scene, String> {
+    if let Some(importer) = importer::find(name) {
+        return importer.import_with_overrides(name, overrides, variables);
+    }
+    if !overrides.is_empty() {
+        return Err(format!("'{name}' is a built-in scene, whose materials have no names to override"));
+    }
+    if !variables.is_empty() {
+        return Err(format!("'{name}' is a built-in scene, which has no variables to set"));
+    }
+    by_name(name).ok_or_else(|| format!("Unknown scene '{name}'"))
+}
+
+pub fn by_name(name: &str) -> Option<Scene> {
+    match name {
+        "test" => Some(test_scene()),
+        "car-paint" => Some(car_paint_scene()),
+        "velvet" => Some(velvet_scene()),
+        "rusted-metal" => Some(rusted_metal_scene()),
+        "lights" => Some(lights_scene()),
+        "checker" => Some(checker_scene()),
+        "globe" => Some(globe_scene()),
+        "cover" => Some(cover_scene()),
+        "cornell" => Some(cornell_scene()),
+        "cornell-box" => Some(cornell_box_scene()),
+        "caustics" => Some(caustics_scene()),
+        "sphere-field" => Some(sphere_field_scene()),
+        _ => None,
+    }
+}
+
+// One of the GENERATED scenes, made from `seed` instead of its usual one
+pub fn generate(name: &str, seed: u64) -> Option<Scene> {
+    match name {
+        "cover" => Some(cover_scene_seeded(seed)),
+        _ => None,
+    }
+}
+
+pub fn test_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.8, 0.8, 0.0)));
+    let material_center = Arc::new(LambertianMaterial::new(Color::new(0.7, 0.3, 0.3)));
+    let material_left = Arc::new(MetalMaterial::new(Color::new(0.8, 0.8, 0.8), 0.3));
+    let material_right = Arc::new(MetalMaterial::new(Color::new(0.8, 0.6, 0.2), 1.0));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    world.add(Arc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    Scene::new(world)
+}
+
+pub fn car_paint_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+
+    // Plain coat over a red base
+    let material_left = Arc::new(ClearcoatMaterial::new(
+        Arc::new(LambertianMaterial::new(Color::new(0.7, 0.05, 0.05))),
+        1.5,
+    ));
+    // Metallic blue with silver flakes under the coat
+    let flakes = Arc::new(FlakeTexture::new(Color::new(0.9, 0.9, 0.95), 0.25, 150.0).in_space(TextureSpace::Object));
+    let material_center = Arc::new(
+        ClearcoatMaterial::new(Arc::new(LambertianMaterial::new(Color::new(0.05, 0.15, 0.5))), 1.5)
+            .with_flakes(flakes, 0.2),
+    );
+    // Lacquered black over brushed metal
+    let material_right = Arc::new(ClearcoatMaterial::new(
+        Arc::new(MetalMaterial::new(Color::new(0.05, 0.05, 0.05), 0.6)),
+        1.6,
+    ));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    Scene::new(world)
+}
+
+pub fn velvet_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+    let material_left = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.05, 0.1)));
+    let material_center = Arc::new(SheenMaterial::velvet(Color::new(0.5, 0.05, 0.1)));
+    let material_right = Arc::new(SheenMaterial::new(
+        Arc::new(LambertianMaterial::new(Color::new(0.05, 0.1, 0.3))),
+        Color::new(0.8, 0.7, 0.9),
+        0.2,
+    ));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    Scene::new(world)
+}
+
+pub fn rusted_metal_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+    let steel: Arc<dyn Material> = Arc::new(MetalMaterial::new(Color::new(0.7, 0.7, 0.72), 0.1));
+    let rust: Arc<dyn Material> = Arc::new(LambertianMaterial::new(Color::new(0.45, 0.2, 0.07)));
+
+    let material_left = Arc::new(MixMaterial::new(steel.clone(), rust.clone(), 0.5));
+    let material_center = Arc::new(MixMaterial::with_mask(
+        steel.clone(),
+        rust.clone(),
+        Arc::new(NoiseTexture::new(4.0, 4).with_contrast(6.0).in_space(TextureSpace::Object)),
+    ));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, steel)));
+
+    Scene::new(world)
+}
+
+pub fn lights_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+    let material_center = Arc::new(LambertianMaterial::new(Color::new(0.7, 0.3, 0.3)));
+    let material_right = Arc::new(MetalMaterial::new(Color::new(0.8, 0.8, 0.8), 0.05));
+
+    // A strong key light overhead that the camera doesn't see directly
+    let key_light = Arc::new(
+        DiffuseLight::from_watts(Color::new(1.0, 0.9, 0.8), 400.0, 4.0 * PI * 0.5 * 0.5)
+            .visible_to_camera(false),
+    );
+    // A small, dimmer bulb that does show up in frame and glows from both sides
+    let bulb = Arc::new(
+        DiffuseLight::new(Arc::new(SolidColor::new(Color::new(0.4, 0.6, 1.0))), 4.0).two_sided(true),
+    );
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 1.2, -1.0), 0.5, key_light)));
+    world.add(Arc::new(Sphere::new(Point3::new(-1.0, -0.25, -1.0), 0.25, bulb)));
+
+    let mut scene = Scene::new(world);
+    scene.background = Background::Solid(Color::new(0.01, 0.01, 0.02));
+    scene
+}
+
+pub fn checker_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let checker = Arc::new(CheckerTexture::from_colors(Color::new(0.9, 0.9, 0.9), Color::new(0.2, 0.3, 0.1), 2.0));
+
+    // A world-space board on the floor avoids the pinching at the ground sphere's UV pole
+    let floor = CheckerTexture::from_colors(Color::new(0.9, 0.9, 0.9), Color::new(0.2, 0.2, 0.2), 2.0)
+        .in_space(TextureSpace::World);
+    let material_ground = Arc::new(LambertianMaterial::with_texture(Arc::new(floor)));
+
+    // Clamped and mirrored copies of a scaled, offset checker either side of a tiled, turned one
+    let turned = UvTransform { scale: (4.0, 2.0), rotation: 45.0, ..UvTransform::identity() };
+    let stretched = UvTransform { scale: (3.0, 3.0), offset: (-1.0, -1.0), ..UvTransform::identity() };
+    let material_left = Arc::new(LambertianMaterial::with_texture(Arc::new(UvMapped::new(checker.clone(), stretched, WrapMode::Clamp))));
+    let material_center = Arc::new(LambertianMaterial::with_texture(Arc::new(UvMapped::new(checker.clone(), turned, WrapMode::Repeat))));
+    let material_right = Arc::new(LambertianMaterial::with_texture(Arc::new(UvMapped::new(checker, stretched, WrapMode::Mirror))));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_center)));
+    world.add(Arc::new(Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    Scene::new(world)
+}
+
+pub fn globe_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let cache = Arc::new(TileCache::new(DEFAULT_CACHE_BUDGET_BYTES));
+    let path = "assets/globe.ppm";
+    let surface: Arc<dyn Texture> = match ImageTexture::open(path, cache) {
+        Ok(texture) => Arc::new(texture),
+        Err(e) => {
+            eprintln!("Couldn't load {path} ({e}), using a checker instead");
+            Arc::new(CheckerTexture::from_colors(Color::new(0.2, 0.3, 0.8), Color::new(0.2, 0.6, 0.2), 8.0))
+        }
+    };
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+    let material_globe = Arc::new(LambertianMaterial::with_texture(surface));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material_globe)));
+
+    Scene::new(world)
+}
+
+// The cover of Ray Tracing in One Weekend: three large spheres among a field of
+// small random ones. The field comes from a fixed seed, so it's the same every time.
+pub fn cover_scene() -> Scene {
+    cover_scene_seeded(COVER_SEED)
+}
+
+pub fn cover_scene_seeded(seed: u64) -> Scene {
+    let mut world = HittableList::new();
+    let mut rng = RandomSampler::seeded(seed);
+    let mut random = |min: f64, max: f64| min + (max - min) * rng.get_1d();
+
+    let ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random(0.0, 1.0);
+            let center = Point3::new(a as f64 + 0.9 * random(0.0, 1.0), 0.2, b as f64 + 0.9 * random(0.0, 1.0));
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                continue;
+            }
+            let material: Arc<dyn Material> = if choose_mat < 0.8 {
+                let albedo = Color::new(random(0.0, 1.0), random(0.0, 1.0), random(0.0, 1.0))
+                    * Color::new(random(0.0, 1.0), random(0.0, 1.0), random(0.0, 1.0));
+                Arc::new(LambertianMaterial::new(albedo))
+            } else if choose_mat < 0.95 {
+                let albedo = Color::new(random(0.5, 1.0), random(0.5, 1.0), random(0.5, 1.0));
+                Arc::new(MetalMaterial::new(albedo, random(0.0, 0.5)))
+            } else {
+                Arc::new(DielectricMaterial::new(1.5))
+            };
+            world.add(Arc::new(Sphere::new(center, 0.2, material)));
+        }
+    }
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Arc::new(DielectricMaterial::new(1.5)))));
+    let brown = Arc::new(LambertianMaterial::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Arc::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, brown)));
+    let mirror = Arc::new(MetalMaterial::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Arc::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mirror)));
+
+    let camera = Camera::new(Point3::new(13.0, 2.0, 3.0), Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 20.0, 16.0 / 9.0);
+    Scene::new(world).with_camera(camera)
+}
+
+// The Cornell box as smallpt builds it, with walls made of huge spheres, a mirror
+// ball, a glass ball and a ceiling light
+pub fn cornell_scene() -> Scene {
+    let mut world = HittableList::new();
+    let diffuse = |color: Color| -> Arc<dyn Material> { Arc::new(LambertianMaterial::new(color)) };
+    let white = diffuse(Color::new(0.75, 0.75, 0.75));
+
+    let walls = [
+        (Point3::new(1e5 + 1.0, 40.8, 81.6), diffuse(Color::new(0.75, 0.25, 0.25))),
+        (Point3::new(-1e5 + 99.0, 40.8, 81.6), diffuse(Color::new(0.25, 0.25, 0.75))),
+        (Point3::new(50.0, 40.8, 1e5), white.clone()),
+        (Point3::new(50.0, 40.8, -1e5 + 170.0), diffuse(Color::zero())),
+        (Point3::new(50.0, 1e5, 81.6), white.clone()),
+        (Point3::new(50.0, -1e5 + 81.6, 81.6), white),
+    ];
+    for (center, material) in walls {
+        world.add(Arc::new(Sphere::new(center, 1e5, material)));
+    }
+    let mirror = Arc::new(MetalMaterial::new(Color::one() * 0.999, 0.0));
+    world.add(Arc::new(Sphere::new(Point3::new(27.0, 16.5, 47.0), 16.5, mirror)));
+    world.add(Arc::new(Sphere::new(Point3::new(73.0, 16.5, 78.0), 16.5, Arc::new(DielectricMaterial::new(1.5)))));
+    // A sliver of a huge sphere poking through the ceiling
+    let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(Color::one())), 12.0));
+    world.add(Arc::new(Sphere::new(Point3::new(50.0, 681.6 - 0.27, 81.6), 600.0, light)));
+
+    let camera = Camera::new(Point3::new(50.0, 45.0, 165.0), Point3::new(50.0, 38.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 55.0, 16.0 / 9.0);
+    let mut scene = Scene::new(world).with_camera(camera);
+    scene.background = Background::Solid(Color::zero());
+    scene
+}
+
+// The Cornell box as it was measured, with flat walls and a light panel in the
+// ceiling, 555 units on a side
+pub fn cornell_box_scene() -> Scene {
+    let mut world = HittableList::new();
+    let diffuse = |color: Color| -> Arc<dyn Material> { Arc::new(LambertianMaterial::new(color)) };
+    let red = diffuse(Color::new(0.65, 0.05, 0.05));
+    let green = diffuse(Color::new(0.12, 0.45, 0.15));
+    let white = diffuse(Color::new(0.73, 0.73, 0.73));
+
+    world.add(Arc::new(AxisRect::yz((0.0, 555.0), (0.0, 555.0), 555.0, green)));
+    world.add(Arc::new(AxisRect::yz((0.0, 555.0), (0.0, 555.0), 0.0, red)));
+    world.add(Arc::new(AxisRect::xz((0.0, 555.0), (0.0, 555.0), 0.0, white.clone())));
+    world.add(Arc::new(AxisRect::xz((0.0, 555.0), (0.0, 555.0), 555.0, white.clone())));
+    world.add(Arc::new(AxisRect::xy((0.0, 555.0), (0.0, 555.0), 555.0, white)));
+    // Hung just below the ceiling, shining down from its back face
+    let light = DiffuseLight::new(Arc::new(SolidColor::new(Color::one())), 15.0).two_sided(true);
+    world.add(Arc::new(AxisRect::xz((213.0, 343.0), (227.0, 332.0), 554.0, Arc::new(light))));
+    world.add(Arc::new(Sphere::new(Point3::new(190.0, 90.0, 190.0), 90.0, Arc::new(DielectricMaterial::new(1.5)))));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(370.0, 120.0, 380.0),
+        120.0,
+        Arc::new(MetalMaterial::new(Color::new(0.8, 0.85, 0.88), 0.05)),
+    )));
+
+    let camera = Camera::new(Point3::new(278.0, 278.0, -800.0), Point3::new(278.0, 278.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 16.0 / 9.0);
+    let mut scene = Scene::new(world).with_camera(camera);
+    scene.background = Background::Solid(Color::zero());
+    scene
+}
+
+// A glass ball focusing a small, bright light onto the floor, which only paths
+// that happen to find the light through the glass can show
+pub fn caustics_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(LambertianMaterial::new(Color::new(0.7, 0.7, 0.7)));
+    let material_side = Arc::new(LambertianMaterial::new(Color::new(0.7, 0.3, 0.3)));
+    let light = Arc::new(DiffuseLight::from_watts(Color::one(), 40.0, 4.0 * PI * 0.1 * 0.1));
+
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Arc::new(DielectricMaterial::new(1.5)))));
+    world.add(Arc::new(Sphere::new(Point3::new(1.1, -0.2, -1.3), 0.3, material_side)));
+    world.add(Arc::new(Sphere::new(Point3::new(-0.3, 1.5, -1.2), 0.1, light)));
+
+    let camera = Camera::new(Point3::new(0.0, 1.0, 2.0), Point3::new(0.0, -0.2, -1.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 16.0 / 9.0);
+    let mut scene = Scene::new(world).with_camera(camera);
+    scene.background = Background::Solid(Color::new(0.02, 0.02, 0.03));
+    scene
+}
+
+// Ten thousand small spheres in a grid, for timing scenes made of many small
+// primitives the way meshes are
+pub fn sphere_field_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    let materials: [Arc<dyn Material>; 3] = [
+        Arc::new(LambertianMaterial::new(Color::new(0.7, 0.3, 0.3))),
+        Arc::new(LambertianMaterial::new(Color::new(0.3, 0.5, 0.7))),
+        Arc::new(MetalMaterial::new(Color::new(0.8, 0.8, 0.8), 0.1)),
+    ];
+    let ground = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+    for i in 0..100 {
+        for j in 0..100 {
+            let center = Point3::new(-5.0 + i as f64 * 0.1, 0.04, -5.0 + j as f64 * 0.1);
+            world.add(Arc::new(Sphere::new(center, 0.04, materials[(i + j) % 3].clone())));
+        }
+    }
+
+    let camera = Camera::new(Point3::new(0.0, 2.5, 6.0), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 50.0, 16.0 / 9.0);
+    Scene::new(world).with_camera(camera)
+}
@@ -0,0 +1,311 @@
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
+use crate::json::{number, vector};
+use crate::types::*;
+
+// Iterates this far from the origin have escaped the Mandelbulb of radius 1, so
+// it also bounds the bulb, whatever the power
+const ESCAPE_RADIUS: f64 = 2.0;
+
+// A signed distance function: how far a point is from the surface, negative
+// inside. The built-in shapes are exact, or at least never overestimate, which is
+// what sphere tracing needs to step safely; a custom one has to be the same.
+#[derive(Clone)]
+pub enum Sdf {
+    Sphere { center: Point3, radius: f64 },
+    // `half_size` from the center to a corner
+    Box { center: Point3, half_size: Vec3 },
+    // Lying flat, around the y axis through `center`
+    Torus { center: Point3, major_radius: f64, minor_radius: f64 },
+    Union(Box<Sdf>, Box<Sdf>),
+    // A union filleted where the shapes meet, over about `blend` around the seam
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64),
+    // The first with the second cut out of it
+    Subtract(Box<Sdf>, Box<Sdf>),
+    Intersect(Box<Sdf>, Box<Sdf>),
+    // The power 8 bulb is the classic one; more iterations bring out finer detail,
+    // which also needs a smaller epsilon and more steps to see. `radius` scales the
+    // bulb, which at 1 just fits in a sphere of about 1.2.
+    Mandelbulb { center: Point3, radius: f64, power: f64, iterations: usize },
+    // A cube `half_size` from its center to a face, with each iteration punching
+    // the middle third out of every face of each of the cubes left
+    MengerSponge { center: Point3, half_size: f64, iterations: usize },
+    Custom(Arc<dyn Fn(Point3) -> f64 + Send + Sync>),
+}
+
+impl Sdf {
+    pub fn distance(&self, p: Point3) -> f64 {
+        match self {
+            Sdf::Sphere { center, radius } => (p - *center).length() - radius,
+            Sdf::Box { center, half_size } => {
+                let q = p - *center;
+                let q = Vec3::new(q.x.abs(), q.y.abs(), q.z.abs()) - *half_size;
+                q.max(Vec3::zero()).length() + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            Sdf::Torus { center, major_radius, minor_radius } => {
+                let q = p - *center;
+                let ring = (q.x * q.x + q.z * q.z).sqrt() - major_radius;
+                (ring * ring + q.y * q.y).sqrt() - minor_radius
+            }
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::SmoothUnion(a, b, blend) => {
+                // Quilez's polynomial smooth minimum
+                let (a, b) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (b - a) / blend.max(1e-9)).clamp(0.0, 1.0);
+                b + (a - b) * h - blend * h * (1.0 - h)
+            }
+            Sdf::Subtract(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::Intersect(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Mandelbulb { center, radius, power, iterations } => radius * mandelbulb((p - *center) / *radius, *power, *iterations),
+            Sdf::MengerSponge { center, half_size, iterations } => half_size * menger_sponge((p - *center) / *half_size, *iterations),
+            Sdf::Custom(f) => f(p),
+        }
+    }
+
+    // Encloses the surface; None for a custom function, which can't tell
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Sdf::Sphere { center, radius } => Some(Aabb::new(*center - Vec3::one() * *radius, *center + Vec3::one() * *radius)),
+            Sdf::Box { center, half_size } => Some(Aabb::new(*center - *half_size, *center + *half_size)),
+            Sdf::Torus { center, major_radius, minor_radius } => {
+                let reach = major_radius + minor_radius;
+                let extent = Vec3::new(reach, *minor_radius, reach);
+                Some(Aabb::new(*center - extent, *center + extent))
+            }
+            Sdf::Union(a, b) => Some(a.bounds()?.union(&b.bounds()?)),
+            // The fillet only ever adds material, and no further out than a quarter
+            // of the blend
+            Sdf::SmoothUnion(a, b, blend) => {
+                let bounds = a.bounds()?.union(&b.bounds()?);
+                let pad = Vec3::one() * (0.25 * blend.abs());
+                Some(Aabb::new(bounds.min - pad, bounds.max + pad))
+            }
+            Sdf::Subtract(a, _) => a.bounds(),
+            Sdf::Intersect(a, b) => match (a.bounds(), b.bounds()) {
+                (Some(a), Some(b)) => Some(a.intersection(&b)),
+                (a, b) => a.or(b),
+            },
+            Sdf::Mandelbulb { center, radius, .. } => {
+                let extent = Vec3::one() * (ESCAPE_RADIUS * radius);
+                Some(Aabb::new(*center - extent, *center + extent))
+            }
+            Sdf::MengerSponge { center, half_size, .. } => {
+                let extent = Vec3::one() * *half_size;
+                Some(Aabb::new(*center - extent, *center + extent))
+            }
+            Sdf::Custom(_) => None,
+        }
+    }
+
+    // As a scene file's "shape"; None for a custom function
+    pub fn scene_json(&self) -> Option<String> {
+        let pair = |kind: &str, a: &Sdf, b: &Sdf| -> Option<String> {
+            Some(format!("{{ \"type\": \"{kind}\", \"a\": {}, \"b\": {} }}", a.scene_json()?, b.scene_json()?))
+        };
+        match self {
+            Sdf::Sphere { center, radius } => Some(format!(
+                "{{ \"type\": \"sphere\", \"center\": {}, \"radius\": {} }}",
+                vector(*center),
+                number(*radius)
+            )),
+            Sdf::Box { center, half_size } => Some(format!(
+                "{{ \"type\": \"box\", \"center\": {}, \"half_size\": {} }}",
+                vector(*center),
+                vector(*half_size)
+            )),
+            Sdf::Torus { center, major_radius, minor_radius } => Some(format!(
+                "{{ \"type\": \"torus\", \"center\": {}, \"major_radius\": {}, \"minor_radius\": {} }}",
+                vector(*center),
+                number(*major_radius),
+                number(*minor_radius)
+            )),
+            Sdf::Union(a, b) => pair("union", a, b),
+            Sdf::SmoothUnion(a, b, blend) => Some(format!(
+                "{{ \"type\": \"smooth_union\", \"a\": {}, \"b\": {}, \"blend\": {} }}",
+                a.scene_json()?,
+                b.scene_json()?,
+                number(*blend)
+            )),
+            Sdf::Subtract(a, b) => pair("subtract", a, b),
+            Sdf::Intersect(a, b) => pair("intersect", a, b),
+            Sdf::Mandelbulb { center, radius, power, iterations } => Some(format!(
+                "{{ \"type\": \"mandelbulb\", \"center\": {}, \"radius\": {}, \"power\": {}, \"iterations\": {iterations} }}",
+                vector(*center),
+                number(*radius),
+                number(*power)
+            )),
+            Sdf::MengerSponge { center, half_size, iterations } => Some(format!(
+                "{{ \"type\": \"menger_sponge\", \"center\": {}, \"half_size\": {}, \"iterations\": {iterations} }}",
+                vector(*center),
+                number(*half_size)
+            )),
+            Sdf::Custom(_) => None,
+        }
+    }
+}
+
+// A surface found by sphere tracing its distance function: a ray steps forward by
+// the distance to the nearest surface, which can't overshoot it, until it's
+// within `epsilon` or has taken `max_steps` steps. Normals are the function's
+// gradient, estimated from four more evaluations around the hit.
+pub struct SdfObject {
+    sdf: Sdf,
+    bounds: Option<Aabb>,
+    epsilon: f64,
+    max_steps: usize,
+    mat_ptr: Arc<dyn Material>,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, mat_ptr: Arc<dyn Material>) -> Self {
+        SdfObject {
+            bounds: sdf.bounds(),
+            sdf,
+            epsilon: 1e-4,
+            max_steps: 256,
+            mat_ptr,
+        }
+    }
+
+    // A closure's surface, which has to lie inside `bounds`
+    pub fn custom(distance: impl Fn(Point3) -> f64 + Send + Sync + 'static, bounds: Aabb, mat_ptr: Arc<dyn Material>) -> Self {
+        SdfObject::new(Sdf::Custom(Arc::new(distance)), mat_ptr).with_bounds(bounds)
+    }
+
+    // Only this box is marched through; without bounds, rays march until they run out
+    // of steps
+    pub fn with_bounds(mut self, bounds: Aabb) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    fn normal(&self, p: Point3) -> Vec3 {
+        let h = self.epsilon;
+        let mut gradient = Vec3::zero();
+        for k in [Vec3::new(1.0, -1.0, -1.0), Vec3::new(-1.0, -1.0, 1.0), Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)].iter() {
+            gradient += *k * self.sdf.distance(p + *k * h);
+        }
+        if gradient.near_zero() {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            gradient.unit_vector()
+        }
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let range = match &self.bounds {
+            Some(bounds) => match bounds.clip(r.origin, Vec3::new(1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z), ray_t) {
+                Some(range) => range,
+                None => return false,
+            },
+            None => ray_t,
+        };
+        let speed = r.direction.length();
+        if speed == 0.0 {
+            return false;
+        }
+
+        // A ray leaving the surface starts within epsilon of it, so it has to get
+        // clear before anything counts as a hit, or it would find the surface it
+        // left straight away. Rays from outside the bounds can't be leaving it.
+        let mut t = range.min;
+        let mut clear = range.min > ray_t.min;
+        for _ in 0..self.max_steps {
+            if t > range.max {
+                return false;
+            }
+            let p = r.at(t);
+            let distance = self.sdf.distance(p).abs();
+            if !clear {
+                clear = distance >= 2.0 * self.epsilon;
+                t += distance.max(self.epsilon) / speed;
+                continue;
+            }
+            if distance < self.epsilon {
+                let outward_normal = self.normal(p);
+                rec.set_surface(r, t, outward_normal, Sphere::get_sphere_uv(outward_normal), self.mat_ptr.as_ref());
+                return true;
+            }
+            t += distance / speed;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Padded so a surface marched to within epsilon of the box stays inside it
+        self.bounds.map(|b| Aabb::new(b.min - Vec3::one() * self.epsilon, b.max + Vec3::one() * self.epsilon))
+    }
+
+    fn kind(&self) -> &'static str {
+        "sdf"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"sdf\", \"shape\": {}, \"epsilon\": {}, \"max_steps\": {}, \"material\": {} }}",
+            self.sdf.scene_json()?,
+            number(self.epsilon),
+            self.max_steps,
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+// The distance estimate for the Mandelbulb at the origin with radius 1: z goes to
+// z^power + p over and over, in spherical coordinates about y, and the points
+// that never escape are the bulb. The estimate comes from how fast the iterates'
+// derivative grows as they escape.
+fn mandelbulb(p: Point3, power: f64, iterations: usize) -> f64 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > ESCAPE_RADIUS {
+            break;
+        }
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let theta = (z.y / r).clamp(-1.0, 1.0).acos() * power;
+        let phi = z.z.atan2(z.x) * power;
+        z = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()) * r.powf(power) + p;
+        r = z.length();
+        if r == 0.0 {
+            return 0.0;
+        }
+    }
+    0.5 * r.ln() * r / dr
+}
+
+// Quilez's distance to the sponge filling the cube from -1 to 1
+fn menger_sponge(p: Point3, iterations: usize) -> f64 {
+    let q = Vec3::new(p.x.abs(), p.y.abs(), p.z.abs()) - Vec3::one();
+    let mut distance = q.max(Vec3::zero()).length() + q.x.max(q.y).max(q.z).min(0.0);
+    let mut scale = 1.0;
+    for _ in 0..iterations {
+        // Where in its own cell of the grid of cubes at this size the point is
+        let cell = |x: f64| (x * scale).rem_euclid(2.0) - 1.0;
+        let a = Vec3::new(cell(p.x), cell(p.y), cell(p.z));
+        scale *= 3.0;
+        let r = Vec3::one() - Vec3::new(a.x.abs(), a.y.abs(), a.z.abs()) * 3.0;
+        let r = Vec3::new(r.x.abs(), r.y.abs(), r.z.abs());
+        let cross = r.x.max(r.y).min(r.y.max(r.z)).min(r.z.max(r.x));
+        distance = distance.max((cross - 1.0) / scale);
+    }
+    distance
+}
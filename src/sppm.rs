@@ -0,0 +1,613 @@
+use std::collections::HashMap;
+
+use crate::bvh::{Aabb, BoundingSphere};
+use crate::exposure::luminance;
+use crate::integrator::Integrator;
+use crate::lights::{self, EmitterPoint};
+use crate::render::{for_each_row, sample_pixel, Image, RenderSettings};
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::scenes::Scene;
+use crate::types::*;
+
+const INFINITY: f64 = f64::INFINITY;
+// Keeps scattered rays from re-hitting the surface they start on
+const T_MIN: f64 = 0.0001;
+// Hachisuka and Jensen's alpha: the share of each pass's photons a pixel keeps
+// when its radius shrinks. Lower shrinks faster, trading noise for bias sooner.
+const ALPHA: f64 = 2.0 / 3.0;
+// Photons traced per batch of work handed to a thread
+const PHOTONS_PER_BATCH: usize = 4096;
+// Without a radius given, pixels start gathering from this many times the median
+// width of the first pass's pixel footprints
+const INITIAL_RADIUS_FOOTPRINTS: f64 = 2.0;
+// Photons bounce this many times before Russian roulette starts ending them
+const ROULETTE_AFTER: i64 = 3;
+// The grid of samples estimating the background's power
+const POWER_SAMPLES: usize = 8;
+// The hybrid renderer uses photons on pixels where at least this many caustic
+// photons from its prepass land, and some on those within FEATHER pixels of them
+const CAUSTIC_PHOTONS: usize = 2;
+const FEATHER: usize = 4;
+// The prepass's seeds are those of a pass no render gets to
+const PREPASS: u64 = u64::MAX / 4;
+
+// Stochastic progressive photon mapping (Hachisuka and Jensen, 2009). Each pass
+// traces one camera ray per pixel through mirrors and glass to the first rough
+// surface it finds, then shoots photons from the lights and the background and
+// gathers those landing within the pixel's radius of that point. The radius
+// shrinks pass by pass as photons come in, so the estimate converges, and light
+// that only reaches the camera specular-diffuse-specular (caustics seen in a
+// mirror or through glass) comes out, where a path tracer can never find it.
+pub struct SppmSettings {
+    // Defaults to one per pixel
+    pub photons_per_pass: Option<usize>,
+    // The gather radius every pixel starts from, in scene units; picked from the
+    // pixels' footprints when None
+    pub initial_radius: Option<f64>,
+}
+
+impl SppmSettings {
+    pub fn new() -> Self {
+        SppmSettings { photons_per_pass: None, initial_radius: None }
+    }
+}
+
+impl Default for SppmSettings {
+    fn default() -> Self {
+        SppmSettings::new()
+    }
+}
+
+// Where a pixel's camera ray came to rest this pass: the first surface that
+// isn't a mirror or glass, and what the light leaving it is multiplied by on the
+// way into the pixel
+#[derive(Clone, Copy)]
+struct VisiblePoint<'a> {
+    rec: HitRecord<'a>,
+    r_in: Ray,
+    throughput: Color,
+    // What the material's `scatter` attenuates by, which for a diffuse surface is
+    // its reflectance
+    reflectance: Color,
+}
+
+// What a pixel has gathered so far, carried from pass to pass
+#[derive(Clone, Copy)]
+struct PixelStats {
+    radius: f64,
+    photons: f64,
+    flux: Color,
+    // Emission and background reached without touching a rough surface, summed over passes
+    direct: Color,
+}
+
+#[derive(Clone, Copy)]
+struct Photon {
+    p: Point3,
+    // The way it was travelling, as a unit vector
+    direction: Vec3,
+    // The surface's normal, on the side the photon arrived from
+    normal: Vec3,
+    power: Color,
+}
+
+enum Emitter<'a> {
+    Object { object: &'a dyn Hittable, material: &'a dyn Material },
+    // The background, lighting the scene from outside its bounding sphere
+    Background { center: Point3, radius: f64 },
+}
+
+// Renders the scene through `camera` with SPPM, one pass for every sample per
+// pixel in `settings`
+pub fn render_sppm(scene: &Scene, camera: &Camera, settings: &RenderSettings, sppm: &SppmSettings, show_progress: bool) -> Image {
+    let wanted = vec![true; settings.image_width as usize * settings.image_height as usize];
+    render_passes(scene, camera, settings, sppm, &wanted, None, show_progress)
+}
+
+// Renders with SPPM where a prepass finds caustics, light reaching a rough
+// surface straight off mirrors and glass, and with `integrator` everywhere else.
+// Photon mapping is what finds caustics, and path tracing is cheaper and
+// cleaner for the rest; pixels near the edge of a caustic take some of each,
+// so the seam doesn't show. Takes `settings.samples_per_pixel` photon passes
+// where it uses photons and as many paths per pixel elsewhere.
+pub fn render_hybrid(
+    scene: &Scene,
+    camera: &Camera,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    sppm: &SppmSettings,
+    show_progress: bool,
+) -> Image {
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let (weights, view, radius) = caustic_weights(scene, camera, settings, sppm);
+    let wanted: Vec<bool> = weights.iter().map(|&w| w > 0.0).collect();
+    let flagged = wanted.iter().filter(|&&w| w).count();
+    if show_progress {
+        eprintln!("Caustics cover {:.1}% of the image", 100.0 * flagged as f64 / (width * height) as f64);
+    }
+    let photon_image = if flagged > 0 {
+        let sppm = SppmSettings { photons_per_pass: sppm.photons_per_pass, initial_radius: Some(radius) };
+        render_passes(scene, camera, settings, &sppm, &wanted, view, show_progress)
+    } else {
+        Image::new(width, height)
+    };
+
+    let camera = camera.for_image_height(settings.image_height);
+    let trace_row = |row: usize, sampler: &mut dyn Sampler| {
+        let j = (height - 1 - row) as u16;
+        (0..width)
+            .map(|i| {
+                if weights[row * width + i] < 1.0 {
+                    sample_pixel(scene, integrator, &camera, sampler, settings, (i as u16, j), settings.samples_per_pixel)
+                } else {
+                    Color::zero()
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut image = photon_image;
+    for_each_row(height, settings, &trace_row, &mut |row, colors| {
+        for (x, color) in colors.into_iter().enumerate() {
+            let (w, pixel) = (weights[row * width + x], &mut image.pixels[row * width + x]);
+            *pixel = *pixel * w + color * (1.0 - w);
+        }
+    });
+    image
+}
+
+// How much of each pixel to take from photons, with the view and starting radius
+// the photon passes should use. One camera pass finds what every pixel sees, then
+// one pass of photons that have met only mirrors and glass since leaving the
+// light flags every pixel where enough of them land; the flags are feathered out
+// round their edges.
+fn caustic_weights(scene: &Scene, camera: &Camera, settings: &RenderSettings, sppm: &SppmSettings) -> (Vec<f64>, Option<BoundingSphere>, f64) {
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let camera = camera.for_image_height(settings.image_height);
+    let stage = |stage: u64| RenderSettings { seed: settings.seed.map(|seed| pass_seed(seed, PREPASS, stage)), ..*settings };
+    let visible: Vec<_> = trace_visible(scene, &camera, &stage(0), &vec![true; width * height]).into_iter().map(|(_, point)| point).collect();
+    let radius = sppm.initial_radius.unwrap_or_else(|| initial_radius(&visible));
+    let view = view_bounds(scene, &visible);
+    let emitters = find_emitters(scene, view);
+    let total_power: f64 = emitters.iter().map(|(_, power)| power).sum();
+    if total_power <= 0.0 || radius <= 0.0 {
+        return (vec![0.0; width * height], view, radius);
+    }
+    let photons_per_pass = sppm.photons_per_pass.unwrap_or(width * height).max(1);
+    let photons = shoot_photons(scene, &camera, &emitters, total_power, photons_per_pass, &stage(1), true);
+    let grid = PhotonGrid::new(&photons, radius);
+    let flags: Vec<bool> = visible.iter().map(|point| point.is_some_and(|point| grid.gather(&point, radius).1 >= CAUSTIC_PHOTONS)).collect();
+    (feathered(&flags, width, height), view, radius)
+}
+
+// 1 on every flagged pixel, falling off to 0 over FEATHER pixels round them
+fn feathered(flags: &[bool], width: usize, height: usize) -> Vec<f64> {
+    let mut weights = vec![0.0; flags.len()];
+    let reach = FEATHER as i64;
+    for index in (0..flags.len()).filter(|&i| flags[i]) {
+        let (x, y) = ((index % width) as i64, (index / width) as i64);
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let w = 1.0 - ((dx * dx + dy * dy) as f64).sqrt() / (FEATHER + 1) as f64;
+                let n = ny as usize * width + nx as usize;
+                weights[n] = f64::max(weights[n], w);
+            }
+        }
+    }
+    weights
+}
+
+// The SPPM passes over just the pixels `wanted`, leaving the rest black. Photons
+// from the background are aimed at `view`, or when None at what the first pass's
+// camera rays found.
+fn render_passes(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    sppm: &SppmSettings,
+    wanted: &[bool],
+    view: Option<BoundingSphere>,
+    show_progress: bool,
+) -> Image {
+    let camera = camera.for_image_height(settings.image_height);
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let passes = settings.samples_per_pixel.max(1) as usize;
+    let photons_per_pass = sppm.photons_per_pass.unwrap_or(width * height).max(1);
+    let mut emitters = Vec::new();
+    let mut total_power = 0.0;
+
+    let mut stats = vec![PixelStats { radius: sppm.initial_radius.unwrap_or(0.0), photons: 0.0, flux: Color::zero(), direct: Color::zero() }; width * height];
+    for pass in 0..passes {
+        if show_progress {
+            eprint!("\rPass {} of {passes}      ", pass + 1);
+        }
+        // Every pass and stage draws its own samples, even with a seed
+        let stage = |stage: u64| RenderSettings { seed: settings.seed.map(|seed| pass_seed(seed, pass as u64, stage)), ..*settings };
+
+        let mut visible: Vec<Option<VisiblePoint>> = Vec::with_capacity(width * height);
+        for (s, (direct, point)) in stats.iter_mut().zip(trace_visible(scene, &camera, &stage(0), wanted)) {
+            if finite(direct) {
+                s.direct += direct;
+            }
+            visible.push(point);
+        }
+        if pass == 0 {
+            if sppm.initial_radius.is_none() {
+                let radius = initial_radius(&visible);
+                for s in stats.iter_mut() {
+                    s.radius = radius;
+                }
+            }
+            emitters = find_emitters(scene, view.or_else(|| view_bounds(scene, &visible)));
+            total_power = emitters.iter().map(|(_, power)| power).sum();
+            if total_power <= 0.0 && show_progress {
+                eprintln!("\nThe scene has nothing giving off light that photons can be shot from");
+            }
+        }
+        if total_power <= 0.0 {
+            continue;
+        }
+
+        let photons = shoot_photons(scene, &camera, &emitters, total_power, photons_per_pass, &stage(1), false);
+
+        // With cells as wide as the largest radius, every photon a pixel could
+        // gather is in its own cell or the 26 round it
+        let cell = stats.iter().map(|s| s.radius).fold(0.0, f64::max);
+        if cell <= 0.0 {
+            continue;
+        }
+        let grid = PhotonGrid::new(&photons, cell);
+        let gather_row = |row: usize, _: &mut dyn Sampler| {
+            (0..width)
+                .map(|x| visible[row * width + x].map_or((Color::zero(), 0), |point| grid.gather(&point, stats[row * width + x].radius)))
+                .collect::<Vec<_>>()
+        };
+        let mut gathered = vec![(Color::zero(), 0); width * height];
+        for_each_row(height, &stage(2), &gather_row, &mut |row, found| {
+            gathered[row * width..(row + 1) * width].copy_from_slice(&found);
+        });
+        for (s, (flux, count)) in stats.iter_mut().zip(gathered) {
+            if count == 0 || !finite(flux) {
+                continue;
+            }
+            // Keeping only ALPHA of the new photons shrinks the disk they came
+            // from, and the flux gathered so far with it
+            let count = count as f64;
+            let photons = s.photons + ALPHA * count;
+            let shrink = photons / (s.photons + count);
+            s.radius *= shrink.sqrt();
+            s.flux = (s.flux + flux) * shrink;
+            s.photons = photons;
+        }
+    }
+    if show_progress {
+        eprintln!("\rOperation complete.      ");
+    }
+
+    let mut image = Image::new(width, height);
+    let photons_shot = (passes * photons_per_pass) as f64;
+    for (pixel, s) in image.pixels.iter_mut().zip(&stats) {
+        *pixel = s.direct / passes as f64;
+        if s.radius > 0.0 {
+            *pixel += s.flux / (photons_shot * PI * s.radius * s.radius);
+        }
+    }
+    image
+}
+
+// Every wanted pixel's camera ray traced to where it comes to rest, with the
+// light it picked up on the way, rows from the top
+fn trace_visible<'a>(scene: &'a Scene, camera: &Camera, settings: &RenderSettings, wanted: &[bool]) -> Vec<(Color, Option<VisiblePoint<'a>>)> {
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let trace_row = |row: usize, sampler: &mut dyn Sampler| {
+        let j = (height - 1 - row) as f64;
+        (0..width)
+            .map(|i| {
+                if !wanted[row * width + i] {
+                    return (Color::zero(), None);
+                }
+                let u = (i as f64 + sampler.get_1d()) / (width - 1) as f64;
+                let v = (j + sampler.get_1d()) / (height - 1) as f64;
+                camera_path(scene, camera.sample_ray(u, v, row as f64 / height as f64, sampler), sampler, settings.max_depth)
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut visible = vec![(Color::zero(), None); width * height];
+    for_each_row(height, settings, &trace_row, &mut |row, points| {
+        visible[row * width..(row + 1) * width].copy_from_slice(&points);
+    });
+    visible
+}
+
+// `count` photons shot from the emitters while the camera's shutter is open and
+// traced through the scene, in the same order however many threads there are. With `caustics_only`, only photons
+// that reach a rough surface straight off mirrors and glass are kept.
+fn shoot_photons(
+    scene: &Scene,
+    camera: &Camera,
+    emitters: &[(Emitter, f64)],
+    total_power: f64,
+    count: usize,
+    settings: &RenderSettings,
+    caustics_only: bool,
+) -> Vec<Photon> {
+    let batches = count.div_ceil(PHOTONS_PER_BATCH);
+    let shoot = |batch: usize, sampler: &mut dyn Sampler| {
+        let count = PHOTONS_PER_BATCH.min(count - batch * PHOTONS_PER_BATCH);
+        let mut found = Vec::new();
+        for _ in 0..count {
+            if let Some((r, power)) = emit(scene, emitters, total_power, sampler) {
+                let r = r.with_time(camera.sample_frame_time(sampler));
+                trace_photon(scene, r, power, settings.max_depth, caustics_only, sampler, &mut found);
+            }
+        }
+        found
+    };
+    // Batches finish in any order, but are stored in their own, so the grid and
+    // the sums gathered from it come out the same however many threads there are
+    let mut shot = vec![Vec::new(); batches];
+    for_each_row(batches, settings, &shoot, &mut |batch, found| shot[batch] = found);
+    shot.into_iter().flatten().collect()
+}
+
+// Follows a camera ray through mirrors and glass, which photons can't be gathered
+// on, to the first surface that scatters diffusely. Returns the emission and
+// background picked up on the way and the point it stopped at, if any.
+fn camera_path<'a>(scene: &'a Scene, mut r: Ray, sampler: &mut dyn Sampler, max_depth: i64) -> (Color, Option<VisiblePoint<'a>>) {
+    let mut throughput = Color::one();
+    let mut direct = Color::zero();
+    let mut depth = 0;
+    while depth < max_depth {
+        let mut rec = HitRecord::blank();
+        if !scene.hit_sampled(r, Interval::new(T_MIN, INFINITY), &mut rec, sampler) {
+            let background = if depth == 0 { scene.background.camera_color(r) } else { scene.background.color(r) };
+            return (direct + throughput * background, None);
+        }
+        rec.set_footprint(r);
+        let Some(mat) = rec.mat_ptr else {
+            return (direct, None);
+        };
+        // Camera rays pass straight through emitters that are hidden from the camera
+        if depth == 0 && !mat.is_visible_to_camera() {
+            r = Ray { origin: rec.p, cone: r.cone.advanced(rec.t), ..r };
+            continue;
+        }
+
+        direct += throughput * mat.emitted(&rec);
+        let mut scattered = Ray::new(Vec3::zero(), Vec3::zero());
+        let mut attenuation = Color::zero();
+        if !mat.scatter(r, &rec, &mut attenuation, &mut scattered, sampler) {
+            return (direct, None);
+        }
+        scattered.origin = rec.scatter_origin(scattered.direction);
+        if mat.scattering_pdf(r, &rec, scattered).is_some() {
+            return (direct, Some(VisiblePoint { rec, r_in: r, throughput, reflectance: attenuation }));
+        }
+        throughput = throughput * attenuation;
+        r = scattered;
+        depth += 1;
+    }
+    (direct, None)
+}
+
+// Follows a photon from the light, leaving a copy of it on every diffuse surface
+// it lands on, or with `caustics_only` on just the first, if it got there by way of
+// mirrors and glass
+fn trace_photon(
+    scene: &Scene,
+    mut r: Ray,
+    mut power: Color,
+    max_depth: i64,
+    caustics_only: bool,
+    sampler: &mut dyn Sampler,
+    photons: &mut Vec<Photon>,
+) {
+    for depth in 0..max_depth {
+        let mut rec = HitRecord::blank();
+        if !scene.hit_sampled(r, Interval::new(T_MIN, INFINITY), &mut rec, sampler) {
+            return;
+        }
+        rec.set_footprint(r);
+        let Some(mat) = rec.mat_ptr else {
+            return;
+        };
+        let mut scattered = Ray::new(Vec3::zero(), Vec3::zero());
+        let mut attenuation = Color::zero();
+        if !mat.scatter(r, &rec, &mut attenuation, &mut scattered, sampler) {
+            return;
+        }
+        scattered.origin = rec.scatter_origin(scattered.direction);
+        if mat.scattering_pdf(r, &rec, scattered).is_some() {
+            if !caustics_only || depth > 0 {
+                photons.push(Photon { p: rec.p, direction: r.direction.unit_vector(), normal: rec.normal, power });
+            }
+            if caustics_only {
+                return;
+            }
+        }
+        power = power * attenuation;
+        if depth >= ROULETTE_AFTER {
+            let survival = attenuation.x.max(attenuation.y).max(attenuation.z).min(1.0);
+            if sampler.get_1d() >= survival {
+                return;
+            }
+            power /= survival;
+        }
+        r = scattered;
+    }
+}
+
+// Everything that gives off light with its power, roughly, for picking which to
+// shoot each photon from. Emitters are found among the scene's top-level objects
+// and need a UV layout to be sampled, as spheres, triangles and rects have. The
+// background shines in on `view`.
+fn find_emitters(scene: &Scene, view: Option<BoundingSphere>) -> Vec<(Emitter<'_>, f64)> {
+    let mut emitters = Vec::new();
+    for object in &scene.world.objects {
+        let Some(material) = object.material().filter(|m| m.is_emitter()) else {
+            continue;
+        };
+        let power = lights::power(object.as_ref(), material);
+        if power > 0.0 {
+            emitters.push((Emitter::Object { object: object.as_ref(), material }, power));
+        }
+    }
+
+    if let Some(bounds) = view {
+        let mut sky = 0.0;
+        for n in 0..POWER_SAMPLES * POWER_SAMPLES {
+            let u = ((n % POWER_SAMPLES) as f64 + 0.5) / POWER_SAMPLES as f64;
+            let v = ((n / POWER_SAMPLES) as f64 + 0.5) / POWER_SAMPLES as f64;
+            sky += luminance(scene.background.color(Ray::new(Point3::zero(), sampling::uniform_sphere((u, v)))));
+        }
+        // The background's radiance over every direction, through the disk of the
+        // bounding sphere facing each
+        let power = sky / (POWER_SAMPLES * POWER_SAMPLES) as f64 * 4.0 * PI * PI * bounds.radius * bounds.radius;
+        if power > 0.0 {
+            emitters.push((Emitter::Background { center: bounds.center, radius: bounds.radius }, power));
+        }
+    }
+    emitters
+}
+
+// A photon leaving one of the emitters, picked in proportion to its power: the
+// ray it leaves along and the power it carries
+fn emit(scene: &Scene, emitters: &[(Emitter, f64)], total_power: f64, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+    let mut pick = sampler.get_1d() * total_power;
+    let (emitter, power) = emitters.iter().find(|(_, power)| {
+        pick -= power;
+        pick < 0.0
+    }).unwrap_or(&emitters[emitters.len() - 1]);
+    let chosen = power / total_power;
+
+    match *emitter {
+        Emitter::Object { object, material } => {
+            let (u, v) = sampler.get_2d();
+            let EmitterPoint { p, normal, front, back, area } = lights::emission_at(object, material, u, v)?;
+            // Two-sided lights shoot from either side, half the time each
+            let (radiance, normal, side) = if back.near_zero() {
+                (front, normal, 1.0)
+            } else if front.near_zero() {
+                (back, -normal, 1.0)
+            } else if sampler.get_1d() < 0.5 {
+                (front, normal, 0.5)
+            } else {
+                (back, -normal, 0.5)
+            };
+            let direction = Onb::from_normal(normal).local_to_world(sampling::cosine_hemisphere(sampler.get_2d()));
+            // Radiance times cosine over the densities of the point (one over the
+            // area per uv) and the cosine-weighted direction
+            Some((Ray::new(p, direction), radiance * (area * PI / (chosen * side))))
+        }
+        Emitter::Background { center, radius } => {
+            let direction = sampling::uniform_sphere(sampler.get_2d());
+            let (x, y) = sampling::concentric_disk(sampler.get_2d());
+            let frame = Onb::from_normal(direction);
+            let origin = center + frame.local_to_world(Vec3::new(x, y, 0.0)) * radius - direction * radius;
+            let radiance = scene.background.color(Ray::new(center, -direction));
+            Some((Ray::new(origin, direction), radiance * (PI * radius * radius / (sampling::uniform_sphere_pdf() * chosen))))
+        }
+    }
+}
+
+// The sphere round every point the camera rays came to rest on, or the scene's
+// bounds if they're smaller. Photons from the background are only shot at this,
+// as most of a scene's bounds can be a ground plane stretching far out of view
+// that would take nearly all of them; the price is missing what little light
+// reaches the view only by bouncing off something outside it.
+fn view_bounds(scene: &Scene, visible: &[Option<VisiblePoint>]) -> Option<BoundingSphere> {
+    let mut seen = Aabb::empty();
+    for point in visible.iter().flatten() {
+        seen = seen.union(&Aabb::new(point.rec.p, point.rec.p));
+    }
+    let view = (!seen.is_empty()).then(|| BoundingSphere::around(&seen));
+    match (view, &scene.bounds) {
+        (Some(view), Some(bounds)) if bounds.radius < view.radius => Some(*bounds),
+        (view, _) => view,
+    }
+}
+
+// A pixel-footprint-based radius, from the median width of the rough surfaces'
+// footprints the camera rays found
+fn initial_radius(visible: &[Option<VisiblePoint>]) -> f64 {
+    let mut footprints: Vec<f64> = visible.iter().flatten().map(|p| p.rec.footprint).filter(|f| *f > 0.0 && f.is_finite()).collect();
+    if footprints.is_empty() {
+        return 0.0;
+    }
+    let middle = footprints.len() / 2;
+    let (_, median, _) = footprints.select_nth_unstable_by(middle, f64::total_cmp);
+    *median * INITIAL_RADIUS_FOOTPRINTS
+}
+
+// The photons of a pass sorted into cubic cells, for finding those near a point
+struct PhotonGrid<'a> {
+    photons: &'a [Photon],
+    cell: f64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl<'a> PhotonGrid<'a> {
+    fn new(photons: &'a [Photon], cell: f64) -> Self {
+        let mut cells: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        for (i, photon) in photons.iter().enumerate() {
+            cells.entry(cell_of(photon.p, cell)).or_default().push(i);
+        }
+        PhotonGrid { photons, cell, cells }
+    }
+
+    // The flux reflected towards the camera by the photons within `radius` of the
+    // point, on the same side of the surface, and how many there were
+    fn gather(&self, point: &VisiblePoint, radius: f64) -> (Color, usize) {
+        let (cx, cy, cz) = cell_of(point.rec.p, self.cell);
+        let mut flux = Color::zero();
+        let mut count = 0;
+        for x in cx - 1..=cx + 1 {
+            for y in cy - 1..=cy + 1 {
+                for z in cz - 1..=cz + 1 {
+                    for &i in self.cells.get(&(x, y, z)).into_iter().flatten() {
+                        let photon = &self.photons[i];
+                        if (photon.p - point.rec.p).length_squared() > radius * radius || photon.normal.dot(point.rec.normal) <= 0.0 {
+                            continue;
+                        }
+                        count += 1;
+                        flux += point.throughput * brdf(point, -photon.direction) * photon.power;
+                    }
+                }
+            }
+        }
+        (flux, count)
+    }
+}
+
+fn cell_of(p: Point3, cell: f64) -> (i64, i64, i64) {
+    ((p.x / cell).floor() as i64, (p.y / cell).floor() as i64, (p.z / cell).floor() as i64)
+}
+
+// How much of the light arriving from `towards_light` the visible point sends on
+// to the camera. Materials only give a sampling density, so this takes the
+// reflectance `scatter` gave as flat across directions, which it is for
+// Lambertian surfaces and mixes of them.
+fn brdf(point: &VisiblePoint, towards_light: Vec3) -> Color {
+    let Some(mat) = point.rec.mat_ptr else {
+        return Color::zero();
+    };
+    let cos = point.rec.normal.dot(towards_light);
+    let pdf = mat.scattering_pdf(point.r_in, &point.rec, Ray::new(point.rec.p, towards_light));
+    match pdf {
+        Some(pdf) if cos > 1e-8 => point.reflectance * (pdf / cos),
+        _ => Color::zero(),
+    }
+}
+
+fn finite(c: Color) -> bool {
+    c.x.is_finite() && c.y.is_finite() && c.z.is_finite()
+}
+
+// The seed of one stage of one pass, so no two draw the same samples
+fn pass_seed(seed: u64, pass: u64, stage: u64) -> u64 {
+    seed ^ (3 * pass + stage + 1).wrapping_mul(0xbf58_476d_1ce4_e5b9)
+}
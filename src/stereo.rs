@@ -0,0 +1,57 @@
+use crate::integrator::Integrator;
+use crate::render::{render_view, Image, RenderSettings};
+use crate::scenes::Scene;
+
+// How the two eyes are packed into one frame: left eye on the left, or on top
+#[derive(Clone, Copy)]
+pub enum StereoLayout {
+    SideBySide,
+    TopBottom,
+}
+
+impl StereoLayout {
+    pub fn by_name(name: &str) -> Option<StereoLayout> {
+        match name {
+            "side-by-side" => Some(StereoLayout::SideBySide),
+            "top-bottom" => Some(StereoLayout::TopBottom),
+            _ => None,
+        }
+    }
+}
+
+pub struct StereoSettings {
+    pub layout: StereoLayout,
+    // Distance between the eyes, in scene units
+    pub interaxial: f64,
+    // Distance in front of the camera that ends up on the screen plane; the
+    // distance to the camera's target when not given
+    pub convergence: Option<f64>,
+}
+
+// Renders each eye at the full size in `settings` and packs them into a frame
+// twice as wide or twice as tall
+pub fn render_stereo(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings, stereo: &StereoSettings) -> Image {
+    let camera = scene.camera;
+    let convergence = stereo.convergence.unwrap_or_else(|| (camera.target - camera.origin).length());
+    let left = render_view(scene, &camera.stereo_eye(-stereo.interaxial / 2.0, convergence), integrator, settings, true);
+    let right = render_view(scene, &camera.stereo_eye(stereo.interaxial / 2.0, convergence), integrator, settings, true);
+
+    let (width, height) = (left.width, left.height);
+    match stereo.layout {
+        StereoLayout::SideBySide => {
+            let mut frame = Image::new(width * 2, height);
+            for y in 0..height {
+                let row = &mut frame.pixels[y * width * 2..(y + 1) * width * 2];
+                row[..width].copy_from_slice(&left.pixels[y * width..(y + 1) * width]);
+                row[width..].copy_from_slice(&right.pixels[y * width..(y + 1) * width]);
+            }
+            frame
+        }
+        StereoLayout::TopBottom => {
+            let mut frame = Image::new(width, height * 2);
+            frame.pixels[..width * height].copy_from_slice(&left.pixels);
+            frame.pixels[width * height..].copy_from_slice(&right.pixels);
+            frame
+        }
+    }
+}
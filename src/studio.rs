@@ -0,0 +1,109 @@
+use std::{fs::File, io::BufWriter, sync::Arc};
+
+use crate::integrator::Integrator;
+use crate::render::{render_view, RenderSettings};
+use crate::scenes::Scene;
+use crate::texture::SolidColor;
+use crate::types::*;
+
+// The ground is a sphere this many times the model's size across, so what the
+// camera sees of it is flat and runs out to the horizon
+const GROUND_SCALE: f64 = 1e4;
+// Softboxes as (yaw, pitch, intensity, radius), with yaw taken from the camera's
+// so the lighting turns with it: a broad key up and to the left, a dimmer fill
+// low on the right and a rim light behind to pick out the silhouette
+const SOFTBOXES: [(f64, f64, f64, f64); 3] = [(-45.0, 40.0, 4.0, 0.6), (60.0, 15.0, 1.5, 0.8), (180.0, 50.0, 3.0, 0.4)];
+
+// Where the studio's camera sits, as degrees round the model from the front
+// (positive to the right) and up from level, and how much room it leaves round
+// the model as a multiple of the size that just fits it in frame
+#[derive(Clone, Copy)]
+pub struct StudioSettings {
+    pub yaw: f64,
+    pub pitch: f64,
+    pub margin: f64,
+}
+
+impl StudioSettings {
+    pub fn new() -> Self {
+        StudioSettings { yaw: 30.0, pitch: 15.0, margin: 1.1 }
+    }
+}
+
+impl Default for StudioSettings {
+    fn default() -> Self {
+        StudioSettings::new()
+    }
+}
+
+// Puts everything in `model` on a white studio floor under softboxes against a
+// white background, with a camera framing it, for a product shot of any scene
+// holding one thing. The model's own background and cameras are dropped; its
+// own lights stay. Errors when the model is empty or unbounded, as there's
+// then nothing to frame.
+pub fn studio(model: &Scene, settings: &StudioSettings) -> Result<Scene, String> {
+    let bbox = model
+        .bvh
+        .bounding_box()
+        .filter(|b| !b.is_empty())
+        .ok_or("The studio needs a model with bounds to frame")?;
+    let center = bbox.centroid();
+    let radius = ((bbox.max - bbox.min).length() * 0.5).max(1e-3);
+    let aspect_ratio = model.camera.horizontal.length() / model.camera.vertical.length();
+
+    let mut world = HittableList { objects: model.world.objects.clone() };
+    // The floor meets the bottom of the model
+    let ground = GROUND_SCALE * radius;
+    world.add(Arc::new(Sphere::new(
+        Point3::new(center.x, bbox.min.y - ground, center.z),
+        ground,
+        Arc::new(LambertianMaterial::new(Color::one() * 0.8)),
+    )));
+    for &(yaw, pitch, intensity, size) in SOFTBOXES.iter() {
+        let light = DiffuseLight::new(Arc::new(SolidColor::new(Color::one())), intensity).visible_to_camera(false);
+        world.add(Arc::new(Sphere::new(
+            center + direction(settings.yaw + yaw, pitch) * (4.0 * radius),
+            size * radius,
+            Arc::new(light),
+        )));
+    }
+
+    let lookfrom = center + direction(settings.yaw, settings.pitch.clamp(-85.0, 85.0));
+    let camera = Camera::new(lookfrom, center, Vec3::new(0.0, 1.0, 0.0), 30.0, aspect_ratio).framing(center, radius, settings.margin);
+
+    let mut scene = Scene::new(world).with_camera(camera);
+    scene.background = Background::Solid(Color::one());
+    scene.meters_per_unit = model.meters_per_unit;
+    scene.material_override = model.material_override.clone();
+    scene.opaque_shadows = model.opaque_shadows;
+    Ok(scene)
+}
+
+// Renders `model` in the studio turning once round in `frames` steps, as numbered
+// frames `<prefix>0000.ppm` and so on. The camera and softboxes orbit together, so
+// the model looks to spin on a turntable under fixed lights.
+pub fn turntable(
+    model: &Scene,
+    studio_settings: &StudioSettings,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    frames: usize,
+    prefix: &str,
+) -> Result<(), String> {
+    for frame in 0..frames {
+        eprintln!("Frame {} of {}", frame + 1, frames);
+        let yaw = studio_settings.yaw + 360.0 * frame as f64 / frames as f64;
+        let scene = studio(model, &StudioSettings { yaw, ..*studio_settings })?;
+        let image = render_view(&scene, &scene.camera, integrator, settings, true);
+        let path = format!("{prefix}{frame:04}.ppm");
+        let mut out = File::create(&path).map(BufWriter::new).map_err(|e| format!("couldn't create {path}: {e}"))?;
+        image.write_ppm(&mut out).map_err(|e| format!("couldn't write {path}: {e}"))?;
+    }
+    Ok(())
+}
+
+// The unit vector `yaw` degrees round from +z and `pitch` degrees up
+fn direction(yaw: f64, pitch: f64) -> Vec3 {
+    let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+    Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos())
+}
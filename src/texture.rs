@@ -0,0 +1,569 @@
+use std::sync::Arc;
+
+use crate::json::{number, vector};
+use crate::types::{Color, Point3, Vec3};
+
+// Everything a texture may look up at a hit point
+#[derive(Clone, Copy)]
+pub struct TextureCoords {
+    pub u: f64,
+    pub v: f64,
+    pub p: Point3,
+    pub local_p: Point3,
+    pub normal: Vec3,
+    // World-space width of the area the sample stands for; zero disables filtering
+    pub footprint: f64,
+    // The footprint's width in u and in v; zero disables filtering in UV space
+    pub du: f64,
+    pub dv: f64,
+    pub vertex_color: Option<Color>,
+}
+
+// Which coordinates a procedural texture is laid out in. World space stays put
+// while objects move through it, object space travels with the object, and UV
+// space follows the surface parameterization.
+#[derive(Clone, Copy)]
+pub enum TextureSpace {
+    Uv,
+    World,
+    Object,
+}
+
+impl TextureSpace {
+    // As scene files spell it
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureSpace::Uv => "uv",
+            TextureSpace::World => "world",
+            TextureSpace::Object => "object",
+        }
+    }
+}
+
+impl TextureCoords {
+    pub fn point(&self, space: TextureSpace) -> Point3 {
+        match space {
+            TextureSpace::Uv => Point3::new(self.u, self.v, 0.0),
+            TextureSpace::World => self.p,
+            TextureSpace::Object => self.local_p,
+        }
+    }
+
+    // The width of the footprint in `space`'s units, the wider of its two UV
+    // widths in UV space
+    pub fn footprint_in(&self, space: TextureSpace) -> f64 {
+        match space {
+            TextureSpace::Uv => self.du.max(self.dv),
+            TextureSpace::World | TextureSpace::Object => self.footprint,
+        }
+    }
+}
+
+// How much of a pattern's detail survives when the footprint spans `cells` of it:
+// all of it below half a cell, fading to none at a full cell
+fn detail_fade(cells: f64) -> f64 {
+    (2.0 - 2.0 * cells).clamp(0.0, 1.0)
+}
+
+pub trait Texture: Send + Sync {
+    fn value(&self, tc: &TextureCoords) -> Color;
+
+    // The texture's type, for scene statistics
+    fn name(&self) -> &'static str {
+        "texture"
+    }
+
+    // The texture as a scene file describes it, or None if it can't be written out
+    fn scene_json(&self) -> Option<String> {
+        None
+    }
+
+    // Reads the texture again if the file it came from has changed since, along
+    // with any textures it's built from; true when anything was reloaded
+    fn reload_if_changed(&self) -> bool {
+        false
+    }
+}
+
+pub struct SolidColor {
+    color: Color
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        SolidColor {
+            color
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _tc: &TextureCoords) -> Color {
+        self.color
+    }
+
+    fn name(&self) -> &'static str {
+        "solid"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(vector(self.color))
+    }
+}
+
+// The colors baked into a mesh's vertices, as scans and low-poly models carry
+// them, blended across each face. Surfaces without any show `fallback`.
+pub struct VertexColorTexture {
+    fallback: Arc<dyn Texture>
+}
+
+impl VertexColorTexture {
+    pub fn new(fallback: Arc<dyn Texture>) -> Self {
+        VertexColorTexture {
+            fallback
+        }
+    }
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        tc.vertex_color.unwrap_or_else(|| self.fallback.value(tc))
+    }
+
+    fn name(&self) -> &'static str {
+        "vertex-color"
+    }
+
+    fn reload_if_changed(&self) -> bool {
+        self.fallback.reload_if_changed()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!("{{ \"type\": \"vertex_color\", \"fallback\": {} }}", self.fallback.scene_json()?))
+    }
+}
+
+// A checkerboard with `squares` cells per unit. In UV space that means `squares`
+// cells along each side of the unit square; in world or object space the board
+// is a 3D lattice of cubes. Either way it's box-filtered over the ray footprint
+// so it doesn't shimmer in the distance.
+pub struct CheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    squares: f64,
+    space: TextureSpace
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, squares: f64) -> Self {
+        CheckerTexture {
+            even,
+            odd,
+            squares,
+            space: TextureSpace::Uv
+        }
+    }
+
+    pub fn from_colors(even: Color, odd: Color, squares: f64) -> Self {
+        CheckerTexture::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), squares)
+    }
+
+    pub fn in_space(mut self, space: TextureSpace) -> Self {
+        self.space = space;
+        self
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        let p = tc.point(self.space) * self.squares;
+        if tc.footprint_in(self.space) > 0.0 {
+            // Each axis is filtered over the footprint's extent along it: within the
+            // tangent plane in world or object space, and its UV widths in UV space
+            let n = tc.normal.unit_vector();
+            let widths = match self.space {
+                TextureSpace::Uv => Vec3::new(tc.du, tc.dv, 0.0) * self.squares,
+                TextureSpace::World | TextureSpace::Object => {
+                    let across = |n: f64| tc.footprint * self.squares * (1.0 - n * n).max(0.0).sqrt();
+                    Vec3::new(across(n.x), across(n.y), across(n.z))
+                }
+            };
+            let square_wave = |x: f64, w: f64| {
+                if w < 1e-6 {
+                    return if x.floor().rem_euclid(2.0) == 0.0 { 1.0 } else { -1.0 };
+                }
+                let integral = |x: f64| 1.0 - (x.rem_euclid(2.0) - 1.0).abs();
+                (integral(x + w / 2.0) - integral(x - w / 2.0)) / w
+            };
+            let even = 0.5 + 0.5 * square_wave(p.x, widths.x) * square_wave(p.y, widths.y) * square_wave(p.z, widths.z);
+            return self.even.value(tc) * even + self.odd.value(tc) * (1.0 - even);
+        }
+
+        let cell = p.x.floor() as i64 + p.y.floor() as i64 + p.z.floor() as i64;
+        if cell.rem_euclid(2) == 0 {
+            self.even.value(tc)
+        } else {
+            self.odd.value(tc)
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "checker"
+    }
+
+    // Both halves get their chance, even when the first reloads
+    fn reload_if_changed(&self) -> bool {
+        let even = self.even.reload_if_changed();
+        self.odd.reload_if_changed() || even
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"checker\", \"even\": {}, \"odd\": {}, \"squares\": {}, \"space\": \"{}\" }}",
+            self.even.scene_json()?,
+            self.odd.scene_json()?,
+            number(self.squares),
+            self.space.name()
+        ))
+    }
+}
+
+// How texture coordinates outside [0, 1] are folded back onto the texture
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn wrap(&self, t: f64) -> f64 {
+        match self {
+            WrapMode::Repeat => t - t.floor(),
+            WrapMode::Clamp => t.clamp(0.0, 1.0),
+            WrapMode::Mirror => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 { 2.0 - t } else { t }
+            }
+        }
+    }
+
+    // As scene files spell it
+    pub fn name(&self) -> &'static str {
+        match self {
+            WrapMode::Repeat => "repeat",
+            WrapMode::Clamp => "clamp",
+            WrapMode::Mirror => "mirror",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<WrapMode> {
+        match name {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+}
+
+// Scales, then rotates (counter-clockwise, in degrees, about the UV origin), then offsets UVs
+#[derive(Clone, Copy)]
+pub struct UvTransform {
+    pub offset: (f64, f64),
+    pub scale: (f64, f64),
+    pub rotation: f64,
+}
+
+impl UvTransform {
+    pub fn identity() -> Self {
+        UvTransform {
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+        }
+    }
+
+    pub fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (u, v) = (u * cos - v * sin, u * sin + v * cos);
+        (u + self.offset.0, v + self.offset.1)
+    }
+
+    // The widths in u and v a footprint `du` by `dv` wide covers once transformed:
+    // the extents of the scaled and turned box
+    pub fn widths(&self, du: f64, dv: f64) -> (f64, f64) {
+        let (du, dv) = (du * self.scale.0.abs(), dv * self.scale.1.abs());
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        (du * cos.abs() + dv * sin.abs(), du * sin.abs() + dv * cos.abs())
+    }
+}
+
+// Runs the UVs through a transform and wrap mode before sampling `inner`
+pub struct UvMapped {
+    inner: Arc<dyn Texture>,
+    transform: UvTransform,
+    wrap: WrapMode
+}
+
+impl UvMapped {
+    pub fn new(inner: Arc<dyn Texture>, transform: UvTransform, wrap: WrapMode) -> Self {
+        UvMapped {
+            inner,
+            transform,
+            wrap
+        }
+    }
+}
+
+impl Texture for UvMapped {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        let (u, v) = self.transform.apply(tc.u, tc.v);
+        let (du, dv) = self.transform.widths(tc.du, tc.dv);
+        self.inner.value(&TextureCoords { u: self.wrap.wrap(u), v: self.wrap.wrap(v), du, dv, ..*tc })
+    }
+
+    fn name(&self) -> &'static str {
+        "uv-mapped"
+    }
+
+    fn reload_if_changed(&self) -> bool {
+        self.inner.reload_if_changed()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let pair = |(a, b): (f64, f64)| format!("[{}, {}]", number(a), number(b));
+        Some(format!(
+            "{{ \"type\": \"uv_mapped\", \"texture\": {}, \"offset\": {}, \"scale\": {}, \"rotation\": {}, \"wrap\": \"{}\" }}",
+            self.inner.scene_json()?,
+            pair(self.transform.offset),
+            pair(self.transform.scale),
+            number(self.transform.rotation),
+            self.wrap.name()
+        ))
+    }
+}
+
+// Cheap integer lattice hash mapped to [0, 1), used by the procedural textures
+fn hash_cell(x: i64, y: i64, z: i64) -> f64 {
+    let mut h = (x.wrapping_mul(73856093) ^ y.wrapping_mul(19349663) ^ z.wrapping_mul(83492791)) as u64;
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Scatters small metallic flakes through space: each lattice cell either holds a
+// flake (a randomly brightened copy of `color`) or nothing, in which case it returns black.
+pub struct FlakeTexture {
+    color: Color,
+    density: f64,
+    scale: f64,
+    space: TextureSpace
+}
+
+impl FlakeTexture {
+    pub fn new(color: Color, density: f64, scale: f64) -> Self {
+        FlakeTexture {
+            color,
+            density,
+            scale,
+            space: TextureSpace::Object
+        }
+    }
+
+    pub fn in_space(mut self, space: TextureSpace) -> Self {
+        self.space = space;
+        self
+    }
+}
+
+impl Texture for FlakeTexture {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        let cell = tc.point(self.space) * self.scale;
+        let (x, y, z) = (cell.x.floor() as i64, cell.y.floor() as i64, cell.z.floor() as i64);
+        if hash_cell(x, y, z) < self.density {
+            self.color * (0.5 + 0.5 * hash_cell(z, x, y))
+        } else {
+            Color::zero()
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "flakes"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"flakes\", \"color\": {}, \"density\": {}, \"scale\": {}, \"space\": \"{}\" }}",
+            vector(self.color),
+            number(self.density),
+            number(self.scale),
+            self.space.name()
+        ))
+    }
+}
+
+// Smoothly interpolated lattice noise, summed over a few octaves. Raising the
+// contrast pushes values away from 0.5, which turns the noise into a patchy mask.
+pub struct NoiseTexture {
+    scale: f64,
+    octaves: u32,
+    contrast: f64,
+    space: TextureSpace
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, octaves: u32) -> Self {
+        NoiseTexture {
+            scale,
+            octaves,
+            contrast: 1.0,
+            space: TextureSpace::Object
+        }
+    }
+
+    pub fn with_contrast(mut self, contrast: f64) -> Self {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn in_space(mut self, space: TextureSpace) -> Self {
+        self.space = space;
+        self
+    }
+
+    fn value_noise(p: Point3) -> f64 {
+        let (x, y, z) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (u, v, w) = (smooth(p.x - x), smooth(p.y - y), smooth(p.z - z));
+        let (x, y, z) = (x as i64, y as i64, z as i64);
+
+        let mut accum = 0.0;
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let weight = (if i == 1 { u } else { 1.0 - u })
+                        * (if j == 1 { v } else { 1.0 - v })
+                        * (if k == 1 { w } else { 1.0 - w });
+                    accum += weight * hash_cell(x + i, y + j, z + k);
+                }
+            }
+        }
+        accum
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        let p = tc.point(self.space);
+        let mut accum = 0.0;
+        let mut amplitude = 0.5;
+        let mut frequency = self.scale;
+        let mut total = 0.0;
+        for _ in 0..self.octaves.max(1) {
+            // Octaves finer than the footprint are replaced by their mean
+            let fade = detail_fade(tc.footprint_in(self.space) * frequency);
+            accum += amplitude * (fade * NoiseTexture::value_noise(p * frequency) + (1.0 - fade) * 0.5);
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        let n = (accum / total - 0.5) * self.contrast + 0.5;
+        Color::one() * n.clamp(0.0, 1.0)
+    }
+
+    fn name(&self) -> &'static str {
+        "noise"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"noise\", \"scale\": {}, \"octaves\": {}, \"contrast\": {}, \"space\": \"{}\" }}",
+            number(self.scale),
+            self.octaves,
+            number(self.contrast),
+            self.space.name()
+        ))
+    }
+}
+
+// Values on a regular grid of voxels spanning the box from `min` to `max`,
+// trilinearly interpolated between voxel centers and zero outside the box, for
+// densities simulated or sculpted elsewhere. `values` run along x first, then y,
+// then z.
+pub struct VoxelTexture {
+    resolution: [usize; 3],
+    values: Vec<f64>,
+    min: Point3,
+    max: Point3,
+    space: TextureSpace
+}
+
+impl VoxelTexture {
+    pub fn new(resolution: [usize; 3], values: Vec<f64>, min: Point3, max: Point3) -> Self {
+        assert_eq!(values.len(), resolution.iter().product::<usize>(), "expected one value per voxel");
+        VoxelTexture { resolution, values, min, max, space: TextureSpace::Object }
+    }
+
+    pub fn in_space(mut self, space: TextureSpace) -> Self {
+        self.space = space;
+        self
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[x + nx * (y + ny * z)]
+    }
+}
+
+impl Texture for VoxelTexture {
+    fn value(&self, tc: &TextureCoords) -> Color {
+        let p = tc.point(self.space);
+        let size = self.max - self.min;
+        let local = [(p.x - self.min.x) / size.x, (p.y - self.min.y) / size.y, (p.z - self.min.z) / size.z];
+        if local.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            return Color::zero();
+        }
+        // The voxel below and the one above along each axis, and how far between them
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (local[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            lower[axis] = x as usize;
+            upper[axis] = (lower[axis] + 1).min(n - 1);
+            fraction[axis] = x - lower[axis] as f64;
+        }
+        let mut accum = 0.0;
+        for corner in 0..8 {
+            let pick = |axis: usize| corner >> axis & 1 == 1;
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                index[axis] = if pick(axis) { upper[axis] } else { lower[axis] };
+                weight *= if pick(axis) { fraction[axis] } else { 1.0 - fraction[axis] };
+            }
+            accum += weight * self.voxel(index[0], index[1], index[2]);
+        }
+        Color::one() * accum
+    }
+
+    fn name(&self) -> &'static str {
+        "voxels"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let values: Vec<String> = self.values.iter().map(|&v| number(v)).collect();
+        let [nx, ny, nz] = self.resolution;
+        Some(format!(
+            "{{ \"type\": \"voxels\", \"resolution\": [{nx}, {ny}, {nz}], \"min\": {}, \"max\": {}, \"values\": [{}], \"space\": \"{}\" }}",
+            vector(self.min),
+            vector(self.max),
+            values.join(", "),
+            self.space.name()
+        ))
+    }
+}
@@ -0,0 +1,56 @@
+// Control over the threads a render runs on
+
+// The logical cores this process may use, which in a container can be fewer
+// than the machine has
+pub fn available() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// The cores the process is allowed to run on, in order; empty where that can't be
+// found out
+#[cfg(target_os = "linux")]
+pub fn allowed_cores() -> Vec<usize> {
+    // Safe: the set is plain data, and the kernel writes at most its size
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Vec::new();
+        }
+        (0..libc::CPU_SETSIZE as usize).filter(|&core| libc::CPU_ISSET(core, &set)).collect()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn allowed_cores() -> Vec<usize> {
+    Vec::new()
+}
+
+// Keeps the calling thread on `core` from now on; false if it couldn't be done
+#[cfg(target_os = "linux")]
+pub fn pin_current(core: usize) -> bool {
+    // Safe: as above
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current(_core: usize) -> bool {
+    false
+}
+
+// Gives the calling thread the lowest scheduling priority, so the rest of the
+// machine stays responsive while it works; false if it couldn't be done
+#[cfg(target_os = "linux")]
+pub fn lower_priority() -> bool {
+    // Safe: plain system calls. On Linux a thread's nice value is its own, so
+    // this leaves the rest of the process alone.
+    unsafe { libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, 19) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lower_priority() -> bool {
+    false
+}
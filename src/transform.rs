@@ -0,0 +1,618 @@
+use std::{ops, sync::Arc};
+
+use crate::bvh::Aabb;
+use crate::json;
+use crate::sampler::Sampler;
+use crate::types::*;
+
+// A row-major 4x4 matrix acting on column vectors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4 {
+        m: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+    };
+
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Matrix4 { m }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4::new(t)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None when the matrix is singular
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inv = Matrix4::IDENTITY.m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Matrix4::new(inv))
+    }
+}
+
+impl ops::Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+// An affine transform kept together with its inverse, so moving rays into an
+// object's frame and normals back out of it never needs a matrix inverted per hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { matrix: Matrix4::IDENTITY, inverse: Matrix4::IDENTITY };
+
+    // None for a matrix that flattens space and so can't be undone
+    pub fn from_matrix(matrix: Matrix4) -> Option<Transform> {
+        Some(Transform { matrix, inverse: matrix.inverse()? })
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let (x, y, z) = (offset.x, offset.y, offset.z);
+        Transform {
+            matrix: Matrix4::new([[1.0, 0.0, 0.0, x], [0.0, 1.0, 0.0, y], [0.0, 0.0, 1.0, z], [0.0, 0.0, 0.0, 1.0]]),
+            inverse: Matrix4::new([[1.0, 0.0, 0.0, -x], [0.0, 1.0, 0.0, -y], [0.0, 0.0, 1.0, -z], [0.0, 0.0, 0.0, 1.0]]),
+        }
+    }
+
+    // None when any factor is zero
+    pub fn scale(factors: Vec3) -> Option<Transform> {
+        let (x, y, z) = (factors.x, factors.y, factors.z);
+        if x == 0.0 || y == 0.0 || z == 0.0 {
+            return None;
+        }
+        Some(Transform {
+            matrix: Matrix4::new([[x, 0.0, 0.0, 0.0], [0.0, y, 0.0, 0.0], [0.0, 0.0, z, 0.0], [0.0, 0.0, 0.0, 1.0]]),
+            inverse: Matrix4::new([
+                [1.0 / x, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / y, 0.0, 0.0],
+                [0.0, 0.0, 1.0 / z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+        })
+    }
+
+    // A right-handed rotation of `degrees` about `axis`
+    pub fn rotate(axis: Vec3, degrees: f64) -> Transform {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        let matrix = Matrix4::new([
+            [t * a.x * a.x + cos, t * a.x * a.y - sin * a.z, t * a.x * a.z + sin * a.y, 0.0],
+            [t * a.x * a.y + sin * a.z, t * a.y * a.y + cos, t * a.y * a.z - sin * a.x, 0.0],
+            [t * a.x * a.z - sin * a.y, t * a.y * a.z + sin * a.x, t * a.z * a.z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        // Rotations are orthogonal, so the inverse is just the transpose
+        Transform { inverse: matrix.transpose(), matrix }
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    // This transform followed by `next`
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform { matrix: next.matrix * self.matrix, inverse: self.inverse * next.inverse }
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        let m = &self.matrix.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Point3::new(x, y, z)
+        } else {
+            Point3::new(x / w, y / w, z / w)
+        }
+    }
+
+    // Directions ignore the translation
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.matrix.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    // Normals go through the inverse transpose to stay perpendicular to the
+    // surface under non-uniform scaling. The result isn't normalized.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let inv = &self.inverse.m;
+        Vec3::new(
+            inv[0][0] * n.x + inv[1][0] * n.y + inv[2][0] * n.z,
+            inv[0][1] * n.x + inv[1][1] * n.y + inv[2][1] * n.z,
+            inv[0][2] * n.x + inv[1][2] * n.y + inv[2][2] * n.z,
+        )
+    }
+
+    // The direction isn't renormalized, so hit distances `t` mean the same thing
+    // on both sides of the transform
+    pub fn ray(&self, r: Ray) -> Ray {
+        Ray { origin: self.point(r.origin), direction: self.vector(r.direction), ..r }
+    }
+
+    // The box around all eight transformed corners
+    pub fn aabb(&self, b: &Aabb) -> Aabb {
+        if b.is_empty() {
+            return *b;
+        }
+        let mut result = Aabb::empty();
+        for corner in 0..8 {
+            let pick = |bit: usize, axis: usize| if corner & bit == 0 { b.min[axis] } else { b.max[axis] };
+            let p = self.point(Point3::new(pick(1, 0), pick(2, 1), pick(4, 2)));
+            result = result.union(&Aabb::new(p, p));
+        }
+        result
+    }
+}
+
+// A unit quaternion, for rotations that interpolate smoothly
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    // A right-handed rotation of `degrees` about `axis`
+    pub fn from_axis_angle(axis: Vec3, degrees: f64) -> Quat {
+        let a = axis.unit_vector();
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Quat { w: cos, x: a.x * sin, y: a.y * sin, z: a.z * sin }
+    }
+
+    // Rotates about x, then y, then z, each by the given degrees and about the
+    // fixed world axes
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Quat {
+        let about = |axis: Vec3, degrees: f64| Quat::from_axis_angle(axis, degrees);
+        about(Vec3::new(0.0, 0.0, 1.0), z) * about(Vec3::new(0.0, 1.0, 0.0), y) * about(Vec3::new(1.0, 0.0, 0.0), x)
+    }
+
+    pub fn dot(&self, other: &Quat) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let length = self.dot(self).sqrt();
+        Quat { w: self.w / length, x: self.x / length, y: self.y / length, z: self.z / length }
+    }
+
+    pub fn conjugate(&self) -> Quat {
+        Quat { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+
+    // Constant-speed interpolation along the shorter arc from `self` (at t = 0)
+    // to `other` (at t = 1)
+    pub fn slerp(&self, other: &Quat, t: f64) -> Quat {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        // q and -q are the same rotation; going through the nearer one takes the short way round
+        if cos < 0.0 {
+            cos = -cos;
+            other = Quat { w: -other.w, x: -other.x, y: -other.y, z: -other.z };
+        }
+        let (a, b) = if cos > 0.9995 {
+            // Nearly the same rotation: a straight blend is indistinguishable and avoids dividing by ~0
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quat {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }
+        .normalized()
+    }
+
+    pub fn to_transform(&self) -> Transform {
+        let Quat { w, x, y, z } = self.normalized();
+        let matrix = Matrix4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform { inverse: matrix.transpose(), matrix }
+    }
+}
+
+// Hamilton product: `a * b` rotates by `b` first, then by `a`
+impl ops::Mul<Quat> for Quat {
+    type Output = Quat;
+
+    fn mul(self, b: Quat) -> Quat {
+        let a = self;
+        Quat {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+}
+
+// An object placed in the scene through a transform. Rays are moved into the
+// object's own frame to be tested, and hits moved back out; the hit's `local_p`
+// stays in the object's frame, so textures turn and move along with it.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+    to_object: Transform,
+    overrides: Overrides,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Instance { object, to_object: transform.inverse(), transform, overrides: Overrides::none() }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.overrides.tint = tint;
+        self
+    }
+
+    pub fn with_roughness_scale(mut self, roughness_scale: f64) -> Self {
+        self.overrides.roughness_scale = roughness_scale;
+        self
+    }
+
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        if !hit_object(self.object.as_ref(), self.to_object.ray(r), ray_t, rec, sampler) {
+            return false;
+        }
+        // The inverse transpose keeps the normal facing the same way relative to
+        // the ray, so `front_face` still holds
+        rec.p = self.transform.point(rec.p);
+        rec.normal = self.transform.normal(rec.normal).unit_vector();
+        rec.terminator_offset = self.transform.vector(rec.terminator_offset);
+        rec.dpdu = self.transform.vector(rec.dpdu);
+        rec.dpdv = self.transform.vector(rec.dpdv);
+        rec.overrides = rec.overrides.then(self.overrides);
+        true
+    }
+}
+
+impl Hittable for Instance {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
+
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box().map(|b| self.transform.aabb(&b))
+    }
+
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (p, normal) = self.object.surface_at_uv(u, v)?;
+        Some((self.transform.point(p), self.transform.normal(normal).unit_vector()))
+    }
+
+    fn kind(&self) -> &'static str {
+        self.object.kind()
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        self.object.material()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let rows: Vec<String> = self.transform.matrix().m[..3]
+            .iter()
+            .map(|row| format!("[{}]", row.iter().map(|&x| json::number(x)).collect::<Vec<_>>().join(", ")))
+            .collect();
+        Some(format!(
+            "{{ \"type\": \"instance\", \"matrix\": [{}], \"object\": {}, \"tint\": {}, \"roughness_scale\": {} }}",
+            rows.join(", "),
+            self.object.scene_json()?,
+            json::vector(self.overrides.tint),
+            json::number(self.overrides.roughness_scale)
+        ))
+    }
+}
+
+// An object moved by `offset`. Much cheaper per hit than an Instance, for the
+// common case of copies of one object put in different places.
+pub struct Translate {
+    object: Arc<dyn Hittable>,
+    offset: Vec3,
+}
+
+impl Translate {
+    pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
+        Translate { object, offset }
+    }
+
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        let moved = Ray { origin: r.origin - self.offset, ..r };
+        if !hit_object(self.object.as_ref(), moved, ray_t, rec, sampler) {
+            return false;
+        }
+        rec.p += self.offset;
+        true
+    }
+}
+
+impl Hittable for Translate {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
+
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box().map(|b| Transform::translate(self.offset).aabb(&b))
+    }
+
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (p, normal) = self.object.surface_at_uv(u, v)?;
+        Some((p + self.offset, normal))
+    }
+
+    fn kind(&self) -> &'static str {
+        self.object.kind()
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        self.object.material()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let object = self.object.scene_json()?;
+        Some(format!("{{ \"translate\": {},{}", json::vector(self.offset), object.strip_prefix('{')?))
+    }
+}
+
+// An object turned `degrees` about the y axis through the origin, right-handed
+// as Transform::rotate turns. Like an Instance, the hit's `local_p` stays in the
+// object's frame.
+pub struct RotateY {
+    object: Arc<dyn Hittable>,
+    sin: f64,
+    cos: f64,
+    bbox: Option<Aabb>,
+}
+
+impl RotateY {
+    pub fn new(object: Arc<dyn Hittable>, degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let bbox = object.bounding_box().map(|b| Transform::rotate(Vec3::new(0.0, 1.0, 0.0), degrees).aabb(&b));
+        RotateY { object, sin, cos, bbox }
+    }
+
+    // Out of the object's frame into the world's
+    fn turn(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.cos * v.x + self.sin * v.z, v.y, -self.sin * v.x + self.cos * v.z)
+    }
+
+    fn turn_back(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.cos * v.x - self.sin * v.z, v.y, self.sin * v.x + self.cos * v.z)
+    }
+
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        let turned = Ray { origin: self.turn_back(r.origin), direction: self.turn_back(r.direction), ..r };
+        if !hit_object(self.object.as_ref(), turned, ray_t, rec, sampler) {
+            return false;
+        }
+        // A rotation keeps the normal on the same side of the ray, so `front_face` still holds
+        rec.p = self.turn(rec.p);
+        rec.normal = self.turn(rec.normal);
+        rec.terminator_offset = self.turn(rec.terminator_offset);
+        rec.dpdu = self.turn(rec.dpdu);
+        rec.dpdv = self.turn(rec.dpdv);
+        true
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
+
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (p, normal) = self.object.surface_at_uv(u, v)?;
+        Some((self.turn(p), self.turn(normal)))
+    }
+
+    fn kind(&self) -> &'static str {
+        self.object.kind()
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        self.object.material()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    // A rotated, non-uniformly scaled and moved transform, so no term of the matrix is trivial
+    fn skewed() -> Transform {
+        Transform::rotate(Vec3::new(1.0, 2.0, -0.5), 37.0)
+            .then(&Transform::scale(Vec3::new(2.0, 0.5, 3.0)).unwrap())
+            .then(&Transform::rotate(Vec3::new(0.0, 1.0, 1.0), -71.0))
+            .then(&Transform::translate(Vec3::new(4.0, -1.0, 2.5)))
+    }
+
+    #[test]
+    fn a_matrix_times_its_inverse_is_the_identity() {
+        let m = *skewed().matrix();
+        let product = m * m.inverse().unwrap();
+        for (i, row) in product.m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-9, "entry ({}, {}) is {}", i, j, value);
+            }
+        }
+        assert_eq!(Matrix4::new([[1.0, 2.0, 0.0, 0.0], [2.0, 4.0, 0.0, 0.0], [0.0; 4], [0.0, 0.0, 0.0, 1.0]]).inverse(), None);
+    }
+
+    #[test]
+    fn a_transform_and_its_inverse_undo_each_other() {
+        let t = skewed();
+        let p = Point3::new(0.3, -1.7, 5.0);
+        assert!(close(t.inverse().point(t.point(p)), p));
+        assert!(close(t.point(t.inverse().point(p)), p));
+        assert!(close(t.inverse().vector(t.vector(p)), p));
+    }
+
+    #[test]
+    fn then_applies_the_first_transform_first() {
+        let spin = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        let shift = Transform::translate(Vec3::new(1.0, 0.0, 0.0));
+        let p = Point3::new(1.0, 0.0, 0.0);
+        assert!(close(spin.then(&shift).point(p), Point3::new(1.0, 1.0, 0.0)));
+        assert!(close(shift.then(&spin).point(p), Point3::new(0.0, 2.0, 0.0)));
+        assert!(close(spin.then(&shift).inverse().point(Point3::new(1.0, 1.0, 0.0)), p));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let t = skewed();
+        // Two tangents of a plane and its normal
+        let (a, b) = (Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, -2.0));
+        let n = t.normal(a.cross(b));
+        assert!(n.dot(t.vector(a)).abs() < 1e-9);
+        assert!(n.dot(t.vector(b)).abs() < 1e-9);
+        // The plain vector transform doesn't keep them perpendicular, which is the point
+        assert!(t.vector(a.cross(b)).dot(t.vector(a)).abs() > 1e-3);
+    }
+
+    #[test]
+    fn transformed_bounds_hold_every_corner_tightly() {
+        let b = Aabb::new(Point3::new(-1.0, 0.0, 2.0), Point3::new(3.0, 0.5, 4.0));
+        let t = skewed();
+        let bounds = t.aabb(&b);
+        let corners: Vec<Point3> = (0..8)
+            .map(|c: usize| {
+                let pick = |bit: usize, axis: usize| if c & bit == 0 { b.min[axis] } else { b.max[axis] };
+                t.point(Point3::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+            })
+            .collect();
+        for axis in 0..3 {
+            let lowest = corners.iter().map(|p| p[axis]).fold(f64::INFINITY, f64::min);
+            let highest = corners.iter().map(|p| p[axis]).fold(f64::NEG_INFINITY, f64::max);
+            assert!((bounds.min[axis] - lowest).abs() < 1e-9, "axis {} starts at {} not {}", axis, bounds.min[axis], lowest);
+            assert!((bounds.max[axis] - highest).abs() < 1e-9, "axis {} ends at {} not {}", axis, bounds.max[axis], highest);
+        }
+
+        // A quarter turn about z swaps the x and y extents exactly
+        let turned = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0).aabb(&b);
+        assert!(close(turned.min, Point3::new(-0.5, -1.0, 2.0)));
+        assert!(close(turned.max, Point3::new(0.0, 3.0, 4.0)));
+        assert!(t.aabb(&Aabb::empty()).is_empty());
+    }
+
+    #[test]
+    fn quaternions_match_the_matrix_rotation() {
+        let axis = Vec3::new(1.0, -2.0, 0.5);
+        let q = Quat::from_axis_angle(axis, 123.0);
+        let m = Transform::rotate(axis, 123.0);
+        let v = Vec3::new(0.2, 0.7, -1.1);
+        assert!(close(q.rotate(v), m.vector(v)));
+        assert!(close(q.to_transform().vector(v), m.vector(v)));
+    }
+
+    #[test]
+    fn slerp_runs_from_one_end_to_the_other_at_half_angles() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let a = Quat::from_axis_angle(axis, 10.0);
+        let b = Quat::from_axis_angle(axis, 130.0);
+        let v = Vec3::new(1.0, 0.0, 0.0);
+        assert!(close(a.slerp(&b, 0.0).rotate(v), a.rotate(v)));
+        assert!(close(a.slerp(&b, 1.0).rotate(v), b.rotate(v)));
+        let half = Quat::from_axis_angle(axis, 70.0).rotate(v);
+        assert!(close(a.slerp(&b, 0.5).rotate(v), half));
+        // Constant speed: the quarter point is a quarter of the way round
+        assert!(close(a.slerp(&b, 0.25).rotate(v), Quat::from_axis_angle(axis, 40.0).rotate(v)));
+
+        // -b is the same rotation as b, so it must take the same short way round
+        let flipped = Quat { w: -b.w, x: -b.x, y: -b.y, z: -b.z };
+        assert!(close(a.slerp(&flipped, 0.5).rotate(v), half));
+    }
+}
@@ -1,16 +1,69 @@
-use std::{ops, sync::Arc, thread::Thread};
-use rand::{thread_rng, Rng, prelude::ThreadRng};
-
-#[derive(Clone)]
-pub struct HitRecord {
+pub use std::f64::consts::PI;
+use std::{io, ops, path::Path, sync::Arc};
+
+use crate::bvh::{Aabb, Bvh};
+use crate::json::{number, vector};
+use crate::render::Image;
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::texture::{SolidColor, Texture, TextureCoords};
+
+// Borrows the material from the object that was hit instead of holding an Arc,
+// so recording a hit costs no reference-count traffic and the record stays Copy
+#[derive(Clone, Copy)]
+pub struct HitRecord<'a> {
     pub p: Point3,
+    // The hit point in the object's own frame, so textures can stick to the object
+    pub local_p: Point3,
     pub normal: Vec3,
-    pub mat_ptr: Option<Arc<dyn Material>>,
+    pub mat_ptr: Option<&'a dyn Material>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    // World-space width of the ray's footprint on the surface, for texture filtering
+    pub footprint: f64,
     pub front_face: bool,
+    // Left by the instances the ray went through on the way to the surface
+    pub overrides: Overrides,
+    // Set by an integrator regularizing the path: materials treat themselves as
+    // at least this rough, even where they're perfectly smooth
+    pub min_roughness: f64,
+    // Where smooth shading bends the normal away from a flat face, rays leaving on
+    // this side start off the face by this much (Hanika's shadow terminator fix),
+    // so they don't catch the neighbouring faces and shadow the facets; zero for
+    // everything else
+    pub terminator_offset: Vec3,
+    // The color painted on a mesh's vertices, blended across the face; None on
+    // everything without vertex colors
+    pub vertex_color: Option<Color>,
+    // How far the hit point moves per unit of u and of v, which turns the
+    // footprint into widths in UV space; zero where the surface doesn't say
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+}
+
+// Changes an instance makes to the look of whatever material it's made of, so
+// copies of one object can vary in color and gloss while sharing the material:
+// the material's colors are multiplied by `tint` and its roughness by
+// `roughness_scale`
+#[derive(Clone, Copy)]
+pub struct Overrides {
+    pub tint: Color,
+    pub roughness_scale: f64,
+}
+
+impl Overrides {
+    pub fn none() -> Overrides {
+        Overrides { tint: Color::one(), roughness_scale: 1.0 }
+    }
+
+    // An instance inside another gets both sets of changes
+    pub fn then(self, outer: Overrides) -> Overrides {
+        Overrides { tint: self.tint * outer.tint, roughness_scale: self.roughness_scale * outer.roughness_scale }
+    }
 }
 
-impl HitRecord {
+impl<'a> HitRecord<'a> {
     pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
         self.front_face = r.direction.dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
@@ -20,134 +73,2483 @@ impl HitRecord {
         };
     }
 
-    pub fn blank() -> HitRecord {
-        HitRecord {
-            p: Point3::zero(),
-            normal: Vec3::zero(),
-            mat_ptr: None,
-            t: 0.0,
-            front_face: false,
+    // Records a hit at `t` on a surface of `mat_ptr`, with everything only some
+    // surfaces set (instance overrides, the terminator offset, vertex colors) put
+    // back to its default so nothing carries over from an earlier hit. The local
+    // point is the hit point until the caller says otherwise.
+    pub fn set_surface(&mut self, r: Ray, t: f64, outward_normal: Vec3, (u, v): (f64, f64), mat_ptr: &'a dyn Material) {
+        self.t = t;
+        self.p = r.at(t);
+        self.local_p = self.p;
+        self.set_face_normal(r, outward_normal);
+        (self.u, self.v) = (u, v);
+        self.mat_ptr = Some(mat_ptr);
+        self.overrides = Overrides::none();
+        self.terminator_offset = Vec3::zero();
+        self.vertex_color = None;
+        self.dpdu = Vec3::zero();
+        self.dpdv = Vec3::zero();
+    }
+
+    pub fn texture_coords(&self) -> TextureCoords {
+        let uv_width = |dpd: Vec3| {
+            let length = dpd.length();
+            if length > 0.0 { self.footprint / length } else { 0.0 }
+        };
+        TextureCoords {
+            u: self.u,
+            v: self.v,
+            p: self.p,
+            local_p: self.local_p,
+            normal: self.normal,
+            footprint: self.footprint,
+            du: uv_width(self.dpdu),
+            dv: uv_width(self.dpdv),
+            vertex_color: self.vertex_color,
+        }
+    }
+
+    // Grazing hits stretch the footprint across the surface
+    pub fn set_footprint(&mut self, r: Ray) {
+        let cos = r.direction.unit_vector().dot(self.normal).abs().max(0.05);
+        self.footprint = r.cone.width_at(self.t) / cos;
+    }
+
+    pub fn blank() -> HitRecord<'a> {
+        HitRecord {
+            p: Point3::zero(),
+            local_p: Point3::zero(),
+            normal: Vec3::zero(),
+            mat_ptr: None,
+            t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            footprint: 0.0,
+            front_face: false,
+            overrides: Overrides::none(),
+            min_roughness: 0.0,
+            terminator_offset: Vec3::zero(),
+            vertex_color: None,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+        }
+    }
+
+    // Where a ray leaving the surface along `direction` should start
+    pub fn scatter_origin(&self, direction: Vec3) -> Point3 {
+        if direction.dot(self.terminator_offset) > 0.0 { self.p + self.terminator_offset } else { self.p }
+    }
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit<'a>(&'a self, _r: Ray, _ray_t: Interval, _rec: &mut HitRecord<'a>) -> bool {
+        false
+    }
+
+    // As `hit`, for a ray with a sampler to draw from. Media scatter rays at random
+    // distances drawn from it, and are passed through by plain `hit`; objects
+    // holding others hand it on to them. Everything else just hits.
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, _sampler: &mut dyn Sampler) -> bool {
+        self.hit(r, ray_t, rec)
+    }
+
+    // Objects without finite bounds (or that can't tell) are kept out of the BVH
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // What kind of object this is and the material it's made of, for scene statistics
+    fn kind(&self) -> &'static str {
+        "object"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        None
+    }
+
+    // The object as a scene file describes it, or None if it can't be written out
+    fn scene_json(&self) -> Option<String> {
+        None
+    }
+
+    // The point with texture coordinates (u, v) and the outward normal there, for
+    // objects with a UV layout to bake light into. None for objects without one.
+    fn surface_at_uv(&self, _u: f64, _v: f64) -> Option<(Point3, Vec3)> {
+        None
+    }
+}
+
+pub trait Material: Send + Sync {
+    fn scatter(&self, _r_in: Ray, _rec: &HitRecord, _attenuation: &mut Color, _scattered: &mut Ray, _sampler: &mut dyn Sampler) -> bool {
+        false
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+
+    fn is_visible_to_camera(&self) -> bool {
+        true
+    }
+
+    // Lights keep their own material when the scene's materials are overridden
+    fn is_emitter(&self) -> bool {
+        false
+    }
+
+    // The material's type and every texture it samples, including those of the
+    // materials it layers over, for scene statistics
+    fn name(&self) -> &'static str {
+        "material"
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        Vec::new()
+    }
+
+    // The density, per unit solid angle, of `scatter` sending the light leaving `rec`
+    // off along `scattered`; None for materials that concentrate it in mirror
+    // directions or whose density hasn't been worked out
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> Option<f64> {
+        None
+    }
+
+    // How shadow rays get through the surface; None for opaque surfaces, which is
+    // nearly all of them
+    fn transmission(&self) -> Option<Transmission> {
+        None
+    }
+
+    // The material as a scene file describes it, or None if it can't be written out
+    fn scene_json(&self) -> Option<String> {
+        None
+    }
+}
+
+// What a shadow ray picks up crossing a transparent boundary: the boundary's tint,
+// and the priority that settles which of two overlapping media is really there
+#[derive(Clone, Copy)]
+pub struct Transmission {
+    pub tint: Color,
+    pub priority: i32,
+}
+
+// Spreads a perfectly smooth surface's mirror or refracted `direction` as a
+// surface `rec.min_roughness` rough would, keeping it on the same side of the
+// surface. Unchanged when the path isn't being regularized.
+fn roughen(direction: Vec3, rec: &HitRecord, sampler: &mut dyn Sampler) -> Vec3 {
+    if rec.min_roughness <= 0.0 {
+        return direction;
+    }
+    let spread = direction.unit_vector() + Vec3::random_in_unit_sphere(sampler) * rec.min_roughness;
+    if (spread.dot(rec.normal) > 0.0) == (direction.dot(rec.normal) > 0.0) {
+        spread
+    } else {
+        direction
+    }
+}
+
+// Schlick's approximation for the Fresnel reflectance of a dielectric boundary
+pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    let r0 = r0 * r0;
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+pub struct LambertianMaterial {
+    albedo: Arc<dyn Texture>
+}
+
+impl LambertianMaterial {
+    pub fn new(albedo: Color) -> Self {
+        LambertianMaterial::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
+        LambertianMaterial {
+            albedo
+        }
+    }
+}
+
+impl Material for LambertianMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let scatter_direction = Onb::from_normal(rec.normal).local_to_world(sampling::cosine_hemisphere(sampler.get_2d()));
+        *scattered = Ray { origin: rec.p, direction: scatter_direction, cone: r_in.cone.diffuse(rec.t), time: r_in.time };
+        *attenuation = self.albedo.value(&rec.texture_coords()) * rec.overrides.tint;
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> Option<f64> {
+        Some(sampling::cosine_hemisphere_pdf(rec.normal.dot(scattered.direction.unit_vector())))
+    }
+
+    fn name(&self) -> &'static str {
+        "lambertian"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!("{{ \"type\": \"lambertian\", \"albedo\": {} }}", self.albedo.scene_json()?))
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        vec![self.albedo.as_ref()]
+    }
+}
+
+pub struct MetalMaterial {
+    albedo: Color,
+    fuzz: f64
+}
+
+impl MetalMaterial {
+    pub fn new(albedo: Color, fuzz: f64) -> Self {
+        MetalMaterial {
+            albedo,
+            fuzz
+        }
+    }
+}
+
+impl Material for MetalMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let reflected = Vec3::reflect(r_in.direction.unit_vector(), rec.normal);
+        let fuzz = (self.fuzz * rec.overrides.roughness_scale).min(1.0).max(rec.min_roughness);
+        *scattered = Ray {
+            origin: rec.p,
+            direction: reflected + Vec3::random_in_unit_sphere(sampler) * fuzz,
+            cone: r_in.cone.specular(rec.t, fuzz),
+            time: r_in.time,
+        };
+        *attenuation = self.albedo * rec.overrides.tint;
+        scattered.direction.dot(rec.normal) > 0.0
+    }
+
+    fn name(&self) -> &'static str {
+        "metal"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!("{{ \"type\": \"metal\", \"albedo\": {}, \"fuzz\": {} }}", vector(self.albedo), number(self.fuzz)))
+    }
+}
+
+// Glass, water and the like, which refract or reflect by the Fresnel term and
+// tint light on the way through. Where dielectrics nest (ice in water, water in a
+// glass) the one with the higher `priority` fills the overlap.
+pub struct DielectricMaterial {
+    ior: f64,
+    tint: Color,
+    priority: i32,
+}
+
+impl DielectricMaterial {
+    pub fn new(ior: f64) -> Self {
+        DielectricMaterial { ior, tint: Color::one(), priority: 0 }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+impl Material for DielectricMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let ratio = if rec.front_face { 1.0 / self.ior } else { self.ior };
+        let unit_direction = r_in.direction.unit_vector();
+        let cos_theta = f64::min(-unit_direction.dot(rec.normal), 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let direction = if ratio * sin_theta > 1.0 || sampler.get_1d() < reflectance(cos_theta, ratio) {
+            Vec3::reflect(unit_direction, rec.normal)
+        } else {
+            Vec3::refract(unit_direction, rec.normal, ratio)
+        };
+        let direction = roughen(direction, rec, sampler);
+        *scattered = Ray { origin: rec.p, direction, cone: r_in.cone.specular(rec.t, rec.min_roughness), time: r_in.time };
+        *attenuation = self.tint * rec.overrides.tint;
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "dielectric"
+    }
+
+    fn transmission(&self) -> Option<Transmission> {
+        Some(Transmission { tint: self.tint, priority: self.priority })
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"dielectric\", \"ior\": {}, \"tint\": {}, \"priority\": {} }}",
+            number(self.ior),
+            vector(self.tint),
+            self.priority
+        ))
+    }
+}
+
+// A smooth dielectric coat over an arbitrary base material, optionally with
+// metallic flakes suspended under the coat (car paint).
+pub struct ClearcoatMaterial {
+    base: Arc<dyn Material>,
+    ior: f64,
+    flakes: Option<Arc<dyn Texture>>,
+    flake_fuzz: f64
+}
+
+impl ClearcoatMaterial {
+    pub fn new(base: Arc<dyn Material>, ior: f64) -> Self {
+        ClearcoatMaterial {
+            base,
+            ior,
+            flakes: None,
+            flake_fuzz: 0.0
+        }
+    }
+
+    pub fn with_flakes(mut self, flakes: Arc<dyn Texture>, flake_fuzz: f64) -> Self {
+        self.flakes = Some(flakes);
+        self.flake_fuzz = flake_fuzz;
+        self
+    }
+}
+
+impl Material for ClearcoatMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let unit_direction = r_in.direction.unit_vector();
+        let cos_theta = f64::min(-unit_direction.dot(rec.normal), 1.0);
+
+        // The coat reflects a Fresnel-weighted share of the light without tinting it
+        if sampler.get_1d() < reflectance(cos_theta, self.ior) {
+            *scattered = Ray {
+                origin: rec.p,
+                direction: roughen(Vec3::reflect(unit_direction, rec.normal), rec, sampler),
+                cone: r_in.cone.specular(rec.t, rec.min_roughness),
+                time: r_in.time,
+            };
+            *attenuation = Color::one();
+            return true;
+        }
+
+        if let Some(ref flakes) = self.flakes {
+            let flake = flakes.value(&rec.texture_coords());
+            if !flake.near_zero() {
+                let reflected = Vec3::reflect(unit_direction, rec.normal);
+                let fuzz = (self.flake_fuzz * rec.overrides.roughness_scale).min(1.0).max(rec.min_roughness);
+                *scattered = Ray {
+                    origin: rec.p,
+                    direction: reflected + Vec3::random_in_unit_sphere(sampler) * fuzz,
+                    cone: r_in.cone.specular(rec.t, fuzz),
+                    time: r_in.time,
+                };
+                *attenuation = flake * rec.overrides.tint;
+                return scattered.direction.dot(rec.normal) > 0.0;
+            }
+        }
+
+        self.base.scatter(r_in, rec, attenuation, scattered, sampler)
+    }
+
+    fn name(&self) -> &'static str {
+        "clearcoat"
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        let mut textures = self.base.textures();
+        textures.extend(self.flakes.as_deref());
+        textures
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let flakes = match &self.flakes {
+            Some(flakes) => format!(", \"flakes\": {}, \"flake_fuzz\": {}", flakes.scene_json()?, number(self.flake_fuzz)),
+            None => String::new(),
+        };
+        Some(format!(
+            "{{ \"type\": \"clearcoat\", \"base\": {}, \"ior\": {}{flakes} }}",
+            self.base.scene_json()?,
+            number(self.ior)
+        ))
+    }
+}
+
+// A retroreflective sheen lobe over a base material, giving cloth its bright rim
+// at grazing angles. `roughness` spreads the light sent back towards the viewer.
+pub struct SheenMaterial {
+    base: Arc<dyn Material>,
+    sheen: Color,
+    roughness: f64
+}
+
+impl SheenMaterial {
+    pub fn new(base: Arc<dyn Material>, sheen: Color, roughness: f64) -> Self {
+        SheenMaterial {
+            base,
+            sheen,
+            roughness
+        }
+    }
+
+    // Velvet: a diffuse base with a sheen of the same hue, brightened towards white
+    pub fn velvet(color: Color) -> Self {
+        SheenMaterial::new(
+            Arc::new(LambertianMaterial::new(color)),
+            color * 0.5 + Color::one() * 0.5,
+            0.5,
+        )
+    }
+}
+
+impl Material for SheenMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let unit_direction = r_in.direction.unit_vector();
+        let cos_theta = f64::min(-unit_direction.dot(rec.normal), 1.0).max(0.0);
+
+        // The sheen fibres catch light mostly when seen edge-on
+        if sampler.get_1d() < (1.0 - cos_theta).powi(3) {
+            let roughness = (self.roughness * rec.overrides.roughness_scale).max(rec.min_roughness);
+            let retro = -unit_direction + Vec3::random_in_unit_sphere(sampler) * roughness;
+            let direction = if retro.dot(rec.normal) > 0.0 {
+                retro
+            } else {
+                rec.normal + Vec3::random_unit_vector(sampler)
+            };
+            *scattered = Ray { origin: rec.p, direction, cone: r_in.cone.specular(rec.t, roughness), time: r_in.time };
+            *attenuation = self.sheen * rec.overrides.tint;
+            return true;
+        }
+
+        self.base.scatter(r_in, rec, attenuation, scattered, sampler)
+    }
+
+    fn name(&self) -> &'static str {
+        "sheen"
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        self.base.textures()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"sheen\", \"base\": {}, \"sheen\": {}, \"roughness\": {} }}",
+            self.base.scene_json()?,
+            vector(self.sheen),
+            number(self.roughness)
+        ))
+    }
+}
+
+// Picks one of two materials per scatter event. The mask's average channel gives
+// the probability of choosing `b`, so a solid mask blends by a constant factor and
+// a procedural one paints `b` in patches (e.g. rust on metal).
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    mask: Arc<dyn Texture>
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, factor: f64) -> Self {
+        MixMaterial::with_mask(a, b, Arc::new(SolidColor::new(Color::one() * factor)))
+    }
+
+    pub fn with_mask(a: Arc<dyn Material>, b: Arc<dyn Material>, mask: Arc<dyn Texture>) -> Self {
+        MixMaterial {
+            a,
+            b,
+            mask
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        let m = self.mask.value(&rec.texture_coords());
+        let factor = (m.x + m.y + m.z) / 3.0;
+        if sampler.get_1d() < factor {
+            self.b.scatter(r_in, rec, attenuation, scattered, sampler)
+        } else {
+            self.a.scatter(r_in, rec, attenuation, scattered, sampler)
+        }
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Option<f64> {
+        let m = self.mask.value(&rec.texture_coords());
+        let factor = (m.x + m.y + m.z) / 3.0;
+        let a = self.a.scattering_pdf(r_in, rec, scattered)?;
+        let b = self.b.scattering_pdf(r_in, rec, scattered)?;
+        Some(a * (1.0 - factor) + b * factor)
+    }
+
+    fn name(&self) -> &'static str {
+        "mix"
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        let mut textures = vec![self.mask.as_ref()];
+        textures.extend(self.a.textures());
+        textures.extend(self.b.textures());
+        textures
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"mix\", \"a\": {}, \"b\": {}, \"mask\": {} }}",
+            self.a.scene_json()?,
+            self.b.scene_json()?,
+            self.mask.scene_json()?
+        ))
+    }
+}
+
+// A principled material described by a handful of artist-facing parameters and
+// built out of the layers above: a diffuse base mixed with a metal of the same
+// color by `metallic`, under an optional sheen and then an optional clear coat
+pub struct PrincipledMaterial {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    sheen: Option<(Color, f64)>,
+    coat_ior: Option<f64>,
+    layers: Arc<dyn Material>,
+}
+
+impl PrincipledMaterial {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        let mut material = PrincipledMaterial {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            sheen: None,
+            coat_ior: None,
+            layers: Arc::new(LambertianMaterial::new(base_color)),
+        };
+        material.layers = material.build();
+        material
+    }
+
+    pub fn with_sheen(mut self, sheen: Color, roughness: f64) -> Self {
+        self.sheen = Some((sheen, roughness));
+        self.layers = self.build();
+        self
+    }
+
+    pub fn with_clearcoat(mut self, ior: f64) -> Self {
+        self.coat_ior = Some(ior);
+        self.layers = self.build();
+        self
+    }
+
+    fn build(&self) -> Arc<dyn Material> {
+        let diffuse: Arc<dyn Material> = Arc::new(LambertianMaterial::new(self.base_color));
+        let mut layers: Arc<dyn Material> = match self.metallic {
+            m if m <= 0.0 => diffuse,
+            m => Arc::new(MixMaterial::new(diffuse, Arc::new(MetalMaterial::new(self.base_color, self.roughness)), m)),
+        };
+        if let Some((sheen, roughness)) = self.sheen {
+            layers = Arc::new(SheenMaterial::new(layers, sheen, roughness));
+        }
+        if let Some(ior) = self.coat_ior {
+            layers = Arc::new(ClearcoatMaterial::new(layers, ior));
+        }
+        layers
+    }
+}
+
+impl Material for PrincipledMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        self.layers.scatter(r_in, rec, attenuation, scattered, sampler)
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Option<f64> {
+        self.layers.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn name(&self) -> &'static str {
+        "principled"
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        self.layers.textures()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let sheen = match self.sheen {
+            Some((sheen, roughness)) => format!(", \"sheen\": {}, \"sheen_roughness\": {}", vector(sheen), number(roughness)),
+            None => String::new(),
+        };
+        let coat = self.coat_ior.map_or(String::new(), |ior| format!(", \"clearcoat_ior\": {}", number(ior)));
+        Some(format!(
+            "{{ \"type\": \"principled\", \"base_color\": {}, \"metallic\": {}, \"roughness\": {}{sheen}{coat} }}",
+            vector(self.base_color),
+            number(self.metallic),
+            number(self.roughness)
+        ))
+    }
+}
+
+// An emitter whose radiance is `emit * intensity`. Intensity is relative to the
+// display: a white light at intensity 1.0 shows up as pure white. `from_watts`
+// converts a radiant power spread over the emitter's surface area instead.
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+    intensity: f64,
+    two_sided: bool,
+    visible_to_camera: bool
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Arc<dyn Texture>, intensity: f64) -> Self {
+        DiffuseLight {
+            emit,
+            intensity,
+            two_sided: false,
+            visible_to_camera: true
+        }
+    }
+
+    // A Lambertian emitter of area `area` radiating `watts` from one side has a
+    // radiance of watts / (pi * area)
+    pub fn from_watts(color: Color, watts: f64, area: f64) -> Self {
+        DiffuseLight::new(Arc::new(SolidColor::new(color)), watts / (PI * area))
+    }
+
+    pub fn two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    pub fn visible_to_camera(mut self, visible: bool) -> Self {
+        self.visible_to_camera = visible;
+        self
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face || self.two_sided {
+            self.emit.value(&rec.texture_coords()) * rec.overrides.tint * self.intensity
+        } else {
+            Color::zero()
+        }
+    }
+
+    fn is_visible_to_camera(&self) -> bool {
+        self.visible_to_camera
+    }
+
+    fn is_emitter(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "diffuse-light"
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        vec![self.emit.as_ref()]
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"light\", \"emit\": {}, \"intensity\": {}, \"two_sided\": {}, \"visible_to_camera\": {} }}",
+            self.emit.scene_json()?,
+            number(self.intensity),
+            self.two_sided,
+            self.visible_to_camera
+        ))
+    }
+}
+
+// The phase function of a participating medium: scatters the same amount in
+// every direction, whichever way the light came in
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Isotropic::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, sampler: &mut dyn Sampler) -> bool {
+        *scattered = Ray { origin: rec.p, direction: Vec3::random_unit_vector(sampler), cone: r_in.cone.diffuse(rec.t), time: r_in.time };
+        *attenuation = self.albedo.value(&rec.texture_coords()) * rec.overrides.tint;
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> Option<f64> {
+        Some(1.0 / (4.0 * PI))
+    }
+
+    fn name(&self) -> &'static str {
+        "isotropic"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!("{{ \"type\": \"isotropic\", \"albedo\": {} }}", self.albedo.scene_json()?))
+    }
+
+    fn textures(&self) -> Vec<&dyn Texture> {
+        vec![self.albedo.as_ref()]
+    }
+}
+
+pub enum Background {
+    // The blue-white gradient from the tutorial
+    Sky,
+    Solid(Color),
+    // The same radiance, `color * intensity`, from every direction: an ambient light
+    // for furnace tests and flat-lit lookdev. Hidden from the camera, it still lights
+    // the scene and shows in reflections, with black behind what the camera sees.
+    Constant { color: Color, intensity: f64, visible_to_camera: bool },
+}
+
+impl Background {
+    pub fn color(&self, r: Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction = r.direction.unit_vector();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                Color::one() * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+            }
+            Background::Solid(color) => *color,
+            Background::Constant { color, intensity, .. } => *color * *intensity,
+        }
+    }
+
+    // What a camera ray that escapes sees
+    pub fn camera_color(&self, r: Ray) -> Color {
+        match self {
+            Background::Constant { visible_to_camera: false, .. } => Color::zero(),
+            _ => self.color(r),
+        }
+    }
+}
+
+pub struct Sphere {
+    center: Point3,
+    radius: f64,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Hittable for Sphere {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let oc = r.origin - self.center;
+        let a = r.direction.length_squared();
+        let half_b = oc.dot(r.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            false
+        } else {
+            let sqrtd = discriminant.sqrt();
+            let mut root = (-half_b - sqrtd) / a;
+            if !ray_t.contains(root) {
+                root = (-half_b + sqrtd) / a;
+                if !ray_t.contains(root) {
+                    false
+                } else {
+                    self.record_hit(r, root, rec);
+                    true
+                }
+            } else {
+                self.record_hit(r, root, rec);
+                true
+            }
+        }
+    }
+
+    // The inverse of `get_sphere_uv`
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (theta, phi) = (v * PI, u * 2.0 * PI);
+        let normal = Vec3::new(-phi.cos() * theta.sin(), -theta.cos(), phi.sin() * theta.sin());
+        Some((self.center + normal * self.radius, normal))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::one() * self.radius.abs();
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn kind(&self) -> &'static str {
+        "sphere"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"sphere\", \"center\": {}, \"radius\": {}, \"material\": {} }}",
+            vector(self.center),
+            number(self.radius),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl Sphere {
+    pub fn new(center: Point3, radius: f64, mat_ptr: Arc<dyn Material>) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            mat_ptr
+        }
+    }
+
+    fn record_hit<'a>(&'a self, r: Ray, t: f64, rec: &mut HitRecord<'a>) {
+        let outward_normal = (r.at(t) - self.center) / self.radius;
+        rec.set_surface(r, t, outward_normal, Sphere::get_sphere_uv(outward_normal), self.mat_ptr.as_ref());
+        rec.local_p = rec.p - self.center;
+        (rec.dpdu, rec.dpdv) = Sphere::uv_tangents(outward_normal, self.radius);
+    }
+
+    // Maps a point on the unit sphere to (u, v) in [0, 1], with v running from the -y pole to the +y pole
+    pub fn get_sphere_uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // How far a point at `p` on a sphere of `radius` moves per unit of u and of v
+    // as `get_sphere_uv` lays them out, with `p` on the unit sphere
+    pub fn uv_tangents(p: Point3, radius: f64) -> (Vec3, Vec3) {
+        // Kept off zero at the poles, where u stops moving anything
+        let ring = (1.0 - p.y * p.y).max(0.0).sqrt().max(1e-9);
+        let dpdu = Vec3::new(p.z, 0.0, -p.x) * (2.0 * PI * radius);
+        let dpdv = Vec3::new(-p.x * p.y / ring, ring, -p.y * p.z / ring) * (PI * radius);
+        (dpdu, dpdv)
+    }
+}
+
+// A sphere stretched or squashed along each axis to `radii`, for eggs, pebbles
+// and flattened domes. Rays are traced against the unit sphere in the space
+// where it is one, and normals come back through the inverse transpose of the
+// stretch, so they stay square to the surface.
+pub struct Ellipsoid {
+    center: Point3,
+    radii: Vec3,
+    inv_radii: Vec3,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Ellipsoid {
+    pub fn new(center: Point3, radii: Vec3, mat_ptr: Arc<dyn Material>) -> Self {
+        let inv_radii = Vec3::new(1.0 / radii.x, 1.0 / radii.y, 1.0 / radii.z);
+        Ellipsoid { center, radii, inv_radii, mat_ptr }
+    }
+}
+
+impl Hittable for Ellipsoid {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        // In the unit sphere's space, where t is the same as in the world's
+        let oc = (r.origin - self.center) * self.inv_radii;
+        let direction = r.direction * self.inv_radii;
+        let a = direction.length_squared();
+        let half_b = oc.dot(direction);
+        let c = oc.length_squared() - 1.0;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return false;
+        }
+        let sqrtd = discriminant.sqrt();
+        let Some(root) = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a].iter().copied().find(|&t| ray_t.contains(t)) else {
+            return false;
+        };
+
+        let on_unit = oc + direction * root;
+        let uv = Sphere::get_sphere_uv(on_unit.unit_vector());
+        rec.set_surface(r, root, (on_unit * self.inv_radii).unit_vector(), uv, self.mat_ptr.as_ref());
+        rec.local_p = rec.p - self.center;
+        let (dpdu, dpdv) = Sphere::uv_tangents(on_unit.unit_vector(), 1.0);
+        (rec.dpdu, rec.dpdv) = (dpdu * self.radii, dpdv * self.radii);
+        true
+    }
+
+    // Where a sphere would have it, stretched
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (theta, phi) = (v * PI, u * 2.0 * PI);
+        let on_unit = Vec3::new(-phi.cos() * theta.sin(), -theta.cos(), phi.sin() * theta.sin());
+        Some((self.center + on_unit * self.radii, (on_unit * self.inv_radii).unit_vector()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radii.x.abs(), self.radii.y.abs(), self.radii.z.abs());
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn kind(&self) -> &'static str {
+        "ellipsoid"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"ellipsoid\", \"center\": {}, \"radii\": {}, \"material\": {} }}",
+            vector(self.center),
+            vector(self.radii),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+// A sphere whose center moves in a straight line from `center0` at `time0` to
+// `center1` at `time1`, and on at the same speed outside that interval, for
+// motion blur
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    mat_ptr: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Point3, center1: Point3, time0: f64, time1: f64, radius: f64, mat_ptr: Arc<dyn Material>) -> Self {
+        MovingSphere { center0, center1, time0, time1, radius, mat_ptr }
+    }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        self.center0 + (self.center1 - self.center0) * ((time - self.time0) / (self.time1 - self.time0))
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let center = self.center(r.time);
+        let oc = r.origin - center;
+        let a = r.direction.length_squared();
+        let half_b = oc.dot(r.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return false;
+        }
+        let sqrtd = discriminant.sqrt();
+        let root = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a].iter().copied().find(|&t| ray_t.contains(t));
+        let Some(root) = root else {
+            return false;
+        };
+        let outward_normal = (r.at(root) - center) / self.radius;
+        rec.set_surface(r, root, outward_normal, Sphere::get_sphere_uv(outward_normal), self.mat_ptr.as_ref());
+        // Textures travel with the sphere
+        rec.local_p = rec.p - center;
+        (rec.dpdu, rec.dpdv) = Sphere::uv_tangents(outward_normal, self.radius);
+        true
+    }
+
+    // Round the whole of its path from `time0` to `time1`, which a camera's
+    // shutter is expected to lie within
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::one() * self.radius.abs();
+        let start = Aabb::new(self.center0 - r, self.center0 + r);
+        Some(start.union(&Aabb::new(self.center1 - r, self.center1 + r)))
+    }
+
+    fn kind(&self) -> &'static str {
+        "moving_sphere"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"moving_sphere\", \"center0\": {}, \"center1\": {}, \"time0\": {}, \"time1\": {}, \"radius\": {}, \"material\": {} }}",
+            vector(self.center0),
+            vector(self.center1),
+            number(self.time0),
+            number(self.time1),
+            number(self.radius),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+pub struct Triangle {
+    v0: Point3,
+    v1: Point3,
+    v2: Point3,
+    // Unit normals at the vertices for smooth shading, blended across the face
+    normals: Option<[Vec3; 3]>,
+    mat_ptr: Arc<dyn Material>
+}
+
+// Flat triangles lying in an axis plane get this much thickness in their bounds,
+// as the BVH's slab test misses boxes with none
+const TRIANGLE_BOUNDS_PADDING: f64 = 1e-4;
+
+// Möller-Trumbore: solves for the distance along the ray and the hit's
+// barycentric coordinates at once, without the triangle's plane
+fn intersect_triangle(r: Ray, ray_t: Interval, [v0, v1, v2]: [Point3; 3]) -> Option<(f64, f64, f64)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let pvec = r.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    // Rays in the triangle's plane
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin - v0;
+    let u = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(edge1);
+    let v = r.direction.dot(qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(qvec) * inv_det;
+    ray_t.contains(t).then_some((t, u, v))
+}
+
+// Records the hit `intersect_triangle` found, smooth-shaded when the triangle
+// has normals at its vertices
+fn record_triangle_hit<'a>(
+    rec: &mut HitRecord<'a>,
+    r: Ray,
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    (t, u, v): (f64, f64, f64),
+    mat_ptr: &'a dyn Material,
+) {
+    // Counter-clockwise vertices face the side they're seen from that way
+    let outward_normal = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]).unit_vector();
+    rec.set_surface(r, t, outward_normal, (u, v), mat_ptr);
+    // The UVs are the weights of the second and third vertices
+    (rec.dpdu, rec.dpdv) = (vertices[1] - vertices[0], vertices[2] - vertices[0]);
+    if let Some(normals) = normals {
+        // Which side the ray is on still comes from the face itself
+        let weights = [1.0 - u - v, u, v];
+        let shading = (normals[0] * weights[0] + normals[1] * weights[1] + normals[2] * weights[2]).unit_vector();
+        rec.normal = if rec.front_face { shading } else { -shading };
+        // Lifts the point onto the curved surface the vertex normals describe:
+        // the blend, by the barycentric weights, of where it lands pushed out
+        // onto each vertex's tangent plane when it's below it
+        for i in 0..3 {
+            let below = (rec.p - vertices[i]).dot(normals[i]).min(0.0);
+            rec.terminator_offset = rec.terminator_offset - normals[i] * (below * weights[i]);
+        }
+    }
+}
+
+fn triangle_bounds([v0, v1, v2]: [Point3; 3]) -> Aabb {
+    let pad = Vec3::one() * TRIANGLE_BOUNDS_PADDING;
+    Aabb::new(v0.min(v1).min(v2) - pad, v0.max(v1).max(v2) + pad)
+}
+
+impl Hittable for Triangle {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let vertices = [self.v0, self.v1, self.v2];
+        let Some(hit) = intersect_triangle(r, ray_t, vertices) else {
+            return false;
+        };
+        record_triangle_hit(rec, r, vertices, self.normals, hit, self.mat_ptr.as_ref());
+        true
+    }
+
+    // (u, v) are the barycentric coordinates `hit` gives, so only the half of the
+    // square with u + v up to 1 is on the triangle
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        if u + v > 1.0 {
+            return None;
+        }
+        let (edge1, edge2) = (self.v1 - self.v0, self.v2 - self.v0);
+        Some((self.v0 + edge1 * u + edge2 * v, edge1.cross(edge2).unit_vector()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_bounds([self.v0, self.v1, self.v2]))
+    }
+
+    fn kind(&self) -> &'static str {
+        "triangle"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let normals = match self.normals {
+            Some([n0, n1, n2]) => format!(", \"normals\": [{}, {}, {}]", vector(n0), vector(n1), vector(n2)),
+            None => String::new(),
+        };
+        Some(format!(
+            "{{ \"type\": \"triangle\", \"vertices\": [{}, {}, {}]{}, \"material\": {} }}",
+            vector(self.v0),
+            vector(self.v1),
+            vector(self.v2),
+            normals,
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat_ptr: Arc<dyn Material>) -> Triangle {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            normals: None,
+            mat_ptr
+        }
+    }
+
+    // Smooth-shaded, with these normals at v0, v1 and v2 facing the same side as
+    // the face
+    pub fn with_normals(mut self, n0: Vec3, n1: Vec3, n2: Vec3) -> Self {
+        self.normals = Some([n0.unit_vector(), n1.unit_vector(), n2.unit_vector()]);
+        self
+    }
+}
+
+// Triangles sharing flat buffers, as models come: each is three indices into the
+// vertex positions (and normals, when the mesh is smooth-shaded), so a vertex
+// shared by several triangles is stored once. A BVH over the triangles by index
+// finds the ones a ray might hit, and only the nearest is shaded.
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    colors: Option<Vec<Color>>,
+    // How fast each vertex moves, and the times the BVH's bounds cover its motion over
+    velocities: Option<Vec<Vec3>>,
+    motion_times: (f64, f64),
+    indices: Vec<[u32; 3]>,
+    bvh: Bvh<u32>,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Hittable for TriangleMesh {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let mut nearest = None;
+        self.bvh.traverse(r, ray_t, |&triangle, range| {
+            let vertices = self.vertices(triangle, r.time);
+            let (t, u, v) = intersect_triangle(r, range, vertices)?;
+            nearest = Some((triangle, vertices, (t, u, v)));
+            Some(t)
+        });
+        let Some((triangle, vertices, hit)) = nearest else {
+            return false;
+        };
+        let corners = self.indices[triangle as usize];
+        let normals = self.normals.as_ref().map(|normals| corners.map(|i| normals[i as usize]));
+        record_triangle_hit(rec, r, vertices, normals, hit, self.mat_ptr.as_ref());
+        let (_, u, v) = hit;
+        let blend = |[a, b, c]: [Vec3; 3]| a * (1.0 - u - v) + b * u + c * v;
+        if self.velocities.is_some() {
+            // Textures stick to the surface as it deforms
+            rec.local_p = blend(corners.map(|i| self.positions[i as usize]));
+        }
+        if let Some(colors) = &self.colors {
+            rec.vertex_color = Some(blend(corners.map(|i| colors[i as usize])));
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+
+    fn kind(&self) -> &'static str {
+        "mesh"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let list = |items: Vec<String>| items.join(", ");
+        let normals = match &self.normals {
+            Some(normals) => format!(", \"normals\": [{}]", list(normals.iter().map(|&n| vector(n)).collect())),
+            None => String::new(),
+        };
+        let colors = match &self.colors {
+            Some(colors) => format!(", \"colors\": [{}]", list(colors.iter().map(|&c| vector(c)).collect())),
+            None => String::new(),
+        };
+        let velocities = match &self.velocities {
+            Some(velocities) => format!(
+                ", \"velocities\": [{}], \"time0\": {}, \"time1\": {}",
+                list(velocities.iter().map(|&v| vector(v)).collect()),
+                number(self.motion_times.0),
+                number(self.motion_times.1)
+            ),
+            None => String::new(),
+        };
+        Some(format!(
+            "{{ \"type\": \"mesh\", \"positions\": [{}], \"indices\": [{}]{}{}{}, \"material\": {} }}",
+            list(self.positions.iter().map(|&p| vector(p)).collect()),
+            list(self.indices.iter().map(|[a, b, c]| format!("[{a}, {b}, {c}]")).collect()),
+            normals,
+            colors,
+            velocities,
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl TriangleMesh {
+    // Every index must be below the number of positions
+    pub fn new(positions: Vec<Point3>, indices: Vec<[u32; 3]>, mat_ptr: Arc<dyn Material>) -> TriangleMesh {
+        let bvh = Bvh::with_bounds((0..indices.len() as u32).map(|triangle| {
+            let [a, b, c] = indices[triangle as usize];
+            (triangle, Some(triangle_bounds([a, b, c].map(|i| positions[i as usize]))))
+        }));
+        TriangleMesh { positions, normals: None, colors: None, velocities: None, motion_times: (0.0, 0.0), indices, bvh, mat_ptr }
+    }
+
+    // Smooth-shaded, with a normal for every position facing the same side as the
+    // faces round it
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        self.normals = Some(normals.into_iter().map(|n| n.unit_vector()).collect());
+        self
+    }
+
+    // A color for every position, blended across the faces into the hit record's
+    // vertex_color for a VertexColorTexture to show
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    // Deforming, each position moving at its velocity from where it is at time 0,
+    // for motion blur. The BVH is rebuilt round every triangle's motion from
+    // `time0` to `time1`, which a camera's shutter is expected to lie within.
+    // Normals stay as they are at time 0.
+    pub fn with_velocities(mut self, velocities: Vec<Vec3>, time0: f64, time1: f64) -> Self {
+        self.velocities = Some(velocities);
+        self.motion_times = (time0, time1);
+        self.bvh = Bvh::with_bounds((0..self.indices.len() as u32).map(|triangle| {
+            let bounds = triangle_bounds(self.vertices(triangle, time0)).union(&triangle_bounds(self.vertices(triangle, time1)));
+            (triangle, Some(bounds))
+        }));
+        self
+    }
+
+    // Where the triangle's corners are at `time`
+    fn vertices(&self, triangle: u32, time: f64) -> [Point3; 3] {
+        let corners = self.indices[triangle as usize];
+        match &self.velocities {
+            Some(velocities) => corners.map(|i| self.positions[i as usize] + velocities[i as usize] * time),
+            None => corners.map(|i| self.positions[i as usize]),
+        }
+    }
+}
+
+// A mesh's lists as a file gives them, before they're built into a TriangleMesh:
+// the positions and triangles, and whichever of the normals, colors and
+// velocities it has, one for each position
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub colors: Option<Vec<Color>>,
+    pub velocities: Option<Vec<Vec3>>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl MeshData {
+    // Velocities move it from `motion_times.0` to `motion_times.1`, as in
+    // TriangleMesh::with_velocities
+    pub fn into_mesh(self, material: Arc<dyn Material>, motion_times: (f64, f64)) -> TriangleMesh {
+        let mut mesh = TriangleMesh::new(self.positions, self.indices, material);
+        if let Some(normals) = self.normals {
+            mesh = mesh.with_normals(normals);
+        }
+        if let Some(colors) = self.colors {
+            mesh = mesh.with_colors(colors);
+        }
+        if let Some(velocities) = self.velocities {
+            mesh = mesh.with_velocities(velocities, motion_times.0, motion_times.1);
+        }
+        mesh
+    }
+}
+
+// A rectangle lying across the axis `axis` (0 for x, 1 for y, 2 for z) at
+// `k` along it, spanning `a` and `b` along the other two axes in order, so an
+// XZ rectangle spans x then z. It faces the positive end of its axis.
+pub struct AxisRect {
+    axis: usize,
+    a: (f64, f64),
+    b: (f64, f64),
+    k: f64,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Hittable for AxisRect {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (axis_a, axis_b) = self.span_axes();
+        let t = (self.k - r.origin[self.axis]) / r.direction[self.axis];
+        // Also catches rays parallel to the rectangle, which divide by zero
+        if !ray_t.contains(t) {
+            return false;
+        }
+        let a = r.origin[axis_a] + t * r.direction[axis_a];
+        let b = r.origin[axis_b] + t * r.direction[axis_b];
+        if a < self.a.0 || a > self.a.1 || b < self.b.0 || b > self.b.1 {
+            return false;
+        }
+
+        let uv = ((a - self.a.0) / (self.a.1 - self.a.0), (b - self.b.0) / (self.b.1 - self.b.0));
+        rec.set_surface(r, t, self.normal(), uv, self.mat_ptr.as_ref());
+        rec.dpdu[axis_a] = self.a.1 - self.a.0;
+        rec.dpdv[axis_b] = self.b.1 - self.b.0;
+        true
+    }
+
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (axis_a, axis_b) = self.span_axes();
+        let mut p = Point3::zero();
+        p[self.axis] = self.k;
+        p[axis_a] = self.a.0 + u * (self.a.1 - self.a.0);
+        p[axis_b] = self.b.0 + v * (self.b.1 - self.b.0);
+        Some((p, self.normal()))
+    }
+
+    // Padded across its axis, as the BVH's slab test misses boxes with no thickness
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (axis_a, axis_b) = self.span_axes();
+        let (mut min, mut max) = (Point3::zero(), Point3::zero());
+        (min[self.axis], max[self.axis]) = (self.k - 1e-4, self.k + 1e-4);
+        (min[axis_a], max[axis_a]) = self.a;
+        (min[axis_b], max[axis_b]) = self.b;
+        Some(Aabb::new(min, max))
+    }
+
+    fn kind(&self) -> &'static str {
+        "rect"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let plane = ["yz", "xz", "xy"][self.axis];
+        Some(format!(
+            "{{ \"type\": \"rect\", \"plane\": \"{}\", \"min\": [{}, {}], \"max\": [{}, {}], \"k\": {}, \"material\": {} }}",
+            plane,
+            number(self.a.0),
+            number(self.b.0),
+            number(self.a.1),
+            number(self.b.1),
+            number(self.k),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl AxisRect {
+    // Spanning x0..x1 and y0..y1 at z = k, facing +z
+    pub fn xy(x: (f64, f64), y: (f64, f64), k: f64, mat_ptr: Arc<dyn Material>) -> AxisRect {
+        AxisRect::new(2, x, y, k, mat_ptr)
+    }
+
+    // Spanning x0..x1 and z0..z1 at y = k, facing +y
+    pub fn xz(x: (f64, f64), z: (f64, f64), k: f64, mat_ptr: Arc<dyn Material>) -> AxisRect {
+        AxisRect::new(1, x, z, k, mat_ptr)
+    }
+
+    // Spanning y0..y1 and z0..z1 at x = k, facing +x
+    pub fn yz(y: (f64, f64), z: (f64, f64), k: f64, mat_ptr: Arc<dyn Material>) -> AxisRect {
+        AxisRect::new(0, y, z, k, mat_ptr)
+    }
+
+    // Extents given the wrong way round are swapped
+    fn new(axis: usize, a: (f64, f64), b: (f64, f64), k: f64, mat_ptr: Arc<dyn Material>) -> AxisRect {
+        AxisRect {
+            axis,
+            a: (a.0.min(a.1), a.0.max(a.1)),
+            b: (b.0.min(b.1), b.0.max(b.1)),
+            k,
+            mat_ptr
+        }
+    }
+
+    fn span_axes(&self) -> (usize, usize) {
+        match self.axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        }
+    }
+
+    fn normal(&self) -> Vec3 {
+        let mut normal = Vec3::zero();
+        normal[self.axis] = 1.0;
+        normal
+    }
+}
+
+// Terrain from a grid of heights, `columns` across x by however many rows of them
+// there are down z, each cell split into two triangles. Heights are scaled by
+// `size.y` above `min`, and the grid is stretched over `size.x` by `size.z`. Rays
+// step from cell to cell under their path with a 2D DDA, testing only the cells
+// they cross and stopping at the first they hit, so a large terrain needs neither
+// a triangle per cell kept around nor a BVH over them. It's smooth-shaded with
+// normals from the slope of the grid; (u, v) run across the whole of it.
+pub struct Heightfield {
+    heights: Vec<f64>,
+    columns: usize,
+    rows: usize,
+    min: Point3,
+    size: Vec3,
+    normals: Vec<Vec3>,
+    bbox: Aabb,
+    mat_ptr: Arc<dyn Material>,
+}
+
+impl Heightfield {
+    // Needs at least two rows of at least two heights
+    pub fn new(heights: Vec<f64>, columns: usize, min: Point3, size: Vec3, mat_ptr: Arc<dyn Material>) -> Self {
+        assert!(columns >= 2 && heights.len().is_multiple_of(columns) && heights.len() / columns >= 2, "a heightfield needs a grid of at least 2 by 2");
+        let rows = heights.len() / columns;
+        let (low, high) = heights.iter().fold((f64::INFINITY, -f64::INFINITY), |(low, high), &h| (low.min(h), high.max(h)));
+        let pad = Vec3::one() * TRIANGLE_BOUNDS_PADDING;
+        let bbox = Aabb::new(
+            Point3::new(min.x, min.y + size.y * low, min.z) - pad,
+            Point3::new(min.x + size.x, min.y + size.y * high, min.z + size.z) + pad,
+        );
+        let mut field = Heightfield { heights, columns, rows, min, size, normals: Vec::new(), bbox, mat_ptr };
+        field.normals = (0..rows).flat_map(|j| (0..columns).map(move |i| (i, j))).map(|(i, j)| field.grid_normal(i, j)).collect();
+        field
+    }
+
+    // A grayscale PPM's values, 0 for black and 1 for white, as the heights
+    pub fn from_image<P: AsRef<Path>>(path: P, min: Point3, size: Vec3, mat_ptr: Arc<dyn Material>) -> io::Result<Self> {
+        let image = Image::read_ppm(path)?;
+        if image.width < 2 || image.height < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "a heightfield image needs at least 2 by 2 pixels"));
+        }
+        // Reading the image undoes its gamma, which the heights shouldn't have
+        let heights = image.pixels.iter().map(|c| ((c.x + c.y + c.z) / 3.0).sqrt()).collect();
+        Ok(Heightfield::new(heights, image.width, min, size, mat_ptr))
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (self.size.x / (self.columns - 1) as f64, self.size.z / (self.rows - 1) as f64)
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        let (dx, dz) = self.cell_size();
+        Point3::new(
+            self.min.x + dx * i as f64,
+            self.min.y + self.size.y * self.heights[j * self.columns + i],
+            self.min.z + dz * j as f64,
+        )
+    }
+
+    // Central differences of the neighbouring heights, one-sided at the edges
+    fn grid_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (left, right) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (back, front) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let along_x = self.vertex(right, j) - self.vertex(left, j);
+        let along_z = self.vertex(i, front) - self.vertex(i, back);
+        along_z.cross(along_x).unit_vector()
+    }
+
+    // Tests the two triangles of the cell with (i, j) at its corner nearest `min`
+    fn hit_cell<'a>(&'a self, r: Ray, mut ray_t: Interval, (i, j): (usize, usize), rec: &mut HitRecord<'a>) -> bool {
+        let corners = [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)];
+        let vertex = |k: usize| self.vertex(corners[k].0, corners[k].1);
+        let normal = |k: usize| self.normals[corners[k].1 * self.columns + corners[k].0];
+        let mut hit_anything = false;
+        // Both wound counter-clockwise seen from above
+        for triangle in [[0, 1, 2], [0, 2, 3]].iter() {
+            let vertices = triangle.map(vertex);
+            if let Some(hit) = intersect_triangle(r, ray_t, vertices) {
+                ray_t = ray_t.up_to(hit.0);
+                record_triangle_hit(rec, r, vertices, Some(triangle.map(normal)), hit, self.mat_ptr.as_ref());
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let inv_direction = Vec3::new(1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z);
+        let Some(span) = self.bbox.clip(r.origin, inv_direction, ray_t) else {
+            return false;
+        };
+        let (dx, dz) = self.cell_size();
+        let start = r.at(span.min);
+        let cell = |x: f64, min: f64, size: f64, cells: usize| (((x - min) / size).floor().max(0.0) as usize).min(cells - 2);
+        let (mut i, mut j) = (cell(start.x, self.min.x, dx, self.columns), cell(start.z, self.min.z, dz, self.rows));
+
+        // Where the ray next crosses a grid line along each axis, and how far apart
+        // the crossings are
+        let next_crossing = |index: usize, origin: f64, direction: f64, min: f64, size: f64| {
+            if direction > 0.0 {
+                (min + size * (index + 1) as f64 - origin) / direction
+            } else if direction < 0.0 {
+                (min + size * index as f64 - origin) / direction
+            } else {
+                f64::INFINITY
+            }
+        };
+        let mut next_x = next_crossing(i, r.origin.x, r.direction.x, self.min.x, dx);
+        let mut next_z = next_crossing(j, r.origin.z, r.direction.z, self.min.z, dz);
+        let (step_x, step_z) = ((dx * inv_direction.x).abs(), (dz * inv_direction.z).abs());
+
+        let mut enter = span.min;
+        loop {
+            let exit = next_x.min(next_z).min(span.max);
+            // Skips cells the ray passes wholly above or below
+            let corners = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)].map(|(i, j)| self.heights[j * self.columns + i]);
+            let low = self.min.y + self.size.y * corners.iter().copied().fold(f64::INFINITY, f64::min);
+            let high = self.min.y + self.size.y * corners.iter().copied().fold(-f64::INFINITY, f64::max);
+            let (y0, y1) = (r.at(enter).y, r.at(exit).y);
+            let pad = TRIANGLE_BOUNDS_PADDING;
+            if y0.min(y1) <= high + pad && y0.max(y1) >= low - pad && self.hit_cell(r, ray_t, (i, j), rec) {
+                rec.local_p = rec.p - self.min;
+                (rec.u, rec.v) = ((rec.p.x - self.min.x) / self.size.x, (rec.p.z - self.min.z) / self.size.z);
+                // Across the ground, leaving out the slope
+                (rec.dpdu, rec.dpdv) = (Vec3::new(self.size.x, 0.0, 0.0), Vec3::new(0.0, 0.0, self.size.z));
+                return true;
+            }
+            if exit >= span.max {
+                return false;
+            }
+            enter = exit;
+            if next_x < next_z {
+                if r.direction.x > 0.0 {
+                    i += 1;
+                } else if i > 0 {
+                    i -= 1;
+                } else {
+                    return false;
+                }
+                if i > self.columns - 2 {
+                    return false;
+                }
+                next_x += step_x;
+            } else {
+                if r.direction.z > 0.0 {
+                    j += 1;
+                } else if j > 0 {
+                    j -= 1;
+                } else {
+                    return false;
+                }
+                if j > self.rows - 2 {
+                    return false;
+                }
+                next_z += step_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn kind(&self) -> &'static str {
+        "heightfield"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let rows: Vec<String> = self
+            .heights
+            .chunks(self.columns)
+            .map(|row| format!("[{}]", row.iter().map(|&h| number(h)).collect::<Vec<_>>().join(", ")))
+            .collect();
+        Some(format!(
+            "{{ \"type\": \"heightfield\", \"heights\": [{}], \"min\": {}, \"size\": {}, \"material\": {} }}",
+            rows.join(", "),
+            vector(self.min),
+            vector(self.size),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+// An axis-aligned box between two corners, intersected directly with the slab
+// test rather than as six rectangles
+pub struct BoxObj {
+    min: Point3,
+    max: Point3,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Hittable for BoxObj {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        // Where the ray enters and leaves the box, and across which axis
+        let (mut t_in, mut t_out) = (f64::NEG_INFINITY, f64::INFINITY);
+        let (mut axis_in, mut axis_out) = (0, 0);
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction[axis];
+            let mut t0 = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN comes from rays in the plane of a face, which can't enter across it
+            if t0 > t_in {
+                (t_in, axis_in) = (t0, axis);
+            }
+            if t1 < t_out {
+                (t_out, axis_out) = (t1, axis);
+            }
+            if t0.is_nan() || t1.is_nan() || t_out < t_in {
+                return false;
+            }
+        }
+        // From inside, the ray leaves through the far side
+        let (t, axis, sign) = if ray_t.contains(t_in) {
+            (t_in, axis_in, -r.direction[axis_in].signum())
+        } else if ray_t.contains(t_out) {
+            (t_out, axis_out, r.direction[axis_out].signum())
+        } else {
+            return false;
+        };
+
+        let mut outward_normal = Vec3::zero();
+        outward_normal[axis] = sign;
+        // Each face is mapped over the whole texture, along the other two axes in order
+        let (axis_u, axis_v) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        let p = r.at(t);
+        let along = |axis: usize| (p[axis] - self.min[axis]) / (self.max[axis] - self.min[axis]);
+        rec.set_surface(r, t, outward_normal, (along(axis_u), along(axis_v)), self.mat_ptr.as_ref());
+        rec.dpdu[axis_u] = self.max[axis_u] - self.min[axis_u];
+        rec.dpdv[axis_v] = self.max[axis_v] - self.min[axis_v];
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    fn kind(&self) -> &'static str {
+        "box"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"box\", \"min\": {}, \"max\": {}, \"material\": {} }}",
+            vector(self.min),
+            vector(self.max),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl BoxObj {
+    // Any two opposite corners will do
+    pub fn new(a: Point3, b: Point3, mat_ptr: Arc<dyn Material>) -> BoxObj {
+        BoxObj {
+            min: a.min(b),
+            max: a.max(b),
+            mat_ptr
+        }
+    }
+}
+
+// A cylinder standing on the disk at `base` and running `height` along `axis`,
+// closed off by flat caps unless it's an open tube
+pub struct Cylinder {
+    base: Point3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    // `w` is the axis, so the cylinder is upright in the frame's own coordinates
+    frame: Onb,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Hittable for Cylinder {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let o = self.frame.world_to_local(r.origin - self.base);
+        let d = self.frame.world_to_local(r.direction);
+
+        // The nearest of the side and the caps within range, with its normal in
+        // local coordinates
+        let mut closest: Option<(f64, Vec3)> = None;
+        let mut consider = |t: f64, normal: Vec3| {
+            if ray_t.contains(t) && closest.is_none_or(|(best, _)| t < best) {
+                closest = Some((t, normal));
+            }
+        };
+
+        let a = d.x * d.x + d.y * d.y;
+        let half_b = o.x * d.x + o.y * d.y;
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        // Rays along the axis never cross the side
+        if a > 0.0 && discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                let z = o.z + t * d.z;
+                if (0.0..=self.height).contains(&z) {
+                    consider(t, Vec3::new(o.x + t * d.x, o.y + t * d.y, 0.0) / self.radius);
+                }
+            }
+        }
+        if self.capped && d.z != 0.0 {
+            for (z, normal) in [(0.0, Vec3::new(0.0, 0.0, -1.0)), (self.height, Vec3::new(0.0, 0.0, 1.0))] {
+                let t = (z - o.z) / d.z;
+                let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                if x * x + y * y <= self.radius * self.radius {
+                    consider(t, normal);
+                }
+            }
+        }
+        let Some((t, local_normal)) = closest else {
+            return false;
+        };
+
+        let local = o + d * t;
+        // The side wraps once round the texture with v up the axis; each cap is
+        // mapped across it
+        let uv = if local_normal.z == 0.0 {
+            ((local.y.atan2(local.x) + PI) / (2.0 * PI), local.z / self.height)
+        } else {
+            (0.5 + local.x / (2.0 * self.radius), 0.5 + local.y / (2.0 * self.radius))
+        };
+        rec.set_surface(r, t, self.frame.local_to_world(local_normal), uv, self.mat_ptr.as_ref());
+        rec.local_p = local;
+        let (dpdu, dpdv) = if local_normal.z == 0.0 {
+            (Vec3::new(-local.y, local.x, 0.0) * (2.0 * PI), Vec3::new(0.0, 0.0, self.height))
+        } else {
+            (Vec3::new(2.0 * self.radius, 0.0, 0.0), Vec3::new(0.0, 2.0 * self.radius, 0.0))
+        };
+        (rec.dpdu, rec.dpdv) = (self.frame.local_to_world(dpdu), self.frame.local_to_world(dpdv));
+        true
+    }
+
+    // The bounds of the two end disks, each reaching the radius across the axis
+    fn bounding_box(&self) -> Option<Aabb> {
+        let axis = self.frame.w;
+        let reach = Vec3::new(
+            (1.0 - axis.x * axis.x).max(0.0).sqrt(),
+            (1.0 - axis.y * axis.y).max(0.0).sqrt(),
+            (1.0 - axis.z * axis.z).max(0.0).sqrt(),
+        ) * self.radius;
+        let top = self.base + axis * self.height;
+        Some(Aabb::new(self.base - reach, self.base + reach).union(&Aabb::new(top - reach, top + reach)))
+    }
+
+    fn kind(&self) -> &'static str {
+        "cylinder"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"cylinder\", \"base\": {}, \"axis\": {}, \"radius\": {}, \"height\": {}, \"caps\": {}, \"material\": {} }}",
+            vector(self.base),
+            vector(self.frame.w),
+            number(self.radius),
+            number(self.height),
+            self.capped,
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl Cylinder {
+    // Capped at both ends; `axis` needn't be a unit vector
+    pub fn new(base: Point3, axis: Vec3, radius: f64, height: f64, mat_ptr: Arc<dyn Material>) -> Cylinder {
+        Cylinder {
+            base,
+            radius,
+            height,
+            capped: true,
+            frame: Onb::from_normal(axis.unit_vector()),
+            mat_ptr
+        }
+    }
+
+    // A tube open at both ends, seen into from either
+    pub fn open(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+// A flat disk at `center` facing along `normal`, or a ring when it has a hole of
+// `inner_radius` in the middle
+pub struct Disk {
+    center: Point3,
+    radius: f64,
+    inner_radius: f64,
+    // `w` is the normal, so the disk lies flat in the frame's own coordinates
+    frame: Onb,
+    mat_ptr: Arc<dyn Material>
+}
+
+impl Hittable for Disk {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let o = self.frame.world_to_local(r.origin - self.center);
+        let d = self.frame.world_to_local(r.direction);
+        let t = -o.z / d.z;
+        // Also catches rays parallel to the disk, which divide by zero
+        if !ray_t.contains(t) {
+            return false;
+        }
+        let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+        let distance_squared = x * x + y * y;
+        if distance_squared > self.radius * self.radius || distance_squared < self.inner_radius * self.inner_radius {
+            return false;
+        }
+
+        // u goes once round, v out from the inner edge to the rim
+        let uv = (
+            (y.atan2(x) + PI) / (2.0 * PI),
+            (distance_squared.sqrt() - self.inner_radius) / (self.radius - self.inner_radius),
+        );
+        rec.set_surface(r, t, self.frame.w, uv, self.mat_ptr.as_ref());
+        rec.local_p = Vec3::new(x, y, 0.0);
+        let outward = Vec3::new(x, y, 0.0) / distance_squared.sqrt().max(1e-9);
+        rec.dpdu = self.frame.local_to_world(Vec3::new(-y, x, 0.0) * (2.0 * PI));
+        rec.dpdv = self.frame.local_to_world(outward * (self.radius - self.inner_radius));
+        true
+    }
+
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let angle = 2.0 * PI * u - PI;
+        let distance = self.inner_radius + v * (self.radius - self.inner_radius);
+        let local = Vec3::new(angle.cos(), angle.sin(), 0.0) * distance;
+        Some((self.center + self.frame.local_to_world(local), self.frame.w))
+    }
+
+    // Reaching the radius across the normal, and padded along it, as the BVH's
+    // slab test misses boxes with no thickness
+    fn bounding_box(&self) -> Option<Aabb> {
+        let normal = self.frame.w;
+        let reach = Vec3::new(
+            (1.0 - normal.x * normal.x).max(0.0).sqrt(),
+            (1.0 - normal.y * normal.y).max(0.0).sqrt(),
+            (1.0 - normal.z * normal.z).max(0.0).sqrt(),
+        ) * self.radius
+            + Vec3::one() * 1e-4;
+        Some(Aabb::new(self.center - reach, self.center + reach))
+    }
+
+    fn kind(&self) -> &'static str {
+        "disk"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"disk\", \"center\": {}, \"normal\": {}, \"radius\": {}, \"inner_radius\": {}, \"material\": {} }}",
+            vector(self.center),
+            vector(self.frame.w),
+            number(self.radius),
+            number(self.inner_radius),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+impl Disk {
+    // Solid, with no hole; `normal` needn't be a unit vector
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mat_ptr: Arc<dyn Material>) -> Disk {
+        Disk {
+            center,
+            radius,
+            inner_radius: 0.0,
+            frame: Onb::from_normal(normal.unit_vector()),
+            mat_ptr
+        }
+    }
+
+    // Cuts a hole of `inner_radius` out of the middle, leaving a ring
+    pub fn with_inner_radius(mut self, inner_radius: f64) -> Self {
+        self.inner_radius = inner_radius.clamp(0.0, self.radius);
+        self
+    }
+}
+
+// A thin strand following cubic Bezier segments, for hair, fur, grass and wires.
+// `points` are the segments' control points end to end, each segment starting at
+// the last one's end: 4, 7, 10 and so on. The strand is as wide as `width` at its
+// root, narrowing (or widening) evenly to `tip_width` at its end. It's traced as
+// a flat ribbon turned to face the ray, shaded as if it were round; u runs along
+// it from root to tip and v across it.
+pub struct Curve {
+    points: Vec<Point3>,
+    width: f64,
+    tip_width: f64,
+    mat_ptr: Arc<dyn Material>,
+}
+
+// Where a ray crossed a curve segment, in the segment's own parameter `s` and
+// the ray's distance `z` along its unit direction
+struct CurveHit {
+    s: f64,
+    z: f64,
+    // The hit point's offset from the middle of the strand, in the ray's frame,
+    // as a fraction of the half width there
+    offset: (f64, f64),
+    v: f64,
+}
+
+impl Curve {
+    pub fn new(points: Vec<Point3>, width: f64, mat_ptr: Arc<dyn Material>) -> Self {
+        assert!(points.len() >= 4 && (points.len() - 1).is_multiple_of(3), "expected 4, 7, 10... control points");
+        Curve { points, width, tip_width: width, mat_ptr }
+    }
+
+    pub fn with_tip_width(mut self, tip_width: f64) -> Self {
+        self.tip_width = tip_width;
+        self
+    }
+
+    fn segments(&self) -> usize {
+        (self.points.len() - 1) / 3
+    }
+
+    fn segment(&self, i: usize) -> [Point3; 4] {
+        [self.points[3 * i], self.points[3 * i + 1], self.points[3 * i + 2], self.points[3 * i + 3]]
+    }
+
+    fn width_at(&self, u: f64) -> f64 {
+        self.width + (self.tip_width - self.width) * u
+    }
+
+    // Subdivides the segment `cp` (in the ray's frame, looking down +z) spanning
+    // `u` along the whole strand, keeping the nearest hit closer than `z.max`
+    fn intersect_segment(&self, cp: [Vec3; 4], u: Interval, s: Interval, depth: u32, z: Interval, best: &mut Option<CurveHit>) {
+        let z = match best {
+            Some(hit) => z.up_to(hit.z),
+            None => z,
+        };
+        let half_width = self.width_at(u.min).max(self.width_at(u.max)) / 2.0;
+        let lower = cp[0].min(cp[1]).min(cp[2]).min(cp[3]) - Vec3::one() * half_width;
+        let upper = cp[0].max(cp[1]).max(cp[2]).max(cp[3]) + Vec3::one() * half_width;
+        if lower.x > 0.0 || upper.x < 0.0 || lower.y > 0.0 || upper.y < 0.0 || lower.z > z.max || upper.z < z.min {
+            return;
+        }
+
+        if depth > 0 {
+            let (a, b) = split_bezier(cp);
+            let (u_mid, s_mid) = ((u.min + u.max) / 2.0, (s.min + s.max) / 2.0);
+            self.intersect_segment(a, Interval::new(u.min, u_mid), Interval::new(s.min, s_mid), depth - 1, z, best);
+            self.intersect_segment(b, Interval::new(u_mid, u.max), Interval::new(s_mid, s.max), depth - 1, z, best);
+            return;
+        }
+
+        // Small enough to be straight: the ray has to pass between the lines
+        // square to the strand at either end of it
+        let edge_start = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        let edge_end = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if edge_start < 0.0 || edge_end < 0.0 {
+            return;
+        }
+        let (dx, dy) = (cp[3].x - cp[0].x, cp[3].y - cp[0].y);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0.0 {
+            return;
+        }
+        let w = ((-cp[0].x * dx - cp[0].y * dy) / length_squared).clamp(0.0, 1.0);
+        let hit_width = self.width_at(u.min + (u.max - u.min) * w);
+        let (pc, tangent) = eval_bezier(cp, w);
+        let distance_squared = pc.x * pc.x + pc.y * pc.y;
+        if distance_squared > hit_width * hit_width / 4.0 || !z.surrounds(pc.z) {
+            return;
+        }
+        let distance = distance_squared.sqrt();
+        let side = tangent.x * -pc.y + pc.x * tangent.y;
+        let v = if side > 0.0 { 0.5 + distance / hit_width } else { 0.5 - distance / hit_width };
+        let half = hit_width / 2.0;
+        *best = Some(CurveHit { s: s.min + (s.max - s.min) * w, z: pc.z, offset: (-pc.x / half, -pc.y / half), v });
+    }
+}
+
+// The point and the derivative of a cubic Bezier at `t`
+fn eval_bezier(cp: [Vec3; 4], t: f64) -> (Vec3, Vec3) {
+    let lerp = |a: Vec3, b: Vec3| a * (1.0 - t) + b * t;
+    let (a, b, c) = (lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3]));
+    let (d, e) = (lerp(a, b), lerp(b, c));
+    let derivative = if (e - d).length_squared() > 0.0 { (e - d) * 3.0 } else { cp[3] - cp[0] };
+    (lerp(d, e), derivative)
+}
+
+// The two halves of a cubic Bezier, by de Casteljau's construction
+fn split_bezier(cp: [Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| (a + b) * 0.5;
+    let (a, b, c) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let m = mid(d, e);
+    ([cp[0], a, d, m], [m, e, c, cp[3]])
+}
+
+impl Hittable for Curve {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let length = r.direction.length();
+        let direction = r.direction / length;
+        let frame = Onb::from_normal(direction);
+        let z = Interval::new(ray_t.min * length, ray_t.max * length);
+        let segments = self.segments();
+        let mut nearest: Option<(usize, CurveHit)> = None;
+        for i in 0..segments {
+            let world = self.segment(i);
+            let cp = world.map(|p| frame.world_to_local(p - r.origin));
+            // Enough halvings that the pieces are straight to within a twentieth
+            // of the strand's width (as pbrt reckons it)
+            let bend = (0..2)
+                .map(|j| cp[j] - cp[j + 1] * 2.0 + cp[j + 2])
+                .fold(0.0f64, |m, d| m.max(d.x.abs()).max(d.y.abs()).max(d.z.abs()));
+            let epsilon = self.width.max(self.tip_width) * 0.05;
+            let depth = if bend > 0.0 && epsilon > 0.0 {
+                ((std::f64::consts::SQRT_2 * 6.0 * bend / (8.0 * epsilon)).log2() / 2.0).round().clamp(0.0, 10.0) as u32
+            } else {
+                0
+            };
+            let u = Interval::new(i as f64 / segments as f64, (i + 1) as f64 / segments as f64);
+            let z = nearest.as_ref().map_or(z, |(_, hit)| z.up_to(hit.z));
+            let mut best = None;
+            self.intersect_segment(cp, u, Interval::new(0.0, 1.0), depth, z, &mut best);
+            if let Some(hit) = best {
+                nearest = Some((i, hit));
+            }
+        }
+        let Some((i, hit)) = nearest else {
+            return false;
+        };
+
+        // Round, as a tube seen from the side: facing the ray across the middle
+        // and turning away towards the edges
+        let (_, tangent) = eval_bezier(self.segment(i), hit.s);
+        let tangent = tangent.unit_vector();
+        let facing = -direction + tangent * direction.dot(tangent);
+        let across = frame.local_to_world(Vec3::new(hit.offset.0, hit.offset.1, 0.0));
+        let across = across - tangent * across.dot(tangent);
+        let sideways = across.length().min(1.0);
+        let outward_normal = if facing.near_zero() {
+            -direction
+        } else if sideways > 0.0 {
+            facing.unit_vector() * (1.0 - sideways * sideways).sqrt() + across.unit_vector() * sideways
+        } else {
+            facing.unit_vector()
+        };
+        let uv = ((i as f64 + hit.s) / segments as f64, hit.v);
+        rec.set_surface(r, hit.z / length, outward_normal, uv, self.mat_ptr.as_ref());
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let half_width = Vec3::one() * (self.width.max(self.tip_width) / 2.0);
+        // Each segment lies within the hull of its control points
+        let bbox = self.points.iter().fold(Aabb::empty(), |b, &p| b.union(&Aabb::new(p, p)));
+        Some(Aabb::new(bbox.min - half_width, bbox.max + half_width))
+    }
+
+    fn kind(&self) -> &'static str {
+        "curve"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.mat_ptr.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let points: Vec<String> = self.points.iter().map(|&p| vector(p)).collect();
+        Some(format!(
+            "{{ \"type\": \"curve\", \"points\": [{}], \"width\": {}, \"tip_width\": {}, \"material\": {} }}",
+            points.join(", "),
+            number(self.width),
+            number(self.tip_width),
+            self.mat_ptr.scene_json()?
+        ))
+    }
+}
+
+// Turns an object inside out: what was its front face is now its back, so a light
+// panel shines the other way and a mesh wound the wrong way round faces out again.
+// Only which side counts as the front changes; the surface itself stays put.
+pub struct FlipFace {
+    object: Arc<dyn Hittable>,
+}
+
+impl FlipFace {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        FlipFace { object }
+    }
+
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        if !hit_object(self.object.as_ref(), r, ray_t, rec, sampler) {
+            return false;
         }
+        // The normal already faces the ray, whichever way the outside is
+        rec.front_face = !rec.front_face;
+        true
     }
 }
 
-pub trait Hittable {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        false
+impl Hittable for FlipFace {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
+
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn surface_at_uv(&self, u: f64, v: f64) -> Option<(Point3, Vec3)> {
+        let (p, normal) = self.object.surface_at_uv(u, v)?;
+        Some((p, -normal))
+    }
+
+    fn kind(&self) -> &'static str {
+        self.object.kind()
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        self.object.material()
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        let object = self.object.scene_json()?;
+        Some(format!("{{ \"flip_normals\": true,{}", object.strip_prefix('{')?))
     }
 }
 
-pub trait Material {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, rng: &mut ThreadRng) -> bool {
-        false
+// Hits `object` through `hit_sampled` when there's a sampler, or else `hit`, for
+// the objects holding others to serve both with one body
+pub fn hit_object<'a>(object: &'a dyn Hittable, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+    match sampler {
+        Some(sampler) => object.hit_sampled(r, ray_t, rec, sampler),
+        None => object.hit(r, ray_t, rec),
     }
 }
 
-pub struct LambertianMaterial {
-    albedo: Color
+// Fog or smoke of one density filling a closed boundary object. A ray through it
+// goes an exponentially distributed distance before scattering off the medium,
+// with the isotropic phase function, and passes straight through if that's
+// further than its way out. The boundary must be convex, so a ray goes in and out
+// of it at most once. Only rays with a sampler to draw the distance from see it.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    // The negative reciprocal of the density, the mean distance between scatterings
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
 }
 
-impl LambertianMaterial {
-    pub fn new(albedo: Color) -> Self {
-        LambertianMaterial {
-            albedo
-        }
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        ConstantMedium::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn with_phase_function(boundary: Arc<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>) -> Self {
+        ConstantMedium { boundary, neg_inv_density: -1.0 / density, phase_function }
     }
 }
 
-impl Material for LambertianMaterial {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, rng: &mut ThreadRng) -> bool {
-        let scatter_direction = rec.normal + Vec3::random_unit_vector(rng);
+impl Hittable for ConstantMedium {
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        let Some(inside) = medium_span(self.boundary.as_ref(), r, ray_t) else {
+            return false;
+        };
+        let ray_length = r.direction.length();
+        let hit_distance = self.neg_inv_density * (1.0 - sampler.get_1d()).ln();
+        if hit_distance > inside.size() * ray_length {
+            return false;
+        }
+        record_medium_hit(rec, r, inside.min + hit_distance / ray_length, self.phase_function.as_ref());
+        true
+    }
 
-        let scatter_direction =
-            if scatter_direction.near_zero() {
-                rec.normal
-            } else {
-                rec.normal + Vec3::random_unit_vector(rng)
-            };
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
 
-        *scattered = Ray { origin: rec.p, direction: scatter_direction };
-        *attenuation = self.albedo;
-        true
+    fn kind(&self) -> &'static str {
+        "constant_medium"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.phase_function.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"constant_medium\", \"boundary\": {}, \"density\": {}, \"material\": {} }}",
+            self.boundary.scene_json()?,
+            number(-1.0 / self.neg_inv_density),
+            self.phase_function.scene_json()?
+        ))
     }
 }
 
-pub struct MetalMaterial {
-    albedo: Color,
-    fuzz: f64
+// A medium whose density varies from place to place, for clouds and wisps of
+// smoke: `density` (a noise or voxel texture, say) gives it at each point as a
+// fraction of `max_density`, its brightness clamped between 0 and 1. Rays find
+// where they scatter by delta tracking: they take exponential steps as though the
+// whole medium were at its densest, and at each one scatter with the chance that
+// the density there is of the most, or else carry on. The boundary must be convex,
+// and as with ConstantMedium only rays with a sampler see it.
+pub struct HeterogeneousMedium {
+    boundary: Arc<dyn Hittable>,
+    density: Arc<dyn Texture>,
+    max_density: f64,
+    phase_function: Arc<dyn Material>,
 }
 
-impl MetalMaterial {
-    pub fn new(albedo: Color, fuzz: f64) -> Self {
-        MetalMaterial {
-            albedo,
-            fuzz
+impl HeterogeneousMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: Arc<dyn Texture>, max_density: f64, albedo: Color) -> Self {
+        HeterogeneousMedium::with_phase_function(boundary, density, max_density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn with_phase_function(
+        boundary: Arc<dyn Hittable>,
+        density: Arc<dyn Texture>,
+        max_density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        HeterogeneousMedium { boundary, density, max_density, phase_function }
+    }
+
+    // The density at `p`, in the same frame as the rays the medium is hit by
+    pub fn density_at(&self, p: Point3) -> f64 {
+        let tc = TextureCoords { u: 0.0, v: 0.0, p, local_p: p, normal: Vec3::zero(), footprint: 0.0, du: 0.0, dv: 0.0, vertex_color: None };
+        let c = self.density.value(&tc);
+        self.max_density * ((c.x + c.y + c.z) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        if self.max_density <= 0.0 {
+            return false;
+        }
+        let Some(inside) = medium_span(self.boundary.as_ref(), r, ray_t) else {
+            return false;
+        };
+        let step = 1.0 / (self.max_density * r.direction.length());
+        let mut t = inside.min;
+        loop {
+            t -= (1.0 - sampler.get_1d()).ln() * step;
+            if t >= inside.max {
+                return false;
+            }
+            if sampler.get_1d() * self.max_density < self.density_at(r.at(t)) {
+                record_medium_hit(rec, r, t, self.phase_function.as_ref());
+                return true;
+            }
         }
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn kind(&self) -> &'static str {
+        "heterogeneous_medium"
+    }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.phase_function.as_ref())
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"heterogeneous_medium\", \"boundary\": {}, \"density\": {}, \"max_density\": {}, \"material\": {} }}",
+            self.boundary.scene_json()?,
+            self.density.scene_json()?,
+            number(self.max_density),
+            self.phase_function.scene_json()?
+        ))
+    }
 }
 
-impl Material for MetalMaterial {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray, rng: &mut ThreadRng) -> bool {
-        let reflected = Vec3::reflect(r_in.direction.unit_vector(), rec.normal);
-        *scattered = Ray { origin: rec.p, direction: reflected + Vec3::random_in_unit_sphere(rng) * self.fuzz };
-        *attenuation = self.albedo;
-        scattered.direction.dot(rec.normal) > 0.0
+// The part of `ray_t` the ray spends inside a convex boundary, from where the
+// whole line goes in and out so a ray starting inside is caught too
+fn medium_span(boundary: &dyn Hittable, r: Ray, ray_t: Interval) -> Option<Interval> {
+    let mut enter = HitRecord::blank();
+    let mut exit = HitRecord::blank();
+    if !boundary.hit(r, Interval::UNIVERSE, &mut enter) {
+        return None;
+    }
+    if !boundary.hit(r, Interval::new(enter.t + 0.0001, f64::INFINITY), &mut exit) {
+        return None;
     }
+    let inside = Interval::new(enter.t.max(ray_t.min).max(0.0), exit.t.min(ray_t.max));
+    (inside.min < inside.max).then_some(inside)
 }
 
-pub struct Sphere {
-    center: Point3,
-    radius: f64,
-    mat_ptr: Arc<dyn Material>
+fn record_medium_hit<'a>(rec: &mut HitRecord<'a>, r: Ray, t: f64, phase_function: &'a dyn Material) {
+    rec.set_surface(r, t, Vec3::new(1.0, 0.0, 0.0), (0.0, 0.0), phase_function);
+    // The medium has no surface, so neither matters
+    rec.normal = Vec3::new(1.0, 0.0, 0.0);
+    rec.front_face = true;
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc = r.origin - self.center;
-        let a = r.direction.length_squared();
-        let half_b = oc.dot(r.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
+#[derive(Clone, Copy, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // The first object with the second carved out of it
+    Difference,
+}
 
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            false
-        } else {
-            let sqrtd = discriminant.sqrt();
-            let mut root = (-half_b - sqrtd) / a;
-            if root < t_min || t_max < root {
-                root = (-half_b + sqrtd) / a;
-                if root < t_min || t_max < root {
-                    false
-                } else {
-                    rec.t = root;
-                    rec.p = r.at(rec.t);
-                    rec.normal = (rec.p - self.center) / self.radius;
-                    let outward_normal = (rec.p - self.center) / self.radius;
-                    rec.set_face_normal(r, outward_normal);
-                    rec.mat_ptr = Some(self.mat_ptr.to_owned());
-                    true
-                }
+impl CsgOperation {
+    pub fn by_name(name: &str) -> Option<CsgOperation> {
+        match name {
+            "union" => Some(CsgOperation::Union),
+            "intersection" => Some(CsgOperation::Intersection),
+            "difference" => Some(CsgOperation::Difference),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsgOperation::Union => "union",
+            CsgOperation::Intersection => "intersection",
+            CsgOperation::Difference => "difference",
+        }
+    }
+
+    fn contains(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// How far past one of its surfaces a CSG looks for a child's next, so it doesn't
+// find the same one again
+const CSG_STEP: f64 = 1e-7;
+
+// Two solids combined by a boolean operation, for lenses, pipes and cut-away
+// shapes. The ray is followed through every surface of both in turn, keeping
+// track of whether it's inside each; a surface where that changes whether it's
+// inside the result is a surface of the result, wearing its own object's
+// material. Both objects must be closed, so every entry has a matching exit.
+pub struct Csg {
+    operation: CsgOperation,
+    a: Arc<dyn Hittable>,
+    b: Arc<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Self {
+        Csg { operation, a, b }
+    }
+}
+
+impl Hittable for Csg {
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let next = |object: &'a dyn Hittable, from: f64| {
+            let mut found = HitRecord::blank();
+            object.hit(r, Interval::new(from, f64::INFINITY), &mut found).then_some(found)
+        };
+        let (mut hit_a, mut hit_b) = (next(self.a.as_ref(), ray_t.min), next(self.b.as_ref(), ray_t.min));
+        // Leaving an object first means the ray started inside it
+        let mut in_a = hit_a.is_some_and(|hit| !hit.front_face);
+        let mut in_b = hit_b.is_some_and(|hit| !hit.front_face);
+        loop {
+            let from_a = match (&hit_a, &hit_b) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return false,
+            };
+            let hit = if from_a { hit_a.unwrap() } else { hit_b.unwrap() };
+            if hit.t > ray_t.max {
+                return false;
+            }
+            let inside = self.operation.contains(in_a, in_b);
+            if from_a {
+                in_a = !in_a;
+                hit_a = next(self.a.as_ref(), hit.t + CSG_STEP);
             } else {
-                rec.t = root;
-                rec.p = r.at(rec.t);
-                rec.normal = (rec.p - self.center) / self.radius;
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(r, outward_normal);
-                rec.mat_ptr = Some(self.mat_ptr.to_owned());
-                true
+                in_b = !in_b;
+                hit_b = next(self.b.as_ref(), hit.t + CSG_STEP);
+            }
+            let now_inside = self.operation.contains(in_a, in_b);
+            if now_inside != inside {
+                *rec = hit;
+                // The normal already faces the ray; what changes is that carving
+                // out the second object turns its surface inside out
+                rec.front_face = now_inside;
+                return true;
             }
         }
     }
-}
 
-impl Sphere {
-    pub fn new(center: Point3, radius: f64, mat_ptr: Arc<dyn Material>) -> Sphere {
-        Sphere {
-            center,
-            radius,
-            mat_ptr 
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self.operation {
+            CsgOperation::Union => Some(self.a.bounding_box()?.union(&self.b.bounding_box()?)),
+            CsgOperation::Intersection => match (self.a.bounding_box(), self.b.bounding_box()) {
+                (Some(a), Some(b)) => Some(a.intersection(&b)),
+                (a, b) => a.or(b),
+            },
+            CsgOperation::Difference => self.a.bounding_box(),
         }
     }
+
+    fn kind(&self) -> &'static str {
+        "csg"
+    }
+
+    fn scene_json(&self) -> Option<String> {
+        Some(format!(
+            "{{ \"type\": \"csg\", \"operation\": \"{}\", \"a\": {}, \"b\": {} }}",
+            self.operation.name(),
+            self.a.scene_json()?,
+            self.b.scene_json()?
+        ))
+    }
 }
 
 pub struct HittableList {
@@ -155,28 +2557,30 @@ pub struct HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let mut hit_anything = false;
-        let mut closest_so_far = t_max;
+    fn hit<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.hit_through(r, ray_t, rec, None)
+    }
 
-        for boxed_obj in &self.objects {
-            let mut temp_rec: HitRecord = HitRecord {
-                p: Point3::zero(),
-                front_face: false,
-                mat_ptr: None,
-                normal: Vec3::zero(),
-                t: 0.0,
-            };
-            if boxed_obj
-                .as_ref()
-                .hit(r, t_min, closest_so_far, &mut temp_rec)
-            {
-                hit_anything = true;
-                closest_so_far = temp_rec.t;
-                *rec = temp_rec;
-            }
+    fn hit_sampled<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, sampler: &mut dyn Sampler) -> bool {
+        self.hit_through(r, ray_t, rec, Some(sampler))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut bbox = Aabb::empty();
+        for object in &self.objects {
+            bbox = bbox.union(&object.bounding_box()?);
         }
-        hit_anything
+        Some(bbox)
+    }
+
+    fn kind(&self) -> &'static str {
+        "list"
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
     }
 }
 
@@ -187,6 +2591,21 @@ impl HittableList {
         }
     }
 
+    fn hit_through<'a>(&'a self, r: Ray, ray_t: Interval, rec: &mut HitRecord<'a>, mut sampler: Option<&mut (dyn Sampler + '_)>) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+        let mut temp_rec = HitRecord::blank();
+
+        for boxed_obj in &self.objects {
+            if hit_object(boxed_obj.as_ref(), r, ray_t.up_to(closest_so_far), &mut temp_rec, sampler.as_deref_mut()) {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = temp_rec;
+            }
+        }
+        hit_anything
+    }
+
     pub fn add(&mut self, obj: Arc<dyn Hittable>) {
         self.objects.push(obj)
     }
@@ -349,13 +2768,35 @@ impl ops::MulAssign<f64> for Vec3 {
 
 impl ops::DivAssign<f64> for Vec3 {
     fn div_assign(&mut self, rhs: f64) {
-        *self *= 1 as f64 / rhs
+        *self *= 1.0 / rhs
+    }
+}
+
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            _ => &self.z,
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, axis: usize) -> &mut f64 {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            _ => &mut self.z,
+        }
     }
 }
 
 impl Vec3 {
     pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
-        Vec3 { x: x, y: y, z: z }
+        Vec3 { x, y, z }
     }
 
     pub fn zero() -> Vec3 {
@@ -386,49 +2827,56 @@ impl Vec3 {
         }
     }
 
+    pub fn min(&self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+
+    pub fn max(&self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         f64::abs(self.x) < s && f64::abs(self.y) < s && f64::abs(self.z) < s
     }
 
     pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
-        v - n * (2 as f64) * v.dot(n)
+        v - n * 2.0 * v.dot(n)
+    }
+
+    // Snell's law for a unit vector `uv` meeting a surface with normal `n`, where
+    // `eta_ratio` is the index of refraction it leaves over the one it enters
+    pub fn refract(uv: Vec3, n: Vec3, eta_ratio: f64) -> Vec3 {
+        let cos_theta = f64::min(-uv.dot(n), 1.0);
+        let r_out_perp = (uv + n * cos_theta) * eta_ratio;
+        let r_out_parallel = n * -(1.0 - r_out_perp.length_squared()).abs().sqrt();
+        r_out_perp + r_out_parallel
     }
 
     pub fn unit_vector(&self) -> Vec3 {
         self / self.length()
     }
 
-    pub fn random(min: f64, max: f64, rng: &mut ThreadRng) -> Vec3 {
-        Self::new(
-            rng.gen_range(min..=max),
-            rng.gen_range(min..=max),
-            rng.gen_range(min..=max)
-        )
+    pub fn random(min: f64, max: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut between = || min + (max - min) * sampler.get_1d();
+        Self::new(between(), between(), between())
     }
 
-    pub fn random_in_unit_sphere(rng: &mut ThreadRng) -> Vec3 {
-        loop {
-            let p = Vec3::random(-1.0, 1.0, rng);
-            if p.length_squared() >= 1.0 {
-                continue;
-            } else {
-                return p;
-            }
-        }
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+        sampling::uniform_ball(sampler.get_2d(), sampler.get_1d())
     }
 
-    pub fn random_unit_vector(rng: &mut ThreadRng) -> Vec3 {
-        Self::random_in_unit_sphere(rng).unit_vector()
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+        sampling::uniform_sphere(sampler.get_2d())
     }
 
     // An alternate formula for diffuse
-    pub fn random_in_hemisphere(normal: Vec3, rng: &mut ThreadRng) -> Vec3 {
-        let in_unit_sphere = Self::random_in_unit_sphere(rng);
-        if in_unit_sphere.dot(normal) > 0.0 {
-            in_unit_sphere
+    pub fn random_in_hemisphere(normal: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let on_sphere = Self::random_unit_vector(sampler);
+        if on_sphere.dot(normal) > 0.0 {
+            on_sphere
         } else {
-            -in_unit_sphere
+            -on_sphere
         }
     }
 }
@@ -437,43 +2885,290 @@ impl Vec3 {
 pub type Point3 = Vec3;
 pub type Color = Vec3;
 
+// A closed range of ray parameters (or any other values). An empty interval has
+// min > max, so nothing is inside it.
+#[derive(Clone, Copy)]
+pub struct Interval {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Interval {
+    pub const EMPTY: Interval = Interval::new(f64::INFINITY, -f64::INFINITY);
+    pub const UNIVERSE: Interval = Interval::new(-f64::INFINITY, f64::INFINITY);
+
+    pub const fn new(min: f64, max: f64) -> Self {
+        Interval { min, max }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    // Inside, ends included
+    pub fn contains(&self, x: f64) -> bool {
+        self.min <= x && x <= self.max
+    }
+
+    // Strictly inside, ends excluded
+    pub fn surrounds(&self, x: f64) -> bool {
+        self.min < x && x < self.max
+    }
+
+    pub fn clamp(&self, x: f64) -> f64 {
+        x.max(self.min).min(self.max)
+    }
+
+    // Widened by `delta` in total, half at each end
+    pub fn expand(&self, delta: f64) -> Interval {
+        Interval::new(self.min - delta / 2.0, self.max + delta / 2.0)
+    }
+
+    // The same interval ending at `max` instead, for narrowing to the closest hit so far
+    pub fn up_to(&self, max: f64) -> Interval {
+        Interval::new(self.min, max)
+    }
+}
+
+// A right-handed tangent frame around a unit normal `w`, for turning directions
+// sampled around the z axis into world space and back. Built with the branchless
+// method of Duff et al., "Building an Orthonormal Basis, Revisited" (2017).
+#[derive(Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_normal(n: Vec3) -> Self {
+        let sign = 1.0f64.copysign(n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        Onb {
+            u: Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            v: Vec3::new(b, sign + n.y * n.y * a, -n.y),
+            w: n,
+        }
+    }
+
+    pub fn local_to_world(&self, local: Vec3) -> Vec3 {
+        self.u * local.x + self.v * local.y + self.w * local.z
+    }
+
+    pub fn world_to_local(&self, world: Vec3) -> Vec3 {
+        Vec3::new(world.dot(self.u), world.dot(self.v), world.dot(self.w))
+    }
+}
+
+// A cone around a ray that tracks how wide a pixel's footprint has grown, a
+// cheap stand-in for full ray differentials. `spread` is the cone's angle in radians.
+#[derive(Clone, Copy)]
+pub struct RayCone {
+    pub width: f64,
+    pub spread: f64,
+}
+
+// Diffuse bounces blur whatever they hit next heavily, so a fixed wide spread is enough
+const DIFFUSE_SPREAD: f64 = 0.1;
+
+impl RayCone {
+    pub fn none() -> Self {
+        RayCone { width: 0.0, spread: 0.0 }
+    }
+
+    pub fn width_at(&self, t: f64) -> f64 {
+        self.width + self.spread * t.abs()
+    }
+
+    // The cone carried on past a hit at `t` without changing direction
+    pub fn advanced(&self, t: f64) -> Self {
+        RayCone { width: self.width_at(t), spread: self.spread }
+    }
+
+    // Mirror-like bounces keep the spread, widened by the lobe's roughness
+    pub fn specular(&self, t: f64, roughness: f64) -> Self {
+        RayCone { width: self.width_at(t), spread: self.spread + roughness }
+    }
+
+    pub fn diffuse(&self, t: f64) -> Self {
+        RayCone { width: self.width_at(t), spread: self.spread.max(DIFFUSE_SPREAD) }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    pub cone: RayCone,
+    // The moment in the camera's shutter interval the ray was sent at, which moving
+    // objects are tested where they were at. Rays scattered off a surface keep it.
+    pub time: f64,
 }
 
 impl Ray {
+    pub fn new(origin: Point3, direction: Vec3) -> Ray {
+        Ray { origin, direction, cone: RayCone::none(), time: 0.0 }
+    }
+
+    pub fn with_time(mut self, time: f64) -> Ray {
+        self.time = time;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.origin + (self.direction * t)
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Camera {
     pub origin: Point3,
     pub lower_left_corner: Point3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
+    // What the camera was pointed at and how, so other views can be derived from it
+    pub target: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    // Angle subtended by one pixel, seeds the ray cones used for texture filtering
+    pub pixel_spread: f64,
+    // When the shutter opens and closes: rays go out at times spread evenly across
+    // it, so anything moving in the meantime is blurred. Both the same for a
+    // shutter that's open for an instant, as by default.
+    pub shutter: (f64, f64),
+    // How long a rolling shutter takes to read the frame out, top row to bottom:
+    // each row opens that much later than the top one times how far down the
+    // frame it is, then stays open as long as the shutter, so things moving
+    // sideways lean. Zero for a global shutter opening every row at once.
+    pub readout: f64,
 }
 
 impl Camera {
-    pub fn default_camera() -> Self {
-        let aspect_ratio = 16.0 / 9.0;
-        let viewport_height = 2.0;
+    // Looks from `lookfrom` towards `lookat`, with `vfov` the vertical field of view in degrees
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, vfov: f64, aspect_ratio: f64) -> Self {
+        let viewport_height = 2.0 * (vfov.to_radians() / 2.0).tan();
         let viewport_width = aspect_ratio * viewport_height;
-        let focal_length = 1.0;
 
-        let origin = Point3::zero();
-        let horizontal = Vec3::new(viewport_width, 0.0, 0.0);
-        let vertical = Vec3::new(0.0, viewport_height, 0.0);
-        let lower_left_corner = origin - horizontal / 2 - vertical / 2 - Vec3::new(0.0, 0.0, focal_length);
+        let w = (lookfrom - lookat).unit_vector();
+        let u = vup.cross(w).unit_vector();
+        let v = w.cross(u);
+
+        let origin = lookfrom;
+        let horizontal = u * viewport_width;
+        let vertical = v * viewport_height;
+        let lower_left_corner = origin - horizontal / 2 - vertical / 2 - w;
 
         Camera {
             origin,
             lower_left_corner,
             horizontal,
             vertical,
+            target: lookat,
+            vup,
+            vfov,
+            pixel_spread: 0.0,
+            shutter: (0.0, 0.0),
+            readout: 0.0,
+        }
+    }
+
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn with_rolling_shutter(mut self, readout: f64) -> Self {
+        self.readout = readout;
+        self
+    }
+
+    // The tutorial's camera: at the origin looking down -z with a 90 degree field of view
+    pub fn default_camera() -> Self {
+        Camera::new(Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 90.0, 16.0 / 9.0)
+    }
+
+    // The same camera swung round its target by `yaw` degrees (positive to the
+    // right), raised by `pitch` degrees and moved out to `distance` times as far away
+    pub fn orbited(&self, yaw: f64, pitch: f64, distance: f64) -> Self {
+        let offset = self.origin - self.target;
+        let length = offset.length();
+        let yaw = offset.x.atan2(offset.z) + yaw.to_radians();
+        let pitch = ((offset.y / length).asin() + pitch.to_radians()).clamp(-1.5, 1.5);
+        let direction = Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        let aspect_ratio = self.horizontal.length() / self.vertical.length();
+        Camera::new(self.target + direction * length * distance, self.target, self.vup, self.vfov, aspect_ratio)
+            .with_shutter(self.shutter.0, self.shutter.1)
+            .with_rolling_shutter(self.readout)
+    }
+
+    // The same camera moved along its line of sight and turned onto `center`, just
+    // far enough back for a sphere of `radius` round it to fit in frame with
+    // `margin` times the room it needs
+    pub fn framing(&self, center: Point3, radius: f64, margin: f64) -> Self {
+        let aspect_ratio = self.horizontal.length() / self.vertical.length();
+        let half_tan = (self.vfov.to_radians() / 2.0).tan();
+        // The narrower of the vertical and horizontal fields of view
+        let half_angle = half_tan.min(half_tan * aspect_ratio).atan();
+        let distance = margin * radius / half_angle.sin();
+        let backwards = (self.origin - self.target).unit_vector();
+        Camera::new(center + backwards * distance, center, self.vup, self.vfov, aspect_ratio)
+            .with_shutter(self.shutter.0, self.shutter.1)
+            .with_rolling_shutter(self.readout)
+    }
+
+    // Where `p` lands on the image as (u, v), the inverse of `get_ray`, or None
+    // when it's behind the camera
+    pub fn project(&self, p: Point3) -> Option<(f64, f64)> {
+        let forward = self.vertical.cross(self.horizontal).unit_vector();
+        let corner = self.lower_left_corner - self.origin;
+        let relative = p - self.origin;
+        let depth = relative.dot(forward);
+        if depth <= 0.0 {
+            return None;
         }
+        let on_plane = relative * (corner.dot(forward) / depth) - corner;
+        Some((
+            on_plane.dot(self.horizontal) / self.horizontal.length_squared(),
+            on_plane.dot(self.vertical) / self.vertical.length_squared(),
+        ))
+    }
+
+    // One eye of a stereo pair: moved `offset` along the camera's horizontal axis
+    // (negative for the left eye) with the sensor shifted so both eyes' views
+    // line up at `convergence` units in front of the camera, which puts objects
+    // at that distance on the screen plane
+    pub fn stereo_eye(mut self, offset: f64, convergence: f64) -> Self {
+        let shift = self.horizontal.unit_vector() * offset;
+        let focal_length = (self.lower_left_corner + self.horizontal / 2 + self.vertical / 2 - self.origin).length();
+        self.origin += shift;
+        self.target += shift;
+        self.lower_left_corner += shift * (1.0 - focal_length / convergence);
+        self
+    }
+
+    // The view for a frame of `width` by `height` pixels grown by `pad_x` pixels on
+    // the left and right and `pad_y` on the top and bottom, keeping the pixels
+    // inside the original frame exactly where they were
+    pub fn overscanned(mut self, (width, height): (u16, u16), (pad_x, pad_y): (u16, u16)) -> Self {
+        let step_x = self.horizontal / (width - 1) as f64;
+        let step_y = self.vertical / (height - 1) as f64;
+        self.lower_left_corner = self.lower_left_corner - step_x * pad_x as f64 - step_y * pad_y as f64;
+        self.horizontal = step_x * (width - 1 + 2 * pad_x) as f64;
+        self.vertical = step_y * (height - 1 + 2 * pad_y) as f64;
+        self
+    }
+
+    pub fn for_image_height(mut self, image_height: u16) -> Self {
+        let center = self.lower_left_corner + self.horizontal / 2 + self.vertical / 2;
+        let focal_length = (center - self.origin).length();
+        let vfov = 2.0 * (self.vertical.length() / 2.0).atan2(focal_length);
+        self.pixel_spread = vfov / image_height as f64;
+        self
     }
 
     pub fn get_ray(&self, u: f64, v: f64) -> Ray {
@@ -481,6 +3176,33 @@ impl Camera {
             origin: self.origin,
             direction: self.lower_left_corner + self.horizontal * u + self.vertical * v
                 - self.origin,
+            cone: RayCone { width: 0.0, spread: self.pixel_spread },
+            time: self.shutter.0,
+        }
+    }
+
+    // The ray through (u, v) at a random moment while the shutter is open over
+    // the pixel's row, `scanline` of the way down the frame (0 at the top row)
+    pub fn sample_ray(&self, u: f64, v: f64, scanline: f64, sampler: &mut dyn Sampler) -> Ray {
+        self.get_ray(u, v).with_time(self.sample_time(scanline, sampler))
+    }
+
+    // A random moment while the shutter is open over the row `scanline` of the
+    // way down the frame. A shutter open for an instant draws no sample for it,
+    // so still renders see the same samples they always have.
+    pub fn sample_time(&self, scanline: f64, sampler: &mut dyn Sampler) -> f64 {
+        let (open, close) = self.shutter;
+        let opens = open + self.readout * scanline;
+        if close == open {
+            return opens;
         }
+        opens + (close - open) * sampler.get_1d()
+    }
+
+    // A random moment while any row is open, for light sent out from the lights
+    // rather than through a pixel
+    pub fn sample_frame_time(&self, sampler: &mut dyn Sampler) -> f64 {
+        let scanline = if self.readout > 0.0 { sampler.get_1d() } else { 0.0 };
+        self.sample_time(scanline, sampler)
     }
 }
@@ -0,0 +1,885 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use crate::scenes::Scene;
+use crate::texture::SolidColor;
+use crate::transform::{Instance, Matrix4, Quat, Transform};
+use crate::types::*;
+
+// Loads the subset of a USD ASCII (.usda) layer this renderer can draw:
+//
+// - Xform (and any other prim's) xformOps: translate, scale, rotateX/Y/Z, the
+//   six rotateXYZ orders, orient and transform, in xformOpOrder
+// - Sphere, with its radius
+// - UsdPreviewSurface materials bound with material:binding, as diffuse, metal
+//   (metallic 0.5 or more, with roughness as fuzz), glass (opacity below 1, with
+//   ior) or a light (any emissiveColor), or else displayColor
+// - Camera, with focalLength and horizontalAperture fitted to the image width
+// - SphereLight as a glowing sphere, and a DomeLight's color as the background
+//
+// Only "def" prims are drawn, and only their default values (or first time
+// samples) are used. Everything else, meshes and references included, is left
+// out with a warning on stderr, and "upAxis" "Z" layers are turned to y-up.
+pub fn load(path: &Path) -> Result<Scene, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    let (scene, warnings) = import(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    for warning in &warnings {
+        eprintln!("{}: warning: {warning}", path.display());
+    }
+    Ok(scene)
+}
+
+// The scene a layer's text describes, and warnings about what was left out of it
+fn import(text: &str) -> Result<(Scene, Vec<String>), String> {
+    let layer = Parser::new(text).layer()?;
+    let mut importer = Importer::default();
+    let mut index = HashMap::new();
+    for prim in &layer.prims {
+        index_prims(prim, "", &mut index);
+    }
+    importer.prims = index;
+
+    let root = if layer.up_axis.as_deref() == Some("Z") {
+        Transform::rotate(Vec3::new(1.0, 0.0, 0.0), -90.0)
+    } else {
+        Transform::IDENTITY
+    };
+    for prim in &layer.prims {
+        importer.prim(prim, "", &root)?;
+    }
+
+    let mut scene = Scene::new(importer.world);
+    if let Some(color) = importer.background {
+        scene.background = Background::Solid(color);
+    }
+    if let Some((_, camera)) = importer.cameras.first() {
+        scene.camera = *camera;
+        scene.cameras = importer.cameras;
+    }
+    Ok((scene, importer.warnings))
+}
+
+// A value as written in the file. Tuples and arrays are both lists.
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    String(String),
+    Token(String),
+    Path(String),
+    // An asset path, which nothing imported uses yet
+    Asset,
+    List(Vec<Value>),
+    // Time samples, keyed by time
+    Samples(Vec<(f64, Value)>),
+}
+
+impl Value {
+    fn number(&self) -> Option<f64> {
+        match self {
+            Value::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Value::String(s) | Value::Token(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn vec3(&self) -> Option<Vec3> {
+        match self.list()? {
+            [x, y, z] => Some(Vec3::new(x.number()?, y.number()?, z.number()?)),
+            _ => None,
+        }
+    }
+}
+
+struct Prim {
+    specifier: String,
+    type_name: String,
+    name: String,
+    // Attributes and relationships by name, connections as "name.connect"
+    properties: Vec<(String, Value)>,
+    // Metadata such as references, by name
+    metadata: Vec<String>,
+    children: Vec<Prim>,
+}
+
+impl Prim {
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.properties.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    fn number_or(&self, name: &str, default: f64) -> f64 {
+        self.get(name).and_then(Value::number).unwrap_or(default)
+    }
+}
+
+struct Layer {
+    up_axis: Option<String>,
+    prims: Vec<Prim>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Number(f64),
+    Path(String),
+    Asset,
+    Punct(char),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    tokenize_error: Option<String>,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        let (tokens, tokenize_error) = match tokenize(text) {
+            Ok(tokens) => (tokens, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        Parser { tokens, pos: 0, tokenize_error }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + ahead).map(|(t, _)| t)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone()).ok_or("unexpected end of file")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let line = self.tokens.get(self.pos.saturating_sub(1)).map_or(0, |(_, line)| *line);
+        Err(format!("line {line}: {message}"))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(p) if p == c => Ok(()),
+            other => self.error(&format!("expected '{c}', found {other:?}")),
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Ident(s) => Ok(s),
+            other => self.error(&format!("expected a name, found {other:?}")),
+        }
+    }
+
+    fn layer(&mut self) -> Result<Layer, String> {
+        if let Some(error) = self.tokenize_error.take() {
+            return Err(error);
+        }
+        let mut up_axis = None;
+        if self.peek() == Some(&Token::Punct('(')) {
+            for (key, value) in self.metadata()? {
+                if key == "upAxis" {
+                    up_axis = value;
+                }
+            }
+        }
+        let mut prims = Vec::new();
+        while self.peek().is_some() {
+            prims.push(self.prim()?);
+        }
+        Ok(Layer { up_axis, prims })
+    }
+
+    // A parenthesized metadata block, keeping the names it sets (and the value
+    // when it's a single string or token) and passing over everything else
+    fn metadata(&mut self) -> Result<Vec<(String, Option<String>)>, String> {
+        self.expect('(')?;
+        let mut entries = Vec::new();
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct('(' | '[' | '{') => depth += 1,
+                Token::Punct(')' | ']' | '}') => depth -= 1,
+                Token::Ident(name) if depth == 1 && self.peek() == Some(&Token::Punct('=')) => {
+                    let value = match self.peek_at(1) {
+                        Some(Token::String(s) | Token::Ident(s)) => Some(s.clone()),
+                        _ => None,
+                    };
+                    entries.push((name, value));
+                }
+                _ => {}
+            }
+        }
+        Ok(entries)
+    }
+
+    fn prim(&mut self) -> Result<Prim, String> {
+        let specifier = self.ident()?;
+        if !matches!(specifier.as_str(), "def" | "over" | "class") {
+            return self.error(&format!("expected def, over or class, found '{specifier}'"));
+        }
+        let type_name = match self.peek() {
+            Some(Token::Ident(_)) => self.ident()?,
+            _ => String::new(),
+        };
+        let name = match self.next()? {
+            Token::String(s) => s,
+            other => return self.error(&format!("expected the prim's name, found {other:?}")),
+        };
+        let metadata = if self.peek() == Some(&Token::Punct('(')) {
+            self.metadata()?.into_iter().map(|(key, _)| key).collect()
+        } else {
+            Vec::new()
+        };
+        let mut prim = Prim { specifier, type_name, name, properties: Vec::new(), metadata, children: Vec::new() };
+        self.expect('{')?;
+        while !self.eat('}') {
+            match self.peek() {
+                Some(Token::Ident(word)) if matches!(word.as_str(), "def" | "over" | "class") => prim.children.push(self.prim()?),
+                Some(Token::Ident(word)) if word == "variantSet" => {
+                    self.pos += 1;
+                    self.next()?;
+                    self.expect('=')?;
+                    self.skip_braces()?;
+                }
+                Some(Token::Ident(_)) => self.property(&mut prim)?,
+                _ => return self.error("expected a prim or property"),
+            }
+        }
+        Ok(prim)
+    }
+
+    fn skip_braces(&mut self) -> Result<(), String> {
+        self.expect('{')?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct('{') => depth += 1,
+                Token::Punct('}') => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // An attribute (`[custom] [uniform] type[[]] name [= value] [(metadata)]`) or
+    // relationship (`rel name [= target]`)
+    fn property(&mut self, prim: &mut Prim) -> Result<(), String> {
+        let mut word = self.ident()?;
+        while matches!(word.as_str(), "custom" | "uniform" | "varying" | "prepend" | "append" | "add" | "delete" | "reorder") {
+            word = self.ident()?;
+        }
+        if word != "rel" && self.eat('[') {
+            self.expect(']')?;
+        }
+        let name = self.ident()?;
+        if self.eat('=') {
+            let value = self.value()?;
+            match name.strip_suffix(".timeSamples") {
+                Some(base) => {
+                    // The first sample stands in for an attribute without a default
+                    if prim.get(base).is_none() {
+                        if let Value::Samples(samples) = &value {
+                            if let Some((_, first)) = samples.first() {
+                                prim.properties.push((base.to_string(), first.clone()));
+                            }
+                        }
+                    }
+                }
+                None => {
+                    prim.properties.retain(|(n, _)| *n != name);
+                    prim.properties.push((name, value));
+                }
+            }
+        }
+        if self.peek() == Some(&Token::Punct('(')) {
+            self.metadata()?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        Ok(match self.next()? {
+            Token::Number(x) => Value::Number(x),
+            Token::String(s) => Value::String(s),
+            Token::Path(p) => Value::Path(p),
+            Token::Asset => Value::Asset,
+            Token::Ident(word) => match word.as_str() {
+                "inf" => Value::Number(f64::INFINITY),
+                "nan" => Value::Number(f64::NAN),
+                "true" => Value::Number(1.0),
+                "false" => Value::Number(0.0),
+                _ => Value::Token(word),
+            },
+            Token::Punct(open @ ('(' | '[')) => {
+                let close = if open == '(' { ')' } else { ']' };
+                let mut items = Vec::new();
+                while !self.eat(close) {
+                    items.push(self.value()?);
+                    if !self.eat(',') {
+                        self.expect(close)?;
+                        break;
+                    }
+                }
+                Value::List(items)
+            }
+            Token::Punct('{') => {
+                let mut samples = Vec::new();
+                while !self.eat('}') {
+                    let time = match self.next()? {
+                        Token::Number(t) => t,
+                        other => return self.error(&format!("expected a time sample, found {other:?}")),
+                    };
+                    self.expect(':')?;
+                    samples.push((time, self.value()?));
+                    if !self.eat(',') {
+                        self.expect('}')?;
+                        break;
+                    }
+                }
+                samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+                Value::Samples(samples)
+            }
+            other => return self.error(&format!("expected a value, found {other:?}")),
+        })
+    }
+}
+
+// Splits the text into tokens, each with the line it's on. Comments, including
+// the "#usda 1.0" header, are dropped.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    let until = |i: usize, end: char, line: usize| -> Result<usize, String> {
+        (i..chars.len()).find(|&j| chars[j] == end).ok_or_else(|| format!("line {line}: unclosed {end}"))
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\n' => {
+                line += 1;
+                i += 1;
+            }
+            _ if c.is_whitespace() => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '"' | '\'' => {
+                let triple = chars.get(i + 1) == Some(&c) && chars.get(i + 2) == Some(&c);
+                let mut s = String::new();
+                i += if triple { 3 } else { 1 };
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("line {line}: unclosed string")),
+                        Some(&q) if q == c && (!triple || (chars.get(i + 1) == Some(&c) && chars.get(i + 2) == Some(&c))) => {
+                            i += if triple { 3 } else { 1 };
+                            break;
+                        }
+                        Some('\\') => {
+                            match chars.get(i + 1) {
+                                Some('n') => s.push('\n'),
+                                Some('t') => s.push('\t'),
+                                Some(&other) => s.push(other),
+                                None => {}
+                            }
+                            i += 2;
+                        }
+                        Some(&other) => {
+                            if other == '\n' {
+                                line += 1;
+                            }
+                            s.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push((Token::String(s), line));
+            }
+            '<' => {
+                let end = until(i + 1, '>', line)?;
+                tokens.push((Token::Path(chars[i + 1..end].iter().collect()), line));
+                i = end + 1;
+            }
+            '@' => {
+                let end = until(i + 1, '@', line)?;
+                tokens.push((Token::Asset, line));
+                i = end + 1;
+            }
+            _ if c.is_ascii_digit()
+                || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit() || *n == '.'))
+                || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) =>
+            {
+                let start = i;
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit()
+                        || chars[i] == '.'
+                        || chars[i] == 'e'
+                        || chars[i] == 'E'
+                        || ((chars[i] == '-' || chars[i] == '+') && matches!(chars[i - 1], 'e' | 'E')))
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text.parse().map_err(|_| format!("line {line}: '{text}' isn't a number"))?;
+                tokens.push((Token::Number(number), line));
+            }
+            '-' if chars[i + 1..].starts_with(&['i', 'n', 'f']) => {
+                tokens.push((Token::Number(f64::NEG_INFINITY), line));
+                i += 4;
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | ':' | '.')) {
+                    i += 1;
+                }
+                tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+            }
+            '(' | ')' | '[' | ']' | '{' | '}' | '=' | ',' | ':' => {
+                tokens.push((Token::Punct(c), line));
+                i += 1;
+            }
+            _ => return Err(format!("line {line}: unexpected '{c}'")),
+        }
+    }
+    Ok(tokens)
+}
+
+fn index_prims<'a>(prim: &'a Prim, parent: &str, index: &mut HashMap<String, &'a Prim>) {
+    let path = format!("{parent}/{}", prim.name);
+    for child in &prim.children {
+        index_prims(child, &path, index);
+    }
+    index.insert(path, prim);
+}
+
+#[derive(Default)]
+struct Importer<'a> {
+    prims: HashMap<String, &'a Prim>,
+    world: HittableList,
+    cameras: Vec<(String, Camera)>,
+    background: Option<Color>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Importer<'a> {
+    fn prim(&mut self, prim: &Prim, parent: &str, parent_transform: &Transform) -> Result<(), String> {
+        let path = format!("{parent}/{}", prim.name);
+        if prim.specifier != "def" {
+            return Ok(());
+        }
+        if prim.get("visibility").and_then(Value::text) == Some("invisible") {
+            return Ok(());
+        }
+        if prim.metadata.iter().any(|key| key == "references" || key == "payload") {
+            self.warnings.push(format!("{path}: references and payloads aren't followed"));
+        }
+        let transform = local_transform(prim).map_err(|e| format!("{path}: {e}"))?.then(parent_transform);
+
+        match prim.type_name.as_str() {
+            "Sphere" => {
+                let radius = prim.number_or("radius", 1.0);
+                let material = self.material(prim, &path);
+                self.world.add(sphere(radius, material, &transform));
+            }
+            "SphereLight" => {
+                let radius = prim.number_or("inputs:radius", prim.number_or("radius", 0.5));
+                let light = DiffuseLight::new(Arc::new(SolidColor::new(light_color(prim))), 1.0);
+                self.world.add(sphere(radius, Arc::new(light), &transform));
+            }
+            "DomeLight" => self.background = Some(light_color(prim)),
+            "Camera" => {
+                let camera = camera(prim, &transform);
+                self.cameras.push((prim.name.clone(), camera));
+            }
+            "" | "Xform" | "Scope" | "Material" | "Shader" | "NodeGraph" | "GeomSubset" => {}
+            other => self.warnings.push(format!("{path}: {other} prims aren't supported, so it's left out")),
+        }
+        for child in &prim.children {
+            self.prim(child, &path, &transform)?;
+        }
+        Ok(())
+    }
+
+    // The bound UsdPreviewSurface, or else the prim's display color
+    fn material(&mut self, prim: &Prim, path: &str) -> Arc<dyn Material> {
+        let display_color = prim
+            .get("primvars:displayColor")
+            .and_then(|v| v.list().and_then(|items| items.first()).and_then(Value::vec3).or_else(|| v.vec3()))
+            .unwrap_or(Vec3::one() * 0.5);
+        let binding = match prim.get("material:binding") {
+            Some(Value::Path(target)) => target.clone(),
+            Some(Value::List(targets)) => match targets.first() {
+                Some(Value::Path(target)) => target.clone(),
+                _ => return Arc::new(LambertianMaterial::new(display_color)),
+            },
+            _ => return Arc::new(LambertianMaterial::new(display_color)),
+        };
+        match self.preview_surface(&binding) {
+            Some(shader) => self.surface(shader, path),
+            None => {
+                self.warnings.push(format!("{path}: no UsdPreviewSurface found at {binding}, so it's shown in its display color"));
+                Arc::new(LambertianMaterial::new(display_color))
+            }
+        }
+    }
+
+    // The shader the material's surface output connects to, or failing that its
+    // first UsdPreviewSurface
+    fn preview_surface(&self, material_path: &str) -> Option<&'a Prim> {
+        let material = self.prims.get(material_path)?;
+        if let Some(Value::Path(target)) = material.get("outputs:surface.connect") {
+            let shader_path = target.split('.').next().unwrap_or_default();
+            if let Some(shader) = self.prims.get(shader_path) {
+                return Some(shader);
+            }
+        }
+        material
+            .children
+            .iter()
+            .find(|child| child.get("info:id").and_then(Value::text) == Some("UsdPreviewSurface"))
+    }
+
+    fn surface(&mut self, shader: &Prim, path: &str) -> Arc<dyn Material> {
+        let color = |name: &str, default: Color| shader.get(name).and_then(Value::vec3).unwrap_or(default);
+        if shader.properties.iter().any(|(name, _)| name.starts_with("inputs:") && name.ends_with(".connect")) {
+            self.warnings.push(format!("{path}: textures aren't imported, so the material's plain values are used"));
+        }
+        let emissive = color("inputs:emissiveColor", Color::zero());
+        if emissive.x > 0.0 || emissive.y > 0.0 || emissive.z > 0.0 {
+            return Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(emissive)), 1.0));
+        }
+        let diffuse = color("inputs:diffuseColor", Vec3::one() * 0.18);
+        if shader.number_or("inputs:opacity", 1.0) < 1.0 {
+            return Arc::new(DielectricMaterial::new(shader.number_or("inputs:ior", 1.5)));
+        }
+        if shader.number_or("inputs:metallic", 0.0) >= 0.5 {
+            return Arc::new(MetalMaterial::new(diffuse, shader.number_or("inputs:roughness", 0.5)));
+        }
+        Arc::new(LambertianMaterial::new(diffuse))
+    }
+}
+
+// A sphere about the prim's origin. Imported materials are plain colors, so
+// turning a sphere changes nothing; unless it's squashed it becomes a sphere
+// with its own center and radius, which can be saved as a scene file.
+fn sphere(radius: f64, material: Arc<dyn Material>, transform: &Transform) -> Arc<dyn Hittable> {
+    let axes = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)].map(|axis| transform.vector(axis));
+    let scale = axes[0].length();
+    let tolerance = 1e-9 * scale;
+    let uniform = axes.iter().all(|axis| (axis.length() - scale).abs() < tolerance)
+        && axes[0].dot(axes[1]).abs() < tolerance * scale
+        && axes[1].dot(axes[2]).abs() < tolerance * scale
+        && axes[0].dot(axes[2]).abs() < tolerance * scale;
+    if uniform {
+        return Arc::new(Sphere::new(transform.point(Point3::zero()), radius * scale, material));
+    }
+    Arc::new(Instance::new(Arc::new(Sphere::new(Point3::zero(), radius, material)), *transform))
+}
+
+// UsdLux lights give a color, an intensity and an exposure in stops
+fn light_color(prim: &Prim) -> Color {
+    let color = prim.get("inputs:color").or_else(|| prim.get("color")).and_then(Value::vec3).unwrap_or(Vec3::one());
+    let intensity = prim.number_or("inputs:intensity", prim.number_or("intensity", 1.0));
+    let exposure = prim.number_or("inputs:exposure", prim.number_or("exposure", 0.0));
+    color * (intensity * exposure.exp2())
+}
+
+// Cameras look down their own -z with y up. The horizontal aperture, in the same
+// units as the focal length, spans the image's width.
+fn camera(prim: &Prim, transform: &Transform) -> Camera {
+    let aspect_ratio = 16.0 / 9.0;
+    let focal_length = prim.number_or("focalLength", 50.0).max(1e-6);
+    let aperture = prim.number_or("horizontalAperture", 20.955);
+    let vfov = 2.0 * (aperture / 2.0 / aspect_ratio).atan2(focal_length).to_degrees();
+    let focus_distance = prim.number_or("focusDistance", 0.0);
+    let focus_distance = if focus_distance > 0.0 { focus_distance } else { 10.0 };
+    let origin = transform.point(Point3::zero());
+    let forward = transform.vector(Vec3::new(0.0, 0.0, -1.0)).unit_vector();
+    let up = transform.vector(Vec3::new(0.0, 1.0, 0.0)).unit_vector();
+    Camera::new(origin, origin + forward * focus_distance, up, vfov, aspect_ratio)
+}
+
+// The prim's xformOps composed in xformOpOrder, the first op outermost
+fn local_transform(prim: &Prim) -> Result<Transform, String> {
+    let Some(order) = prim.get("xformOpOrder").and_then(Value::list) else {
+        return Ok(Transform::IDENTITY);
+    };
+    let mut transform = Transform::IDENTITY;
+    for op in order.iter().rev() {
+        let name = op.text().ok_or("xformOpOrder: expected op names")?;
+        if name == "!resetXformStack!" {
+            continue;
+        }
+        if name.starts_with("!invert!") {
+            return Err(format!("inverted xformOps ({name}) aren't supported"));
+        }
+        let value = prim.get(name).ok_or_else(|| format!("xformOpOrder names {name}, which isn't set"))?;
+        transform = transform.then(&xform_op(name, value)?);
+    }
+    Ok(transform)
+}
+
+fn xform_op(name: &str, value: &Value) -> Result<Transform, String> {
+    let kind = name.strip_prefix("xformOp:").ok_or_else(|| format!("{name} isn't an xformOp"))?;
+    // Ops can carry a suffix that tells two of the same kind apart: xformOp:translate:pivot
+    let kind = kind.split(':').next().unwrap_or_default();
+    let vector = || value.vec3().ok_or_else(|| format!("{name}: expected three numbers"));
+    let angle = || value.number().ok_or_else(|| format!("{name}: expected an angle"));
+    let axis = |c: char| match c {
+        'X' => Vec3::new(1.0, 0.0, 0.0),
+        'Y' => Vec3::new(0.0, 1.0, 0.0),
+        _ => Vec3::new(0.0, 0.0, 1.0),
+    };
+    match kind {
+        "translate" => Ok(Transform::translate(vector()?)),
+        "scale" => Transform::scale(vector()?).ok_or_else(|| format!("{name}: can't scale by zero")),
+        "rotateX" | "rotateY" | "rotateZ" => Ok(Transform::rotate(axis(kind.chars().last().unwrap_or('Z')), angle()?)),
+        "rotateXYZ" | "rotateXZY" | "rotateYXZ" | "rotateYZX" | "rotateZXY" | "rotateZYX" => {
+            // The first named axis turns first
+            let angles = vector()?;
+            let mut rotation = Transform::IDENTITY;
+            for c in kind["rotate".len()..].chars() {
+                let degrees = match c {
+                    'X' => angles.x,
+                    'Y' => angles.y,
+                    _ => angles.z,
+                };
+                rotation = rotation.then(&Transform::rotate(axis(c), degrees));
+            }
+            Ok(rotation)
+        }
+        "orient" => match value.list() {
+            Some([w, x, y, z]) => {
+                let part = |v: &Value| v.number().ok_or_else(|| format!("{name}: expected a quaternion"));
+                Ok(Quat { w: part(w)?, x: part(x)?, y: part(y)?, z: part(z)? }.to_transform())
+            }
+            _ => Err(format!("{name}: expected a quaternion")),
+        },
+        // Matrices are written row by row for row vectors, translation last
+        "transform" => {
+            let rows = value.list().filter(|rows| rows.len() == 4).ok_or_else(|| format!("{name}: expected a 4x4 matrix"))?;
+            let mut m = [[0.0; 4]; 4];
+            for (i, row) in rows.iter().enumerate() {
+                let row = row.list().filter(|row| row.len() == 4).ok_or_else(|| format!("{name}: expected a 4x4 matrix"))?;
+                for (j, x) in row.iter().enumerate() {
+                    m[j][i] = x.number().ok_or_else(|| format!("{name}: expected numbers"))?;
+                }
+            }
+            Transform::from_matrix(Matrix4::new(m)).ok_or_else(|| format!("{name}: the matrix can't be inverted"))
+        }
+        other => Err(format!("{name}: {other} xformOps aren't supported")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    fn scene(text: &str) -> Scene {
+        let (scene, warnings) = import(text).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        scene
+    }
+
+    // Where a ray from `origin` along `direction` first hits the scene, and the
+    // name of the material there
+    fn hit(scene: &Scene, origin: Point3, direction: Vec3) -> Option<(Point3, &'static str)> {
+        let mut rec = HitRecord::blank();
+        if !scene.hit(Ray::new(origin, direction), Interval::new(0.0, f64::INFINITY), &mut rec) {
+            return None;
+        }
+        Some((rec.p, rec.mat_ptr.map_or("none", |m| m.name())))
+    }
+
+    #[test]
+    fn xform_stacks_apply_the_first_op_outermost_and_parents_last() {
+        let scene = scene(
+            r#"#usda 1.0
+            def Xform "World" {
+                double3 xformOp:translate = (0, 1, 0)
+                float xformOp:rotateY = 90
+                uniform token[] xformOpOrder = ["xformOp:translate", "xformOp:rotateY"]
+
+                def Sphere "Ball" {
+                    double radius = 1
+                    double3 xformOp:translate = (2, 0, 0)
+                    float3 xformOp:scale = (0.5, 0.5, 0.5)
+                    uniform token[] xformOpOrder = ["xformOp:translate", "xformOp:scale"]
+                }
+            }
+            "#,
+        );
+        // (2, 0, 0) turns a quarter round y to (0, 0, -2), then moves up by one; the
+        // uniform scale leaves a plain sphere of radius 0.5
+        assert_eq!(scene.world.objects.len(), 1);
+        let (p, _) = hit(&scene, Point3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!(close(p, Point3::new(0.0, 1.0, -1.5)), "{:?}", p);
+        let (p, _) = hit(&scene, Point3::new(5.0, 1.0, -2.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert!(close(p, Point3::new(0.5, 1.0, -2.0)), "{:?}", p);
+    }
+
+    #[test]
+    fn non_uniform_scales_and_matrix_ops_become_instances() {
+        let scene = scene(
+            r#"#usda 1.0
+            def Sphere "Egg" {
+                float3 xformOp:scale = (1, 2, 1)
+                matrix4d xformOp:transform = ((1, 0, 0, 0), (0, 1, 0, 0), (0, 0, 1, 0), (3, 0, 0, 1))
+                uniform token[] xformOpOrder = ["xformOp:transform", "xformOp:scale"]
+            }
+            "#,
+        );
+        // The matrix's last row is its translation
+        let (p, _) = hit(&scene, Point3::new(3.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!(close(p, Point3::new(3.0, 2.0, 0.0)), "{:?}", p);
+        assert!(import(r#"def Sphere "S" { uniform token[] xformOpOrder = ["!invert!xformOp:translate"] }"#).is_err());
+    }
+
+    #[test]
+    fn bound_preview_surfaces_pick_the_material() {
+        let scene = scene(
+            r#"#usda 1.0
+            def Scope "Looks" {
+                def Material "Chrome" {
+                    token outputs:surface.connect = </Looks/Chrome/Surface.outputs:surface>
+                    def Shader "Surface" {
+                        uniform token info:id = "UsdPreviewSurface"
+                        color3f inputs:diffuseColor = (0.9, 0.9, 0.9)
+                        float inputs:metallic = 1
+                        float inputs:roughness = 0.1
+                    }
+                }
+                def Material "Glass" {
+                    def Shader "Surface" {
+                        uniform token info:id = "UsdPreviewSurface"
+                        float inputs:opacity = 0.2
+                    }
+                }
+            }
+            def Sphere "Chrome" {
+                rel material:binding = </Looks/Chrome>
+            }
+            def Sphere "Glass" {
+                double3 xformOp:translate = (0, 0, -5)
+                uniform token[] xformOpOrder = ["xformOp:translate"]
+                rel material:binding = </Looks/Glass>
+            }
+            def Sphere "Plain" {
+                double3 xformOp:translate = (0, 0, -10)
+                uniform token[] xformOpOrder = ["xformOp:translate"]
+                color3f[] primvars:displayColor = [(1, 0, 0)]
+            }
+            "#,
+        );
+        let at = |z: f64| hit(&scene, Point3::new(0.0, 5.0, z), Vec3::new(0.0, -1.0, 0.0)).unwrap().1;
+        assert_eq!(at(0.0), "metal");
+        assert_eq!(at(-5.0), "dielectric");
+        assert_eq!(at(-10.0), "lambertian");
+    }
+
+    #[test]
+    fn missing_bindings_fall_back_with_a_warning() {
+        let (scene, warnings) = import(r#"def Sphere "Ball" { rel material:binding = </Nowhere> }"#).unwrap();
+        assert_eq!(hit(&scene, Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap().1, "lambertian");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("/Ball: no UsdPreviewSurface"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn cameras_look_down_their_own_minus_z() {
+        let scene = scene(
+            r#"#usda 1.0
+            def Camera "Side" {
+                float focalLength = 50
+                float horizontalAperture = 20.955
+                double3 xformOp:translate = (10, 0, 0)
+                float xformOp:rotateY = 90
+                uniform token[] xformOpOrder = ["xformOp:translate", "xformOp:rotateY"]
+            }
+            def Camera "Front" {
+                double3 xformOp:translate = (0, 0, 10)
+                uniform token[] xformOpOrder = ["xformOp:translate"]
+            }
+            "#,
+        );
+        let names: Vec<&str> = scene.cameras.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["Side", "Front"]);
+        // The first camera is the one rendered
+        assert!(close(scene.camera.origin, Point3::new(10.0, 0.0, 0.0)));
+        let center = scene.camera.get_ray(0.5, 0.5).direction.unit_vector();
+        assert!(close(center, Vec3::new(-1.0, 0.0, 0.0)), "{:?}", center);
+        let front = scene.cameras[1].1;
+        assert!(close(front.get_ray(0.5, 0.5).direction.unit_vector(), Vec3::new(0.0, 0.0, -1.0)));
+
+        // A 50mm lens behind a 20.955mm-wide aperture sees 2 * atan(20.955 / 100) across
+        let left = front.get_ray(0.0, 0.5).direction.unit_vector();
+        let right = front.get_ray(1.0, 0.5).direction.unit_vector();
+        let across = left.dot(right).acos();
+        assert!((across - 2.0 * (20.955f64 / 100.0).atan()).abs() < 1e-9, "{}", across);
+    }
+
+    #[test]
+    fn z_up_layers_are_turned_y_up() {
+        let scene = scene(
+            r#"#usda 1.0
+            (
+                upAxis = "Z"
+            )
+            def Sphere "Ball" {
+                double radius = 0.5
+                double3 xformOp:translate = (0, 0, 3)
+                uniform token[] xformOpOrder = ["xformOp:translate"]
+            }
+            "#,
+        );
+        let (p, _) = hit(&scene, Point3::new(0.0, 3.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!(close(p, Point3::new(0.0, 3.0, 0.5)), "{:?}", p);
+    }
+
+    #[test]
+    fn unsupported_prims_are_left_out_with_a_warning() {
+        let (scene, warnings) = import(r#"def Mesh "Teapot" { } over "Later" { } class Sphere "Template" { }"#).unwrap();
+        assert!(scene.world.objects.is_empty());
+        assert_eq!(warnings, ["/Teapot: Mesh prims aren't supported, so it's left out"]);
+    }
+
+    #[test]
+    fn syntax_errors_name_their_line() {
+        let error = import("#usda 1.0\ndef Sphere \"Ball\" {\n    double radius = \n}\n").err().unwrap();
+        assert!(error.starts_with("line 4:"), "{}", error);
+        assert!(import("def Sphere \"Ball\" {").is_err());
+        assert!(import("def Sphere \"Ball\" { string s = \"open }").err().unwrap().contains("unclosed string"));
+    }
+}
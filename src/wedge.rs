@@ -0,0 +1,190 @@
+use crate::integrator::Integrator;
+use crate::json::Json;
+use crate::render::{render_view, Image, RenderSettings};
+use crate::scene_file::Variable;
+use crate::scenes;
+use crate::types::*;
+
+// Pixels of dark border between cells
+const GAP: usize = 2;
+// Glyphs are 3x5 pixels, drawn this many times larger when the caption fits
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const MAX_SCALE: usize = 2;
+const TEXT_COLOR: f64 = 0.8;
+
+// One scene file variable to sweep and the values it takes, set on the command
+// line as `name=from:to:count` (evenly spaced, ends included) or `name=a,b,c`
+pub struct Sweep {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+impl Sweep {
+    pub fn parse(spec: &str) -> Result<Sweep, String> {
+        let (name, range) = spec.split_once('=').ok_or("expected NAME=FROM:TO:COUNT or NAME=A,B,...")?;
+        if name.is_empty() {
+            return Err("the variable needs a name".to_string());
+        }
+        let number = |text: &str| text.trim().parse::<f64>().map_err(|_| format!("'{text}' isn't a number"));
+        let values = match range.split(':').collect::<Vec<_>>()[..] {
+            [from, to, count] => {
+                let (from, to) = (number(from)?, number(to)?);
+                let count: usize = count.trim().parse().map_err(|_| format!("'{count}' isn't a count"))?;
+                match count {
+                    0 => return Err("the count must be at least 1".to_string()),
+                    1 => vec![from],
+                    _ => (0..count).map(|i| from + (to - from) * i as f64 / (count - 1) as f64).collect(),
+                }
+            }
+            [list] => list.split(',').map(number).collect::<Result<_, _>>()?,
+            _ => return Err("expected NAME=FROM:TO:COUNT or NAME=A,B,...".to_string()),
+        };
+        Ok(Sweep { name: name.to_string(), values })
+    }
+}
+
+// Renders the scene file once for every combination of the swept values (one
+// or two sweeps: the first across, the second down) and lays the renders out in
+// a grid, each captioned with its values. `settings` gives the size of one cell.
+// Variables in `fixed` are set in every cell, as with --set.
+pub fn render_wedge(
+    scene_name: &str,
+    sweeps: &[Sweep],
+    fixed: &[Variable],
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+) -> Result<Image, String> {
+    if sweeps.is_empty() || sweeps.len() > 2 {
+        return Err("a wedge sweeps one or two variables".to_string());
+    }
+    let single = [0.0];
+    let (across, down) = (&sweeps[0].values[..], sweeps.get(1).map_or(&single[..], |sweep| &sweep.values[..]));
+    let (cell_width, cell_height) = (settings.image_width as usize, settings.image_height as usize);
+
+    // Every caption is drawn at the same size: the largest at which all of them
+    // fit, with a margin either side
+    let captions: Vec<Vec<String>> = down
+        .iter()
+        .flat_map(|&y| across.iter().map(move |&x| [x, y]))
+        .map(|values| sweeps.iter().zip(values).map(|(sweep, value)| format!("{}={}", sweep.name, format_value(value))).collect())
+        .collect();
+    let longest = captions.iter().flatten().map(|line| line.chars().count()).max().unwrap_or(0);
+    let scale = (1..=MAX_SCALE).rev().find(|scale| longest * (GLYPH_WIDTH + 1) * scale + scale <= cell_width).unwrap_or(1);
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+    let caption_height = sweeps.len() * line_height + scale;
+
+    let (columns, rows) = (across.len(), down.len());
+    let (pitch_x, pitch_y) = (cell_width + GAP, cell_height + caption_height + GAP);
+    let mut sheet = Image::new(columns * pitch_x + GAP, rows * pitch_y + GAP);
+    for pixel in sheet.pixels.iter_mut() {
+        *pixel = Color::one() * 0.01;
+    }
+
+    for (index, caption) in captions.iter().enumerate() {
+        let (row, column) = (index / columns, index % columns);
+        let mut variables = fixed.to_vec();
+        for (sweep, value) in sweeps.iter().zip([across[column], down[row]]) {
+            variables.retain(|v| v.name != sweep.name);
+            variables.push(Variable { name: sweep.name.clone(), value: Json::Number(value) });
+        }
+        eprintln!("[{row}, {column}] {}", caption.join(", "));
+        let scene = scenes::load_with_overrides(scene_name, &[], &variables)?;
+
+        let render = render_view(&scene, &scene.camera, integrator, settings, false);
+        let (x0, y0) = (GAP + column * pitch_x, GAP + row * pitch_y);
+        for y in 0..cell_height {
+            let source = &render.pixels[y * cell_width..(y + 1) * cell_width];
+            let start = (y0 + y) * sheet.width + x0;
+            sheet.pixels[start..start + cell_width].copy_from_slice(source);
+        }
+        for (line_index, line) in caption.iter().enumerate() {
+            let y = y0 + cell_height + scale + line_index * line_height;
+            draw_text(&mut sheet, line, x0 + scale, y, scale, cell_width - scale);
+        }
+    }
+    Ok(sheet)
+}
+
+// Up to three decimal places, without trailing zeros
+fn format_value(value: f64) -> String {
+    let text = format!("{value:.3}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0".to_string() } else { text.to_string() }
+}
+
+// Draws `text` with its top left corner at (x, y), cutting it off at `max_width`
+fn draw_text(image: &mut Image, text: &str, x: usize, y: usize, scale: usize, max_width: usize) {
+    for (index, c) in text.chars().enumerate() {
+        let left = index * (GLYPH_WIDTH + 1) * scale;
+        if left + GLYPH_WIDTH * scale > max_width {
+            break;
+        }
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (x + left + column * scale + dx, y + row * scale + dy);
+                        if px < image.width && py < image.height {
+                            image.pixels[py * image.width + px] = Color::one() * TEXT_COLOR;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A 3x5 pixel font, one row of three bits per line, for enough characters to
+// write variable names and numbers. Anything else comes out as a '?'.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_lowercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        ' ' => [0b000; GLYPH_HEIGHT],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}